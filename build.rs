@@ -0,0 +1,18 @@
+// Regenerates include/nes.h from src/ffi.rs with the ffi feature on. Only
+// that file is parsed, so nothing else in the crate leaks into the header;
+// the layout is set in cbindgen.toml.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let config = cbindgen::Config::from_file("cbindgen.toml").expect("cbindgen.toml is valid");
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/ffi.rs")
+            .generate()
+            .expect("src/ffi.rs has a C interface")
+            .write_to_file("include/nes.h");
+    }
+}
\ No newline at end of file
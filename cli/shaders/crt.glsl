@@ -0,0 +1,25 @@
+#version 150
+
+uniform sampler2D frame;
+uniform vec4 source;
+
+in vec2 uv;
+out vec4 color;
+
+// How far the corners bow out, across and down
+const vec2 CURVATURE = vec2(0.06, 0.08);
+
+// A curved tube: the picture bent outwards, dimmed towards the edges and
+// split into scanlines
+void main() {
+    vec2 screen = (uv - source.xy) / (source.zw - source.xy) * 2.0 - 1.0;
+    screen += screen * screen.yx * screen.yx * CURVATURE;
+    if (abs(screen.x) > 1.0 || abs(screen.y) > 1.0) {
+        color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    vec2 bent = mix(source.xy, source.zw, screen * 0.5 + 0.5);
+    float beam = 0.7 + 0.3 * cos((fract(bent.y * 240.0) - 0.5) * 6.2832);
+    float vignette = 1.0 - 0.2 * dot(screen, screen);
+    color = vec4(texture(frame, bent).rgb * beam * vignette, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,15 @@
+#version 150
+
+// What every --shader gets: the 256x240 frame, filtered by --smooth/Shift+F4,
+// the part of it on show (see vertex.glsl) and the size in pixels of the
+// area it's drawn to. Shaders declare only the uniforms they use.
+uniform sampler2D frame;
+uniform vec4 source;
+uniform vec2 output_size;
+
+in vec2 uv;
+out vec4 color;
+
+void main() {
+    color = texture(frame, uv);
+}
\ No newline at end of file
@@ -0,0 +1,13 @@
+#version 150
+
+uniform sampler2D frame;
+
+in vec2 uv;
+out vec4 color;
+
+// Darkens the gap between NES lines, full brightness at each line's middle
+void main() {
+    float row = fract(uv.y * 240.0);
+    float beam = 0.75 + 0.25 * cos((row - 0.5) * 6.2832);
+    color = vec4(texture(frame, uv).rgb * beam, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,14 @@
+#version 150
+
+// Rectangle of the frame texture on show, left, top, right and bottom in
+// texture coordinates
+uniform vec4 source;
+
+out vec2 uv;
+
+// A quad over the viewport, made from the vertex number alone
+void main() {
+    vec2 corner = vec2(gl_VertexID & 1, gl_VertexID >> 1);
+    uv = mix(source.xy, source.zw, vec2(corner.x, 1.0 - corner.y));
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+}
\ No newline at end of file
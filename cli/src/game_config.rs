@@ -0,0 +1,120 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use nes_cpu::SystemVersion;
+
+// Per-user data directory: %APPDATA%, ~/Library/Application Support or
+// $XDG_DATA_HOME (~/.local/share), falling back to the working directory.
+pub fn data_dir() -> PathBuf {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.unwrap_or_else(|| PathBuf::from(".")).join("nes-emulator")
+}
+
+// Everything kept for one game (settings, .state files and the battery
+// save) lives in a directory named after the ROM's CRC32, so renaming the
+// file keeps them.
+pub fn game_dir(crc32: u32) -> PathBuf {
+    data_dir().join("games").join(format!("{:08X}", crc32))
+}
+
+// PRG-RAM of a battery-backed cartridge, raw
+pub fn battery_path(crc32: u32) -> PathBuf {
+    game_dir(crc32).join("battery.sav")
+}
+
+// Settings applied whenever the game is loaded. Stored as `key = value`
+// lines; cheats are Game Genie codes, one `cheat =` line each.
+#[derive(Default)]
+pub struct GameConfig {
+    pub region: Option<SystemVersion>,
+    // Hide the top and bottom 8 lines, which most TVs cut off
+    pub overscan: bool,
+    pub palette: Option<PathBuf>,
+    // VS System or cartridge (NWC timer) DIP switches, bit 0 switch 1
+    pub dip_switches: Option<u8>,
+    pub cheats: Vec<String>,
+}
+
+impl GameConfig {
+    fn path(crc32: u32) -> PathBuf {
+        game_dir(crc32).join("config.txt")
+    }
+
+    pub fn load(crc32: u32) -> Self {
+        let mut config = GameConfig::default();
+        let Ok(text) = fs::read_to_string(Self::path(crc32)) else {
+            return config;
+        };
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let Some((key, value)) = line.split_once('=') else {
+                println!("Ignoring config line: {}", line);
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "region" => config.region = parse_region(value),
+                "overscan" => config.overscan = value == "true",
+                "palette" => config.palette = Some(PathBuf::from(value)),
+                "dip_switches" => config.dip_switches = parse_dip_switches(value),
+                "cheat" => config.cheats.push(value.to_string()),
+                other => println!("Unknown config key: {}", other),
+            }
+        }
+        config
+    }
+
+    pub fn save(&self, crc32: u32, rom_name: &str) -> std::io::Result<()> {
+        let mut text = format!("# Settings for {}\n", rom_name);
+        if let Some(region) = self.region {
+            text += &format!("region = {:?}\n", region);
+        }
+        text += &format!("overscan = {}\n", self.overscan);
+        if let Some(palette) = &self.palette {
+            text += &format!("palette = {}\n", palette.display());
+        }
+        if let Some(dip_switches) = self.dip_switches {
+            text += &format!("dip_switches = 0x{:02X}\n", dip_switches);
+        }
+        for cheat in &self.cheats {
+            text += &format!("cheat = {}\n", cheat);
+        }
+
+        fs::create_dir_all(game_dir(crc32))?;
+        fs::write(Self::path(crc32), text)
+    }
+}
+
+// Hex with a 0x prefix or decimal
+fn parse_dip_switches(value: &str) -> Option<u8> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| println!("Invalid DIP switches: {}", value)).ok()
+}
+
+fn parse_region(name: &str) -> Option<SystemVersion> {
+    let region = match name {
+        "NTSC" => SystemVersion::NTSC,
+        "PAL" => SystemVersion::PAL,
+        "Dendy" => SystemVersion::Dendy,
+        "RGB" => SystemVersion::RGB,
+        "BrazilFamiclone" => SystemVersion::BrazilFamiclone,
+        "ArgentinaFamiclone" => SystemVersion::ArgentinaFamiclone,
+        "Auto" => SystemVersion::Auto,
+        _ => {
+            println!("Unknown region: {}", name);
+            return None;
+        }
+    };
+    Some(region)
+}
\ No newline at end of file
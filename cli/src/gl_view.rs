@@ -0,0 +1,169 @@
+use std::ffi::CString;
+use std::fs;
+use std::ptr;
+
+use gl::types::GLchar;
+use sdl2::rect::Rect;
+use sdl2::video::{GLContext, Window};
+use sdl2::VideoSubsystem;
+
+const VERTEX_SHADER: &str = include_str!("../shaders/vertex.glsl");
+
+// Post-process shaders that come with the emulator, by the names --shader
+// and F12 know them by. shaders/none.glsl says what a shader is given.
+pub const BUILTIN_SHADERS: [(&str, &str); 3] = [
+    ("none", include_str!("../shaders/none.glsl")),
+    ("scanlines", include_str!("../shaders/scanlines.glsl")),
+    ("crt", include_str!("../shaders/crt.glsl")),
+];
+
+// A built in shader's source, or else the GLSL file `name`, read afresh
+// each time so edits show up on the next F12
+pub fn shader_source(name: &str) -> Result<String, String> {
+    match BUILTIN_SHADERS.iter().find(|(builtin, _)| *builtin == name) {
+        Some((_, source)) => Ok(source.to_string()),
+        None => fs::read_to_string(name).map_err(|e| format!("{}: {}", name, e)),
+    }
+}
+
+// Draws frames with OpenGL through a fragment shader, in place of SDL's
+// renderer, for --shader
+pub struct GlView {
+    _context: GLContext,
+    vertex_shader: u32,
+    program: u32,
+    texture: u32,
+    vertex_array: u32,
+}
+
+impl GlView {
+    // The window needs a 3.2 core context, asked for before it's built
+    pub fn new(video: &VideoSubsystem, window: &Window, fragment: &str) -> Result<Self, String> {
+        let context = window.gl_create_context()?;
+        gl::load_with(|name| video.gl_get_proc_address(name) as *const _);
+        let mut view = GlView { _context: context, vertex_shader: compile(gl::VERTEX_SHADER, VERTEX_SHADER)?, program: 0, texture: 0, vertex_array: 0 };
+        unsafe {
+            // The quad comes from gl_VertexID, but core profile still wants
+            // a vertex array bound to draw
+            gl::GenVertexArrays(1, &mut view.vertex_array);
+            gl::GenTextures(1, &mut view.texture);
+            gl::BindTexture(gl::TEXTURE_2D, view.texture);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB8 as i32, 256, 240, 0, gl::RGB, gl::UNSIGNED_BYTE, ptr::null());
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        }
+        view.set_shader(fragment)?;
+        Ok(view)
+    }
+
+    // Swaps the fragment shader, keeping the old one if the new one
+    // doesn't compile
+    pub fn set_shader(&mut self, fragment: &str) -> Result<(), String> {
+        let fragment_shader = compile(gl::FRAGMENT_SHADER, fragment)?;
+        let program = link(self.vertex_shader, fragment_shader);
+        unsafe {
+            gl::DeleteShader(fragment_shader);
+        }
+        let program = program?;
+        if self.program != 0 {
+            unsafe {
+                gl::DeleteProgram(self.program);
+            }
+        }
+        self.program = program;
+        Ok(())
+    }
+
+    // `frame` is the 256x240 RGB picture, `src` the part of it on show and
+    // `dst` where that goes in the window's drawable, both in SDL's top
+    // down coordinates. Swapping the window is left to the caller.
+    pub fn draw(&mut self, frame: &[u8], drawable: (u32, u32), src: Rect, dst: Rect, smooth: bool) {
+        let filter = if smooth { gl::LINEAR } else { gl::NEAREST } as i32;
+        unsafe {
+            gl::Viewport(0, 0, drawable.0 as i32, drawable.1 as i32);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::Viewport(dst.x(), drawable.1 as i32 - dst.bottom(), dst.width() as i32, dst.height() as i32);
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter);
+            gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, 256, 240, gl::RGB, gl::UNSIGNED_BYTE, frame.as_ptr() as *const _);
+
+            gl::UseProgram(self.program);
+            gl::Uniform1i(self.uniform("frame"), 0);
+            gl::Uniform4f(self.uniform("source"), src.x() as f32 / 256.0, src.y() as f32 / 240.0, src.right() as f32 / 256.0, src.bottom() as f32 / 240.0);
+            gl::Uniform2f(self.uniform("output_size"), dst.width() as f32, dst.height() as f32);
+            gl::BindVertexArray(self.vertex_array);
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+        }
+    }
+
+    // -1 for a uniform the shader doesn't declare, which GL ignores setting
+    fn uniform(&self, name: &str) -> i32 {
+        let name = CString::new(name).unwrap();
+        unsafe { gl::GetUniformLocation(self.program, name.as_ptr()) }
+    }
+}
+
+impl Drop for GlView {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteProgram(self.program);
+            gl::DeleteShader(self.vertex_shader);
+            gl::DeleteTextures(1, &self.texture);
+            gl::DeleteVertexArrays(1, &self.vertex_array);
+        }
+    }
+}
+
+fn compile(kind: u32, source: &str) -> Result<u32, String> {
+    let source = CString::new(source).map_err(|e| e.to_string())?;
+    unsafe {
+        let shader = gl::CreateShader(kind);
+        gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
+        gl::CompileShader(shader);
+        let mut compiled = 0;
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut compiled);
+        if compiled == 0 {
+            let log = info_log(shader, gl::GetShaderiv, gl::GetShaderInfoLog);
+            gl::DeleteShader(shader);
+            return Err(log);
+        }
+        Ok(shader)
+    }
+}
+
+fn link(vertex: u32, fragment: u32) -> Result<u32, String> {
+    unsafe {
+        let program = gl::CreateProgram();
+        gl::AttachShader(program, vertex);
+        gl::AttachShader(program, fragment);
+        gl::LinkProgram(program);
+        gl::DetachShader(program, vertex);
+        gl::DetachShader(program, fragment);
+        let mut linked = 0;
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut linked);
+        if linked == 0 {
+            let log = info_log(program, gl::GetProgramiv, gl::GetProgramInfoLog);
+            gl::DeleteProgram(program);
+            return Err(log);
+        }
+        Ok(program)
+    }
+}
+
+// Compiler or linker output for a shader or program
+unsafe fn info_log(
+    object: u32,
+    get_parameter: unsafe fn(u32, u32, *mut i32),
+    get_log: unsafe fn(u32, i32, *mut i32, *mut GLchar),
+) -> String {
+    let mut length = 0;
+    get_parameter(object, gl::INFO_LOG_LENGTH, &mut length);
+    let mut log = vec![0u8; length.max(1) as usize];
+    get_log(object, length, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+    String::from_utf8_lossy(&log).trim_end_matches('\0').trim().to_string()
+}
\ No newline at end of file
@@ -4,41 +4,227 @@ extern crate sdl2;
 use std::env;
 use std::fs::File;
 use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 
-use nes_cpu::rom::Rom;
+use game_config::data_dir;
+use nes_cpu::rom::{database::RomDatabase, patch, Rom};
+use nes_cpu::audit::LockstepAudit;
+use nes_cpu::config::{AudioSynthesis, NesConfig};
+use nes_cpu::control::ControlServer;
+use nes_cpu::controller::PortLayout;
+use nes_cpu::pacer::Pacer;
 use nes_cpu::Nes;
-use sdl_wrapper::SDLWrapper;
+use sdl_wrapper::{DisplayOptions, SDLWrapper};
 
+mod game_config;
+mod gl_view;
+mod menu;
+mod mic;
+mod osd;
+mod rom_watch;
 mod sdl_wrapper;
 
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let has_flag = |flag: &str| args.iter().any(|arg| arg == flag);
 
-    if args.len() < 2 {
-        panic!("Missing ROM file path.");
+    // Without a ROM path the emulator starts in the ROM menu, listing
+    // --rom-dir (default: the working directory) and recently played games
+    let value_index = |flag: &str| args.iter().position(|arg| arg == flag).map(|i| i + 1);
+    let rom_dir_index = value_index("--rom-dir");
+    let rom_dir = rom_dir_index.and_then(|i| args.get(i)).map_or(PathBuf::from("."), PathBuf::from);
+    // --control <address> takes JSON commands over TCP, e.g. 127.0.0.1:7878,
+    // and --headless runs from those alone without a window
+    let control_index = value_index("--control");
+    // --log <filters> for the emulator's diagnostics, in RUST_LOG syntax:
+    // mapper=debug shows mapper register writes, ppu=trace PPU ones
+    let log_index = value_index("--log");
+    // --display <n> opens the window on monitor n, counting from 0
+    let display_index = value_index("--display");
+    // --shader <name or file> draws through OpenGL with a post-process
+    // shader: none, scanlines, crt or a GLSL file. F12 cycles them.
+    let shader_index = value_index("--shader");
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(filters) = log_index.and_then(|i| args.get(i)) {
+        logger.parse_filters(filters);
     }
+    logger.init();
+    let filepath = args.iter().enumerate()
+        .find(|&(i, arg)| !arg.starts_with("--") && ![rom_dir_index, control_index, log_index, display_index, shader_index].contains(&Some(i)))
+        .map(|(_, arg)| arg);
+    let control = control_index.map(|i| {
+        let addr = args.get(i).map_or("127.0.0.1:7878", String::as_str);
+        let server = ControlServer::bind(addr).unwrap_or_else(|e| panic!("Failed to listen on {}: {}", addr, e));
+        println!("Control server listening on {}", addr);
+        server
+    });
 
-    let filepath = &args[1];
-    let file = File::open(filepath).expect("Failed to open file");
-    let mut reader = BufReader::new(file);
-    let mut data = Vec::new();
-    reader.read_to_end(&mut data).expect("Failed to read file");
-
-    let rom = Rom::new(data);
-    debug_rom(&rom);
-    
-    let mut nes = Nes::new(nes_cpu::SystemVersion::NTSC);
-    nes.set_rom(rom);
-    // nes.set_debug_mode();
-    nes.on();
+    let mut config = NesConfig::new(nes_cpu::SystemVersion::Auto);
+    if has_flag("--band-limited") {
+        config.audio_synthesis = AudioSynthesis::BandLimited;
+    }
+    // Only --vaus and --keyboard plug devices in, whatever the ROM asks for
+    if has_flag("--manual-input") {
+        config.auto_input_devices = false;
+    }
+    // Checks the ROM runs deterministically, through save states too
+    if has_flag("--audit") {
+        let path = filepath.expect("--audit needs a ROM");
+        audit(path, config);
+        return;
+    }
+
+    let mut nes = Nes::with_config(config);
+    // Shown if the game crashes the CPU
+    nes.record_history(64);
+    // Wires --vaus to the expansion port, as on a Famicom. --mic needs it
+    // too, only the Famicom's second controller has a microphone.
+    if has_flag("--famicom") || has_flag("--mic") {
+        nes.set_port_layout(PortLayout::Famicom);
+    }
+    if has_flag("--vaus") {
+        nes.connect_vaus(true);
+    }
+    // Scroll Lock toggles capturing the host keyboard for it
+    if has_flag("--keyboard") {
+        nes.connect_keyboard(true);
+    }
+    // nes.set_trace_log(std::fs::File::create("debug.log").unwrap());
     // nes.set_start(0xC000);
-    // nes.run();
-    let mut wrapper = SDLWrapper::new(nes);
+    // nes.run_until(|_, _| std::ops::ControlFlow::<()>::Continue(()));
+    if has_flag("--headless") {
+        let Some(control) = control else {
+            panic!("--headless needs --control <address>");
+        };
+        if let Some(path) = filepath {
+            nes.load_rom(read_rom(path).unwrap_or_else(|e| panic!("{}", e)));
+            nes.on();
+        }
+        run_headless(nes, control);
+        return;
+    }
+
+    let display = DisplayOptions {
+        integer_scale: has_flag("--integer-scale"),
+        aspect_correction: has_flag("--aspect-8-7"),
+        smooth: has_flag("--smooth"),
+        ..DisplayOptions::default()
+    };
+    let mut wrapper = SDLWrapper::new(nes, rom_dir, display);
+    if let Some(control) = control {
+        wrapper.set_control_server(control);
+    }
+    // Borderless, over the whole display; F11 or Alt+Enter switch back
+    wrapper.set_fullscreen(has_flag("--fullscreen"));
+    if let Some(display) = display_index.and_then(|i| args.get(i)) {
+        wrapper.use_display(display.parse().unwrap_or_else(|_| panic!("--display takes a number, not {}", display)));
+    }
+    match filepath {
+        Some(path) => wrapper.load_rom(path).unwrap_or_else(|e| panic!("{}", e)),
+        None => wrapper.open_menu(),
+    }
+    // Reloads the ROM whenever it's rebuilt, for homebrew development.
+    // --keep-ram carries system RAM over the power cycle that comes with it.
+    if has_flag("--watch") {
+        wrapper.watch_rom(has_flag("--keep-ram"));
+    }
+    if let Some(shader) = shader_index.and_then(|i| args.get(i)) {
+        wrapper.use_shader(shader);
+    }
+    // Frames paced by the audio device's clock rather than vsync and a
+    // timer, for long sessions without drift or stutter
+    if has_flag("--audio-sync") {
+        wrapper.sync_to_audio();
+    }
+    // Famicom microphone from the host's recording device
+    if has_flag("--mic") {
+        wrapper.capture_mic();
+    }
     wrapper.run();
 }
 
-fn debug_rom(rom: &Rom){
+// No window, audio or keyboard: the console runs at full speed unless a
+// control client pauses it, and only ever stops when killed
+fn run_headless(mut nes: Nes, mut control: ControlServer) {
+    let mut pacer = Pacer::new(nes.config().sample_rate, Duration::ZERO, nes.frame_period());
+    loop {
+        control.poll(&mut nes);
+        if !control.is_paused() {
+            nes.run_frame();
+        }
+        // Nothing plays the audio, so don't let it pile up
+        nes.take_audio_samples();
+        pacer.set_frame_period(nes.frame_period());
+        pacer.wait(None);
+    }
+}
+
+// A minute of play with no input on two consoles in lockstep, one of them
+// restored from a save state every frame
+fn audit(path: &str, config: NesConfig) {
+    const FRAMES: u64 = 60 * 60;
+    let rom = std::fs::read(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+    let mut audit = LockstepAudit::new(&rom, config).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    audit.set_reload(true);
+    match audit.run((0..FRAMES).map(|_| [0, 0])) {
+        Ok(()) => println!("{} frames in lockstep, no divergence", FRAMES),
+        Err(divergence) => println!("{}", divergence),
+    }
+}
+
+// Loads an iNES/NES 2.0 image. Anything without the header magic and short
+// files are errors, so a stray file dropped on the window doesn't take the
+// emulator down; unsupported mappers load with a warning.
+pub fn read_rom(path: &str) -> Result<Rom, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let mut reader = BufReader::new(file);
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+
+    // Translations and hacks: <rom>.ips or <rom>.bps next to the ROM is
+    // applied in memory, leaving the file untouched
+    let ips = Path::new(path).with_extension("ips");
+    let bps = Path::new(path).with_extension("bps");
+    if let Ok(patch) = std::fs::read(&ips) {
+        data = patch::apply_ips(&data, &patch).map_err(|e| format!("{}: {}", ips.display(), e))?;
+        println!("Applied {}", ips.display());
+    } else if let Ok(patch) = std::fs::read(&bps) {
+        data = patch::apply_bps(&data, &patch).map_err(|e| format!("{}: {}", bps.display(), e))?;
+        println!("Applied {}", bps.display());
+    }
+
+    if data.len() < 16 || &data[0..4] != b"NES\x1A" {
+        return Err(format!("{} is not an iNES ROM", path));
+    }
+
+    match database() {
+        Some(database) => Rom::with_database(data, database),
+        None => Rom::from_bytes(data),
+    }.map_err(|e| format!("{}: {}", path, e))
+}
+
+// Headers are only corrected from an nes20db.xml in the data directory,
+// parsed on the first load
+fn database() -> Option<&'static RomDatabase> {
+    static DATABASE: OnceLock<Option<RomDatabase>> = OnceLock::new();
+    DATABASE.get_or_init(|| {
+        let xml = std::fs::read_to_string(data_dir().join("nes20db.xml")).ok()?;
+        Some(RomDatabase::from_nes20db(&xml))
+    }).as_ref()
+}
+
+pub fn rom_name(path: &str) -> String {
+    Path::new(path).file_stem().map_or(path.to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+pub fn debug_rom(rom: &Rom){
+    if let Some(title) = &rom.metadata().title {
+        println!("Title: {}", title);
+    }
+    println!("CRC32: {:08X}", rom.crc32());
     println!("iNES Version: {:?}", rom.header.nes_version);
     println!("PRG ROM SIZE: {}", rom.header.prg_rom_size);
     println!("PRG RAM SIZE: {}", rom.header.prg_ram_size);
@@ -52,4 +238,10 @@ fn debug_rom(rom: &Rom){
     println!("Console: {:?}", rom.header.console);
     println!("Mirroring: {:?}", rom.header.mirroring);
     println!("TV System: {:?}", rom.header.tv);
+    for warning in rom.warnings() {
+        println!("Warning: {}", warning);
+    }
+    if rom.header.playchoice_range().is_some() {
+        println!("PlayChoice-10 dump: INST-ROM and PROM are skipped, running as a regular cartridge");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{game_config::data_dir, osd::draw_text, rom_name};
+
+const RECENT_FILE: &str = "recent_roms.txt";
+const MAX_RECENT: usize = 10;
+const LINE_HEIGHT: usize = 10;
+const VISIBLE_LINES: usize = 21;
+const MAX_NAME_CHARS: usize = 38;
+
+fn recent_file() -> PathBuf {
+    data_dir().join(RECENT_FILE)
+}
+
+// Most recently played first
+pub fn recent_roms() -> Vec<String> {
+    fs::read_to_string(recent_file())
+        .map(|list| list.lines().filter(|line| !line.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+pub fn add_recent_rom(path: &str) {
+    let path = fs::canonicalize(path).map_or(path.to_string(), |path| path.to_string_lossy().into_owned());
+    let mut recent = recent_roms();
+    recent.retain(|entry| *entry != path);
+    recent.insert(0, path);
+    recent.truncate(MAX_RECENT);
+    let result = fs::create_dir_all(data_dir()).and_then(|_| fs::write(recent_file(), recent.join("\n")));
+    if let Err(e) = result {
+        println!("Failed to save recent ROMs: {}", e);
+    }
+}
+
+enum Entry {
+    Heading(&'static str),
+    Rom(String),
+}
+
+// ROM picker drawn over the frame with the OSD font: recently played titles
+// followed by the .nes files in the ROM directory.
+pub struct Menu {
+    entries: Vec<Entry>,
+    selected: usize,
+    scroll: usize,
+}
+
+impl Menu {
+    pub fn new(rom_dir: &Path) -> Self {
+        let mut entries = Vec::new();
+
+        let recent = recent_roms();
+        if !recent.is_empty() {
+            entries.push(Entry::Heading("Recent"));
+            entries.extend(recent.into_iter().map(Entry::Rom));
+        }
+
+        let mut roms: Vec<String> = fs::read_dir(rom_dir)
+            .map(|dir| {
+                dir.filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("nes")))
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        roms.sort();
+        entries.push(Entry::Heading(if roms.is_empty() { "No ROMs found" } else { "ROMs" }));
+        entries.extend(roms.into_iter().map(Entry::Rom));
+
+        let selected = entries.iter().position(|entry| matches!(entry, Entry::Rom(_))).unwrap_or(0);
+        Menu { entries, selected, scroll: 0 }
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        match self.entries.get(self.selected) {
+            Some(Entry::Rom(path)) => Some(path),
+            _ => None
+        }
+    }
+
+    pub fn up(&mut self) {
+        if let Some(index) = (0..self.selected).rev().find(|&i| matches!(self.entries[i], Entry::Rom(_))) {
+            self.selected = index;
+        }
+        self.scroll = self.scroll.min(self.selected.saturating_sub(1));
+    }
+
+    pub fn down(&mut self) {
+        if let Some(index) = (self.selected + 1..self.entries.len()).find(|&i| matches!(self.entries[i], Entry::Rom(_))) {
+            self.selected = index;
+        }
+        if self.selected >= self.scroll + VISIBLE_LINES {
+            self.scroll = self.selected + 1 - VISIBLE_LINES;
+        }
+    }
+
+    pub fn draw(&self, frame: &mut [u8]) {
+        // Dim whatever is behind the list
+        for byte in frame.iter_mut() {
+            *byte /= 4;
+        }
+
+        draw_text(frame, 4, 4, "Select a ROM - Enter to play");
+        for (line, entry) in self.entries.iter().enumerate().skip(self.scroll).take(VISIBLE_LINES) {
+            let y = 20 + (line - self.scroll) * LINE_HEIGHT;
+            match entry {
+                Entry::Heading(text) => draw_text(frame, 4, y, text),
+                Entry::Rom(path) => {
+                    let name: String = rom_name(path).chars().take(MAX_NAME_CHARS).collect();
+                    if line == self.selected {
+                        draw_text(frame, 4, y, ">");
+                    }
+                    draw_text(frame, 16, y, &name);
+                }
+            }
+        }
+    }
+}
\ No newline at end of file
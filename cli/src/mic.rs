@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+// Peak level in a capture buffer that counts as a shout or a blow. The
+// Famicom's microphone only tells the game loud or quiet.
+const THRESHOLD: f32 = 0.25;
+
+// --mic: the host's default recording device, standing in for the
+// microphone on the Famicom's second controller
+pub struct Microphone {
+    loud: Arc<AtomicBool>,
+    _device: AudioDevice<Capture>,
+}
+
+struct Capture {
+    loud: Arc<AtomicBool>,
+}
+
+impl AudioCallback for Capture {
+    type Channel = f32;
+
+    fn callback(&mut self, samples: &mut [f32]) {
+        let peak = samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        self.loud.store(peak > THRESHOLD, Ordering::Relaxed);
+    }
+}
+
+impl Microphone {
+    pub fn open(audio: &AudioSubsystem) -> Result<Self, String> {
+        // Small buffers, so the level follows the voice within a frame or two
+        let spec = AudioSpecDesired { freq: Some(22050), channels: Some(1), samples: Some(512) };
+        let loud = Arc::new(AtomicBool::new(false));
+        let capture = Capture { loud: loud.clone() };
+        let device = audio.open_capture(None, &spec, |_| capture)?;
+        device.resume();
+        Ok(Microphone { loud, _device: device })
+    }
+
+    pub fn is_loud(&self) -> bool {
+        self.loud.load(Ordering::Relaxed)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+
+use nes_cpu::{ppu_viewer::PpuImage, stats::SpeedStats};
+
+const WIDTH: usize = 256;
+const HEIGHT: usize = 240;
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+
+// 5x7 glyphs, one byte per row with bit 4 as the leftmost pixel. Lowercase
+// is drawn as uppercase and anything missing as a blank.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        _ => [0; GLYPH_HEIGHT]
+    }
+}
+
+// On-screen display drawn into the frame before it's uploaded: an optional
+// performance counter and short-lived status messages.
+pub struct Osd {
+    show_fps: bool,
+    stats: SpeedStats,
+    message: Option<(String, Instant)>,
+    // A save state's preview, shown in the top right with the message
+    thumbnail: Option<PpuImage>,
+}
+
+impl Osd {
+    pub fn new() -> Self {
+        Osd {
+            show_fps: false,
+            stats: SpeedStats::default(),
+            message: None,
+            thumbnail: None,
+        }
+    }
+
+    pub fn toggle_fps(&mut self) {
+        self.show_fps = !self.show_fps;
+    }
+
+    pub fn message(&mut self, text: &str) {
+        self.message = Some((text.to_string(), Instant::now()));
+        self.thumbnail = None;
+    }
+
+    pub fn message_with_thumbnail(&mut self, text: &str, thumbnail: PpuImage) {
+        self.message(text);
+        self.thumbnail = Some(thumbnail);
+    }
+
+    pub fn frame_done(&mut self, stats: SpeedStats) {
+        self.stats = stats;
+    }
+
+    pub fn draw(&mut self, frame: &mut [u8]) {
+        if self.show_fps {
+            let stats = &self.stats;
+            let mut text = format!("{:.0} FPS {:.1}MS {:.0}%", stats.fps, stats.frame_time.as_secs_f32() * 1000.0, stats.speed * 100.0);
+            if let Some(fill) = stats.audio_fill {
+                text += &format!(" A:{:.0}%", fill * 100.0);
+            }
+            if stats.falling_behind() {
+                text += " SLOW";
+            }
+            draw_text(frame, 4, 4, &text);
+        }
+
+        if let Some((text, shown_at)) = &self.message {
+            if shown_at.elapsed() < MESSAGE_TIME {
+                draw_text(frame, 4, HEIGHT - GLYPH_HEIGHT - 5, text);
+                if let Some(thumbnail) = &self.thumbnail {
+                    draw_thumbnail(frame, WIDTH - thumbnail.width - 5, 5, thumbnail);
+                }
+            } else {
+                self.message = None;
+                self.thumbnail = None;
+            }
+        }
+    }
+}
+
+// White text with a one pixel drop shadow, clipped to the frame
+pub fn draw_text(frame: &mut [u8], x: usize, y: usize, text: &str) {
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i * (GLYPH_WIDTH + 1);
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0x10 >> col) != 0 {
+                    put_pixel(frame, left + col + 1, y + row + 1, 0x00);
+                    put_pixel(frame, left + col, y + row, 0xFF);
+                }
+            }
+        }
+    }
+}
+
+// With a white border around it
+fn draw_thumbnail(frame: &mut [u8], x: usize, y: usize, image: &PpuImage) {
+    for border_x in x - 1..=x + image.width {
+        put_pixel(frame, border_x, y - 1, 0xFF);
+        put_pixel(frame, border_x, y + image.height, 0xFF);
+    }
+    for border_y in y..y + image.height {
+        put_pixel(frame, x - 1, border_y, 0xFF);
+        put_pixel(frame, x + image.width, border_y, 0xFF);
+    }
+    for row in 0..image.height.min(HEIGHT - y) {
+        for col in 0..image.width.min(WIDTH - x) {
+            let idx = ((y + row) * WIDTH + x + col) * 3;
+            frame[idx..idx + 3].copy_from_slice(&image.pixel(col, row));
+        }
+    }
+}
+
+fn put_pixel(frame: &mut [u8], x: usize, y: usize, value: u8) {
+    if x < WIDTH && y < HEIGHT {
+        let idx = (y * WIDTH + x) * 3;
+        frame[idx..idx + 3].fill(value);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,53 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+// How often the ROM file's modification time is checked
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// --watch: notices the assembler rewriting the ROM so it can be reloaded.
+// A new modification time only counts once it's held for a poll, so a ROM
+// still being written isn't loaded half done.
+pub struct RomWatch {
+    path: String,
+    modified: Option<SystemTime>,
+    pending: Option<SystemTime>,
+    last_poll: Instant,
+    // Carry the 2KB of system RAM over to the reloaded game (--keep-ram)
+    pub keep_ram: bool,
+}
+
+impl RomWatch {
+    pub fn new(path: &str, keep_ram: bool) -> Self {
+        let modified = modified(path);
+        RomWatch { path: path.to_string(), modified, pending: modified, last_poll: Instant::now(), keep_ram }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // True once for each finished rewrite of the file
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        // Gone for a moment while the linker replaces it
+        let Some(modified) = modified(&self.path) else {
+            return false;
+        };
+        if Some(modified) == self.modified {
+            return false;
+        }
+        if Some(modified) != self.pending {
+            self.pending = Some(modified);
+            return false;
+        }
+        self.modified = Some(modified);
+        true
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
\ No newline at end of file
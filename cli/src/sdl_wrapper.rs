@@ -1,46 +1,628 @@
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
-use nes_cpu::{controller::Button, Nes};
-use sdl2::{event::Event, keyboard::{Keycode, Scancode}, rect::Rect};
+use nes_cpu::{apu::Channel, control::ControlServer, controller::{Button, PortLayout}, crash::CrashReport, family_keyboard::Key, overlay::Overlays, pacer::Pacer, rom::header::HeaderWarning, save_state::state_preview, watch::MemoryRegion, Nes, SystemVersion};
+use sdl2::{audio::AudioSpecDesired, event::{Event, WindowEvent}, keyboard::{Keycode, Mod, Scancode}, mouse::MouseButton, pixels::{Color, PixelFormatEnum}, rect::Rect, render::{Canvas, Texture, TextureCreator}, surface::Surface, video::{FullscreenType, GLProfile, SwapInterval, Window, WindowContext, WindowPos}, VideoSubsystem};
+
+use crate::{debug_rom, game_config::{battery_path, game_dir, GameConfig}, gl_view::{shader_source, GlView, BUILTIN_SHADERS}, menu::{add_recent_rom, Menu}, mic::Microphone, osd::Osd, read_rom, rom_name, rom_watch::RomWatch};
+
+// Written to the working directory when the emulator panics
+const CRASH_REPORT: &str = "crash-report.txt";
+// And the console as it crashed, to load back in
+const CRASH_STATE: &str = "crash-report.state";
+
+// Audio kept queued ahead of the speakers under --audio-sync
+const AUDIO_LATENCY: Duration = Duration::from_millis(50);
+
+// F9 saves the nametable, pattern table and palette images in here
+const PPU_DUMP_DIR: &str = "ppu-dump";
+
+// Save state slots per game, 0-9, picked with Page Up/Page Down
+const STATE_SLOTS: u8 = 10;
+
+// How the 256x240 frame is fitted into the window
+#[derive(Clone, Copy, Default)]
+pub struct DisplayOptions {
+    // Only scale by whole multiples so every NES pixel is the same size
+    pub integer_scale: bool,
+    // Stretch horizontally to the NTSC 8:7 pixel aspect ratio
+    pub aspect_correction: bool,
+    // Crop the top and bottom 8 lines like a TV would (per-game setting)
+    pub overscan: bool,
+    // Blend pixels when scaling instead of keeping them square and crisp
+    pub smooth: bool,
+}
+
+// The loaded ROM and its saved settings
+struct Game {
+    path: String,
+    crc32: u32,
+    name: String,
+    config: GameConfig,
+    battery: bool,
+}
 
 pub struct SDLWrapper{
     nes: Nes,
-    previous_keyboard_state: [bool; 8]
+    title: String,
+    game: Option<Game>,
+    // Used for games whose settings don't name a region
+    default_region: SystemVersion,
+    osd: Osd,
+    menu: Option<Menu>,
+    rom_dir: PathBuf,
+    display: DisplayOptions,
+    overlays: Overlays,
+    // Whether the overlays turned scroll capture on, so they only turn off
+    // their own
+    overlay_capture: bool,
+    // Borderless, covering the display the window is on
+    fullscreen: bool,
+    // Display to move the window to, taken by the next frame
+    move_to_display: Option<i32>,
+    // The one it's on now, and how many there are
+    window_display: i32,
+    display_count: i32,
+    previous_keyboard_state: [[bool; 8]; 2],
+    window_width: u32,
+    // Host keys go to the Family BASIC keyboard instead of the joypads
+    keyboard_capture: bool,
+    // So a CPU jam is only reported once
+    jammed: bool,
+    // Remote control for scripts, see --control
+    control: Option<ControlServer>,
+    // Follows whichever ROM is loaded, see --watch
+    watch: Option<RomWatch>,
+    // Open the host microphone once running, see --mic
+    mic_capture: bool,
+    // Pace frames off the audio device instead of vsync, see --audio-sync
+    audio_sync: bool,
+    // Names or paths F12 cycles through, and the one in use. None draws
+    // with SDL's renderer, without shaders.
+    shaders: Vec<String>,
+    shader: Option<usize>,
+    shader_changed: bool,
+    // Where F1 saves and Shift+F1 loads
+    state_slot: u8,
+}
+
+// Where frames are drawn: SDL's renderer, or OpenGL when there are shaders
+enum Output {
+    Canvas(Canvas<Window>),
+    Gl(Window, GlView),
+}
+
+impl Output {
+    fn window(&self) -> &Window {
+        match self {
+            Output::Canvas(canvas) => canvas.window(),
+            Output::Gl(window, _) => window,
+        }
+    }
+
+    fn window_mut(&mut self) -> &mut Window {
+        match self {
+            Output::Canvas(canvas) => canvas.window_mut(),
+            Output::Gl(window, _) => window,
+        }
+    }
 }
 
 impl SDLWrapper {
-    pub fn new(nes: Nes) -> Self {
+    pub fn new(nes: Nes, rom_dir: PathBuf, display: DisplayOptions) -> Self {
         SDLWrapper{
+            default_region: nes.config().region,
             nes,
-            previous_keyboard_state: [false; 8]
+            title: "nes-emulator".to_string(),
+            game: None,
+            osd: Osd::new(),
+            menu: None,
+            rom_dir,
+            display,
+            overlays: Overlays::default(),
+            overlay_capture: false,
+            fullscreen: false,
+            move_to_display: None,
+            window_display: 0,
+            display_count: 1,
+            previous_keyboard_state: [[false; 8]; 2],
+            window_width: 256,
+            keyboard_capture: false,
+            jammed: false,
+            control: None,
+            watch: None,
+            mic_capture: false,
+            audio_sync: false,
+            shaders: Vec::new(),
+            shader: None,
+            shader_changed: false,
+            state_slot: 0,
+        }
+    }
+
+    pub fn set_control_server(&mut self, server: ControlServer) {
+        self.control = Some(server);
+    }
+
+    // Inserts the cartridge and powers on, or power cycles a running console
+    pub fn load_rom(&mut self, path: &str) -> Result<(), String> {
+        let rom = read_rom(path)?;
+        debug_rom(&rom);
+        let crc32 = rom.crc32();
+        let unsupported = rom.warnings().iter().find(|warning| matches!(warning, HeaderWarning::UnsupportedMapper(_))).map(|warning| warning.to_string());
+        let config = GameConfig::load(crc32);
+        let battery = rom.header.battery;
+
+        self.save_battery();
+        self.nes.load_rom(rom);
+        self.apply_game_config(&config);
+        self.load_symbols(path);
+        if !self.nes.is_on() {
+            self.nes.on();
+        }
+        if battery {
+            if let Ok(ram) = fs::read(battery_path(crc32)) {
+                self.nes.load_prg_ram(&ram);
+            }
+        }
+        let name = rom_name(path);
+        self.title = format!("nes-emulator - {}", name);
+        add_recent_rom(path);
+
+        // Write out a settings file on first run so there's one to edit
+        if let Err(e) = config.save(crc32, &name) {
+            println!("Failed to save game settings: {}", e);
+        }
+        self.game = Some(Game { path: path.to_string(), crc32, name, config, battery });
+        if let Some(watch) = &mut self.watch {
+            *watch = RomWatch::new(path, watch.keep_ram);
+        }
+        if let Some(warning) = unsupported {
+            self.osd.message(&warning);
+        }
+        Ok(())
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+    }
+
+    // Display `index` from 0, as SDL numbers them
+    pub fn use_display(&mut self, index: i32) {
+        self.move_to_display = Some(index);
+    }
+
+    // Draw through OpenGL with a post-process shader, one of
+    // BUILTIN_SHADERS by name or a GLSL file. F12 cycles through the
+    // built in ones and this.
+    pub fn use_shader(&mut self, name: &str) {
+        let mut shaders: Vec<String> = BUILTIN_SHADERS.iter().map(|(builtin, _)| builtin.to_string()).collect();
+        if !shaders.iter().any(|shader| shader == name) {
+            shaders.push(name.to_string());
+        }
+        self.shader = shaders.iter().position(|shader| shader == name);
+        self.shaders = shaders;
+    }
+
+    // Where frames go: OpenGL if a shader was asked for and it works out,
+    // otherwise SDL's renderer
+    fn output(&mut self, video: &VideoSubsystem, window: Window) -> Output {
+        // Vsync would hold frames to the monitor's rate, which isn't quite
+        // the console's, fighting the audio pacing
+        let swap_interval = if self.audio_sync { SwapInterval::Immediate } else { SwapInterval::VSync };
+        if let Some(index) = self.shader {
+            let source = shader_source(&self.shaders[index]).unwrap_or_else(|e| {
+                println!("Failed to load shader {}", e);
+                self.shader = Some(0);
+                BUILTIN_SHADERS[0].1.to_string()
+            });
+            match GlView::new(video, &window, &source) {
+                Ok(view) => {
+                    if let Err(e) = video.gl_set_swap_interval(swap_interval) {
+                        println!("Can't set the swap interval: {}", e);
+                    }
+                    return Output::Gl(window, view);
+                }
+                Err(e) => {
+                    println!("No shaders, OpenGL failed: {}", e);
+                    self.shader = None;
+                    let gl_attr = video.gl_attr();
+                    gl_attr.set_context_profile(GLProfile::Compatibility);
+                    gl_attr.set_context_version(2, 1);
+                }
+            }
+        }
+        let canvas = window.into_canvas().accelerated();
+        let canvas = if swap_interval == SwapInterval::VSync { canvas.present_vsync() } else { canvas };
+        Output::Canvas(canvas.build().unwrap())
+    }
+
+    // F12: the next shader in the list
+    fn next_shader(&mut self) {
+        match self.shader {
+            Some(index) => {
+                self.shader = Some((index + 1) % self.shaders.len());
+                self.shader_changed = true;
+            }
+            None => self.osd.message("Shaders need --shader"),
+        }
+    }
+
+    fn apply_shader(&mut self, output: &mut Output) {
+        let (Output::Gl(_, view), Some(index)) = (output, self.shader) else {
+            return;
+        };
+        let name = &self.shaders[index];
+        match shader_source(name).and_then(|source| view.set_shader(&source)) {
+            Ok(()) => self.osd.message(&format!("Shader: {}", name)),
+            Err(e) => {
+                println!("Shader {} failed: {}", name, e);
+                self.osd.message("Shader failed");
+            }
+        }
+    }
+
+    // Writes out a battery-backed game's PRG-RAM before it's swapped out or
+    // the emulator quits
+    fn save_battery(&self) {
+        let Some(game) = self.game.as_ref().filter(|game| game.battery) else {
+            return;
+        };
+        let save = || -> std::io::Result<()> {
+            fs::create_dir_all(game_dir(game.crc32))?;
+            fs::write(battery_path(game.crc32), self.nes.memory(MemoryRegion::PrgRam))
+        };
+        if let Err(e) = save() {
+            println!("Failed to save battery RAM for {}: {}", game.name, e);
+        }
+    }
+
+    fn state_path(&self) -> Option<PathBuf> {
+        let game = self.game.as_ref()?;
+        Some(game_dir(game.crc32).join(format!("slot{}.state", self.state_slot)))
+    }
+
+    // F1
+    fn save_state(&mut self) {
+        let Some(path) = self.state_path() else {
+            return;
+        };
+        let save = || -> std::io::Result<()> {
+            let state = self.nes.save_state()?;
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, state)
+        };
+        match save() {
+            Ok(()) => self.osd.message_with_thumbnail(&format!("Saved slot {}", self.state_slot), self.nes.thumbnail()),
+            Err(e) => {
+                println!("Failed to save {}: {}", path.display(), e);
+                self.osd.message("Save state failed");
+            }
+        }
+    }
+
+    // Shift+F1
+    fn load_state(&mut self) {
+        let Some(path) = self.state_path() else {
+            return;
+        };
+        let Ok(state) = fs::read(&path) else {
+            self.osd.message(&format!("Slot {} empty", self.state_slot));
+            return;
+        };
+        match self.nes.load_state(&state) {
+            Ok(()) => {
+                self.jammed = false;
+                self.osd.message(&format!("Loaded slot {}", self.state_slot));
+            }
+            Err(e) => {
+                println!("Failed to load {}: {}", path.display(), e);
+                self.osd.message("Load state failed");
+            }
+        }
+    }
+
+    // Page Up/Page Down, showing what's in the slot
+    fn select_state_slot(&mut self, forward: bool) {
+        let step = if forward { 1 } else { STATE_SLOTS - 1 };
+        self.state_slot = (self.state_slot + step) % STATE_SLOTS;
+        let preview = self.state_path().and_then(|path| fs::read(path).ok()).and_then(|state| state_preview(&state).ok());
+        match preview {
+            Some(preview) => self.osd.message_with_thumbnail(&format!("Slot {}", self.state_slot), preview.thumbnail),
+            None => self.osd.message(&format!("Slot {} empty", self.state_slot)),
+        }
+    }
+
+    pub fn sync_to_audio(&mut self) {
+        self.audio_sync = true;
+    }
+
+    pub fn capture_mic(&mut self) {
+        self.mic_capture = true;
+    }
+
+    // Watches the loaded ROM, and any loaded after it, for rebuilds
+    pub fn watch_rom(&mut self, keep_ram: bool) {
+        let path = self.game.as_ref().map_or("", |game| game.path.as_str());
+        self.watch = Some(RomWatch::new(path, keep_ram));
+    }
+
+    fn reload_watched_rom(&mut self) {
+        let Some(watch) = &mut self.watch else {
+            return;
+        };
+        if !watch.poll() {
+            return;
+        }
+        let (path, keep_ram) = (watch.path().to_string(), watch.keep_ram);
+        let ram = self.nes.memory(MemoryRegion::SystemRam).to_vec();
+        match self.load_rom(&path) {
+            Ok(()) => {
+                if keep_ram {
+                    for (addr, value) in ram.into_iter().enumerate() {
+                        self.nes.poke(addr as u16, value);
+                    }
+                }
+                self.osd.message("ROM reloaded");
+            }
+            // Most likely a broken build, so keep playing the last good one
+            Err(e) => {
+                println!("{}", e);
+                self.osd.message("Reload failed");
+            }
+        }
+    }
+
+    // game.dbg from ld65, or FCEUX's game.nes.ram.nl and game.nes.<bank>.nl,
+    // next to the ROM
+    fn load_symbols(&mut self, path: &str) {
+        self.nes.set_symbols(None);
+        let rom = Path::new(path);
+        let nl = |suffix: &str| PathBuf::from(format!("{}.{}.nl", path, suffix));
+        let files = std::iter::once(rom.with_extension("dbg"))
+            .chain(std::iter::once(nl("ram")))
+            .chain((0..).map(|bank: u32| nl(&bank.to_string())).take_while(|file| file.exists()));
+        for file in files.filter(|file| file.exists()) {
+            match self.nes.load_symbols(&file) {
+                Ok(count) => println!("Loaded {} symbols from {}", count, file.display()),
+                Err(e) => println!("Failed to load symbols: {}", e),
+            }
+        }
+    }
+
+    fn apply_game_config(&mut self, config: &GameConfig) {
+        self.nes.set_region(config.region.unwrap_or(self.default_region));
+        self.display.overscan = config.overscan;
+        if let Some(dip_switches) = config.dip_switches {
+            self.nes.set_dip_switches(dip_switches);
+        }
+
+        self.nes.reset_palette();
+        if let Some(path) = &config.palette {
+            let result = std::fs::read(path).map_err(|e| e.to_string()).and_then(|pal| self.nes.set_palette(&pal));
+            if let Err(e) = result {
+                println!("Failed to load palette {}: {}", path.display(), e);
+            }
+        }
+
+        self.nes.clear_cheats();
+        let mut enabled = 0;
+        for code in &config.cheats {
+            match self.nes.add_cheat(code) {
+                Ok(()) => enabled += 1,
+                Err(e) => println!("{}", e),
+            }
+        }
+        if enabled > 0 {
+            self.osd.message(&format!("{} cheats enabled", enabled));
+        }
+    }
+
+    // 6 toggles the attribute grid, Shift+6 the tile grid, 7 the scroll seam
+    fn overlay_key(&mut self, keycode: Keycode, shift: bool) {
+        let (name, shown) = match (keycode, shift) {
+            (Keycode::Num7, _) => {
+                self.overlays.scroll_seam = !self.overlays.scroll_seam;
+                ("Scroll seam", self.overlays.scroll_seam)
+            }
+            (_, true) => {
+                self.overlays.tiles = !self.overlays.tiles;
+                ("Tile grid", self.overlays.tiles)
+            }
+            _ => {
+                self.overlays.attributes = !self.overlays.attributes;
+                ("Attribute grid", self.overlays.attributes)
+            }
+        };
+        // Follows raster splits instead of assuming one scroll all frame
+        if self.overlays.any() && self.nes.scroll_capture().is_none() {
+            self.nes.capture_scroll(true);
+            self.overlay_capture = true;
+        } else if !self.overlays.any() && self.overlay_capture {
+            self.nes.capture_scroll(false);
+            self.overlay_capture = false;
+        }
+        self.osd.message(&format!("{} {}", name, if shown { "on" } else { "off" }));
+    }
+
+    // 8 toggles the background, 9 the sprites
+    fn layer_key(&mut self, keycode: Keycode) {
+        let (mut background, mut sprites) = self.nes.layer_visibility();
+        let (name, shown) = if keycode == Keycode::Num8 {
+            background = !background;
+            ("Background", background)
+        } else {
+            sprites = !sprites;
+            ("Sprites", sprites)
+        };
+        self.nes.set_layer_visibility(background, sprites);
+        self.osd.message(&format!("{} {}", name, if shown { "shown" } else { "hidden" }));
+    }
+
+    // Number keys 1-5 mute a channel, Shift+number solos it, 0 restores all
+    fn channel_key(&mut self, keycode: Keycode, solo: bool) {
+        let channel = match keycode {
+            Keycode::Num1 => Channel::Pulse1,
+            Keycode::Num2 => Channel::Pulse2,
+            Keycode::Num3 => Channel::Triangle,
+            Keycode::Num4 => Channel::Noise,
+            Keycode::Num5 => Channel::Dmc,
+            _ => {
+                for channel in Channel::ALL {
+                    self.nes.set_channel_enabled(channel, true);
+                }
+                self.osd.message("All channels on");
+                return;
+            }
+        };
+        if solo {
+            for other in Channel::ALL {
+                self.nes.set_channel_enabled(other, other == channel);
+            }
+            self.osd.message(&format!("{:?} solo", channel));
+        } else {
+            let enabled = !self.nes.channel_enabled(channel);
+            self.nes.set_channel_enabled(channel, enabled);
+            self.osd.message(&format!("{:?} {}", channel, if enabled { "on" } else { "off" }));
+        }
+    }
+
+    fn toggle_audio_capture(&mut self) {
+        let Some(game) = &self.game else { return };
+        let path = format!("{}.wav", game.name);
+        let result = if self.nes.is_capturing_audio() {
+            self.nes.stop_audio_capture().map(|_| "Audio saved")
+        } else {
+            self.nes.start_audio_capture(&path).map(|_| "Recording audio")
+        };
+        match result {
+            Ok(message) => self.osd.message(message),
+            Err(e) => {
+                println!("{}: {}", path, e);
+                self.osd.message("Audio capture failed");
+            }
         }
     }
 
+    fn toggle_code_data_log(&mut self) {
+        let Some(game) = &self.game else { return };
+        let path = format!("{}.cdl", game.name);
+        let result = match self.nes.stop_code_data_log() {
+            Some(log) => std::fs::write(&path, log.to_cdl()).map(|_| "Code/data log saved").map_err(|e| e.to_string()),
+            None => match std::fs::read(&path) {
+                Ok(cdl) => self.nes.resume_code_data_log(&cdl).map(|_| "Code/data log resumed"),
+                Err(_) => {
+                    self.nes.start_code_data_log();
+                    Ok("Code/data logging")
+                }
+            }
+        };
+        match result {
+            Ok(message) => self.osd.message(message),
+            Err(e) => {
+                println!("{}: {}", path, e);
+                self.osd.message("Code/data log failed");
+            }
+        }
+    }
+
+    // Prints the instructions leading up to a JAM opcode
+    fn report_jam(&mut self) {
+        self.osd.message("CPU jammed");
+        println!("CPU jammed. Last instructions:");
+        if let Some(history) = self.nes.history() {
+            for entry in history.entries() {
+                match self.nes.symbols() {
+                    Some(symbols) => println!("{}", symbols.annotate(entry)),
+                    None => println!("{}", entry),
+                }
+            }
+        }
+    }
+
+    // The emulator panicked: keep the report for a bug report and switch
+    // the console off rather than run it on in whatever state it was left
+    fn save_crash_report(&mut self, report: &CrashReport) {
+        self.nes.off();
+        println!("Emulator crashed: {}", report.reason);
+        match fs::write(CRASH_REPORT, report.to_string()) {
+            Ok(()) => {
+                println!("Crash report saved to {}", CRASH_REPORT);
+                self.osd.message("Crashed, report saved");
+                if let Some(state) = &report.save_state {
+                    match fs::write(CRASH_STATE, state) {
+                        Ok(()) => println!("Crash state saved to {}", CRASH_STATE),
+                        Err(e) => println!("Failed to save crash state: {}", e),
+                    }
+                }
+            }
+            Err(e) => {
+                println!("Failed to save crash report: {}", e);
+                println!("{}", report);
+                self.osd.message("Crashed");
+            }
+        }
+    }
+
+    pub fn open_menu(&mut self) {
+        self.menu = Some(Menu::new(&self.rom_dir));
+    }
+
     pub fn run(&mut self){
         let sdl = sdl2::init().unwrap();
         let video_subsystem = sdl.video().unwrap();
 
+        // Shaders draw with OpenGL 3.2, which has to be asked for before
+        // the window is made
+        if self.shader.is_some() {
+            let gl_attr = video_subsystem.gl_attr();
+            gl_attr.set_context_profile(GLProfile::Core);
+            gl_attr.set_context_version(3, 2);
+        }
+
         let scale = 3;
-        let window = video_subsystem
-            .window("nes-emulator", 256 * scale, 240 * scale)
+        let mut window = video_subsystem
+            .window(&self.title, 256 * scale, 240 * scale)
             .position_centered()
+            .resizable()
             .opengl()
             .build()
             .unwrap();
+        window.set_icon(window_icon());
+        self.window_width = window.size().0;
 
-        let mut renderer = window.into_canvas().accelerated().present_vsync().build().unwrap();
-        let texture_creator = renderer.texture_creator();
-
-        let mut texture = texture_creator.create_texture(
-            sdl2::pixels::PixelFormatEnum::RGB24,
-            sdl2::render::TextureAccess::Streaming,
-            256,
-            240,
-        ).unwrap();
+        let mut output = self.output(&video_subsystem, window);
+        let texture_creator = match &output {
+            Output::Canvas(canvas) => Some(canvas.texture_creator()),
+            Output::Gl(..) => None,
+        };
+        let mut texture = texture_creator.as_ref().map(|creator| frame_texture(creator, self.display.smooth));
+        let mut texture_smooth = self.display.smooth;
+        self.display_count = video_subsystem.num_video_displays().unwrap_or(1).max(1);
 
         let mut event_pump = sdl.event_pump().unwrap();
 
+        // Mono output straight from the APU's mixer
+        let sample_rate = self.nes.config().sample_rate;
+        let audio_subsystem = sdl.audio().unwrap();
+        let audio_spec = AudioSpecDesired { freq: Some(sample_rate as i32), channels: Some(1), samples: Some(1024) };
+        let audio = audio_subsystem.open_queue::<f32, _>(None, &audio_spec).map_err(|e| println!("No audio: {}", e)).ok();
+        if let Some(audio) = &audio {
+            audio.resume();
+        }
+        let mic = if self.mic_capture {
+            // A game plugging in the NES Vaus moves the ports back
+            if self.nes.port_layout() == PortLayout::Nes {
+                println!("The NES port layout has no microphone, the game won't hear it");
+            }
+            Microphone::open(&audio_subsystem).map_err(|e| println!("No microphone: {}", e)).ok()
+        } else {
+            None
+        };
+
         const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60); // 60 FPS
+        let mut pacer = Pacer::new(sample_rate, AUDIO_LATENCY, self.nes.frame_period());
         let mut last_frame_time = Instant::now();
         let mut frame_start: Instant;
         
@@ -52,99 +634,410 @@ impl SDLWrapper {
                 break 'running;
             }
 
-            // Run the NES until we have a new frame
-            loop {
-                self.nes.step();
-                if self.nes.poll_frame() {
-                    break;
+            if let Some(index) = self.move_to_display.take() {
+                // Fullscreen is tied to its display, so leave it to move and
+                // go back below
+                let window = output.window_mut();
+                if window.fullscreen_state() != FullscreenType::Off {
+                    window.set_fullscreen(FullscreenType::Off).unwrap();
+                }
+                match center_on_display(window, &video_subsystem, index) {
+                    Ok(()) => self.osd.message(&format!("Display {}", index)),
+                    Err(e) => println!("Can't move to display {}: {}", index, e),
+                }
+            }
+            self.window_display = output.window().display_index().unwrap_or(0);
+            let fullscreen = if self.fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
+            if output.window().fullscreen_state() != fullscreen {
+                output.window_mut().set_fullscreen(fullscreen).unwrap();
+                self.window_width = output.window().size().0;
+            }
+            if output.window().title() != self.title {
+                output.window_mut().set_title(&self.title).unwrap();
+            }
+            if self.shader_changed {
+                self.shader_changed = false;
+                self.apply_shader(&mut output);
+            }
+
+            if let Some(mic) = &mic {
+                self.nes.set_mic_level(mic.is_loud());
+            }
+            if let Some(control) = &mut self.control {
+                control.poll(&mut self.nes);
+            }
+            self.reload_watched_rom();
+            let paused = self.control.as_ref().is_some_and(ControlServer::is_paused);
+
+            // Run the NES until we have a new frame, unless it's paused behind
+            // the menu or by a control client
+            let running = self.menu.is_none() && !paused;
+            let frame = self.nes.catch_crash(|nes| {
+                while running && nes.is_on() {
+                    nes.step();
+                    if nes.poll_frame() {
+                        break;
+                    }
+                }
+            });
+            if let Err(report) = frame {
+                self.save_crash_report(&report);
+            }
+            if self.nes.is_jammed() != self.jammed {
+                self.jammed = self.nes.is_jammed();
+                if self.jammed {
+                    self.report_jam();
+                }
+            }
+
+            // Queue this frame's audio. Video paces the loop, so drop the
+            // backlog if it drifts past a quarter second ahead.
+            let samples = self.nes.take_audio_samples();
+            let mut queued = None;
+            if let Some(audio) = &audio {
+                if audio.size() as usize > sample_rate as usize / 4 * std::mem::size_of::<f32>() {
+                    audio.clear();
+                }
+                if let Err(e) = audio.queue_audio(&samples) {
+                    println!("Failed to queue audio: {}", e);
                 }
+                let samples = audio.size() as usize / std::mem::size_of::<f32>();
+                self.nes.report_audio_queue(samples, sample_rate as usize / 4);
+                queued = Some(samples);
             }
 
             // Render the frame
-            renderer.clear();
-            texture.update(None, &self.nes.frame(), 256 * 3).unwrap();
-            
-            // Get current window size for proper scaling
-            let (window_width, window_height) = renderer.output_size().unwrap();
-            let scale_x = window_width as f32 / 256.0;
-            let scale_y = window_height as f32 / 240.0;
-            let scale = scale_x.min(scale_y);
-
-            let scaled_width = (256.0 * scale) as u32;
-            let scaled_height = (240.0 * scale) as u32;
-            let x_offset = (window_width - scaled_width) / 2;
-            let y_offset = (window_height - scaled_height) / 2;
-
-            let dst = Rect::new(
-                x_offset as i32,
-                y_offset as i32,
-                scaled_width,
-                scaled_height,
-            );
-
-            renderer.copy(&texture, None, Some(dst)).unwrap();
-            renderer.present();
+            let mut frame = self.nes.frame_with_overlays(self.overlays);
+            if let Some(menu) = &self.menu {
+                menu.draw(&mut frame);
+            }
+            self.osd.draw(&mut frame);
+
+            let src = if self.display.overscan { Rect::new(0, 8, 256, 224) } else { Rect::new(0, 0, 256, 240) };
+            match &mut output {
+                Output::Canvas(renderer) => {
+                    let (Some(creator), Some(texture)) = (&texture_creator, &mut texture) else {
+                        unreachable!("canvas output without a texture");
+                    };
+                    if texture_smooth != self.display.smooth {
+                        texture_smooth = self.display.smooth;
+                        *texture = frame_texture(creator, texture_smooth);
+                    }
+                    texture.update(None, &frame, 256 * 3).unwrap();
+                    let (window_width, window_height) = renderer.output_size().unwrap();
+                    let dst = display_rect(window_width, window_height, self.display);
+                    renderer.clear();
+                    renderer.copy(texture, src, dst).unwrap();
+                    renderer.present();
+                }
+                Output::Gl(window, view) => {
+                    let drawable = window.drawable_size();
+                    let dst = display_rect(drawable.0, drawable.1, self.display);
+                    view.draw(&frame, drawable, src, dst, self.display.smooth);
+                    window.gl_swap_window();
+                }
+            }
 
             // Frame timing
             let frame_duration = frame_start.elapsed();
-            if frame_duration < FRAME_TIME {
+            self.osd.frame_done(self.nes.stats());
+            if self.audio_sync {
+                // Paused, the queue stays empty and the clock takes over
+                pacer.set_frame_period(self.nes.frame_period());
+                pacer.wait(queued.filter(|_| self.nes.is_on() && running));
+            } else if frame_duration < FRAME_TIME {
                 std::thread::sleep(FRAME_TIME - frame_duration);
             }
 
             last_frame_time = frame_start;
         }
+        self.save_battery();
     }
 
     fn handle_input(&mut self, event_pump: &mut sdl2::EventPump) -> bool {
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => return false,
+                Event::DropFile { filename, .. } => {
+                    // Loading can put up a warning of its own instead
+                    self.osd.message("ROM loaded");
+                    match self.load_rom(&filename) {
+                        Ok(()) => self.menu = None,
+                        Err(e) => {
+                            println!("{}", e);
+                            self.osd.message("Failed to load ROM");
+                        }
+                    }
+                }
+                // Alt+Enter, ahead of the menu and the Family BASIC keyboard
+                // that would otherwise take the Enter
+                Event::KeyDown { keycode: Some(Keycode::Return), keymod, repeat: false, .. } if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) => {
+                    self.fullscreen = !self.fullscreen;
+                }
+                Event::KeyDown { keycode: Some(keycode), .. } if self.menu.is_some() => {
+                    if !self.menu_key(keycode) {
+                        return false;
+                    }
+                }
+                Event::KeyDown { scancode: Some(Scancode::ScrollLock), repeat: false, .. } => {
+                    self.keyboard_capture = !self.keyboard_capture;
+                    self.osd.message(if self.keyboard_capture { "Keyboard capture on" } else { "Keyboard capture off" });
+                }
+                Event::KeyDown { scancode: Some(scancode), .. } if self.keyboard_capture => {
+                    if let Some(key) = family_key(scancode) {
+                        self.nes.set_keyboard_key(key, true);
+                    }
+                }
+                Event::KeyUp { scancode: Some(scancode), .. } if self.keyboard_capture => {
+                    if let Some(key) = family_key(scancode) {
+                        self.nes.set_keyboard_key(key, false);
+                    }
+                }
                 Event::KeyDown {
                     keycode: Some(keycode),
+                    keymod,
                     ..
                 } => match keycode {
                     Keycode::Escape => return false,
-                    Keycode::Num1 => {
+                    Keycode::Num0 | Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 | Keycode::Num5 => {
+                        self.channel_key(keycode, keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD));
+                    }
+                    Keycode::Num6 | Keycode::Num7 => {
+                        self.overlay_key(keycode, keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD));
+                    }
+                    Keycode::Num8 | Keycode::Num9 => self.layer_key(keycode),
+                    Keycode::F9 => {
                         println!("Dumping nametables...");
-                        if let Err(e) = self.nes.dump_ppu() {
+                        if let Err(e) = self.nes.dump_nametables_png(PPU_DUMP_DIR) {
                             println!("Failed to dump nametables: {}", e);
+                            self.osd.message("Nametable dump failed");
                         } else {
-                            println!("Nametables dumped successfully!");
+                            println!("Nametables dumped to {}/", PPU_DUMP_DIR);
+                            self.osd.message("Nametables dumped");
                         }
                     }
                     Keycode::Backspace => {
                         self.nes.reset();
+                        self.osd.message("Reset");
+                    }
+                    Keycode::F2 => self.open_menu(),
+                    Keycode::F3 => self.osd.toggle_fps(),
+                    Keycode::F4 if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                        self.display.smooth = !self.display.smooth;
+                        self.osd.message(if self.display.smooth { "Smooth scaling" } else { "Sharp scaling" });
+                    }
+                    Keycode::F4 => {
+                        self.display.integer_scale = !self.display.integer_scale;
+                        self.osd.message(if self.display.integer_scale { "Integer scaling on" } else { "Integer scaling off" });
+                    }
+                    Keycode::F5 => {
+                        self.display.aspect_correction = !self.display.aspect_correction;
+                        self.osd.message(if self.display.aspect_correction { "Aspect 8:7" } else { "Aspect 1:1" });
+                    }
+                    Keycode::F6 => {
+                        self.display.overscan = !self.display.overscan;
+                        self.osd.message(if self.display.overscan { "Overscan hidden" } else { "Overscan shown" });
+                        if let Some(game) = &mut self.game {
+                            game.config.overscan = self.display.overscan;
+                            if let Err(e) = game.config.save(game.crc32, &game.name) {
+                                println!("Failed to save game settings: {}", e);
+                            }
+                        }
+                    }
+                    // Profiles until pressed again, then writes the hot spots
+                    Keycode::F7 => match self.nes.stop_profiling() {
+                        Some(profiler) => match std::fs::write("profile.txt", profiler.report(50)) {
+                            Ok(()) => self.osd.message("Profile saved"),
+                            Err(e) => {
+                                println!("Failed to write profile.txt: {}", e);
+                                self.osd.message("Profile failed");
+                            }
+                        },
+                        None => {
+                            self.nes.start_profiling();
+                            self.osd.message("Profiling");
+                        }
+                    },
+                    // Code/Data Logger, kept in <rom>.cdl and resumed from it
+                    Keycode::F8 => self.toggle_code_data_log(),
+                    // Mixed audio to <rom>.wav until pressed again
+                    Keycode::F10 => self.toggle_audio_capture(),
+                    // Shift+F11 moves to the next display, fullscreen or not
+                    Keycode::F11 if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                        self.move_to_display = Some((self.window_display + 1) % self.display_count);
                     }
+                    Keycode::F11 => self.fullscreen = !self.fullscreen,
+                    Keycode::F12 => self.next_shader(),
+                    // Save states, kept per game in the slot Page Up/Page
+                    // Down pick
+                    Keycode::F1 if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => self.load_state(),
+                    Keycode::F1 => self.save_state(),
+                    Keycode::PageUp => self.select_state_slot(true),
+                    Keycode::PageDown => self.select_state_slot(false),
                     _ => {}
                 },
+                // Mouse drives the Vaus paddle when one is connected
+                Event::MouseMotion { x, .. } => {
+                    self.nes.set_paddle(x as f32 / self.window_width as f32);
+                }
+                Event::Window { win_event: WindowEvent::Resized(width, _), .. } => {
+                    self.window_width = width.max(1) as u32;
+                }
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, .. } => self.nes.set_paddle_fire(true),
+                Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } => self.nes.set_paddle_fire(false),
                 _ => {}
             }
         }
         
+        if self.keyboard_capture || self.menu.is_some() {
+            return true;
+        }
+
         let keyboard_state = event_pump.keyboard_state();
         
-        const KEY_MAPPINGS: [(Scancode, Button, usize); 8] = [
-            (Scancode::Up, Button::Up, 0),
-            (Scancode::Down, Button::Down, 1),
-            (Scancode::Left, Button::Left, 2),
-            (Scancode::Right, Button::Right, 3),
-            (Scancode::X, Button::A, 4),
-            (Scancode::Z, Button::B, 5),
-            (Scancode::Return, Button::Start, 6),
-            (Scancode::LShift, Button::Select, 7),
+        const KEY_MAPPINGS: [[(Scancode, Button, usize); 8]; 2] = [
+            [
+                (Scancode::Up, Button::Up, 0),
+                (Scancode::Down, Button::Down, 1),
+                (Scancode::Left, Button::Left, 2),
+                (Scancode::Right, Button::Right, 3),
+                (Scancode::X, Button::A, 4),
+                (Scancode::Z, Button::B, 5),
+                (Scancode::Return, Button::Start, 6),
+                (Scancode::LShift, Button::Select, 7),
+            ],
+            [
+                (Scancode::I, Button::Up, 0),
+                (Scancode::K, Button::Down, 1),
+                (Scancode::J, Button::Left, 2),
+                (Scancode::L, Button::Right, 3),
+                (Scancode::Period, Button::A, 4),
+                (Scancode::Comma, Button::B, 5),
+                (Scancode::RShift, Button::Start, 6),
+                (Scancode::RCtrl, Button::Select, 7),
+            ],
         ];
 
         // Check each key and update controller only if state changed
-        for &(scancode, button, index) in KEY_MAPPINGS.iter() {
-            let is_pressed = keyboard_state.is_scancode_pressed(scancode);
-            if is_pressed != self.previous_keyboard_state[index] {
-                self.nes.set_button(button, is_pressed);
-                self.previous_keyboard_state[index] = is_pressed;
+        for (port, mappings) in KEY_MAPPINGS.iter().enumerate() {
+            for &(scancode, button, index) in mappings.iter() {
+                let is_pressed = keyboard_state.is_scancode_pressed(scancode);
+                if is_pressed != self.previous_keyboard_state[port][index] {
+                    self.nes.set_port_button(port, button, is_pressed);
+                    self.previous_keyboard_state[port][index] = is_pressed;
+                }
             }
         }
 
+        // VS System coin slot
+        self.nes.set_coin(0, keyboard_state.is_scancode_pressed(Scancode::C));
+
+        true
+    }
+
+    // Returns false to quit. Escape backs out to the game if one is running.
+    fn menu_key(&mut self, keycode: Keycode) -> bool {
+        let Some(menu) = &mut self.menu else {
+            return true;
+        };
+        match keycode {
+            Keycode::Up => menu.up(),
+            Keycode::Down => menu.down(),
+            Keycode::Return => {
+                if let Some(path) = menu.selected().map(str::to_string) {
+                    match self.load_rom(&path) {
+                        Ok(()) => self.menu = None,
+                        Err(e) => {
+                            println!("{}", e);
+                            self.osd.message("Failed to load ROM");
+                        }
+                    }
+                }
+            }
+            Keycode::Escape | Keycode::F2 if self.nes.is_on() => self.menu = None,
+            Keycode::Escape => return false,
+            _ => {}
+        }
         true
     }
+}
+
+// Streaming texture for the 256x240 frame. SDL fixes the filtering when
+// a texture is made, from the scale quality hint.
+fn frame_texture(creator: &TextureCreator<WindowContext>, smooth: bool) -> Texture<'_> {
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", if smooth { "linear" } else { "nearest" });
+    creator.create_texture_streaming(PixelFormatEnum::RGB24, 256, 240).unwrap()
+}
+
+fn center_on_display(window: &mut Window, video: &VideoSubsystem, index: i32) -> Result<(), String> {
+    let bounds = video.display_bounds(index)?;
+    let (width, height) = window.size();
+    let x = bounds.x() + (bounds.width() as i32 - width as i32) / 2;
+    let y = bounds.y() + (bounds.height() as i32 - height as i32) / 2;
+    window.set_position(WindowPos::Positioned(x), WindowPos::Positioned(y));
+    Ok(())
+}
+
+// Largest centered area for the frame under the given options. Integer
+// scaling rounds the vertical scale down; with aspect correction on the
+// width follows it at 8:7, so columns may not all be the same size.
+fn display_rect(window_width: u32, window_height: u32, options: DisplayOptions) -> Rect {
+    let pixel_aspect = if options.aspect_correction { 8.0 / 7.0 } else { 1.0 };
+    let frame_width = 256.0 * pixel_aspect;
+    let frame_height = if options.overscan { 224.0 } else { 240.0 };
+    let mut scale = (window_width as f32 / frame_width).min(window_height as f32 / frame_height);
+    if options.integer_scale && scale >= 1.0 {
+        scale = scale.floor();
+    }
+
+    let scaled_width = ((frame_width * scale) as u32).min(window_width);
+    let scaled_height = ((frame_height * scale) as u32).min(window_height);
+    let x_offset = (window_width - scaled_width) / 2;
+    let y_offset = (window_height - scaled_height) / 2;
+
+    Rect::new(x_offset as i32, y_offset as i32, scaled_width.max(1), scaled_height.max(1))
+}
+
+// 16x16 controller pad: grey body, black d-pad, red A/B buttons
+fn window_icon() -> Surface<'static> {
+    let mut icon = Surface::new(16, 16, PixelFormatEnum::RGB888).unwrap();
+    icon.fill_rect(None, Color::RGB(0, 0, 0)).unwrap();
+    icon.fill_rect(Rect::new(0, 4, 16, 8), Color::RGB(0xB0, 0xB0, 0xB0)).unwrap();
+    icon.fill_rect(Rect::new(3, 6, 1, 4), Color::RGB(0, 0, 0)).unwrap();
+    icon.fill_rect(Rect::new(2, 7, 3, 2), Color::RGB(0, 0, 0)).unwrap();
+    icon.fill_rect(Rect::new(10, 8, 2, 2), Color::RGB(0xD0, 0x20, 0x20)).unwrap();
+    icon.fill_rect(Rect::new(13, 7, 2, 2), Color::RGB(0xD0, 0x20, 0x20)).unwrap();
+    icon
+}
 
-    
+// Host key for each Family BASIC key, by position on a US layout
+fn family_key(scancode: Scancode) -> Option<Key> {
+    let key = match scancode {
+        Scancode::F1 => Key::F1, Scancode::F2 => Key::F2, Scancode::F3 => Key::F3, Scancode::F4 => Key::F4,
+        Scancode::F5 => Key::F5, Scancode::F6 => Key::F6, Scancode::F7 => Key::F7, Scancode::F8 => Key::F8,
+        Scancode::Num1 => Key::Num1, Scancode::Num2 => Key::Num2, Scancode::Num3 => Key::Num3,
+        Scancode::Num4 => Key::Num4, Scancode::Num5 => Key::Num5, Scancode::Num6 => Key::Num6,
+        Scancode::Num7 => Key::Num7, Scancode::Num8 => Key::Num8, Scancode::Num9 => Key::Num9,
+        Scancode::Num0 => Key::Num0,
+        Scancode::A => Key::A, Scancode::B => Key::B, Scancode::C => Key::C, Scancode::D => Key::D,
+        Scancode::E => Key::E, Scancode::F => Key::F, Scancode::G => Key::G, Scancode::H => Key::H,
+        Scancode::I => Key::I, Scancode::J => Key::J, Scancode::K => Key::K, Scancode::L => Key::L,
+        Scancode::M => Key::M, Scancode::N => Key::N, Scancode::O => Key::O, Scancode::P => Key::P,
+        Scancode::Q => Key::Q, Scancode::R => Key::R, Scancode::S => Key::S, Scancode::T => Key::T,
+        Scancode::U => Key::U, Scancode::V => Key::V, Scancode::W => Key::W, Scancode::X => Key::X,
+        Scancode::Y => Key::Y, Scancode::Z => Key::Z,
+        Scancode::Minus => Key::Minus, Scancode::Equals => Key::Caret, Scancode::Backslash => Key::Yen,
+        Scancode::LeftBracket => Key::At, Scancode::RightBracket => Key::LeftBracket,
+        Scancode::NonUsHash => Key::RightBracket, Scancode::Semicolon => Key::Semicolon,
+        Scancode::Apostrophe => Key::Colon, Scancode::Comma => Key::Comma, Scancode::Period => Key::Period,
+        Scancode::Slash => Key::Slash, Scancode::Grave => Key::Underscore,
+        Scancode::Escape => Key::Escape, Scancode::LCtrl => Key::Ctrl, Scancode::LShift => Key::LeftShift,
+        Scancode::RShift => Key::RightShift, Scancode::LAlt => Key::Graph, Scancode::RAlt => Key::Kana,
+        Scancode::Pause => Key::Stop, Scancode::Return => Key::Return, Scancode::Space => Key::Space,
+        Scancode::Home => Key::ClrHome, Scancode::Insert => Key::Insert, Scancode::Backspace => Key::Delete,
+        Scancode::Delete => Key::Delete, Scancode::Up => Key::Up, Scancode::Down => Key::Down,
+        Scancode::Left => Key::Left, Scancode::Right => Key::Right,
+        _ => return None
+    };
+    Some(key)
 }
\ No newline at end of file
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Every opcode, from any register state, on a flat 64KB bus
+fuzz_target!(|data: &[u8]| {
+    let _ = nes_cpu::fuzz::instruction(data);
+});
\ No newline at end of file
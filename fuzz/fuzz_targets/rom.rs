@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Header parsing, the ROM database and mapper construction and banking
+fuzz_target!(|data: &[u8]| {
+    let _ = nes_cpu::fuzz::rom(data);
+});
\ No newline at end of file
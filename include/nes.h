@@ -0,0 +1,104 @@
+/*
+ * C interface to the nes-cpu emulator core, from src/ffi.rs. Build the
+ * crate with `cargo build --release --features ffi` and link against the
+ * resulting libnes_cpu shared library.
+ *
+ * Every function taking a Nes pointer needs one from nes_create that hasn't
+ * been destroyed. A console belongs to one thread at a time. Buffers must
+ * hold at least the length passed with them. A panic inside the core comes
+ * back as the function's error value.
+ */
+
+#ifndef NES_H
+#define NES_H
+
+/* Generated by build.rs with cbindgen; edit src/ffi.rs instead. */
+
+#include <stddef.h>
+#include <stdint.h>
+typedef struct Nes Nes;
+
+#define NES_REGION_NTSC 0
+
+#define NES_REGION_PAL 1
+
+#define NES_REGION_DENDY 2
+
+// Picked from each loaded ROM's header
+#define NES_REGION_AUTO 3
+
+#define NES_WIDTH 256
+
+#define NES_HEIGHT 240
+
+// Saved states carry a picture of the screen a quarter the size each way
+#define NES_THUMBNAIL_WIDTH 64
+
+#define NES_THUMBNAIL_HEIGHT 60
+
+// Controller bits for nes_set_input
+#define NES_BUTTON_A (1 << 0)
+
+#define NES_BUTTON_B (1 << 1)
+
+#define NES_BUTTON_SELECT (1 << 2)
+
+#define NES_BUTTON_START (1 << 3)
+
+#define NES_BUTTON_UP (1 << 4)
+
+#define NES_BUTTON_DOWN (1 << 5)
+
+#define NES_BUTTON_LEFT (1 << 6)
+
+#define NES_BUTTON_RIGHT (1 << 7)
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// A console for NES_REGION_*, or NULL if it couldn't be made
+Nes *nes_create(uint32_t region);
+
+void nes_destroy(Nes *nes);
+
+// An iNES or NES 2.0 image, copied. Powers the console on, or power cycles
+// it. Returns 0, or -1 if the data isn't a ROM.
+int32_t nes_load_rom(Nes *nes, const uint8_t *data, size_t len);
+
+void nes_reset(Nes *nes);
+
+// Runs until the next frame is complete
+void nes_run_frame(Nes *nes);
+
+// NES_WIDTH x NES_HEIGHT RGB24, rows top to bottom. Valid until the Nes is
+// destroyed, loaded states included, and updated as frames are run.
+const uint8_t *nes_framebuffer(const Nes *nes);
+
+// NES_BUTTON_* bits held on controller 0 or 1
+void nes_set_input(Nes *nes, uint32_t port, uint8_t buttons);
+
+// Mono 44.1kHz samples since the last call. Copies up to capacity of them
+// and returns how many; any more are dropped.
+size_t nes_audio_samples(Nes *nes, float *out, size_t capacity);
+
+// Returns the saved state's size, writing it only when capacity is enough:
+// call with NULL first to size the buffer. 0 if it couldn't be saved. The
+// ROM is left out, so a state only loads back with the same game.
+size_t nes_save_state(const Nes *nes, uint8_t *out, size_t capacity);
+
+// Returns 0, or -1 if the data isn't a saved state of the loaded ROM,
+// leaving the console as it was
+int32_t nes_load_state(Nes *nes, const uint8_t *data, size_t len);
+
+// Copies a saved state's NES_THUMBNAIL_WIDTH x NES_THUMBNAIL_HEIGHT RGB24
+// thumbnail into out and returns when it was saved, in seconds since the
+// Unix epoch. -1 if the data isn't a saved state or its thumbnail isn't
+// that size.
+int64_t nes_state_thumbnail(const uint8_t *data, size_t len, uint8_t *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* NES_H */
@@ -0,0 +1,96 @@
+use std::{f64::consts::PI, sync::OnceLock};
+
+// Half the kernel width in output samples, which is also the added latency
+const HALF_WIDTH: usize = 8;
+const TAPS: usize = HALF_WIDTH * 2;
+// Sub-sample positions a step can land on
+const PHASES: usize = 64;
+// Cutoff as a fraction of the output rate, a little under Nyquist
+const CUTOFF: f64 = 0.45;
+
+type Kernel = [[f32; TAPS]; PHASES];
+
+// Windowed-sinc impulses, one per phase, each summing to 1 so a step of
+// `delta` settles at exactly `delta` once integrated.
+fn kernel() -> &'static Kernel {
+    static KERNEL: OnceLock<Kernel> = OnceLock::new();
+    KERNEL.get_or_init(|| {
+        let mut kernel = [[0.0; TAPS]; PHASES];
+        let width = (TAPS + 2) as f64;
+        for (phase, taps) in kernel.iter_mut().enumerate() {
+            let center = HALF_WIDTH as f64 + phase as f64 / PHASES as f64;
+            let mut impulse = [0.0f64; TAPS];
+            for (tap, value) in impulse.iter_mut().enumerate() {
+                let x = tap as f64 - center;
+                let sinc = if x == 0.0 { 2.0 * CUTOFF } else { (2.0 * PI * CUTOFF * x).sin() / (PI * x) };
+                // Blackman window
+                let w = 2.0 * PI * (x / width + 0.5);
+                *value = sinc * (0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos());
+            }
+            let sum: f64 = impulse.iter().sum();
+            for (tap, value) in taps.iter_mut().zip(impulse) {
+                *tap = (value / sum) as f32;
+            }
+        }
+        kernel
+    })
+}
+
+// Band-limited step synthesis in the style of blip_buf. Rather than
+// sampling the mixer, every change in its level is spread over the
+// following output samples as a band-limited step, so square waves and
+// noise don't alias down into audible tones at low output rates.
+#[derive(Default)]
+pub struct BlipBuffer {
+    // Pending deltas for the current output sample and the ones after it
+    deltas: [f32; TAPS],
+    level: f32,
+    accumulator: f32,
+}
+
+impl BlipBuffer {
+    pub fn new() -> Self {
+        BlipBuffer::default()
+    }
+
+    // The input moves to `level` at `offset` (0.0-1.0) into the current
+    // output sample.
+    pub fn set_level(&mut self, offset: f32, level: f32) {
+        let delta = level - self.level;
+        if delta == 0.0 {
+            return;
+        }
+        self.level = level;
+        let phase = ((offset * PHASES as f32) as usize).min(PHASES - 1);
+        for (pending, tap) in self.deltas.iter_mut().zip(kernel()[phase]) {
+            *pending += delta * tap;
+        }
+    }
+
+    // Finishes the current output sample and returns it
+    pub fn next_sample(&mut self) -> f32 {
+        self.accumulator += self.deltas[0];
+        self.deltas.rotate_left(1);
+        self.deltas[TAPS - 1] = 0.0;
+        self.accumulator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_settles_at_its_level() {
+        let mut blip = BlipBuffer::new();
+        blip.set_level(0.3, 1.0);
+        let samples: Vec<f32> = (0..TAPS + 4).map(|_| blip.next_sample()).collect();
+
+        // Rings around the edge, then holds the new level
+        assert!(samples[0].abs() < 0.01);
+        assert!(samples[HALF_WIDTH - 1] < 0.5 && samples[HALF_WIDTH] > 0.5);
+        for sample in &samples[TAPS..] {
+            assert!((sample - 1.0).abs() < 1e-5);
+        }
+    }
+}
\ No newline at end of file
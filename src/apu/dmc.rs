@@ -0,0 +1,205 @@
+// NTSC DMC timer periods in CPU cycles, indexed by the $4010 rate bits
+const RATE_TABLE: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+
+// Delta modulation channel. Sample bytes are pulled from CPU memory by DMA,
+// which the CPU services through dma_request()/dma_fill().
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dmc {
+    irq_enabled: bool,
+    loop_flag: bool,
+    timer_period: u16,
+    timer: u16,
+
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    pub output_level: u8,
+
+    pub irq_pending: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Dmc::new()
+    }
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Dmc {
+            irq_enabled: false,
+            loop_flag: false,
+            timer_period: RATE_TABLE[0],
+            timer: RATE_TABLE[0],
+
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            output_level: 0,
+
+            irq_pending: false,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4010 => {
+                self.irq_enabled = data & 0x80 != 0;
+                self.loop_flag = data & 0x40 != 0;
+                self.timer_period = RATE_TABLE[(data & 0x0F) as usize];
+                if !self.irq_enabled {
+                    self.irq_pending = false;
+                }
+            }
+            0x4011 => self.output_level = data & 0x7F,
+            0x4012 => self.sample_address = 0xC000 | ((data as u16) << 6),
+            0x4013 => self.sample_length = ((data as u16) << 4) | 1,
+            _ => {}
+        }
+    }
+
+    // $4015 bit 4
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_pending = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    // One CPU cycle
+    pub fn step(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period;
+
+        if !self.silence {
+            if self.shift_register & 1 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true
+            }
+        }
+    }
+
+    // Address the memory reader wants fetched, if the sample buffer ran dry.
+    pub fn dma_request(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    pub fn dma_fill(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        self.current_address = if self.current_address == 0xFFFF { 0x8000 } else { self.current_address + 1 };
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enabling_requests_sample_bytes_in_order() {
+        let mut dmc = Dmc::new();
+        dmc.write(0x4012, 0x01);
+        dmc.write(0x4013, 0x01);
+        assert_eq!(dmc.dma_request(), None);
+
+        dmc.set_enabled(true);
+        assert_eq!(dmc.dma_request(), Some(0xC040));
+        dmc.dma_fill(0xFF);
+        assert_eq!(dmc.dma_request(), None);
+
+        // The buffer empties into the shift register on the next output cycle
+        for _ in 0..RATE_TABLE[0] as usize * 8 {
+            dmc.step();
+        }
+        assert_eq!(dmc.dma_request(), Some(0xC041));
+    }
+
+    #[test]
+    fn irq_at_end_of_sample() {
+        let mut dmc = Dmc::new();
+        dmc.write(0x4010, 0x80);
+        dmc.write(0x4013, 0x00);
+        dmc.set_enabled(true);
+        dmc.dma_fill(0);
+        assert!(dmc.irq_pending);
+        assert!(!dmc.is_active());
+    }
+
+    #[test]
+    fn looping_sample_restarts_without_irq() {
+        let mut dmc = Dmc::new();
+        dmc.write(0x4010, 0xC0);
+        dmc.write(0x4013, 0x00);
+        dmc.set_enabled(true);
+        dmc.dma_fill(0);
+        assert!(!dmc.irq_pending);
+        assert!(dmc.is_active());
+    }
+
+    #[test]
+    fn address_wraps_to_8000() {
+        let mut dmc = Dmc::new();
+        dmc.write(0x4012, 0xFF);
+        dmc.write(0x4013, 0x04);
+        dmc.set_enabled(true);
+        for _ in 0..0x40 {
+            dmc.dma_fill(0);
+            dmc.sample_buffer = None;
+        }
+        assert_eq!(dmc.dma_request(), Some(0x8000));
+    }
+}
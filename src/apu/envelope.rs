@@ -0,0 +1,75 @@
+// Volume envelope shared by the pulse and noise channels: either a constant
+// volume or a sawtooth decaying from 15, clocked every quarter frame.
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    // Constant volume, or the divider period
+    period: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Envelope::default()
+    }
+
+    // --LC VVVV of the channel's first register
+    pub fn write(&mut self, data: u8) {
+        self.looping = data & 0x20 != 0;
+        self.constant = data & 0x10 != 0;
+        self.period = data & 0x0F;
+    }
+
+    // Writing the channel's length register restarts the envelope
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.period;
+        } else if self.divider == 0 {
+            self.divider = self.period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        if self.constant { self.period } else { self.decay }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decays_from_fifteen_and_loops() {
+        let mut envelope = Envelope::new();
+        envelope.write(0x20); // Loop, period 0
+        envelope.restart();
+        envelope.clock();
+        assert_eq!(envelope.volume(), 15);
+        for _ in 0..15 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume(), 0);
+        envelope.clock();
+        assert_eq!(envelope.volume(), 15);
+
+        envelope.write(0x17);
+        assert_eq!(envelope.volume(), 7);
+    }
+}
\ No newline at end of file
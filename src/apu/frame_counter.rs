@@ -0,0 +1,98 @@
+// NTSC sequencer steps in CPU cycles since the last $4017 write or wrap
+const STEP_1: u32 = 7457;
+const STEP_2: u32 = 14913;
+const STEP_3: u32 = 22371;
+const FOUR_STEP_LAST: u32 = 29829;
+const FIVE_STEP_LAST: u32 = 37281;
+
+// What the frame counter clocked this cycle. Half frames (length counters,
+// sweeps) also clock everything a quarter frame does (envelopes, linear
+// counter).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameClock {
+    None,
+    Quarter,
+    Half,
+}
+
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameCounter {
+    cycle: u32,
+    five_step: bool,
+    irq_inhibit: bool,
+    pub irq_pending: bool,
+}
+
+impl FrameCounter {
+    pub fn new() -> Self {
+        FrameCounter::default()
+    }
+
+    // $4017. Five-step mode clocks a half frame straight away.
+    pub fn write(&mut self, data: u8) -> FrameClock {
+        self.five_step = data & 0x80 != 0;
+        self.irq_inhibit = data & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq_pending = false;
+        }
+        self.cycle = 0;
+        if self.five_step { FrameClock::Half } else { FrameClock::None }
+    }
+
+    // RESET restarts the sequence in the mode last written
+    pub fn reset(&mut self) {
+        self.cycle = 0;
+        self.irq_pending = false;
+    }
+
+    // One CPU cycle
+    pub fn step(&mut self) -> FrameClock {
+        self.cycle += 1;
+        match (self.cycle, self.five_step) {
+            (STEP_1, _) | (STEP_3, _) => FrameClock::Quarter,
+            (STEP_2, _) => FrameClock::Half,
+            (FOUR_STEP_LAST, false) => {
+                if !self.irq_inhibit {
+                    self.irq_pending = true;
+                }
+                self.cycle = 0;
+                FrameClock::Half
+            }
+            (FIVE_STEP_LAST, true) => {
+                self.cycle = 0;
+                FrameClock::Half
+            }
+            _ => FrameClock::None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clocks(counter: &mut FrameCounter, cycles: u32) -> Vec<FrameClock> {
+        (0..cycles).map(|_| counter.step()).filter(|&clock| clock != FrameClock::None).collect()
+    }
+
+    #[test]
+    fn four_step_sequence_raises_irq() {
+        let mut counter = FrameCounter::new();
+        assert_eq!(clocks(&mut counter, FOUR_STEP_LAST), vec![FrameClock::Quarter, FrameClock::Half, FrameClock::Quarter, FrameClock::Half]);
+        assert!(counter.irq_pending);
+
+        counter.write(0x40);
+        assert!(!counter.irq_pending);
+        clocks(&mut counter, FOUR_STEP_LAST);
+        assert!(!counter.irq_pending);
+    }
+
+    #[test]
+    fn five_step_sequence_never_raises_irq() {
+        let mut counter = FrameCounter::new();
+        assert_eq!(counter.write(0x80), FrameClock::Half);
+        assert_eq!(clocks(&mut counter, FIVE_STEP_LAST).len(), 4);
+        assert!(!counter.irq_pending);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,83 @@
+// Lengths loaded by the upper five bits of $4003/$4007/$400B/$400F
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// Silences a pulse, triangle or noise channel once it counts down to zero.
+// Clocked every half frame unless halted; $4015 reports which are non-zero.
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LengthCounter {
+    enabled: bool,
+    halted: bool,
+    value: u8,
+}
+
+impl LengthCounter {
+    pub fn new() -> Self {
+        LengthCounter::default()
+    }
+
+    // $4015 bits 0-3. Disabling clears the counter right away.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.value = 0;
+        }
+    }
+
+    pub fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    // Upper five bits of the channel's fourth register
+    pub fn load(&mut self, data: u8) {
+        if self.enabled {
+            self.value = LENGTH_TABLE[(data >> 3) as usize];
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if !self.halted && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.value > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_only_while_enabled() {
+        let mut counter = LengthCounter::new();
+        counter.load(0x08);
+        assert!(!counter.is_active());
+
+        counter.set_enabled(true);
+        counter.load(0x18); // Index 3: length 2
+        counter.clock();
+        assert!(counter.is_active());
+        counter.clock();
+        assert!(!counter.is_active());
+    }
+
+    #[test]
+    fn halt_freezes_and_disable_clears() {
+        let mut counter = LengthCounter::new();
+        counter.set_enabled(true);
+        counter.load(0x18);
+        counter.set_halted(true);
+        counter.clock();
+        counter.clock();
+        assert!(counter.is_active());
+
+        counter.set_enabled(false);
+        assert!(!counter.is_active());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,356 @@
+pub mod blip;
+pub mod dmc;
+pub mod envelope;
+pub mod frame_counter;
+pub mod length_counter;
+pub mod noise;
+pub mod pulse;
+pub mod triangle;
+pub mod wav;
+
+use std::{fs::File, io::{self, BufWriter}, path::Path};
+
+use blip::BlipBuffer;
+use dmc::Dmc;
+use frame_counter::{FrameClock, FrameCounter};
+use noise::Noise;
+use pulse::Pulse;
+use triangle::Triangle;
+use wav::WavWriter;
+
+use crate::{config::AudioSynthesis, cpu::cpu::clock_mhz, SystemVersion};
+
+// Default rate of the mixed output handed to frontends
+pub const SAMPLE_RATE: u32 = 44_100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 5] = [Channel::Pulse1, Channel::Pulse2, Channel::Triangle, Channel::Noise, Channel::Dmc];
+}
+
+// Audio processing unit: two pulse channels, triangle, noise and the DMC,
+// sequenced by the frame counter and mixed down to mono.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Apu {
+    pub pulse1: Pulse,
+    pub pulse2: Pulse,
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+    frame_counter: FrameCounter,
+    // Pulse timers tick once every two CPU cycles
+    odd_cycle: bool,
+    // Channels left out of the mix, for muting or soloing while debugging.
+    // They keep running so unmuting picks up mid-note.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    muted: [bool; 5],
+    synthesis: AudioSynthesis,
+    // CPU clock of the region, which samples are taken against
+    cpu_hz: u32,
+    sample_rate: u32,
+    sample_clock: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    blip: BlipBuffer,
+    samples: Vec<f32>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    capture: Option<WavWriter<BufWriter<File>>>,
+}
+
+fn region_hz(region: SystemVersion) -> u32 {
+    (clock_mhz(region) as f64 * 1_000_000.0).round() as u32
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Apu::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_counter: FrameCounter::new(),
+            odd_cycle: false,
+            muted: [false; 5],
+            synthesis: AudioSynthesis::Sampled,
+            cpu_hz: region_hz(SystemVersion::NTSC),
+            sample_rate: SAMPLE_RATE,
+            sample_clock: 0,
+            blip: BlipBuffer::new(),
+            samples: Vec::new(),
+            capture: None,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr - 0x4000, data),
+            0x4004..=0x4007 => self.pulse2.write(addr - 0x4004, data),
+            0x4008..=0x400B => self.triangle.write(addr - 0x4008, data),
+            0x400C..=0x400F => self.noise.write(addr - 0x400C, data),
+            0x4010..=0x4013 => self.dmc.write(addr, data),
+            0x4015 => {
+                self.pulse1.length.set_enabled(data & 0x01 != 0);
+                self.pulse2.length.set_enabled(data & 0x02 != 0);
+                self.triangle.length.set_enabled(data & 0x04 != 0);
+                self.noise.length.set_enabled(data & 0x08 != 0);
+                self.dmc.set_enabled(data & 0x10 != 0);
+            }
+            0x4017 => {
+                let clock = self.frame_counter.write(data);
+                self.clock_frame(clock);
+            }
+            _ => {}
+        }
+    }
+
+    // $4015: bits 0-3 are set while a channel's length counter runs, bit 4
+    // while the DMC has bytes left, then the frame (6) and DMC (7) IRQs.
+    // Reading acknowledges the frame IRQ.
+    pub fn read_status(&mut self) -> u8 {
+        let lengths = [&self.pulse1.length, &self.pulse2.length, &self.triangle.length, &self.noise.length];
+        let mut status = 0;
+        for (i, length) in lengths.iter().enumerate() {
+            if length.is_active() {
+                status |= 1 << i;
+            }
+        }
+        if self.dmc.is_active() {
+            status |= 0x10;
+        }
+        if self.frame_counter.irq_pending {
+            status |= 0x40;
+        }
+        if self.dmc.irq_pending {
+            status |= 0x80;
+        }
+        self.frame_counter.irq_pending = false;
+        status
+    }
+
+    pub fn frame_irq(&self) -> bool {
+        self.frame_counter.irq_pending
+    }
+
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.muted[channel as usize] = !enabled;
+    }
+
+    pub fn channel_enabled(&self, channel: Channel) -> bool {
+        !self.muted[channel as usize]
+    }
+
+    pub fn set_output(&mut self, synthesis: AudioSynthesis, sample_rate: u32) {
+        self.synthesis = synthesis;
+        self.sample_rate = sample_rate.clamp(1, self.cpu_hz);
+        self.sample_clock = 0;
+        self.blip = BlipBuffer::new();
+    }
+
+    // PAL and Dendy CPUs run slower, so a second of their audio takes
+    // fewer cycles
+    pub fn set_region(&mut self, region: SystemVersion) {
+        self.cpu_hz = region_hz(region);
+        self.set_output(self.synthesis, self.sample_rate);
+    }
+
+    // Also writes every output sample to a WAV file until stopped, replacing
+    // any capture already running
+    pub fn start_capture(&mut self, path: &Path) -> io::Result<()> {
+        self.stop_capture()?;
+        let file = BufWriter::new(File::create(path)?);
+        self.capture = Some(WavWriter::new(file, self.sample_rate)?);
+        Ok(())
+    }
+
+    pub fn stop_capture(&mut self) -> io::Result<()> {
+        match self.capture.take() {
+            Some(capture) => capture.finish().map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    // A WAV capture and the muted channels carry on across a loaded state
+    pub(crate) fn keep_attachments(&mut self, old: &mut Apu) {
+        self.muted = old.muted;
+        self.capture = old.capture.take();
+    }
+
+    // Channels and the frame counter as they come up. The output settings,
+    // region clock, muting and any capture belong to the frontend and stay.
+    pub fn power_on(&mut self) {
+        *self = Apu {
+            muted: self.muted,
+            synthesis: self.synthesis,
+            cpu_hz: self.cpu_hz,
+            sample_rate: self.sample_rate,
+            capture: self.capture.take(),
+            ..Apu::new()
+        };
+    }
+
+    // Reset acts like writing 0 to $4015, silencing every channel.
+    pub fn reset(&mut self) {
+        self.write(0x4015, 0);
+        self.frame_counter.reset();
+    }
+
+    fn clock_frame(&mut self, clock: FrameClock) {
+        if clock == FrameClock::None {
+            return;
+        }
+        self.pulse1.clock_quarter();
+        self.pulse2.clock_quarter();
+        self.triangle.clock_quarter();
+        self.noise.clock_quarter();
+        if clock == FrameClock::Half {
+            self.pulse1.clock_half();
+            self.pulse2.clock_half();
+            self.triangle.clock_half();
+            self.noise.clock_half();
+        }
+    }
+
+    // One CPU cycle
+    pub fn step(&mut self) {
+        let clock = self.frame_counter.step();
+        self.clock_frame(clock);
+
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.step();
+
+        if self.synthesis == AudioSynthesis::BandLimited {
+            let offset = self.sample_clock as f32 / self.cpu_hz as f32;
+            self.blip.set_level(offset, self.output());
+        }
+        self.sample_clock += self.sample_rate;
+        if self.sample_clock >= self.cpu_hz {
+            self.sample_clock -= self.cpu_hz;
+            let sample = match self.synthesis {
+                AudioSynthesis::Sampled => self.output(),
+                AudioSynthesis::BandLimited => self.blip.next_sample(),
+            };
+            // Keep about a second's worth when nobody drains them
+            let max_buffered = self.sample_rate as usize;
+            if self.samples.len() >= max_buffered {
+                self.samples.drain(..max_buffered / 2);
+            }
+            self.samples.push(sample);
+            if let Some(capture) = &mut self.capture {
+                capture.write(sample);
+            }
+        }
+    }
+
+    // Nonlinear mixer from the NESdev wiki, 0.0-1.0
+    pub fn output(&self) -> f32 {
+        let level = |channel: Channel, output: u8| if self.muted[channel as usize] { 0.0 } else { output as f32 };
+        let pulse = level(Channel::Pulse1, self.pulse1.output()) + level(Channel::Pulse2, self.pulse2.output());
+        let triangle = level(Channel::Triangle, self.triangle.output());
+        let noise = level(Channel::Noise, self.noise.output());
+        let dmc = level(Channel::Dmc, self.dmc.output_level);
+
+        let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+        let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+        let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+        pulse_out + tnd_out
+    }
+
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_reports_length_counters_and_irqs() {
+        let mut apu = Apu::new();
+        apu.write(0x4015, 0x05);
+        apu.write(0x4003, 0x08);
+        apu.write(0x4007, 0x08); // Pulse 2 is disabled, so this is ignored
+        apu.write(0x400B, 0x08);
+        assert_eq!(apu.read_status(), 0x05);
+
+        // A full four-step sequence raises the frame IRQ; reading clears it
+        for _ in 0..29829 {
+            apu.step();
+        }
+        assert!(apu.frame_irq());
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.frame_irq());
+
+        apu.write(0x4015, 0x00);
+        assert_eq!(apu.read_status(), 0x00);
+    }
+
+    #[test]
+    fn muted_channels_leave_the_mix() {
+        let mut apu = Apu::new();
+        let silent = apu.output();
+        apu.write(0x4011, 0x40);
+        let level = apu.output();
+        assert!(level > silent);
+
+        apu.set_channel_enabled(Channel::Dmc, false);
+        assert_eq!(apu.output(), silent);
+        assert!(!apu.channel_enabled(Channel::Dmc));
+        apu.set_channel_enabled(Channel::Dmc, true);
+        assert_eq!(apu.output(), level);
+    }
+
+    #[test]
+    fn band_limited_output_settles_on_the_mixer_level() {
+        let mut apu = Apu::new();
+        apu.set_output(AudioSynthesis::BandLimited, 22_050);
+        apu.write(0x4011, 0x40);
+        for _ in 0..apu.cpu_hz / 100 {
+            apu.step();
+        }
+        let samples = apu.take_samples();
+        assert_eq!(samples.len(), 220);
+        assert!((samples[samples.len() - 1] - apu.output()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn capture_records_every_sample() {
+        let path = std::env::temp_dir().join(format!("nes-apu-capture-{}.wav", std::process::id()));
+        let mut apu = Apu::new();
+        apu.start_capture(&path).unwrap();
+        for _ in 0..apu.cpu_hz / 100 {
+            apu.step();
+        }
+        let samples = apu.take_samples().len();
+        apu.stop_capture().unwrap();
+        assert!(!apu.is_capturing());
+
+        let wav = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(wav.len(), 44 + samples * 2);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,109 @@
+use super::{envelope::Envelope, length_counter::LengthCounter};
+
+// NTSC timer periods in CPU cycles, indexed by the low bits of $400E
+const PERIOD_TABLE: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+
+// Noise channel, $400C-$400F: a 15-bit LFSR, in short (93 step) mode when
+// bit 7 of $400E is set.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Noise {
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift_register: u16,
+    envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise::new()
+    }
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            short_mode: false,
+            timer_period: PERIOD_TABLE[0],
+            timer: 0,
+            shift_register: 1,
+            envelope: Envelope::new(),
+            length: LengthCounter::new(),
+        }
+    }
+
+    // Register 0-3 ($400D is unused)
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.length.set_halted(data & 0x20 != 0);
+                self.envelope.write(data);
+            }
+            2 => {
+                self.short_mode = data & 0x80 != 0;
+                self.timer_period = PERIOD_TABLE[(data & 0x0F) as usize];
+            }
+            3 => {
+                self.length.load(data);
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    // Every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period;
+
+        let tap = if self.short_mode { 6 } else { 1 };
+        let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    pub fn clock_quarter(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half(&mut self) {
+        self.length.clock();
+    }
+
+    // 0-15
+    pub fn output(&self) -> u8 {
+        if !self.length.is_active() || self.shift_register & 1 != 0 {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Steps until the shift register comes back to its start
+    fn sequence_length(short_mode: bool) -> usize {
+        let mut noise = Noise::new();
+        noise.write(2, if short_mode { 0x80 } else { 0x00 });
+        let start = noise.shift_register;
+        (1..=32767).find(|_| {
+            for _ in 0..PERIOD_TABLE[0] {
+                noise.clock_timer();
+            }
+            noise.shift_register == start
+        }).unwrap()
+    }
+
+    #[test]
+    fn lfsr_sequence_lengths() {
+        assert_eq!(sequence_length(false), 32767);
+        // From the power-on seed short mode lands in the 93-step loop
+        assert_eq!(sequence_length(true), 93);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,181 @@
+use super::{envelope::Envelope, length_counter::LengthCounter};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+// Square wave channel, $4000-$4003 or $4004-$4007. The two only differ in
+// how the sweep unit negates: pulse 1 subtracts one more.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pulse {
+    ones_complement: bool,
+    duty: u8,
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+
+    envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Self {
+        Pulse {
+            ones_complement,
+            duty: 0,
+            step: 0,
+            timer_period: 0,
+            timer: 0,
+
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_divider: 0,
+            sweep_reload: false,
+
+            envelope: Envelope::new(),
+            length: LengthCounter::new(),
+        }
+    }
+
+    // Register 0-3
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = data >> 6;
+                self.length.set_halted(data & 0x20 != 0);
+                self.envelope.write(data);
+            }
+            1 => {
+                self.sweep_enabled = data & 0x80 != 0;
+                self.sweep_period = (data >> 4) & 0x07;
+                self.sweep_negate = data & 0x08 != 0;
+                self.sweep_shift = data & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x700) | data as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0xFF) | ((data as u16 & 0x07) << 8);
+                self.length.load(data);
+                self.step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    // Every other CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.step = (self.step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half(&mut self) {
+        self.length.clock();
+
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.sweep_muted() {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if !self.sweep_negate {
+            self.timer_period + change
+        } else if self.ones_complement {
+            self.timer_period.saturating_sub(change + 1)
+        } else {
+            self.timer_period.saturating_sub(change)
+        }
+    }
+
+    // Too high or too low a period silences the channel, sweep enabled or not
+    fn sweep_muted(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    // 0-15
+    pub fn output(&self) -> u8 {
+        if !self.length.is_active() || self.sweep_muted() || DUTY_TABLE[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing(period: u16) -> Pulse {
+        let mut pulse = Pulse::new(false);
+        pulse.length.set_enabled(true);
+        pulse.write(0, 0xFF); // 75% duty, halted, constant volume 15
+        pulse.write(2, period as u8);
+        pulse.write(3, (period >> 8) as u8);
+        pulse
+    }
+
+    #[test]
+    fn duty_cycle_sets_the_waveform() {
+        let mut pulse = playing(0x100);
+        let mut high = 0;
+        for _ in 0..8 {
+            if pulse.output() > 0 {
+                high += 1;
+            }
+            for _ in 0..=0x100 {
+                pulse.clock_timer();
+            }
+        }
+        assert_eq!(high, 6);
+    }
+
+    #[test]
+    fn low_periods_and_sweep_overflow_mute() {
+        assert_eq!(playing(8).output(), 15);
+        assert_eq!(playing(7).output(), 0);
+
+        let mut pulse = playing(0x7F0);
+        pulse.write(1, 0x81); // Sweep up by period >> 1
+        assert!(pulse.sweep_muted());
+    }
+
+    #[test]
+    fn pulse_1_negates_with_ones_complement() {
+        let mut pulse_1 = Pulse::new(true);
+        let mut pulse_2 = Pulse::new(false);
+        for pulse in [&mut pulse_1, &mut pulse_2] {
+            pulse.write(2, 0x00);
+            pulse.write(3, 0x01);
+            pulse.write(1, 0x89);
+        }
+        assert_eq!(pulse_1.sweep_target(), 0x100 - 0x80 - 1);
+        assert_eq!(pulse_2.sweep_target(), 0x100 - 0x80);
+    }
+}
\ No newline at end of file
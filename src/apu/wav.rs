@@ -0,0 +1,109 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+const HEADER_SIZE: u32 = 44;
+
+// Streams mono 16-bit PCM as a WAV file. The chunk sizes in the header are
+// left as 0 until finish() goes back and fills them in, or until it's
+// dropped, which does the same and ignores errors.
+pub struct WavWriter<W: Write + Seek> {
+    // Only None once finish() has taken it
+    out: Option<W>,
+    samples: u32,
+    // First write error, reported by finish() so the APU never has to
+    error: Option<io::Error>,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&1u16.to_le_bytes()); // Mono
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // Bytes per second
+        header.extend_from_slice(&2u16.to_le_bytes()); // Bytes per frame
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        out.write_all(&header)?;
+        Ok(WavWriter { out: Some(out), samples: 0, error: None })
+    }
+
+    // The mixer's 0.0-1.0 scaled to the positive half of the 16-bit range
+    pub fn write(&mut self, sample: f32) {
+        if self.error.is_some() {
+            return;
+        }
+        let Some(out) = &mut self.out else {
+            return;
+        };
+        let value = (sample.clamp(0.0, 1.0) * i16::MAX as f32) as i16;
+        match out.write_all(&value.to_le_bytes()) {
+            Ok(()) => self.samples += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let mut out = self.out.take().expect("only finish() takes the output");
+        write_sizes(&mut out, self.samples)?;
+        Ok(out)
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if let Some(out) = &mut self.out {
+            let _ = write_sizes(out, self.samples);
+        }
+    }
+}
+
+fn write_sizes<W: Write + Seek>(out: &mut W, samples: u32) -> io::Result<()> {
+    let data_size = samples * 2;
+    out.seek(SeekFrom::Start(4))?;
+    out.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    out.seek(SeekFrom::Start(40))?;
+    out.write_all(&data_size.to_le_bytes())?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn header_sizes_match_the_data() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 22_050).unwrap();
+        for sample in [0.0, 0.5, 1.0, 2.0] {
+            wav.write(sample);
+        }
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 22_050);
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 8);
+        assert_eq!(&bytes[44..], &[0x00, 0x00, 0xFF, 0x3F, 0xFF, 0x7F, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn dropping_fills_in_the_sizes() {
+        let mut bytes = Vec::new();
+        let mut wav = WavWriter::new(Cursor::new(&mut bytes), 22_050).unwrap();
+        wav.write(0.5);
+        drop(wav);
+
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 2);
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 2);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,151 @@
+use std::{fmt, hash::{DefaultHasher, Hash, Hasher}};
+
+use serde_json::Value;
+
+use crate::{config::NesConfig, rom::{Rom, RomError}, Nes};
+
+// Where two consoles' states first differ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    // Frames run when it was found
+    pub frame: u64,
+    // Field path into the serialized state, like cpu.bus.ram.data[18]
+    pub path: String,
+    pub left: String,
+    pub right: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Frame {}: {} is {} on the left and {} on the right", self.frame, self.path, self.left, self.right)
+    }
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Depth first, so the path is the first difference in field order
+fn first_difference(path: &str, left: &Value, right: &Value) -> Option<(String, String, String)> {
+    match (left, right) {
+        (Value::Object(l), Value::Object(r)) => l.iter()
+            .find_map(|(key, value)| {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                first_difference(&path, value, r.get(key).unwrap_or(&Value::Null))
+            }),
+        (Value::Array(l), Value::Array(r)) if l.len() == r.len() => l.iter().zip(r)
+            .enumerate()
+            .find_map(|(i, (l, r))| first_difference(&format!("{}[{}]", path, i), l, r)),
+        _ if left != right => Some((path.to_string(), left.to_string(), right.to_string())),
+        _ => None,
+    }
+}
+
+// Runs two consoles with the same ROM, configuration and input in lockstep,
+// comparing hashes of their full serialized state after every frame. Any
+// difference is nondeterminism: state a feature forgot to reset, save,
+// or seed. Netplay, rewind and run-ahead all rely on there being none.
+pub struct LockstepAudit {
+    left: Nes,
+    right: Nes,
+    reload: bool,
+    frame: u64,
+}
+
+impl LockstepAudit {
+    // Both consoles get the same seed, picked now if the config has none
+    pub fn new(rom: &[u8], config: NesConfig) -> Result<Self, RomError> {
+        let mut left = Nes::with_config(config);
+        let mut right = Nes::with_config(left.config().clone());
+        left.set_rom(Rom::from_bytes(rom.to_vec())?);
+        right.set_rom(Rom::from_bytes(rom.to_vec())?);
+        left.on();
+        right.on();
+        Ok(LockstepAudit { left, right, reload: false, frame: 0 })
+    }
+
+    // Puts the right console through a save state round trip after every
+    // frame, so state that save states miss shows up as a divergence
+    pub fn set_reload(&mut self, reload: bool) {
+        self.reload = reload;
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn left(&mut self) -> &mut Nes {
+        &mut self.left
+    }
+
+    pub fn right(&mut self) -> &mut Nes {
+        &mut self.right
+    }
+
+    // One frame on both consoles with the same buttons held on each port
+    pub fn run_frame(&mut self, buttons: [u8; 2]) -> Result<(), Divergence> {
+        for nes in [&mut self.left, &mut self.right] {
+            nes.set_port_buttons(0, buttons[0]);
+            nes.set_port_buttons(1, buttons[1]);
+            nes.run_frame();
+        }
+        self.frame += 1;
+
+        let serialize = |nes: &Nes| serde_json::to_vec(nes).expect("console state serializes");
+        let (left, right) = (serialize(&self.left), serialize(&self.right));
+        if hash(&left) != hash(&right) {
+            return Err(self.divergence(&left, &right));
+        }
+        if self.reload {
+            let state = serde_json::from_slice(&right).expect("console state deserializes");
+            self.right.restore(state).expect("state is of the same game");
+        }
+        Ok(())
+    }
+
+    // Stops at the first divergence
+    pub fn run<I: IntoIterator<Item = [u8; 2]>>(&mut self, inputs: I) -> Result<(), Divergence> {
+        inputs.into_iter().try_for_each(|buttons| self.run_frame(buttons))
+    }
+
+    fn divergence(&self, left: &[u8], right: &[u8]) -> Divergence {
+        let parse = |bytes| serde_json::from_slice::<Value>(bytes).unwrap_or(Value::Null);
+        let (path, left, right) = first_difference("", &parse(left), &parse(right))
+            .unwrap_or_else(|| (String::new(), "?".to_string(), "?".to_string()));
+        Divergence { frame: self.frame, path, left, right }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::TestRom, SystemVersion};
+    use super::*;
+
+    fn audit() -> LockstepAudit {
+        let config = NesConfig { random_ppu_state: true, ..NesConfig::new(SystemVersion::NTSC) };
+        LockstepAudit::new(&TestRom::new(0).bytes(), config).unwrap()
+    }
+
+    #[test]
+    fn identical_consoles_stay_in_lockstep() {
+        let mut audit = audit();
+        audit.set_reload(true);
+        let inputs = (0..8u8).map(|frame| [frame, frame.rotate_left(3)]);
+        assert_eq!(audit.run(inputs), Ok(()));
+        assert_eq!(audit.frame(), 8);
+    }
+
+    #[test]
+    fn reports_the_first_divergence() {
+        let mut audit = audit();
+        audit.run_frame([0, 0]).unwrap();
+        audit.right().poke(0x0012, 0x34);
+
+        let divergence = audit.run_frame([0, 0]).unwrap_err();
+        assert_eq!(divergence.frame, 2);
+        assert_eq!(divergence.path, "cpu.bus.ram.data[18]");
+        assert_eq!((divergence.left.as_str(), divergence.right.as_str()), ("0", "52"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,188 @@
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+use crate::{cdl::CodeDataLog, mapper::{MapperDebugInfo, NametableSource}, rom::{header::{RomHeader, HEADER_SIZE}, Rom, RomMetadata}};
+
+// The inserted game. It sits on the CPU bus, which hands it to the PPU for
+// pattern and nametable fetches, so neither chip owns the other's path to
+// the mapper.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cartridge {
+    rom: Rom,
+    // PRG bank size of the mapper's debug state, for bank:address traces
+    prg_window: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    cdl: Option<CodeDataLog>,
+}
+
+impl Cartridge {
+    pub fn new(rom: Rom) -> Self {
+        let prg_window = rom.mapper.debug_state().prg_banks.first().map_or(0x8000, |window| window.size);
+        Cartridge { rom, prg_window, cdl: None }
+    }
+
+    // Nothing inserted: an NROM board with no PRG or CHR, reading as 0
+    pub fn empty() -> Self {
+        Cartridge::new(Rom::new(vec![0; HEADER_SIZE]))
+    }
+
+    pub fn header(&self) -> &RomHeader {
+        &self.rom.header
+    }
+
+    pub fn metadata(&self) -> &RomMetadata {
+        self.rom.metadata()
+    }
+
+    // Starts logging code and data accesses, or continues `log`
+    pub fn start_cdl(&mut self, log: Option<CodeDataLog>) {
+        let header = &self.rom.header;
+        self.cdl = Some(log.unwrap_or_else(|| CodeDataLog::new(header.prg_rom_size as usize, header.chr_rom_size as usize)));
+    }
+
+    pub fn stop_cdl(&mut self) -> Option<CodeDataLog> {
+        self.cdl.take()
+    }
+
+    // A state of the same game takes the inserted ROM, which states leave
+    // out, and the code/data log carries over
+    pub(crate) fn keep_attachments(&mut self, old: &mut Cartridge) {
+        if self.rom.crc32() == old.rom.crc32() {
+            #[cfg(feature = "serde")]
+            self.rom.rebind(&mut old.rom);
+            self.cdl = old.cdl.take();
+        }
+    }
+
+    pub fn cdl(&self) -> Option<&CodeDataLog> {
+        self.cdl.as_ref()
+    }
+
+    pub fn cdl_mut(&mut self) -> Option<&mut CodeDataLog> {
+        self.cdl.as_mut()
+    }
+
+    // CPU side, $4020-$FFFF
+
+    pub fn cpu_read(&mut self, addr: u16) -> u8 {
+        if let Some(cdl) = &mut self.cdl {
+            if addr >= 0x8000 {
+                cdl.log_prg(addr, self.rom.mapper.map(addr));
+            }
+        }
+        self.rom.mapper.read(addr)
+    }
+
+    pub fn log_indirect_jump(&mut self, target: u16) {
+        if let Some(cdl) = &mut self.cdl {
+            if target >= 0x8000 {
+                cdl.log_indirect_jump(self.rom.mapper.map(target));
+            }
+        }
+    }
+
+    pub fn cpu_write(&mut self, addr: u16, data: u8, cycle: u64) {
+        // $6000-$7FFF is PRG RAM on most boards, anything else a register
+        if (0x6000..0x8000).contains(&addr) {
+            log::trace!(target: "mapper", "${:04X} <- ${:02X}", addr, data);
+        } else {
+            log::debug!(target: "mapper", "${:04X} <- ${:02X} at cycle {}", addr, data, cycle);
+        }
+        self.rom.mapper.cpu_write(addr, data, cycle);
+    }
+
+    pub fn out_latch_write(&mut self, data: u8) {
+        self.rom.mapper.out_latch_write(data);
+    }
+
+    // PRG/CHR offset an address currently maps to
+    pub fn map(&self, addr: u16) -> u32 {
+        self.rom.mapper.map(addr)
+    }
+
+    pub fn prg_window(&self) -> u32 {
+        self.prg_window
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.rom.mapper.irq_pending()
+    }
+
+    pub fn clock(&mut self, cycles: u8) {
+        self.rom.mapper.clock(cycles);
+    }
+
+    pub fn reset(&mut self) {
+        self.rom.mapper.reset();
+    }
+
+    pub fn power_on(&mut self) {
+        self.rom.mapper.power_on();
+    }
+
+    pub fn dip_switches(&self) -> Option<u8> {
+        self.rom.mapper.dip_switches()
+    }
+
+    pub fn set_dip_switches(&mut self, value: u8) {
+        self.rom.mapper.set_dip_switches(value);
+    }
+
+    pub fn prg_ram(&self) -> &[u8] {
+        self.rom.mapper.prg_ram()
+    }
+
+    pub fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.rom.mapper.prg_ram_mut()
+    }
+
+    // The pattern table RAM of boards without CHR-ROM, empty on the rest
+    pub fn chr_ram(&self) -> &[u8] {
+        if self.rom.header.chr_rom_size == 0 { self.rom.mapper.chr() } else { &[] }
+    }
+
+    // The board as a save state has it: registers and RAM, no ROM
+    #[cfg(feature = "serde")]
+    pub fn mapper_state(&self) -> MapperState {
+        self.rom.mapper.state()
+    }
+
+    pub fn mapper_debug_info(&self) -> MapperDebugInfo {
+        let mut info = self.rom.mapper.debug_state();
+        info.mirroring.get_or_insert(self.rom.header.mirroring);
+        info
+    }
+
+    // PPU side: pattern tables at $0000-$1FFF and nametable routing
+
+    pub fn chr_read(&mut self, addr: u16) -> u8 {
+        if let Some(cdl) = &mut self.cdl {
+            cdl.log_chr(self.rom.mapper.map(addr));
+        }
+        self.rom.mapper.read(addr)
+    }
+
+    // Reads CHR without touching the mapper or the CDL
+    pub fn chr_peek(&self, addr: u16) -> u8 {
+        let chr = self.rom.mapper.chr();
+        if chr.is_empty() {
+            return 0;
+        }
+        chr[self.rom.mapper.map(addr) as usize % chr.len()]
+    }
+
+    pub fn chr_write(&mut self, addr: u16, data: u8) {
+        self.rom.mapper.write(addr, data);
+    }
+
+    pub fn nametable_source(&self, table: u16) -> NametableSource {
+        self.rom.mapper.nametable_source(table, self.rom.header.mirroring)
+    }
+
+    pub fn read_nametable(&mut self, addr: u16) -> u8 {
+        self.rom.mapper.read_nametable(addr)
+    }
+
+    pub fn write_nametable(&mut self, addr: u16, data: u8) {
+        self.rom.mapper.write_nametable(addr, data);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,171 @@
+// Code/Data Logger. Marks every PRG-ROM byte the CPU executes or reads and
+// every CHR-ROM byte the PPU draws, using the FCEUX .cdl layout: one flag
+// byte per PRG byte followed by one per CHR byte.
+
+// PRG flags
+pub const CODE: u8 = 0x01;
+pub const DATA: u8 = 0x02;
+// Bits 2-3: which 8KB window of $8000-$FFFF the byte was accessed through
+const WINDOW_SHIFT: u8 = 2;
+pub const INDIRECT_CODE: u8 = 0x10;
+pub const INDIRECT_DATA: u8 = 0x20;
+pub const PCM_DATA: u8 = 0x40;
+
+// CHR flags
+pub const RENDERED: u8 = 0x01;
+pub const READ: u8 = 0x02;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeDataLog {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+
+    // What the CPU is doing, so a read can be told apart: the bytes of the
+    // current instruction are code, anything else it reads is data
+    code_start: u16,
+    code_end: u32,
+    indirect: bool,
+    pcm: bool,
+    chr_by_cpu: bool,
+}
+
+impl CodeDataLog {
+    pub fn new(prg_size: usize, chr_size: usize) -> Self {
+        CodeDataLog {
+            prg: vec![0; prg_size],
+            chr: vec![0; chr_size],
+            code_start: 0,
+            code_end: 0,
+            indirect: false,
+            pcm: false,
+            chr_by_cpu: false,
+        }
+    }
+
+    // Continues a log saved by to_cdl(), e.g. from an earlier session
+    pub fn from_cdl(data: &[u8], prg_size: usize, chr_size: usize) -> Result<Self, String> {
+        if data.len() != prg_size + chr_size {
+            return Err(format!("CDL file is {} bytes, this ROM needs {}", data.len(), prg_size + chr_size));
+        }
+        let mut log = CodeDataLog::new(prg_size, chr_size);
+        log.prg.copy_from_slice(&data[..prg_size]);
+        log.chr.copy_from_slice(&data[prg_size..]);
+        Ok(log)
+    }
+
+    pub fn prg(&self) -> &[u8] {
+        &self.prg
+    }
+
+    pub fn chr(&self) -> &[u8] {
+        &self.chr
+    }
+
+    pub fn to_cdl(&self) -> Vec<u8> {
+        [self.prg.as_slice(), self.chr.as_slice()].concat()
+    }
+
+    // PRG bytes logged as code, and as data
+    pub fn prg_coverage(&self) -> (usize, usize) {
+        let count = |flag| self.prg.iter().filter(|&&byte| byte & flag != 0).count();
+        (count(CODE), count(DATA))
+    }
+
+    pub(crate) fn begin_instruction(&mut self, pc: u16, len: u16, indirect: bool) {
+        self.code_start = pc;
+        self.code_end = pc as u32 + len as u32;
+        self.indirect = indirect;
+    }
+
+    pub(crate) fn set_pcm(&mut self, pcm: bool) {
+        self.pcm = pcm;
+    }
+
+    pub(crate) fn set_chr_by_cpu(&mut self, by_cpu: bool) {
+        self.chr_by_cpu = by_cpu;
+    }
+
+    // A CPU read of $8000-$FFFF that landed on PRG-ROM `offset`
+    pub(crate) fn log_prg(&mut self, addr: u16, offset: u32) {
+        let window = (((addr >> 13) & 0x03) as u8) << WINDOW_SHIFT;
+        let flags = if self.pcm {
+            DATA | PCM_DATA
+        } else if addr >= self.code_start && (addr as u32) < self.code_end {
+            CODE
+        } else if self.indirect {
+            DATA | INDIRECT_DATA
+        } else {
+            DATA
+        };
+        if let Some(byte) = self.prg.get_mut(offset as usize) {
+            *byte |= flags | window;
+        }
+    }
+
+    // Target of JMP (addr), which the next fetch will also log as code
+    pub(crate) fn log_indirect_jump(&mut self, offset: u32) {
+        if let Some(byte) = self.prg.get_mut(offset as usize) {
+            *byte |= INDIRECT_CODE;
+        }
+    }
+
+    pub(crate) fn log_chr(&mut self, offset: u32) {
+        let flag = if self.chr_by_cpu { READ } else { RENDERED };
+        if let Some(byte) = self.chr.get_mut(offset as usize) {
+            *byte |= flag;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::Rom, test_support::TestRom, Nes, SystemVersion};
+    use super::*;
+
+    #[test]
+    fn logs_code_data_and_indirect_accesses() {
+        let mut data = TestRom::new(0).bytes();
+        let program = [
+            0xAD, 0x00, 0x90, // LDA $9000
+            0xB1, 0x10,       // LDA ($10),Y
+            0x6C, 0x20, 0x00, // JMP ($0020)
+        ];
+        data[16..16 + program.len()].copy_from_slice(&program);
+        data[16 + 0x10..16 + 0x13].copy_from_slice(&[0x4C, 0x10, 0x80]); // JMP $8010
+        data[16 + 0x7FFC..16 + 0x7FFE].copy_from_slice(&[0x00, 0x80]);
+
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(Rom::new(data));
+        nes.start_code_data_log();
+        nes.on();
+        for (addr, value) in [(0x10, 0x00), (0x11, 0xA0), (0x20, 0x10), (0x21, 0x80)] {
+            nes.cpu.bus.write(addr, value);
+        }
+        nes.cpu.y = 0;
+        for _ in 0..5 {
+            nes.step();
+        }
+
+        let log = nes.stop_code_data_log().unwrap();
+        let prg = log.prg();
+        assert_eq!(&prg[0..8], &[CODE; 8]);
+        assert_eq!(prg[0x1000], DATA);
+        assert_eq!(prg[0x2000], DATA | INDIRECT_DATA | (1 << WINDOW_SHIFT));
+        assert_eq!(prg[0x10], CODE | INDIRECT_CODE);
+        assert_eq!(prg[0x7FFC], DATA | (3 << WINDOW_SHIFT));
+        assert_eq!(log.prg_coverage(), (11, 4));
+    }
+
+    #[test]
+    fn cdl_file_is_prg_then_chr() {
+        let mut log = CodeDataLog::new(4, 2);
+        log.log_chr(1);
+        log.set_chr_by_cpu(true);
+        log.log_chr(0);
+        assert_eq!(log.to_cdl(), vec![0, 0, 0, 0, READ, RENDERED]);
+
+        let resumed = CodeDataLog::from_cdl(&log.to_cdl(), 4, 2).unwrap();
+        assert_eq!(resumed.chr(), log.chr());
+        assert!(CodeDataLog::from_cdl(&[0; 5], 4, 2).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,75 @@
+// Game Genie letters in order of the nibble they encode
+const LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+// A decoded Game Genie code. The cartridge read at `addr` returns `value`
+// instead, as long as the ROM byte there matches `compare` (8 letter codes).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameGenie {
+    pub addr: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl GameGenie {
+    pub fn decode(code: &str) -> Result<Self, String> {
+        let mut n = [0u8; 8];
+        let len = code.chars().count();
+        if len != 6 && len != 8 {
+            return Err(format!("Game Genie codes are 6 or 8 letters: {}", code));
+        }
+        for (i, c) in code.chars().enumerate() {
+            n[i] = LETTERS.iter()
+                .position(|&letter| letter as char == c.to_ascii_uppercase())
+                .ok_or_else(|| format!("Invalid Game Genie letter '{}' in {}", c, code))? as u8;
+        }
+
+        let addr = 0x8000
+            | ((n[3] & 7) as u16) << 12
+            | ((n[5] & 7) as u16) << 8
+            | ((n[4] & 8) as u16) << 8
+            | ((n[2] & 7) as u16) << 4
+            | ((n[1] & 8) as u16) << 4
+            | (n[4] & 7) as u16
+            | (n[3] & 8) as u16;
+
+        let value_bits = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7);
+        if len == 6 {
+            return Ok(GameGenie { addr, value: value_bits | (n[5] & 8), compare: None });
+        }
+        let compare = ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8);
+        Ok(GameGenie { addr, value: value_bits | (n[7] & 8), compare: Some(compare) })
+    }
+
+    // What the CPU sees at `addr` given the byte the cartridge put there
+    pub fn apply(&self, addr: u16, data: u8) -> u8 {
+        match self.compare {
+            _ if addr != self.addr => data,
+            Some(compare) if compare != data => data,
+            _ => self.value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_six_letter_code() {
+        let code = GameGenie::decode("SXIOPO").unwrap();
+        assert_eq!(code, GameGenie { addr: 0x91D9, value: 0xAD, compare: None });
+        assert_eq!(GameGenie::decode("sxiopo"), Ok(code));
+        assert!(GameGenie::decode("SXIOP").is_err());
+        assert!(GameGenie::decode("SXIOPB").is_err());
+    }
+
+    #[test]
+    fn eight_letter_code_checks_compare_byte() {
+        let code = GameGenie::decode("SXIOPOAA").unwrap();
+        assert_eq!(code, GameGenie { addr: 0x91D9, value: 0xA5, compare: Some(0x08) });
+        assert_eq!(code.apply(0x91D9, 0x08), 0xA5);
+        assert_eq!(code.apply(0x91D9, 0x09), 0x09);
+        assert_eq!(code.apply(0x91DA, 0x08), 0x08);
+    }
+}
\ No newline at end of file
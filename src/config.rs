@@ -0,0 +1,80 @@
+use crate::SystemVersion;
+
+// What CPU RAM holds at power on. Real consoles come up with a mostly
+// unpredictable pattern, which a few games depend on or trip over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RamPattern {
+    Zero,
+    Ones,
+    // Four bytes of 0x00 then four of 0xFF, like many consoles power up
+    Alternating,
+    Random
+}
+
+// How the APU's output is brought down to the host sample rate
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AudioSynthesis {
+    // Takes the mixer's level once per output sample. Cheap, but harmonics
+    // above the output Nyquist fold back as aliasing.
+    Sampled,
+    // Band-limited steps, see apu::blip. Clean at any rate for some CPU.
+    BandLimited,
+}
+
+// Options fixed at construction time, see Nes::with_config.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NesConfig {
+    pub region: SystemVersion,
+    pub ram_pattern: RamPattern,
+    // Randomizes OAM, palette RAM and the power-on PPUSTATUS bits, which are
+    // all undefined on hardware.
+    pub random_ppu_state: bool,
+    // Seed for every random power-on value. Left as None a seed is picked
+    // from the clock and written back, so config().seed can reproduce it.
+    pub seed: Option<u64>,
+    // Hardware draws at most 8 sprites per scanline; turning this off
+    // removes the flicker at the cost of accuracy.
+    pub sprite_limit: bool,
+    // Idle scanlines added to the end of vblank, during which only the CPU
+    // runs. Cuts lag in games like Gradius; 0 keeps stock timing.
+    pub overclock_scanlines: u16,
+    // Constant ORed into A by the unstable ANE/LXA opcodes. It differs
+    // between CPU batches; 0xEE and 0xFF are the common values.
+    pub unstable_magic: u8,
+    // Jump here instead of the reset vector on power on (nestest automation).
+    pub start_pc: Option<u16>,
+    pub audio_synthesis: AudioSynthesis,
+    // Plug in the Vaus paddle or Family BASIC keyboard when a ROM's header
+    // names one as its expansion device, unplugging the other. Headers that
+    // name nothing leave the frontend's choice alone.
+    pub auto_input_devices: bool,
+    // Output rate of take_audio_samples, in Hz
+    pub sample_rate: u32,
+}
+
+impl Default for NesConfig {
+    fn default() -> Self {
+        NesConfig {
+            region: SystemVersion::NTSC,
+            ram_pattern: RamPattern::Zero,
+            random_ppu_state: false,
+            seed: None,
+            sprite_limit: true,
+            overclock_scanlines: 0,
+            unstable_magic: 0xEE,
+            start_pc: None,
+            audio_synthesis: AudioSynthesis::Sampled,
+            auto_input_devices: true,
+            sample_rate: crate::apu::SAMPLE_RATE,
+        }
+    }
+}
+
+impl NesConfig {
+    pub fn new(region: SystemVersion) -> Self {
+        NesConfig { region, ..NesConfig::default() }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,324 @@
+use std::{io::{self, ErrorKind, Read, Write}, net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs}};
+
+use serde_json::{json, Value};
+
+use crate::{controller::Button, rom::Rom, Nes};
+
+const BUTTONS: [(&str, Button); 8] = [
+    ("a", Button::A),
+    ("b", Button::B),
+    ("select", Button::Select),
+    ("start", Button::Start),
+    ("up", Button::Up),
+    ("down", Button::Down),
+    ("left", Button::Left),
+    ("right", Button::Right),
+];
+
+// A request line longer than this drops the client
+const MAX_REQUEST: usize = 64 * 1024;
+// As does letting this much of its answers pile up unread
+const MAX_UNSENT: usize = 64 * 1024 * 1024;
+// Frames one frame_advance may run, about ten seconds, since polling waits
+// on it
+const MAX_FRAME_ADVANCE: u64 = 600;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+struct Client {
+    stream: TcpStream,
+    // Bytes of a request whose newline hasn't arrived yet
+    pending: Vec<u8>,
+    // Answers the socket hasn't taken yet, sent as it has room
+    unsent: Vec<u8>,
+}
+
+// Lets external scripts drive the emulator: test harnesses, bots, tool
+// assisted runs. Clients connect over TCP and send one JSON object per
+// line, each answered with one line:
+//
+//   {"cmd": "load_rom", "path": "game.nes"}
+//   {"cmd": "pause"} / {"cmd": "resume"}
+//   {"cmd": "frame_advance", "frames": 1}
+//   {"cmd": "peek", "addr": 768, "len": 4}
+//   {"cmd": "poke", "addr": 768, "data": [1, 2]}
+//   {"cmd": "input", "port": 0, "buttons": ["a", "right"]}
+//   {"cmd": "screenshot"}
+//   {"cmd": "reset"} / {"cmd": "status"}
+//
+// Answers carry "ok": true plus any results, or "ok": false and "error".
+// Nothing happens between calls to poll, so the frontend decides when
+// commands run; while paused it should only advance frames on request.
+pub struct ControlServer {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    paused: bool,
+}
+
+impl ControlServer {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(ControlServer { listener, clients: Vec::new(), paused: false })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Accepts new clients and answers every complete request. Never blocks
+    // waiting for input, or on a client slow to read its answers.
+    pub fn poll(&mut self, nes: &mut Nes) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                self.clients.push(Client { stream, pending: Vec::new(), unsent: Vec::new() });
+            }
+        }
+
+        let mut clients = std::mem::take(&mut self.clients);
+        clients.retain_mut(|client| self.serve(client, nes).is_ok());
+        self.clients = clients;
+    }
+
+    // Fails once the client has gone
+    fn serve(&mut self, client: &mut Client, nes: &mut Nes) -> io::Result<()> {
+        let mut buffer = [0; 4096];
+        loop {
+            match client.stream.read(&mut buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(len) => client.pending.extend_from_slice(&buffer[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        while let Some(end) = client.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = client.pending.drain(..=end).collect();
+            let request = String::from_utf8_lossy(&line);
+            if request.trim().is_empty() {
+                continue;
+            }
+            client.unsent.extend_from_slice(self.handle(nes, &request).as_bytes());
+            client.unsent.push(b'\n');
+        }
+        if client.pending.len() > MAX_REQUEST || client.unsent.len() > MAX_UNSENT {
+            return Err(ErrorKind::InvalidData.into());
+        }
+
+        // Screenshots don't fit a socket buffer, so answers can take a few
+        // polls to go out
+        let mut sent = 0;
+        while sent < client.unsent.len() {
+            match client.stream.write(&client.unsent[sent..]) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(len) => sent += len,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        client.unsent.drain(..sent);
+        Ok(())
+    }
+
+    // Runs one request line, returning the answer line without its newline
+    pub fn handle(&mut self, nes: &mut Nes, request: &str) -> String {
+        let response = match serde_json::from_str::<Value>(request) {
+            Ok(request) => self.run(nes, &request),
+            Err(e) => Err(format!("Bad JSON: {}", e)),
+        };
+        match response {
+            Ok(Value::Object(mut fields)) => {
+                fields.insert("ok".to_string(), Value::Bool(true));
+                Value::Object(fields).to_string()
+            }
+            Ok(_) => json!({ "ok": true }).to_string(),
+            Err(error) => json!({ "ok": false, "error": error }).to_string(),
+        }
+    }
+
+    fn run(&mut self, nes: &mut Nes, request: &Value) -> Result<Value, String> {
+        let field = |name: &str| request.get(name).ok_or_else(|| format!("Missing \"{}\"", name));
+        let number = |name: &str| field(name)?.as_u64().ok_or_else(|| format!("\"{}\" should be a number", name));
+        let cmd = field("cmd")?.as_str().ok_or("\"cmd\" should be a string")?;
+        match cmd {
+            "load_rom" => {
+                let path = field("path")?.as_str().ok_or("\"path\" should be a string")?;
+                let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+                let rom = Rom::from_bytes(data).map_err(|e| format!("{}: {}", path, e))?;
+                nes.load_rom(rom);
+                if !nes.is_on() {
+                    nes.on();
+                }
+                Ok(Value::Null)
+            }
+            "pause" => {
+                self.paused = true;
+                Ok(Value::Null)
+            }
+            "resume" => {
+                self.paused = false;
+                Ok(Value::Null)
+            }
+            "reset" => {
+                nes.reset();
+                Ok(Value::Null)
+            }
+            "frame_advance" => {
+                if !nes.is_on() {
+                    return Err("The console is off".to_string());
+                }
+                let frames = if request.get("frames").is_some() { number("frames")? } else { 1 };
+                if frames > MAX_FRAME_ADVANCE {
+                    return Err(format!("At most {} frames per advance", MAX_FRAME_ADVANCE));
+                }
+                for _ in 0..frames {
+                    nes.run_frame();
+                }
+                Ok(json!({ "frame": nes.cpu.bus.ppu.frame_count }))
+            }
+            "peek" => {
+                let addr = u16::try_from(number("addr")?).map_err(|_| "Address out of range")?;
+                let len = if request.get("len").is_some() { number("len")? } else { 1 };
+                let end = u16::try_from(len).ok().and_then(|len| addr.checked_add(len)).ok_or("Address out of range")?;
+                let data = (addr..end)
+                    .map(|addr| nes.peek(addr))
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or("Only RAM can be peeked")?;
+                Ok(json!({ "data": data }))
+            }
+            "poke" => {
+                let addr = number("addr")?;
+                let data = field("data")?.as_array().ok_or("\"data\" should be an array")?;
+                for (i, value) in data.iter().enumerate() {
+                    let value = value.as_u64().and_then(|v| u8::try_from(v).ok()).ok_or("\"data\" should hold bytes")?;
+                    let addr = addr.checked_add(i as u64).and_then(|addr| u16::try_from(addr).ok()).ok_or("Address out of range")?;
+                    nes.poke(addr, value);
+                }
+                Ok(Value::Null)
+            }
+            "input" => {
+                let port = number("port")? as usize;
+                let held = field("buttons")?.as_array().ok_or("\"buttons\" should be an array")?;
+                let mut pressed = [false; 8];
+                for name in held {
+                    let name = name.as_str().unwrap_or("");
+                    let i = BUTTONS.iter().position(|&(button, _)| button.eq_ignore_ascii_case(name))
+                        .ok_or_else(|| format!("Unknown button {}", name))?;
+                    pressed[i] = true;
+                }
+                for (&(_, button), pressed) in BUTTONS.iter().zip(pressed) {
+                    nes.set_port_button(port, button, pressed);
+                }
+                Ok(Value::Null)
+            }
+            "screenshot" => Ok(json!({ "width": 256, "height": 240, "rgb": base64(&nes.frame()) })),
+            "status" => Ok(json!({
+                "on": nes.is_on(),
+                "paused": self.paused,
+                "jammed": nes.is_jammed(),
+                "frame": nes.cpu.bus.ppu.frame_count,
+            })),
+            _ => Err(format!("Unknown command {}", cmd)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use crate::test_support::console;
+    use super::*;
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+    }
+
+    #[test]
+    fn runs_commands() {
+        let mut nes = console();
+        let mut server = ControlServer::bind("127.0.0.1:0").unwrap();
+
+        assert_eq!(server.handle(&mut nes, r#"{"cmd": "poke", "addr": 768, "data": [1, 2]}"#), r#"{"ok":true}"#);
+        assert_eq!(server.handle(&mut nes, r#"{"cmd": "peek", "addr": 2816, "len": 3}"#), r#"{"data":[1,2,0],"ok":true}"#);
+        assert!(server.handle(&mut nes, r#"{"cmd": "peek", "addr": 8192}"#).contains(r#""ok":false"#));
+        assert!(server.handle(&mut nes, r#"{"cmd": "peek", "addr": 18446744073709551615, "len": 2}"#).contains("out of range"));
+        assert!(server.handle(&mut nes, r#"{"cmd": "peek", "addr": 65535, "len": 2}"#).contains("out of range"));
+        assert!(server.handle(&mut nes, r#"{"cmd": "poke", "addr": 18446744073709551615, "data": [1, 2]}"#).contains("out of range"));
+
+        server.handle(&mut nes, r#"{"cmd": "pause"}"#);
+        assert!(server.is_paused());
+        assert_eq!(server.handle(&mut nes, r#"{"cmd": "frame_advance", "frames": 2}"#), r#"{"frame":2,"ok":true}"#);
+        assert!(server.handle(&mut nes, r#"{"cmd": "frame_advance", "frames": 18446744073709551615}"#).contains("At most 600"));
+
+        server.handle(&mut nes, r#"{"cmd": "input", "port": 1, "buttons": ["A", "start"]}"#);
+        assert_eq!(nes.cpu.bus.controller2.buttons(), 0b1001);
+        assert!(server.handle(&mut nes, r#"{"cmd": "input", "port": 0, "buttons": ["turbo"]}"#).contains("Unknown button"));
+        assert!(server.handle(&mut nes, "{").contains("Bad JSON"));
+    }
+
+    #[test]
+    fn answers_over_tcp() {
+        let mut nes = console();
+        let mut server = ControlServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"{\"cmd\": \"screenshot\"}\n{\"cmd\": \"status\"}\n").unwrap();
+            BufReader::new(stream).lines().take(2).collect::<io::Result<Vec<String>>>().unwrap()
+        });
+        while !client.is_finished() {
+            server.poll(&mut nes);
+        }
+
+        let lines = client.join().unwrap();
+        let screenshot: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(screenshot["rgb"].as_str().unwrap().len(), 256 * 240 * 4);
+        assert!(lines[1].contains(r#""paused":false"#));
+    }
+
+    #[test]
+    fn slow_and_runaway_clients_never_block() {
+        let mut nes = console();
+        let mut server = ControlServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        // Far more than the socket buffers hold, never read
+        let mut slow = TcpStream::connect(addr).unwrap();
+        slow.write_all("{\"cmd\": \"screenshot\"}\n".repeat(50).as_bytes()).unwrap();
+        let mut runaway = TcpStream::connect(addr).unwrap();
+        runaway.write_all(&vec![b' '; MAX_REQUEST + 1]).unwrap();
+        for _ in 0..20 {
+            server.poll(&mut nes);
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(server.clients.len(), 1);
+        assert!(!server.clients[0].unsent.is_empty());
+        drop(slow);
+    }
+}
\ No newline at end of file
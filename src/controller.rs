@@ -10,6 +10,26 @@ pub enum Button {
     Right = 0b1000_0000,
 }
 
+impl Button {
+    // In bit order
+    pub const ALL: [Button; 8] = [Button::A, Button::B, Button::Select, Button::Start, Button::Up, Button::Down, Button::Left, Button::Right];
+}
+
+// How the controllers and any other input devices share $4016 and $4017.
+// Both consoles strobe everything from $4016 writes and return controller
+// 1 and 2 on D0; the rest of the bits differ.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PortLayout {
+    // Two front ports, each device in one of them also driving D3 and D4
+    #[default]
+    Nes,
+    // Controllers hardwired, with the 15-pin expansion port on D1 of
+    // $4016 and D1-D4 of $4017
+    Famicom,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Controller {
     button_states: u8,
     strobe: bool,
@@ -43,7 +63,7 @@ impl Controller {
             self.cursor += 1;
         }
 
-        0x40 | v
+        v
     }
 
     pub fn set_button(&mut self, button: Button, pressed: bool) {
@@ -52,4 +72,62 @@ impl Controller {
             self.button_states |= button as u8;
         }
     }
+
+    pub fn buttons(&self) -> u8 {
+        self.button_states
+    }
+
+    // All eight buttons at once, as Button bits
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.button_states = buttons;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cpu::bus::Bus, family_keyboard::FamilyKeyboard, vaus::Vaus};
+    use super::*;
+
+    #[test]
+    fn ports_share_the_strobe_but_not_the_frame_counter() {
+        let mut bus = Bus::new();
+        bus.controller1.set_buttons(Button::A as u8);
+        bus.controller2.set_buttons(Button::B as u8);
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+        assert_eq!([bus.read(0x4016), bus.read(0x4017)], [0x41, 0x40]);
+
+        // A frame counter write with bit 0 set doesn't restrobe controller 2
+        bus.write(0x4017, 0x01);
+        assert_eq!([bus.read(0x4016), bus.read(0x4017)], [0x40, 0x41]);
+        for _ in 0..6 {
+            bus.read(0x4017);
+        }
+        assert_eq!(bus.read(0x4017), 0x41);
+    }
+
+    #[test]
+    fn famicom_expansion_port_fills_the_middle_bits() {
+        let mut bus = Bus::new();
+        bus.vaus = Some(Vaus::new());
+        bus.vaus.as_mut().unwrap().set_fire(true);
+        bus.controller2.set_buttons(Button::A as u8);
+        bus.write(0x4016, 1);
+        // The NES paddle replaces controller 2
+        assert_eq!([bus.read(0x4016), bus.read(0x4017) & 0x09], [0x40, 0x08]);
+
+        bus.port_layout = PortLayout::Famicom;
+        assert_eq!([bus.read(0x4016), bus.read(0x4017) & 0x19], [0x42, 0x01]);
+
+        bus.mic = true;
+        assert_eq!(bus.read(0x4016), 0x46);
+        bus.port_layout = PortLayout::Nes;
+        assert_eq!(bus.read(0x4016), 0x40);
+
+        bus.port_layout = PortLayout::Famicom;
+        bus.vaus = None;
+        bus.keyboard = Some(FamilyKeyboard::new());
+        bus.write(0x4016, 0x05);
+        assert_eq!(bus.read(0x4017), 0x41 | 0x1E);
+    }
 }
\ No newline at end of file
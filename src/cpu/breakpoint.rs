@@ -0,0 +1,271 @@
+use super::condition::Condition;
+
+// What the debugger can stop on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Breakpoint {
+    // The instruction at this address, before it runs
+    Execute(u16),
+    // Any CPU read or write of this address
+    Read(u16),
+    Write(u16),
+    // A PPU register, $2000-$2007 (mirrors match too) or OAM DMA at $4014
+    PpuRead(u16),
+    PpuWrite(u16),
+    // An interrupt being taken, as the CPU pushes the return address
+    Nmi,
+    Irq,
+}
+
+// The access or interrupt that matched
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakEvent {
+    Execute { pc: u16 },
+    Read { addr: u16, value: u8 },
+    Write { addr: u16, value: u8 },
+    PpuRead { addr: u16, value: u8 },
+    PpuWrite { addr: u16, value: u8 },
+    Nmi,
+    Irq,
+}
+
+// CPU and PPU state as the triggering instruction or interrupt began
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuContext {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub p: u8,
+    pub cycle: u64,
+    pub scanline: usize,
+    pub dot: usize,
+    pub frame: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreakHit {
+    pub breakpoint: Breakpoint,
+    pub event: BreakEvent,
+    pub context: CpuContext,
+}
+
+// $2008-$3FFF mirror the eight PPU registers
+fn register(addr: u16) -> u16 {
+    match addr {
+        0x2000..=0x3FFF => addr & 0x2007,
+        _ => addr,
+    }
+}
+
+// Armed breakpoints. The bus reports memory accesses and the CPU
+// interrupts; matches are kept until the CPU attaches its context at the end
+// of the instruction and the first one whose condition holds is the hit.
+#[derive(Default)]
+pub struct Breakpoints {
+    list: Vec<Breakpoint>,
+    conditions: Vec<(Breakpoint, Condition)>,
+    pending: Vec<(Breakpoint, BreakEvent)>,
+    // An execute breakpoint stops before its instruction, so resuming has to
+    // let that instruction through once
+    resume_pc: Option<u16>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Breakpoints::default()
+    }
+
+    pub fn add(&mut self, breakpoint: Breakpoint) {
+        if !self.list.contains(&breakpoint) {
+            self.list.push(breakpoint);
+        }
+    }
+
+    // Only stops when `condition` holds, see Condition
+    pub fn add_conditional(&mut self, breakpoint: Breakpoint, condition: &str) -> Result<(), String> {
+        let condition = Condition::parse(condition)?;
+        self.add(breakpoint);
+        self.conditions.retain(|(b, _)| *b != breakpoint);
+        self.conditions.push((breakpoint, condition));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, breakpoint: Breakpoint) {
+        self.list.retain(|&b| b != breakpoint);
+        self.conditions.retain(|(b, _)| *b != breakpoint);
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.conditions.clear();
+        self.pending.clear();
+    }
+
+    pub fn condition(&self, breakpoint: Breakpoint) -> Option<&Condition> {
+        self.conditions.iter().find(|(b, _)| *b == breakpoint).map(|(_, condition)| condition)
+    }
+
+    pub fn list(&self) -> &[Breakpoint] {
+        &self.list
+    }
+
+    pub fn is_armed(&self) -> bool {
+        !self.list.is_empty()
+    }
+
+    // Every breakpoint the access matches is kept, so each one's condition
+    // gets a say
+    pub(crate) fn access(&mut self, addr: u16, value: u8, write: bool) {
+        let ppu = matches!(addr, 0x2000..0x4000 | 0x4014);
+        for &breakpoint in &self.list {
+            let event = match breakpoint {
+                Breakpoint::Read(target) if !write && target == addr => BreakEvent::Read { addr, value },
+                Breakpoint::Write(target) if write && target == addr => BreakEvent::Write { addr, value },
+                Breakpoint::PpuRead(target) if ppu && !write && register(target) == register(addr) => {
+                    BreakEvent::PpuRead { addr: register(addr), value }
+                }
+                Breakpoint::PpuWrite(target) if ppu && write && register(target) == register(addr) => {
+                    BreakEvent::PpuWrite { addr: register(addr), value }
+                }
+                _ => continue,
+            };
+            self.pending.push((breakpoint, event));
+        }
+    }
+
+    // Checked before an instruction runs. A hit means it shouldn't.
+    pub(crate) fn execute(&mut self, context: CpuContext) -> Option<BreakHit> {
+        let pc = context.pc;
+        if self.resume_pc.take() == Some(pc) || !self.list.contains(&Breakpoint::Execute(pc)) {
+            return None;
+        }
+        self.pending.push((Breakpoint::Execute(pc), BreakEvent::Execute { pc }));
+        let hit = self.take_hit(context);
+        if hit.is_some() {
+            self.resume_pc = Some(pc);
+        }
+        hit
+    }
+
+    pub(crate) fn interrupt(&mut self, nmi: bool) {
+        let (breakpoint, event) = if nmi { (Breakpoint::Nmi, BreakEvent::Nmi) } else { (Breakpoint::Irq, BreakEvent::Irq) };
+        if self.list.contains(&breakpoint) {
+            self.pending.push((breakpoint, event));
+        }
+    }
+
+    pub(crate) fn take_hit(&mut self, context: CpuContext) -> Option<BreakHit> {
+        let hit = self.pending.iter()
+            .find(|(breakpoint, event)| self.condition(*breakpoint).is_none_or(|condition| condition.is_met(&context, event)))
+            .map(|&(breakpoint, event)| BreakHit { breakpoint, event, context });
+        self.pending.clear();
+        hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::console, Nes};
+    use super::*;
+
+    // Steps until a breakpoint fires, giving up after a few frames
+    fn run_to_break(nes: &mut Nes) -> BreakHit {
+        for _ in 0..200_000 {
+            if let Some(hit) = nes.step().breakpoint {
+                return hit;
+            }
+        }
+        panic!("no breakpoint hit");
+    }
+
+    #[test]
+    fn mirrors_match_ppu_registers() {
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.add(Breakpoint::PpuRead(0x2002));
+        breakpoints.add(Breakpoint::PpuWrite(0x4014));
+
+        breakpoints.access(0x2002, 0x80, true);
+        breakpoints.access(0x4014, 0x02, false);
+        assert!(breakpoints.pending.is_empty());
+
+        breakpoints.access(0x3FFA, 0x80, false);
+        breakpoints.access(0x4014, 0x02, true);
+        assert_eq!(breakpoints.pending, [
+            (Breakpoint::PpuRead(0x2002), BreakEvent::PpuRead { addr: 0x2002, value: 0x80 }),
+            (Breakpoint::PpuWrite(0x4014), BreakEvent::PpuWrite { addr: 0x4014, value: 0x02 }),
+        ]);
+    }
+
+    #[test]
+    fn breaks_on_register_read_with_cpu_context() {
+        let mut nes = console();
+        // $0200: LDA #$5A; LDX $3FFA; JMP $0202
+        for (i, byte) in [0xA9, 0x5A, 0xAE, 0xFA, 0x3F, 0x4C, 0x02, 0x02].iter().enumerate() {
+            nes.cpu.bus.write(0x0200 + i as u16, *byte);
+        }
+        nes.set_start(0x0200);
+        nes.add_breakpoint(Breakpoint::PpuRead(0x2002));
+
+        let hit = run_to_break(&mut nes);
+        assert_eq!(hit.breakpoint, Breakpoint::PpuRead(0x2002));
+        assert!(matches!(hit.event, BreakEvent::PpuRead { addr: 0x2002, .. }));
+        assert_eq!((hit.context.pc, hit.context.a), (0x0202, 0x5A));
+    }
+
+    #[test]
+    fn breaks_on_nmi() {
+        let mut nes = console();
+        nes.add_breakpoint(Breakpoint::Nmi);
+        // Past the warm-up, then enable NMI on vblank
+        while nes.cpu.bus.cycles < 30_000 {
+            nes.step();
+        }
+        nes.cpu.bus.write(0x2000, 0x80);
+
+        let hit = run_to_break(&mut nes);
+        assert_eq!(hit.event, BreakEvent::Nmi);
+        assert_eq!(hit.context.scanline, 241);
+        nes.remove_breakpoint(Breakpoint::Nmi);
+        assert!(nes.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn conditions_filter_hits() {
+        let mut nes = console();
+        // $0200: INX; JMP $0200
+        for (i, byte) in [0xE8, 0x4C, 0x00, 0x02].iter().enumerate() {
+            nes.cpu.bus.write(0x0200 + i as u16, *byte);
+        }
+        nes.set_start(0x0200);
+        assert!(nes.add_conditional_breakpoint(Breakpoint::Execute(0x0200), "x ==").is_err());
+        nes.add_conditional_breakpoint(Breakpoint::Execute(0x0200), "X == 5").unwrap();
+
+        // Stops before the INX runs, then lets it through on the next step
+        let hit = run_to_break(&mut nes);
+        assert_eq!(hit.event, BreakEvent::Execute { pc: 0x0200 });
+        assert_eq!((nes.cpu.pc, nes.cpu.x), (0x0200, 5));
+        nes.step();
+        assert_eq!(nes.cpu.x, 6);
+        assert_eq!(run_to_break(&mut nes).context.x, 5);
+    }
+
+    #[test]
+    fn conditions_filter_memory_accesses() {
+        let mut nes = console();
+        // $0200: INX; STX $10; LDA $10; JMP $0200
+        for (i, byte) in [0xE8, 0x86, 0x10, 0xA5, 0x10, 0x4C, 0x00, 0x02].iter().enumerate() {
+            nes.cpu.bus.write(0x0200 + i as u16, *byte);
+        }
+        nes.set_start(0x0200);
+        nes.add_conditional_breakpoint(Breakpoint::Write(0x0010), "value == 3").unwrap();
+        nes.add_conditional_breakpoint(Breakpoint::Read(0x0010), "value == 7 && addr == 0x10").unwrap();
+
+        let hit = run_to_break(&mut nes);
+        assert_eq!(hit.event, BreakEvent::Write { addr: 0x0010, value: 3 });
+        assert_eq!(hit.context.pc, 0x0201);
+        let hit = run_to_break(&mut nes);
+        assert_eq!(hit.event, BreakEvent::Read { addr: 0x0010, value: 7 });
+        assert_eq!(hit.context.pc, 0x0203);
+    }
+}
\ No newline at end of file
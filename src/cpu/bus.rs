@@ -1,10 +1,34 @@
-use crate::{controller::Controller, memory::Memory, ppu::Ppu};
+use crate::{apu::Apu, cartridge::Cartridge, cheat::GameGenie, cpu::{breakpoint::Breakpoints, bus_trace::{BusAccess, BusOrigin, BusTrace}, irq::{IrqLine, IrqSource}}, config::RamPattern, controller::{Controller, PortLayout}, family_keyboard::FamilyKeyboard, memory::Memory, ppu::Ppu, rng::Rng, vaus::Vaus, vs_system::VsSystem};
 
 const CPU_RAM_SIZE: usize = 0x800; //2KB
 
+// Bits of $4016 and $4017 nothing drives, which keep the $40 left on the
+// data bus by the high byte of the address
+const PORT_OPEN_BUS: u8 = 0x40;
+
+// What the CPU sees of the system it's in: memory to read and write, and
+// everything else to run for the cycles each instruction takes
+pub trait BusInterface {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+    fn tick(&mut self, cycles: u8);
+
+    // A read the CPU makes on a cycle it has nothing to fetch, and throws
+    // away
+    fn dummy_read(&mut self, addr: u16) -> u8 {
+        self.read(addr)
+    }
+
+    // The console's RESET line, pulled as the CPU starts its reset sequence
+    fn reset(&mut self) {}
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bus {
     ram: Memory,
     pub ppu: Ppu,
+    pub apu: Apu,
+    pub cartridge: Cartridge,
 
     pub cycles: u64,
     pub reset: bool,
@@ -12,6 +36,38 @@ pub struct Bus {
     pub dma_transfer: (bool, u8),
     pub controller1: Controller,
     pub controller2: Controller,
+    pub port_layout: PortLayout,
+    // The Famicom's second controller has a microphone in place of Select
+    // and Start, reading as loud or not on D2 of $4016
+    pub mic: bool,
+    pub vs_system: Option<VsSystem>,
+    // Plugged into the second port in place of controller 2
+    pub vaus: Option<Vaus>,
+    // Famicom expansion port, read alongside controller 2
+    pub keyboard: Option<FamilyKeyboard>,
+    pub irq: IrqLine,
+    pub cheats: Vec<GameGenie>,
+
+    // Address of the most recent access if it was a read, so a DMC fetch
+    // landing on it can repeat it
+    pub last_read: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub breakpoints: Breakpoints,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    trace: Option<BusTrace>,
+    // Who the next accesses are for, for the trace
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) origin: BusOrigin,
+    // Dot within the last tick that the PPU raised an NMI edge on, three
+    // to a CPU cycle
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) nmi_dot: Option<u16>,
+    // Cycles of the instruction being executed, 0 between instructions
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) instruction_cycles: u8,
+    // Dots of it the PPU has already been run for
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ppu_dots_ahead: u16,
 }
 
 impl Bus {
@@ -19,16 +75,161 @@ impl Bus {
         Bus {
             ram: Memory::new(vec![0; CPU_RAM_SIZE]),
             ppu: Ppu::new(),
+            apu: Apu::new(),
+            cartridge: Cartridge::empty(),
             cycles: 0,
             reset: false,
 
             dma_transfer: (false, 0),
             controller1: Controller::new(),
             controller2: Controller::new(),
+            port_layout: PortLayout::Nes,
+            mic: false,
+            vs_system: None,
+            vaus: None,
+            keyboard: None,
+            irq: IrqLine::new(),
+            cheats: Vec::new(),
+
+            last_read: None,
+            breakpoints: Breakpoints::new(),
+            trace: None,
+            origin: BusOrigin::Cpu,
+            nmi_dot: None,
+            instruction_cycles: 0,
+            ppu_dots_ahead: 0,
+        }
+    }
+
+    pub fn fill_ram(&mut self, pattern: RamPattern, rng: &mut Rng) {
+        for addr in 0..CPU_RAM_SIZE as u16 {
+            let value = match pattern {
+                RamPattern::Zero => 0x00,
+                RamPattern::Ones => 0xFF,
+                RamPattern::Alternating => if addr & 4 == 0 { 0x00 } else { 0xFF },
+                RamPattern::Random => rng.next_u8()
+            };
+            self.ram.write(addr, value);
         }
     }
 
+    pub fn ram(&self) -> &[u8] {
+        self.ram.as_slice()
+    }
+
+    // Refreshes the IRQ line from the devices that drive it
+    pub fn update_irq(&mut self) {
+        self.irq.set(IrqSource::Dmc, self.apu.dmc.irq_pending);
+        self.irq.set(IrqSource::FrameCounter, self.apu.frame_irq());
+        self.irq.set(IrqSource::Mapper, self.cartridge.irq_pending());
+    }
+
+    // Devices on the CPU bus that see the console's RESET line
+    pub fn reset_devices(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+        self.cartridge.reset();
+        self.dma_transfer = (false, 0);
+        self.update_irq();
+    }
+
+    pub fn step_ppu(&mut self) {
+        self.ppu.step(&mut self.cartridge);
+        self.drain_ppu_accesses();
+    }
+
+    // Dot `dot` of an instruction's cycles
+    fn step_ppu_dot(&mut self, dot: u16) {
+        self.step_ppu();
+        if self.ppu.trigger_nmi {
+            self.ppu.trigger_nmi = false;
+            self.nmi_dot.get_or_insert(dot);
+        }
+    }
+
+    // The PPU runs behind the CPU, catching up after each instruction. A
+    // register write brings it up to the instruction's last cycle first, so
+    // mask, scroll and palette changes land on the dot they're made on.
+    fn catch_up_ppu(&mut self) {
+        let dots = u16::from(self.instruction_cycles.saturating_sub(1)) * 3;
+        while self.ppu_dots_ahead < dots {
+            self.step_ppu_dot(self.ppu_dots_ahead);
+            self.ppu_dots_ahead += 1;
+        }
+    }
+
+    pub fn set_trace(&mut self, trace: Option<BusTrace>) {
+        self.ppu.access_log = trace.as_ref().filter(|trace| trace.traces_ppu()).map(|_| Vec::new());
+        self.trace = trace;
+    }
+
+    pub fn take_trace(&mut self) -> Option<BusTrace> {
+        self.ppu.access_log = None;
+        self.trace.take()
+    }
+
+    pub fn trace(&self) -> Option<&BusTrace> {
+        self.trace.as_ref()
+    }
+
+    pub(crate) fn trace_access(&mut self, addr: u16, value: u8, write: bool) {
+        if let Some(trace) = &mut self.trace {
+            trace.record(BusAccess {
+                cycle: self.cycles,
+                scanline: self.ppu.scanline as u16,
+                dot: self.ppu.cycle as u16,
+                addr,
+                value,
+                write,
+                origin: self.origin,
+            });
+        }
+    }
+
+    // The PPU logs its own accesses, as it has no way to the trace
+    fn drain_ppu_accesses(&mut self) {
+        let (Some(log), Some(trace)) = (&mut self.ppu.access_log, &mut self.trace) else {
+            return;
+        };
+        for mut access in log.drain(..) {
+            access.cycle = self.cycles;
+            trace.record(access);
+        }
+    }
+
+    pub fn end_tile_change_frame(&mut self) {
+        if let Some(changes) = &mut self.ppu.tile_changes {
+            changes.end_frame(&self.cartridge);
+        }
+    }
+
+    // PPU address space as the PPU sees it, for debugging views. Kept out
+    // of the bus trace.
+    pub fn ppu_read(&mut self, addr: u16) -> u8 {
+        let log = self.ppu.access_log.take();
+        let data = self.ppu.read(&mut self.cartridge, addr);
+        self.ppu.access_log = log;
+        data
+    }
+
+    // Like ppu_read, but leaves mapper state alone
+    pub fn ppu_peek(&self, addr: u16) -> u8 {
+        self.ppu.peek(&self.cartridge, addr)
+    }
+
     pub fn read(&mut self, addr: u16) -> u8 {
+        self.last_read = Some(addr);
+        let data = self.read_mapped(addr);
+        if self.trace.is_some() {
+            self.trace_access(addr, data, false);
+        }
+        if self.breakpoints.is_armed() {
+            self.breakpoints.access(addr, data, false);
+        }
+        data
+    }
+
+    fn read_mapped(&mut self, addr: u16) -> u8 {
         match addr {
             0x0000..0x2000 => {
                 self.ram.read(addr & 0x7FF)
@@ -38,28 +239,40 @@ impl Bus {
                 match m_addr {
                     0x2002 => self.ppu.read_status(),
                     0x2004 => self.ppu.read_oam(),
-                    0x2007 => self.ppu.read_data(),
+                    0x2007 => self.ppu.read_data(&mut self.cartridge),
                     _ => self.ppu.open_bus
                 }
             }
-            0x4016 => self.controller1.read(),
-            0x4017 => self.controller2.read(),
+            0x4016 => self.read_4016(),
+            // Writes here go to the APU's frame counter instead
+            0x4017 => self.read_4017(),
+            0x4015 => self.apu.read_status(),
             0x4000..0x4020 => { //APU / I/O
                 0
             }
             0x4020..=0xFFFF => {
-                self.ppu.rom.mapper.read(addr)
+                let data = self.cartridge.cpu_read(addr);
+                self.cheats.iter().fold(data, |data, cheat| cheat.apply(addr, data))
             }
         }
     }
 
     pub fn write(&mut self, addr: u16, data: u8) {
+        self.last_read = None;
+        if self.trace.is_some() {
+            self.trace_access(addr, data, true);
+        }
+        if self.breakpoints.is_armed() {
+            self.breakpoints.access(addr, data, true);
+        }
         match addr {
             0x0000..0x2000 => {
                 self.ram.write(addr & 0x7FF, data);
             }
             0x2000..0x4000 => {
+                self.catch_up_ppu();
                 let m_addr = addr & 0x2007;
+                log::trace!(target: "ppu", "${:04X} <- ${:02X} at {},{}", m_addr, data, self.ppu.scanline, self.ppu.cycle);
                 match m_addr {
                     0x2000 => if !self.ignore_ppu_writes() { self.ppu.write_ctrl(data) },
                     0x2001 => if !self.ignore_ppu_writes() { self.ppu.write_mask(data) },
@@ -67,30 +280,132 @@ impl Bus {
                     0x2004 => self.ppu.write_oamdata(data),
                     0x2005 => if !self.ignore_ppu_writes() { self.ppu.write_scroll(data) },
                     0x2006 => if !self.ignore_ppu_writes() { self.ppu.write_addr(data) },
-                    0x2007 => self.ppu.write_data(data),
+                    0x2007 => self.ppu.write_data(&mut self.cartridge, data),
                     _ => {}
                 }
                 self.ppu.open_bus = data;
             }
             0x4016 => {
+                log::trace!(target: "bus", "$4016 <- ${:02X}", data);
                 self.controller1.write(data);
                 self.controller2.write(data);
+                if let Some(vaus) = &mut self.vaus {
+                    vaus.write(data);
+                }
+                if let Some(keyboard) = &mut self.keyboard {
+                    keyboard.write(data);
+                }
+                self.cartridge.out_latch_write(data);
             }
             0x4000..0x4020 => { //APU / I/O
                 if addr == 0x4014 { //DMA
+                    log::debug!(target: "bus", "OAM DMA from ${:02X}00 at cycle {}", data, self.cycles);
                     self.dma_transfer = (true, data);
                     return;
                 }
+                log::trace!(target: "apu", "${:04X} <- ${:02X}", addr, data);
+                self.apu.write(addr, data);
             }
             0x4020..=0xFFFF => {
-                self.ppu.rom.mapper.write(addr, data);
+                self.cartridge.cpu_write(addr, data, self.cycles);
                 //RAM write
             }
         }
     }
 
+    // Controller 1 on D0. A VS System drives the rest with its coin slots
+    // and DIP switches; a Famicom has its expansion port on D1 and the
+    // microphone on D2.
+    fn read_4016(&mut self) -> u8 {
+        let controller = self.controller1.read();
+        if let Some(vs) = &self.vs_system {
+            return controller | vs.read_4016();
+        }
+        if self.port_layout == PortLayout::Nes {
+            return PORT_OPEN_BUS | controller;
+        }
+        let fire = self.vaus.as_ref().is_some_and(|vaus| vaus.fire());
+        PORT_OPEN_BUS | controller | (fire as u8) << 1 | (self.mic as u8) << 2
+    }
+
+    // Whatever is in the NES's second port, or on a Famicom controller 2
+    // with the expansion port's D1-D4 alongside
+    fn read_4017(&mut self) -> u8 {
+        if let Some(vs) = &self.vs_system {
+            return self.controller2.read() | vs.read_4017();
+        }
+        let data = match self.port_layout {
+            PortLayout::Nes => match &mut self.vaus {
+                Some(vaus) => vaus.read(),
+                None => self.controller2.read()
+            },
+            PortLayout::Famicom => {
+                let mut data = self.controller2.read();
+                // The paddle's serial data moves from D4 to D1
+                if let Some(vaus) = &mut self.vaus {
+                    data |= (vaus.read() >> 3) & 0x02;
+                }
+                if let Some(keyboard) = &self.keyboard {
+                    data |= keyboard.read();
+                }
+                data
+            }
+        };
+        PORT_OPEN_BUS | data
+    }
+
     //Wil
     fn ignore_ppu_writes(&self) -> bool {
         self.reset && self.cycles < 29658
     }
+
+    // Breakpoints and the trace stay with the frontend; the catch-up
+    // fields are all idle between instructions, where states are made
+    pub(crate) fn keep_attachments(&mut self, old: &mut Bus) {
+        self.breakpoints = std::mem::take(&mut old.breakpoints);
+        self.trace = old.trace.take();
+        self.ppu.keep_attachments(&mut old.ppu);
+        self.apu.keep_attachments(&mut old.apu);
+        self.cartridge.keep_attachments(&mut old.cartridge);
+    }
+}
+
+impl BusInterface for Bus {
+    fn read(&mut self, addr: u16) -> u8 {
+        Bus::read(self, addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        Bus::write(self, addr, data)
+    }
+
+    // Side effects and all, but the byte isn't code or data the game uses,
+    // so it stays out of the code/data log
+    fn dummy_read(&mut self, addr: u16) -> u8 {
+        let cdl = self.cartridge.stop_cdl();
+        let data = Bus::read(self, addr);
+        if cdl.is_some() {
+            self.cartridge.start_cdl(cdl);
+        }
+        data
+    }
+
+    // Three PPU dots per cycle, less any a register write already ran, then
+    // the IRQ line as the cycles left it
+    fn tick(&mut self, cycles: u8) {
+        for dot in self.ppu_dots_ahead..u16::from(cycles) * 3 {
+            self.step_ppu_dot(dot);
+        }
+        self.ppu_dots_ahead = 0;
+        self.cycles += u64::from(cycles);
+        self.cartridge.clock(cycles);
+        self.update_irq();
+    }
+
+    fn reset(&mut self) {
+        self.reset_devices();
+        self.reset = true;
+        self.cycles = 0;
+        self.nmi_dot = None;
+    }
 }
\ No newline at end of file
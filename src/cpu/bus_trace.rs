@@ -0,0 +1,202 @@
+use std::{collections::VecDeque, fmt, ops::RangeInclusive};
+
+// What drove the bus for an access
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BusOrigin {
+    #[default]
+    Cpu,
+    // Sprite DMA copying a page to $2004
+    OamDma,
+    // The APU fetching a DMC sample byte
+    DmcDma,
+    // The PPU on its own bus: pattern, nametable and palette fetches, and
+    // the $2007 accesses the CPU asks for
+    Ppu,
+}
+
+// One read or write
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BusAccess {
+    // CPU cycles: exact for DMA, where the instruction began for the CPU
+    pub cycle: u64,
+    // Where the PPU was when it happened
+    pub scanline: u16,
+    pub dot: u16,
+    // A CPU address, or a PPU one for BusOrigin::Ppu
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+    pub origin: BusOrigin,
+}
+
+impl fmt::Display for BusAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let origin = match self.origin {
+            BusOrigin::Cpu => "CPU",
+            BusOrigin::OamDma => "OAMDMA",
+            BusOrigin::DmcDma => "DMCDMA",
+            BusOrigin::Ppu => "PPU",
+        };
+        write!(f, "CYC:{} PPU:{:3},{:3} {:<6} {} ${:04X} = ${:02X}",
+            self.cycle, self.scanline, self.dot, origin, if self.write { "W" } else { "R" }, self.addr, self.value)
+    }
+}
+
+// The last `capacity` bus accesses that pass the filters, for following
+// mapper register sequences and DMA interleaving. CPU bus accesses are all
+// kept unless CPU ranges are given; the PPU fetches a byte every other dot,
+// so its accesses are only kept inside the PPU ranges given.
+pub struct BusTrace {
+    entries: VecDeque<BusAccess>,
+    capacity: usize,
+    cpu_ranges: Vec<RangeInclusive<u16>>,
+    ppu_ranges: Vec<RangeInclusive<u16>>,
+}
+
+impl BusTrace {
+    pub fn new(capacity: usize) -> Self {
+        BusTrace { entries: VecDeque::with_capacity(capacity), capacity, cpu_ranges: Vec::new(), ppu_ranges: Vec::new() }
+    }
+
+    // Keeps CPU bus accesses in `range`, along with any other ranges given
+    pub fn cpu_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.cpu_ranges.push(range);
+        self
+    }
+
+    // Starts keeping PPU bus accesses in `range`
+    pub fn ppu_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.ppu_ranges.push(range);
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Oldest first
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &BusAccess> + '_ {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn traces_ppu(&self) -> bool {
+        !self.ppu_ranges.is_empty()
+    }
+
+    pub(crate) fn record(&mut self, access: BusAccess) {
+        let keep = match access.origin {
+            BusOrigin::Ppu => self.ppu_ranges.iter().any(|range| range.contains(&access.addr)),
+            _ => self.cpu_ranges.is_empty() || self.cpu_ranges.iter().any(|range| range.contains(&access.addr)),
+        };
+        if !keep || self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(access);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::console, Nes};
+    use super::*;
+
+    // Past the PPU's warm up, running `program` from $0200
+    fn nes_running(program: &[u8]) -> Nes {
+        let mut nes = console();
+        nes.run_frame();
+        nes.run_frame();
+        for (i, byte) in program.iter().enumerate() {
+            nes.cpu.bus.write(0x0200 + i as u16, *byte);
+        }
+        nes.set_start(0x0200);
+        nes
+    }
+
+    #[test]
+    fn keeps_accesses_in_range() {
+        // LDA #$42; STA $6000; LDA $6000
+        let mut nes = nes_running(&[0xA9, 0x42, 0x8D, 0x00, 0x60, 0xAD, 0x00, 0x60]);
+        nes.trace_bus(BusTrace::new(8).cpu_range(0x6000..=0x7FFF));
+        for _ in 0..3 {
+            nes.step();
+        }
+
+        let trace = nes.stop_bus_trace().unwrap();
+        let accesses: Vec<_> = trace.entries().map(|access| (access.addr, access.value, access.write, access.origin)).collect();
+        assert_eq!(accesses, [(0x6000, 0x42, true, BusOrigin::Cpu), (0x6000, 0x42, false, BusOrigin::Cpu)]);
+        assert!(trace.entries().next().unwrap().to_string().ends_with("CPU    W $6000 = $42"));
+        assert!(nes.bus_trace().is_none());
+    }
+
+    #[test]
+    fn shows_dummy_reads_and_rmw_double_writes() {
+        // LDX #$03; LDA $3FFF,X; INC $6000
+        let mut nes = nes_running(&[0xA2, 0x03, 0xBD, 0xFF, 0x3F, 0xEE, 0x00, 0x60]);
+        let old = nes.peek(0x6000).unwrap();
+        nes.trace_bus(BusTrace::new(16).cpu_range(0x3F00..=0x40FF).cpu_range(0x6000..=0x6000));
+        for _ in 0..3 {
+            nes.step();
+        }
+
+        let accesses: Vec<_> = nes.bus_trace().unwrap().entries().map(|access| (access.addr, access.write)).collect();
+        // $3F02 before the carry reaches the high byte, a mirror of $2002
+        assert_eq!(accesses, [(0x3F02, false), (0x4002, false), (0x6000, false), (0x6000, true), (0x6000, true)]);
+        let written: Vec<_> = nes.bus_trace().unwrap().entries().filter(|access| access.write).map(|access| access.value).collect();
+        assert_eq!(written, [old, old.wrapping_add(1)]);
+    }
+
+    #[test]
+    fn interleaves_dma_reads_and_writes() {
+        // LDA #$03; STA $4014
+        let mut nes = nes_running(&[0xA9, 0x03, 0x8D, 0x14, 0x40]);
+        nes.trace_bus(BusTrace::new(1024).cpu_range(0x0300..=0x03FF).cpu_range(0x2004..=0x2004));
+        for _ in 0..3 {
+            nes.step();
+        }
+
+        let trace = nes.bus_trace().unwrap();
+        assert_eq!(trace.len(), 512);
+        for (i, pair) in trace.entries().collect::<Vec<_>>().chunks(2).enumerate() {
+            assert_eq!((pair[0].addr, pair[0].write, pair[0].origin), (0x0300 + i as u16, false, BusOrigin::OamDma));
+            assert_eq!((pair[1].addr, pair[1].write, pair[1].origin), (0x2004, true, BusOrigin::OamDma));
+            assert_eq!(pair[1].value, pair[0].value);
+            assert_eq!(pair[1].cycle, pair[0].cycle + 1);
+        }
+    }
+
+    #[test]
+    fn ppu_accesses_need_a_range() {
+        // Palette entry 0 = $21 through $2006/$2007
+        let program = [0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, 0xA9, 0x21, 0x8D, 0x07, 0x20];
+        let mut nes = nes_running(&program);
+        nes.trace_bus(BusTrace::new(64).cpu_range(0x2007..=0x2007));
+        for _ in 0..7 {
+            nes.step();
+        }
+        assert_eq!(nes.bus_trace().unwrap().len(), 1);
+
+        let mut nes = nes_running(&program);
+        nes.trace_bus(BusTrace::new(64).cpu_range(0x2007..=0x2007).ppu_range(0x3F00..=0x3FFF));
+        for _ in 0..7 {
+            nes.step();
+        }
+        nes.cpu.bus.ppu_read(0x3F00);
+        let origins: Vec<_> = nes.bus_trace().unwrap().entries().map(|access| (access.addr, access.origin)).collect();
+        assert_eq!(origins, [(0x2007, BusOrigin::Cpu), (0x3F00, BusOrigin::Ppu)]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,321 @@
+use std::fmt;
+
+use super::breakpoint::{BreakEvent, CpuContext};
+
+// State a condition can refer to, by name
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variable {
+    A,
+    X,
+    Y,
+    Sp,
+    P,
+    Pc,
+    Cycle,
+    Scanline,
+    Dot,
+    Frame,
+    // The byte read or written, and the address it went to
+    Value,
+    Addr,
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Variable> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "a" => Variable::A,
+            "x" => Variable::X,
+            "y" => Variable::Y,
+            "sp" | "s" => Variable::Sp,
+            "p" => Variable::P,
+            "pc" => Variable::Pc,
+            "cycle" => Variable::Cycle,
+            "scanline" => Variable::Scanline,
+            "dot" => Variable::Dot,
+            "frame" => Variable::Frame,
+            "value" => Variable::Value,
+            "addr" => Variable::Addr,
+            _ => return None,
+        })
+    }
+
+    fn get(self, context: &CpuContext, event: &BreakEvent) -> i64 {
+        match self {
+            Variable::A => context.a as i64,
+            Variable::X => context.x as i64,
+            Variable::Y => context.y as i64,
+            Variable::Sp => context.sp as i64,
+            Variable::P => context.p as i64,
+            Variable::Pc => context.pc as i64,
+            Variable::Cycle => context.cycle as i64,
+            Variable::Scanline => context.scanline as i64,
+            Variable::Dot => context.dot as i64,
+            Variable::Frame => context.frame as i64,
+            Variable::Value => match *event {
+                BreakEvent::Read { value, .. } | BreakEvent::Write { value, .. }
+                | BreakEvent::PpuRead { value, .. } | BreakEvent::PpuWrite { value, .. } => value as i64,
+                _ => 0,
+            },
+            Variable::Addr => match *event {
+                BreakEvent::Read { addr, .. } | BreakEvent::Write { addr, .. }
+                | BreakEvent::PpuRead { addr, .. } | BreakEvent::PpuWrite { addr, .. } => addr as i64,
+                BreakEvent::Execute { pc } => pc as i64,
+                _ => 0,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+impl Op {
+    fn apply(self, lhs: i64, rhs: i64) -> i64 {
+        match self {
+            Op::Or => (lhs != 0 || rhs != 0) as i64,
+            Op::And => (lhs != 0 && rhs != 0) as i64,
+            Op::Eq => (lhs == rhs) as i64,
+            Op::Ne => (lhs != rhs) as i64,
+            Op::Lt => (lhs < rhs) as i64,
+            Op::Le => (lhs <= rhs) as i64,
+            Op::Gt => (lhs > rhs) as i64,
+            Op::Ge => (lhs >= rhs) as i64,
+            Op::BitOr => lhs | rhs,
+            Op::BitXor => lhs ^ rhs,
+            Op::BitAnd => lhs & rhs,
+            Op::Add => lhs.wrapping_add(rhs),
+            Op::Sub => lhs.wrapping_sub(rhs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(i64),
+    Variable(Variable),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn evaluate(&self, context: &CpuContext, event: &BreakEvent) -> i64 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Variable(variable) => variable.get(context, event),
+            Expr::Not(expr) => (expr.evaluate(context, event) == 0) as i64,
+            Expr::Binary(op, lhs, rhs) => op.apply(lhs.evaluate(context, event), rhs.evaluate(context, event)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(Op),
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = source.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() || c == '$' {
+            let (radix, start) = match (c, next) {
+                ('$', _) => (16, i + 1),
+                ('0', Some('x' | 'X')) => (16, i + 2),
+                _ => (10, i),
+            };
+            let mut end = start;
+            while end < chars.len() && chars[end].is_digit(radix) {
+                end += 1;
+            }
+            let digits: String = chars[start..end].iter().collect();
+            let value = i64::from_str_radix(&digits, radix).map_err(|_| format!("Bad number at {}", i))?;
+            tokens.push(Token::Number(value));
+            i = end;
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = i;
+            while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_') {
+                end += 1;
+            }
+            tokens.push(Token::Name(chars[i..end].iter().collect()));
+            i = end;
+            continue;
+        }
+        let (token, len) = match (c, next) {
+            ('|', Some('|')) => (Token::Op(Op::Or), 2),
+            ('&', Some('&')) => (Token::Op(Op::And), 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('|', _) => (Token::Op(Op::BitOr), 1),
+            ('^', _) => (Token::Op(Op::BitXor), 1),
+            ('&', _) => (Token::Op(Op::BitAnd), 1),
+            ('+', _) => (Token::Op(Op::Add), 1),
+            ('-', _) => (Token::Op(Op::Sub), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            _ => return Err(format!("Unexpected '{}' at {}", c, i)),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+// Binary operators from loosest to tightest binding
+const PRECEDENCE: [&[Op]; 6] = [
+    &[Op::Or],
+    &[Op::And],
+    &[Op::Eq, Op::Ne, Op::Lt, Op::Le, Op::Gt, Op::Ge],
+    &[Op::BitOr, Op::BitXor],
+    &[Op::BitAnd],
+    &[Op::Add, Op::Sub],
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(&Token::Op(op)) = self.peek() {
+            if !PRECEDENCE[level].contains(&op) {
+                break;
+            }
+            self.position += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Name(name)) => Variable::from_name(&name)
+                .map(Expr::Variable)
+                .ok_or_else(|| format!("Unknown name '{}'", name)),
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Op(Op::Sub)) => Ok(Expr::Binary(Op::Sub, Box::new(Expr::Number(0)), Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.binary(0)?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("Missing ')'".to_string()),
+                }
+            }
+            _ => Err("Expected a number, name or '('".to_string()),
+        }
+    }
+}
+
+// A breakpoint condition such as `A == 0x20 && scanline > 200`. Numbers
+// are decimal, 0x or $ hex; names are the CPU registers (a x y sp p pc),
+// cycle, scanline, dot, frame, and the value and addr of the access that
+// triggered the breakpoint. Non-zero is true, like C.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, String> {
+        let mut parser = Parser { tokens: tokenize(source)?, position: 0 };
+        let expr = parser.binary(0)?;
+        if parser.position != parser.tokens.len() {
+            return Err(format!("Unexpected {:?}", parser.tokens[parser.position]));
+        }
+        Ok(Condition { source: source.to_string(), expr })
+    }
+
+    pub fn is_met(&self, context: &CpuContext, event: &BreakEvent) -> bool {
+        self.expr.evaluate(context, event) != 0
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> CpuContext {
+        CpuContext { pc: 0xC000, a: 0x20, x: 1, y: 2, sp: 0xFD, p: 0x24, cycle: 1000, scanline: 210, dot: 5, frame: 3 }
+    }
+
+    fn check(source: &str) -> bool {
+        let event = BreakEvent::PpuWrite { addr: 0x2006, value: 0x3F };
+        Condition::parse(source).unwrap().is_met(&context(), &event)
+    }
+
+    #[test]
+    fn evaluates_against_cpu_state() {
+        assert!(check("A == 0x20 && scanline > 200"));
+        assert!(!check("a == $21 || scanline < 200"));
+        assert!(check("value == 0x3F && addr == $2006"));
+        assert!(check("(p & 0x04) != 0"));
+        assert!(check("!(x + y > 3)"));
+        assert!(check("sp - 0xFD == 0 && pc >= $8000"));
+        assert!(check("1 | 0 == 1"));
+    }
+
+    #[test]
+    fn rejects_malformed_conditions() {
+        assert!(Condition::parse("A ==").is_err());
+        assert!(Condition::parse("(A == 1").is_err());
+        assert!(Condition::parse("foo > 1").is_err());
+        assert!(Condition::parse("A = 1").is_err());
+        assert!(Condition::parse("A == 1 2").is_err());
+    }
+}
\ No newline at end of file
@@ -1,8 +1,8 @@
 
-use std::{fs::OpenOptions, io::{self, Write}};
+use std::io::Write;
 
-use crate::SystemVersion;
-use super::{bus::Bus, instructions::{AddressingMode, Instruction, OPCODE_TABLE}};
+use crate::{symbols::SymbolTable, SystemVersion};
+use super::{breakpoint::{BreakHit, CpuContext}, bus::{Bus, BusInterface}, bus_trace::BusOrigin, history::{InstructionHistory, TraceEntry}, instructions::{AddressingMode, Instruction}, irq::IrqSource, profiler::{CodeAddress, Profiler}};
 
 const NTSC_CLOCK_FREQ: f32 = 1.789773;
 const PAL_CLOCK_FREQ: f32 = 1.662607;
@@ -14,6 +14,12 @@ const NMI_ADDR: u16 = 0xFFFA;
 const RESET_ADDR: u16 = 0xFFFC;
 const IRQ_ADDR: u16 = 0xFFFE;
 
+// Halt, dummy, optional alignment and the fetch itself
+const DMC_DMA_CYCLES: u8 = 4;
+const INTERRUPT_CYCLES: u8 = 7;
+// An NMI edge within the first four cycles of BRK/IRQ redirects its vector
+const HIJACK_DOTS: u16 = 4 * 3;
+
 #[derive(Debug, Copy, Clone)]
 pub enum StatusFlag {
     Carry = 0b0000_0001,
@@ -33,7 +39,11 @@ pub enum Interrupt {
     BRK,
 }
 
-pub struct Cpu {
+// A 6502 on any bus: the console's by default, or a flat one to test
+// instructions away from the PPU and cartridge. Interrupts, DMA and the
+// debugging tools need the console's bus.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cpu<B = Bus> {
     pub a: u8,
     pub x: u8,
     pub y: u8,
@@ -44,11 +54,34 @@ pub struct Cpu {
     clock_period: f32,
 
     pub update_interrupt_disable: (bool, u8),
-    pub bus: Bus,
+    // Halted by a JAM opcode; only RESET recovers
+    pub jammed: bool,
+    // Constant ORed into A by the unstable ANE/LXA opcodes; varies per chip
+    pub unstable_magic: u8,
+    // NMI edge seen in time to be taken before the next instruction, or one
+    // that arrived on the last cycle and has to wait an instruction more
+    nmi_pending: bool,
+    nmi_delayed: bool,
+    // Set while a BRK/IRQ sequence is still before its vector fetch
+    hijack_window: bool,
+    pub bus: B,
 
     //Debugging
-    pub debug_mode: bool,
-    opcode: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub profiler: Option<Profiler>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub history: Option<InstructionHistory>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub symbols: Option<SymbolTable>,
+    // Gets a line per instruction, nestest.log style
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub trace_log: Option<Box<dyn Write + Send>>,
+    // Trace addresses as bank:address, which no longer diffs against
+    // nestest.log
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub trace_banked: bool,
+    // Last opcode fetched
+    pub(crate) opcode: u8,
     operand: Vec<u8>,
     db_a: u8,
     db_x: u8,
@@ -58,57 +91,35 @@ pub struct Cpu {
     db_p: u8
 }
 
-impl Cpu {
-    pub fn new(version: SystemVersion) -> Self{
-
-        let clock_speed = match version {
-            SystemVersion::NTSC | SystemVersion::RGB => {
-                NTSC_CLOCK_FREQ
-            }
-            SystemVersion::PAL => PAL_CLOCK_FREQ,
-            SystemVersion::Dendy => DENDY_CLOCK_FREQ,
-            SystemVersion::BrazilFamiclone => BRAZIL_FAMICLONE_CLOCK_FREQ,
-            SystemVersion::ArgentinaFamiclone => ARGENTINA_FAMICLONE_CLOCK_FREQ
-        };
-        let clock_period = 1.0 / (clock_speed * 1_000_000.0);
-        Cpu {
-            a: 0,
-            x: 0,
-            y: 0,
-            pc: 0xFFFC,
-            sp: 0,
-            p: 0x24,
-
-            clock_period,
-            update_interrupt_disable: (false, 0),
-            bus: Bus::new(),
+fn clock_period(version: SystemVersion) -> f32 {
+    1.0 / (clock_mhz(version) * 1_000_000.0)
+}
 
-            debug_mode: false,
-            opcode: 0,
-            operand: vec![],
-            db_a: 0,
-            db_x: 0,
-            db_y: 0,
-            db_pc: 0,
-            db_sp: 0,
-            db_p: 0
+// CPU clock of the region in MHz
+pub(crate) fn clock_mhz(version: SystemVersion) -> f32 {
+    match version {
+        SystemVersion::NTSC | SystemVersion::RGB | SystemVersion::Auto => {
+            NTSC_CLOCK_FREQ
         }
+        SystemVersion::PAL => PAL_CLOCK_FREQ,
+        SystemVersion::Dendy => DENDY_CLOCK_FREQ,
+        SystemVersion::BrazilFamiclone => BRAZIL_FAMICLONE_CLOCK_FREQ,
+        SystemVersion::ArgentinaFamiclone => ARGENTINA_FAMICLONE_CLOCK_FREQ
     }
+}
 
-    pub fn set_debug_mode(&mut self, value: bool){
-        self.debug_mode = value;
+impl Cpu {
+    pub fn new(version: SystemVersion) -> Self{
+        Cpu::with_bus(version, Bus::new())
     }
-    
-    fn append_to_file(&self, filename: &str, content: &str) -> io::Result<()> {
-        
-        let mut file = OpenOptions::new()
-        .create(true)  // Create the file if it doesn't exist
-        .append(true)  // Append to the file
-        .open(filename)?;
-    
-        // Write the content to the file
-        file.write_all(content.as_bytes())?;
-        Ok(())
+
+    pub fn set_region(&mut self, version: SystemVersion) {
+        self.clock_period = clock_period(version);
+    }
+
+    pub fn set_trace_log(&mut self, log: Option<Box<dyn Write + Send>>, banked: bool) {
+        self.trace_log = log;
+        self.trace_banked = banked;
     }
     
     fn pad_to_width(&self, str: String, width: usize) -> String {
@@ -121,17 +132,80 @@ impl Cpu {
         }
     }
 
-    pub fn step(&mut self){
+    // Runs one instruction or interrupt, returning the breakpoint it hit
+    pub fn step(&mut self) -> Option<BreakHit> {
+        if !self.bus.breakpoints.is_armed() {
+            self.execute();
+            return None;
+        }
+        let context = self.context();
+        if !self.jammed && !self.nmi_pending && !self.irq_due() {
+            if let Some(hit) = self.bus.breakpoints.execute(context) {
+                return Some(hit);
+            }
+        }
+        self.execute();
+        self.bus.breakpoints.take_hit(context)
+    }
+
+    // IRQ is level triggered and polled with the I flag as the previous
+    // instruction left it, so CLI/SEI/PLP take effect one instruction late.
+    fn irq_due(&self) -> bool {
+        self.bus.irq.is_asserted() && self.p & StatusFlag::InterruptDisable as u8 == 0
+    }
+
+    pub fn context(&self) -> CpuContext {
+        CpuContext {
+            pc: self.pc,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            sp: self.sp,
+            p: self.p,
+            cycle: self.bus.cycles,
+            scanline: self.bus.ppu.scanline,
+            dot: self.bus.ppu.cycle,
+            frame: self.bus.ppu.frame_count,
+        }
+    }
+
+    fn execute(&mut self) {
+        // The rest of the system keeps running while the CPU is stuck
+        if self.jammed {
+            self.tick(1);
+            return;
+        }
 
         if self.bus.dma_transfer.0 {
-            let bank = self.bus.dma_transfer.1;
-            for i in 0..256 {
-                let addr = bank as u16 * 0x100 + i;
-                let data = self.read_byte(addr);
-                self.bus.ppu.write_oamdata(data);
-            }
-            self.bus.cycles += 514;
+            let page = self.bus.dma_transfer.1;
             self.bus.dma_transfer = (false, 0);
+            self.oam_dma(page);
+        }
+
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            if self.bus.breakpoints.is_armed() {
+                self.bus.breakpoints.interrupt(true);
+            }
+            self.interrupt(Interrupt::NMI);
+            let cycles = INTERRUPT_CYCLES + self.step_apu(INTERRUPT_CYCLES);
+            self.profile_interrupt(cycles);
+            self.tick(cycles);
+            return;
+        }
+
+        if self.irq_due() {
+            self.update_interrupt_disable = (false, 0);
+            // A forced IRQ is held until it's taken
+            self.bus.irq.deassert(IrqSource::External);
+            if self.bus.breakpoints.is_armed() {
+                self.bus.breakpoints.interrupt(false);
+            }
+            self.interrupt(Interrupt::IRQ);
+            let cycles = INTERRUPT_CYCLES + self.step_apu(INTERRUPT_CYCLES);
+            self.profile_interrupt(cycles);
+            self.tick(cycles);
+            return;
         }
 
         if self.update_interrupt_disable.0 {
@@ -139,7 +213,7 @@ impl Cpu {
             self.update_interrupt_disable = (false, 0);
         }
 
-        if self.debug_mode {
+        if self.trace_log.is_some() {
             self.db_a = self.a;
             self.db_x = self.x;
             self.db_y = self.y;
@@ -148,21 +222,66 @@ impl Cpu {
             self.db_p = self.p;
         }
 
+        let location = (self.profiler.is_some() || self.tracing()).then(|| self.code_address(self.pc));
+        let start = self.history.as_ref().map(|_| self.context());
+        let pc = self.pc;
+        if let Some(cdl) = self.bus.cartridge.cdl_mut() {
+            cdl.begin_instruction(pc, 1, false);
+        }
         let instruction = self.fetch_instruction();
+        if let Some(cdl) = self.bus.cartridge.cdl_mut() {
+            let indirect = matches!(instruction.mode, AddressingMode::IndirectX | AddressingMode::IndirectY);
+            cdl.begin_instruction(pc, instruction.mode.size(), indirect);
+        }
         //REFACTOR: FETCH OPERAND FIRST
+        self.bus.instruction_cycles = instruction.min_cycles;
         let page_cross_cycle = (instruction.function)(self, instruction.mode);
-        let cycles = instruction.min_cycles + page_cross_cycle;
+        self.bus.instruction_cycles = 0;
+        let mut cycles = instruction.min_cycles + page_cross_cycle;
+        cycles += self.step_apu(cycles);
+        if let (Some(location), true) = (location, self.profiler.is_some()) {
+            self.profile_instruction(location, cycles);
+        }
+        // JMP ($xxxx)
+        if self.opcode == 0x6C {
+            self.bus.cartridge.log_indirect_jump(self.pc);
+        }
+        if let Some(context) = start {
+            let operand_count = (instruction.mode.size() as usize - 1).min(self.operand.len());
+            let operands = &self.operand[..operand_count];
+            let target = self.operand_target(context.pc, instruction.mode, operands);
+            let location = location.unwrap_or_else(|| self.code_address(context.pc));
+            let entry = TraceEntry::new(context, location, target, self.opcode, operands, self.bus.cartridge.prg_window());
+            if let Some(history) = &mut self.history {
+                history.push(entry);
+            }
+            if self.trace_log.is_none() {
+                self.operand.clear();
+            }
+        }
 
 
-        if self.debug_mode {
+        if self.trace_log.is_some() {
+            let location = location.unwrap_or_else(|| self.code_address(self.db_pc));
+            let address = if self.trace_banked {
+                location.banked(self.bus.cartridge.prg_window())
+            } else {
+                format!("{:04X}", self.db_pc)
+            };
             let operands_str = self.operand.iter()
                 .map(|op| format!("{:02X}", op))
                 .collect::<Vec<String>>()
                 .join(" ");
+            let comment = self.symbols.as_ref()
+                .and_then(|symbols| {
+                    let target = self.operand_target(self.db_pc, instruction.mode, &self.operand);
+                    symbols.comment(location, target)
+                })
+                .map_or(String::new(), |comment| format!("  ; {}", comment));
 
             let output_str = format!(
-                "{:04X}  {:02X} {:<42}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU: {}, {} CYC:{}\n",
-                self.db_pc,
+                "{}  {:02X} {:<42}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU: {}, {} CYC:{}{}\n",
+                address,
                 self.opcode,
                 self.pad_to_width(operands_str, 42),
                 self.db_a,
@@ -170,27 +289,146 @@ impl Cpu {
                 self.db_y,
                 self.db_p,
                 self.db_sp,
-                self.bus.ppu.scanline, self.bus.ppu.cycle, self.bus.cycles
+                self.bus.ppu.scanline, self.bus.ppu.cycle, self.bus.cycles,
+                comment
             );
-            match self.append_to_file("debug.log", &output_str) {
-                Ok(_) => (),
-                Err(e) => eprintln!("Error writing to file: {}", e),
+            // A log that fails is dropped rather than retried every instruction
+            if let Some(log) = &mut self.trace_log {
+                if log.write_all(output_str.as_bytes()).is_err() {
+                    self.trace_log = None;
+                }
             }
             self.operand.clear();
         }
 
-        for _ in 0..cycles * 3 {
-            self.bus.ppu.step();
-            if self.bus.ppu.trigger_nmi {
-                self.bus.ppu.trigger_nmi = false;
-                self.interrupt(Interrupt::NMI);
+        self.tick(cycles);
+        //std::thread::sleep(std::time::Duration::from_secs_f32(cycles as f32 * self.clock_period));
+    }
+
+
+    // Bank-aware location of code at `pc`
+    fn code_address(&self, pc: u16) -> CodeAddress {
+        let prg_offset = if pc >= 0x8000 { Some(self.bus.cartridge.map(pc)) } else { None };
+        CodeAddress { pc, prg_offset }
+    }
+
+    fn operand_target(&self, pc: u16, mode: AddressingMode, operands: &[u8]) -> Option<CodeAddress> {
+        mode.target(pc, operands).map(|addr| self.code_address(addr))
+    }
+
+    fn profile_instruction(&mut self, location: CodeAddress, cycles: u8) {
+        let target = self.code_address(self.pc);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(location, u64::from(cycles));
+            match self.opcode {
+                // JSR and BRK enter a function, RTS and RTI leave one
+                0x20 | 0x00 => profiler.enter(target),
+                0x60 | 0x40 => profiler.leave(),
+                _ => {}
             }
         }
+    }
 
-        self.bus.cycles += u64::from(cycles);
-        //std::thread::sleep(std::time::Duration::from_secs_f32(cycles as f32 * self.clock_period));
+    fn profile_interrupt(&mut self, cycles: u8) {
+        let handler = self.code_address(self.pc);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(handler);
+            profiler.record_cycles(u64::from(cycles));
+        }
+    }
+
+    // Runs the PPU alongside the CPU cycles just spent
+    fn tick(&mut self, cycles: u8) {
+        let dots = u16::from(cycles) * 3;
+        BusInterface::tick(&mut self.bus, cycles);
+
+        // Interrupts are polled before the last cycle of an instruction
+        if self.nmi_delayed {
+            self.nmi_delayed = false;
+            self.nmi_pending = true;
+        }
+        match self.bus.nmi_dot.take() {
+            // BRK/IRQ pushed everything but hadn't fetched the vector yet, so
+            // it carries on into the NMI handler and the NMI is consumed
+            Some(dot) if self.hijack_window && dot < HIJACK_DOTS => {
+                self.pc = self.read_word(NMI_ADDR);
+            }
+            Some(dot) if dot + 3 >= dots => self.nmi_delayed = true,
+            Some(_) => self.nmi_pending = true,
+            None => {}
+        }
+        self.hijack_window = false;
     }
 
+    // The CPU halts while the page is copied through the bus one byte per
+    // read and write cycle pair, so every source reads as it would for the
+    // CPU: mirrors, PRG RAM, ROM, and registers with their side effects, at
+    // the dot they happen on. 513 cycles, or 514 starting on an odd one.
+    fn oam_dma(&mut self, page: u8) {
+        self.bus.origin = BusOrigin::OamDma;
+        self.dma_cycles(if self.bus.cycles % 2 == 1 { 2 } else { 1 });
+        for i in 0..=255 {
+            let data = self.bus.read(u16::from_le_bytes([i, page]));
+            self.dma_cycles(1);
+            self.bus.trace_access(0x2004, data, true);
+            self.bus.ppu.write_oamdata(data);
+            self.dma_cycles(1);
+        }
+        self.bus.origin = BusOrigin::Cpu;
+    }
+
+    fn dma_cycles(&mut self, cycles: u8) {
+        // Not an instruction operand, so a DMC fetch doesn't repeat it
+        self.bus.last_read = None;
+        let halted = self.step_apu(cycles);
+        self.tick(cycles + halted);
+    }
+
+    // Clocks the APU through the instruction just executed and services the
+    // DMC sample fetches it raised. Returns the cycles the CPU was halted for.
+    fn step_apu(&mut self, cycles: u8) -> u8 {
+        let mut halted = 0;
+        // The APU sits out the overclock's idle scanlines with the PPU, so
+        // audio and the frame IRQ keep stock timing
+        let idle_dots = self.bus.ppu.idle_dots();
+        for i in 0..cycles {
+            if u32::from(i) * 3 < idle_dots {
+                continue;
+            }
+            self.bus.apu.step();
+            if let Some(addr) = self.bus.apu.dmc.dma_request() {
+                // The CPU keeps repeating its read while halted. Operand reads
+                // happen on the last cycle, and on registers with read side
+                // effects the repeat shifts the controller or bumps the VRAM
+                // address a second time.
+                match self.bus.last_read {
+                    Some(last) if i + 1 == cycles && Self::has_read_side_effects(last) => {
+                        self.bus.read(last);
+                    }
+                    _ => {}
+                }
+                if let Some(cdl) = self.bus.cartridge.cdl_mut() {
+                    cdl.set_pcm(true);
+                }
+                let origin = std::mem::replace(&mut self.bus.origin, BusOrigin::DmcDma);
+                let data = self.bus.read(addr);
+                self.bus.origin = origin;
+                if let Some(cdl) = self.bus.cartridge.cdl_mut() {
+                    cdl.set_pcm(false);
+                }
+                self.bus.apu.dmc.dma_fill(data);
+                for _ in 0..DMC_DMA_CYCLES {
+                    self.bus.apu.step();
+                }
+                halted += DMC_DMA_CYCLES;
+            }
+        }
+        halted
+    }
+
+    fn has_read_side_effects(addr: u16) -> bool {
+        matches!(addr, 0x4016 | 0x4017) || (0x2000..0x4000).contains(&addr) && addr & 0x0007 == 0x0007
+    }
 
     fn get_test_result(&mut self) -> String{
         let mut idx = 0x6004;
@@ -208,17 +446,95 @@ impl Cpu {
         String::from_utf8(result).expect("Invalid UTF-8 sequence")
     }
 
+    // Registers come up cleared; the reset sequence then takes SP to $FD.
+    pub fn power_on(&mut self){
+        self.a = 0;
+        self.x = 0;
+        self.y = 0;
+        self.sp = 0;
+        self.p = 0x24;
+        self.update_interrupt_disable = (false, 0);
+        self.bus.ppu.power_on();
+        self.bus.apu.power_on();
+        self.reset();
+        self.bus.cartridge.power_on();
+    }
+
+    // Takes over the debugging attachments of the CPU this one replaces,
+    // which save states leave out
+    pub(crate) fn keep_attachments(&mut self, old: &mut Cpu) {
+        self.profiler = old.profiler.take();
+        self.history = old.history.take();
+        self.symbols = old.symbols.take();
+        self.trace_log = old.trace_log.take();
+        self.trace_banked = old.trace_banked;
+        self.bus.keep_attachments(&mut old.bus);
+    }
+}
+
+impl<B: BusInterface> Cpu<B> {
+    pub fn with_bus(version: SystemVersion, bus: B) -> Self {
+        let clock_period = clock_period(version);
+        Cpu {
+            a: 0,
+            x: 0,
+            y: 0,
+            pc: 0xFFFC,
+            sp: 0,
+            p: 0x24,
+
+            clock_period,
+            update_interrupt_disable: (false, 0),
+            jammed: false,
+            unstable_magic: 0xEE,
+            nmi_pending: false,
+            nmi_delayed: false,
+            hijack_window: false,
+            bus,
+
+            profiler: None,
+            history: None,
+            symbols: None,
+            trace_log: None,
+            trace_banked: false,
+            opcode: 0,
+            operand: vec![],
+            db_a: 0,
+            db_x: 0,
+            db_y: 0,
+            db_pc: 0,
+            db_sp: 0,
+            db_p: 0
+        }
+    }
+
+    // Operand bytes are collected for the trace log and the history
+    fn tracing(&self) -> bool {
+        self.trace_log.is_some() || self.history.is_some()
+    }
+
     pub fn reset(&mut self){
-        self.bus.reset = true;
+        self.jammed = false;
+        self.nmi_pending = false;
+        self.nmi_delayed = false;
+        self.bus.reset();
         self.pc = self.read_word(RESET_ADDR);
+        log::debug!(target: "cpu", "Reset to ${:04X}", self.pc);
         self.sp = self.sp.wrapping_sub(3);
-        self.bus.cycles = 7;
         self.set_flag(StatusFlag::InterruptDisable, true);
-        for _ in 0..self.bus.cycles * 3 {
-            self.bus.ppu.step();
-        }
+        // The rest of the system runs through the reset sequence
+        self.bus.tick(INTERRUPT_CYCLES);
     }
 
+    // An NMI edge, taken before the next instruction
+    pub fn raise_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    // Includes an edge waiting out one more instruction
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi_pending || self.nmi_delayed
+    }
 
     pub fn interrupt(&mut self, interrupt: Interrupt){
         match interrupt {
@@ -233,11 +549,22 @@ impl Cpu {
                 self.set_flag(StatusFlag::Break, false);
                 self.set_flag(StatusFlag::InterruptDisable, true);
                 self.pc = self.read_word(IRQ_ADDR);
+                self.hijack_window = true;
             }
             Interrupt::IRQ => {
-
+                log::trace!(target: "cpu", "IRQ at ${:04X}", self.pc);
+                for b in self.pc.to_be_bytes() {
+                    self.stack_push(b);
+                }
+                self.set_flag(StatusFlag::Break, false);
+                self.set_flag(StatusFlag::BreakIrq, true);
+                self.stack_push(self.p);
+                self.set_flag(StatusFlag::InterruptDisable, true);
+                self.pc = self.read_word(IRQ_ADDR);
+                self.hijack_window = true;
             }
             Interrupt::NMI => {
+                log::trace!(target: "cpu", "NMI at ${:04X}", self.pc);
                 let pc_bytes = self.pc.to_be_bytes();
                 self.stack_push(pc_bytes[0]);
                 self.stack_push(pc_bytes[1]);
@@ -256,18 +583,63 @@ impl Cpu {
     }
 
 
-    fn fetch_instruction(&mut self) -> Instruction {
-        let opcode = self.read_byte(self.pc);
-        if self.debug_mode {
-            self.opcode = opcode;
+    // The instruction at PC alone, without the DMA, interrupts and debugging
+    // the console's bus adds around it. Returns the cycles it takes.
+    pub(crate) fn run_instruction(&mut self) -> u8 {
+        if self.update_interrupt_disable.0 {
+            self.set_flag(StatusFlag::InterruptDisable, self.update_interrupt_disable.1 != 0);
+            self.update_interrupt_disable = (false, 0);
         }
+        let instruction = self.fetch_instruction();
+        instruction.min_cycles + (instruction.function)(self, instruction.mode)
+    }
+
+    fn fetch_instruction(&mut self) -> Instruction<B> {
+        let opcode = self.read_byte(self.pc);
+        self.opcode = opcode;
 
         self.inc_pc();
-        OPCODE_TABLE[opcode as usize]
+        let instruction = Instruction::OPCODE_TABLE[opcode as usize];
+        // One byte instructions still read the byte after the opcode
+        if matches!(instruction.mode, AddressingMode::Implied | AddressingMode::Accumulator) {
+            self.bus.dummy_read(self.pc);
+        }
+        instruction
     }
 
     pub fn fetch_operand_addr(&mut self, mode: AddressingMode) -> (u16, u8) {
-        
+        self.operand_addr(mode, false)
+    }
+
+    // Address of a store or read-modify-write. Indexed modes always do their
+    // dummy read here, page crossing or not.
+    pub fn fetch_write_addr(&mut self, mode: AddressingMode) -> (u16, u8) {
+        self.operand_addr(mode, true)
+    }
+
+    // Read-modify-write instructions write the unmodified value back before
+    // the result, so registers with write side effects see both.
+    pub fn rmw_write(&mut self, addr: u16, old: u8, new: u8) {
+        self.bus.write(addr, old);
+        self.bus.write(addr, new);
+    }
+
+    // Indexed modes add the index to the low byte first and read from that
+    // address while the high byte gets fixed up.
+    fn indexed_dummy_read(&mut self, base_addr: u16, addr: u16, write: bool) {
+        let unfixed = (base_addr & 0xFF00) | (addr & 0x00FF);
+        if write || unfixed != addr {
+            self.bus.dummy_read(unfixed);
+        }
+    }
+
+    // Reads only pay for the high byte fix-up when the index carried into
+    // it. Stores and RMW always spend that cycle, which min_cycles includes.
+    fn page_cross_penalty(&self, base_addr: u16, addr: u16, write: bool) -> u8 {
+        if write { 0 } else { self.page_boundary_cycle(addr, base_addr) }
+    }
+
+    fn operand_addr(&mut self, mode: AddressingMode, write: bool) -> (u16, u8) {
         match mode {
             AddressingMode::Absolute => {
                 let lo = self.read_byte(self.pc) as u16;
@@ -276,7 +648,7 @@ impl Cpu {
                 self.inc_pc();
                 let addr = (hi << 8) | lo;
 
-                if self.debug_mode {
+                if self.tracing() {
                     self.operand.push(lo as u8);
                     self.operand.push(hi as u8);
                 }
@@ -291,12 +663,13 @@ impl Cpu {
                 let base_addr = (hi << 8) | lo;
                 let addr = base_addr.wrapping_add(self.x as u16);
 
-                if self.debug_mode {
+                if self.tracing() {
                     self.operand.push(lo as u8);
                     self.operand.push(hi as u8);
                 }
+                self.indexed_dummy_read(base_addr, addr, write);
 
-                (addr, self.page_boundary_cycle(addr, base_addr))
+                (addr, self.page_cross_penalty(base_addr, addr, write))
             }
             AddressingMode::AbsoluteY => {
                 let lo = self.read_byte(self.pc) as u16;
@@ -306,12 +679,13 @@ impl Cpu {
                 let base_addr = (hi << 8) | lo;
                 let addr = base_addr.wrapping_add(self.y as u16);
 
-                if self.debug_mode {
+                if self.tracing() {
                     self.operand.push(lo as u8);
                     self.operand.push(hi as u8);
                 }
+                self.indexed_dummy_read(base_addr, addr, write);
 
-                (addr, self.page_boundary_cycle(addr, base_addr))
+                (addr, self.page_cross_penalty(base_addr, addr, write))
             }
             AddressingMode::Accumulator => (0,0),
             AddressingMode::Immediate => (0,0), //Use fetch_operand for immediate
@@ -335,7 +709,7 @@ impl Cpu {
                 let target_lo = self.read_byte(addr) as u16;
                 let target_hi = self.read_byte(hi_addr) as u16;
                 
-                if self.debug_mode {
+                if self.tracing() {
                     self.operand.push(addr_lo as u8);
                     self.operand.push(addr_hi as u8);
                 }
@@ -347,11 +721,13 @@ impl Cpu {
                 let zp_addr = self.read_byte(self.pc);
                 self.inc_pc();
                 
-                if self.debug_mode {
+                if self.tracing() {
                     self.operand.push(zp_addr);
                 }
                 
-                // Add X register with zero-page wrap
+                // Add X register with zero-page wrap, reading the unindexed
+                // address meanwhile
+                self.bus.dummy_read(zp_addr as u16);
                 let effective_zp = zp_addr.wrapping_add(self.x);
                 
                 // Read 16-bit address from zero page
@@ -365,7 +741,7 @@ impl Cpu {
                 let zp_addr = self.read_byte(self.pc);
                 self.inc_pc();
                 
-                if self.debug_mode {
+                if self.tracing() {
                     self.operand.push(zp_addr);
                 }
                 
@@ -376,53 +752,55 @@ impl Cpu {
                 
                 // Add Y register to the indirect address
                 let final_addr = base_addr.wrapping_add(self.y as u16);
+                self.indexed_dummy_read(base_addr, final_addr, write);
                 
-                (final_addr, self.page_boundary_cycle(final_addr, base_addr))
+                (final_addr, self.page_cross_penalty(base_addr, final_addr, write))
             },
             AddressingMode::Relative => {
                 let offset = self.read_byte(self.pc) as i8;  // Fetch the signed offset
                 self.inc_pc();
-                let addr = (self.pc as i16 + offset as i16) as u16;  // Add offset to the current PC
+                let addr = self.pc.wrapping_add(offset as u16);  // Add offset to the current PC
 
-                if self.debug_mode {
+                if self.tracing() {
                     self.operand.push(offset as u8);
                 }
 
-                (addr, self.page_boundary_cycle(self.pc, addr))
+                // Timing depends on whether the branch is taken, see branch()
+                (addr, 0)
             },
             AddressingMode::ZeroPage => {
                 let addr = self.read_byte(self.pc) as u16;  // Fetch the address (only low byte)
                 self.inc_pc();
 
-                if self.debug_mode {
+                if self.tracing() {
                     self.operand.push(addr as u8);
                 }
 
                 (addr, 0) // Return the address as the operand
             },
             AddressingMode::ZeroPageX => {
-                let addr = self.read_byte(self.pc) as u16;  // Fetch the address (only low byte)
+                let addr = self.read_byte(self.pc);  // Fetch the address (only low byte)
                 self.inc_pc();
-                let addr_x = addr + self.x as u16;  // Add X register to the address
 
-                if self.debug_mode {
-                    self.operand.push(addr as u8);
+                if self.tracing() {
+                    self.operand.push(addr);
                 }
 
-                let addr_x_wrapped = addr_x & 0xFF;
-                (addr_x_wrapped, 0)
+                // Indexing never leaves the zero page, and the unindexed
+                // address is read while it happens
+                self.bus.dummy_read(addr as u16);
+                (addr.wrapping_add(self.x) as u16, 0)
             },
             AddressingMode::ZeroPageY => {
-                let addr = self.read_byte(self.pc) as u16;  // Fetch the address (only low byte)
+                let addr = self.read_byte(self.pc);  // Fetch the address (only low byte)
                 self.inc_pc();
-                let addr_y = addr + self.y as u16;  // Add Y register to the address
 
-                if self.debug_mode {
-                    self.operand.push(addr as u8);
+                if self.tracing() {
+                    self.operand.push(addr);
                 }
 
-                let addr_y_wrapped = addr_y & 0xFF;
-                (addr_y_wrapped, 0)
+                self.bus.dummy_read(addr as u16);
+                (addr.wrapping_add(self.y) as u16, 0)
             }
         }
     }
@@ -433,7 +811,7 @@ impl Cpu {
 
         let byte = self.bus.read(addr);
 
-        if self.debug_mode {
+        if self.tracing() {
             self.operand.push(byte as u8);
         }
 
@@ -475,6 +853,11 @@ impl Cpu {
         self.pc = self.pc.wrapping_add(1);
     }
 
+    // Pulls spend a cycle reading the top of the stack before S moves up
+    pub fn stack_peek(&mut self) {
+        self.bus.dummy_read(0x0100 | self.sp as u16);
+    }
+
     pub fn stack_pop(&mut self) -> u8 {
         self.sp = self.sp.wrapping_add(1);
         let addr = 0x0100 | self.sp as u16;
@@ -486,4 +869,193 @@ impl Cpu {
         self.bus.write(address, value);
         self.sp = self.sp.wrapping_sub(1);
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cartridge::Cartridge, rom::{header::HEADER_SIZE, Rom}, test_support::TestRom};
+    use super::*;
+
+    // Runs one instruction placed at $0200 and returns the cycles it took
+    fn cycles_of(program: &[u8], x: u8, y: u8) -> u64 {
+        run_at(0x0200, program, x, y, 0).1
+    }
+
+    // Returns the PC after one instruction at `origin`, and its cycles
+    fn run_at(origin: u16, program: &[u8], x: u8, y: u8, flags: u8) -> (u16, u64) {
+        let mut cpu = Cpu::new(SystemVersion::NTSC);
+        for (i, byte) in program.iter().enumerate() {
+            cpu.bus.write(origin + i as u16, *byte);
+        }
+        // ($10) points at $00FF
+        cpu.bus.write(0x0010, 0xFF);
+        cpu.bus.write(0x0011, 0x00);
+        cpu.pc = origin;
+        cpu.p |= StatusFlag::InterruptDisable as u8 | flags;
+        cpu.x = x;
+        cpu.y = y;
+
+        let start = cpu.bus.cycles;
+        cpu.step();
+        (cpu.pc, cpu.bus.cycles - start)
+    }
+
+    #[test]
+    fn reads_pay_for_page_crossing() {
+        assert_eq!(cycles_of(&[0xBD, 0xFF, 0x00], 0, 0), 4); // LDA abs,X
+        assert_eq!(cycles_of(&[0xBD, 0xFF, 0x00], 1, 0), 5);
+        assert_eq!(cycles_of(&[0xB9, 0xFF, 0x00], 0, 1), 5); // LDA abs,Y
+        assert_eq!(cycles_of(&[0xB1, 0x10], 0, 0), 5); // LDA (zp),Y
+        assert_eq!(cycles_of(&[0xB1, 0x10], 0, 1), 6);
+    }
+
+    #[test]
+    fn stores_and_rmw_take_fixed_cycles() {
+        for x in [0, 1] {
+            assert_eq!(cycles_of(&[0x9D, 0xFF, 0x00], x, 0), 5); // STA abs,X
+            assert_eq!(cycles_of(&[0xFE, 0xFF, 0x00], x, 0), 7); // INC abs,X
+            assert_eq!(cycles_of(&[0xDF, 0xFF, 0x00], x, 0), 7); // DCP abs,X
+        }
+        for y in [0, 1] {
+            assert_eq!(cycles_of(&[0x99, 0xFF, 0x00], 0, y), 5); // STA abs,Y
+            assert_eq!(cycles_of(&[0x91, 0x10], 0, y), 6); // STA (zp),Y
+            assert_eq!(cycles_of(&[0x13, 0x10], 0, y), 8); // SLO (zp),Y
+        }
+    }
+
+    #[test]
+    fn branch_timing() {
+        let zero = StatusFlag::Zero as u8;
+        // Not taken, even with a target on another page
+        assert_eq!(run_at(0x02FD, &[0xF0, 0x01], 0, 0, 0), (0x02FF, 2));
+        // Taken within the page
+        assert_eq!(run_at(0x0200, &[0xF0, 0x10], 0, 0, zero), (0x0212, 3));
+        // Taken across a page, forwards and backwards
+        assert_eq!(run_at(0x02FD, &[0xF0, 0x01], 0, 0, zero), (0x0300, 4));
+        assert_eq!(run_at(0x0300, &[0xF0, 0xFC], 0, 0, zero), (0x02FE, 4));
+    }
+
+    // NMI vector $0400, IRQ/BRK vector $0500, BRK at $0200, NMI enabled
+    fn brk_with_nmi_at(scanline: usize, cycle: usize) -> Cpu {
+        let mut data = TestRom::new(0).bytes();
+        let vectors = HEADER_SIZE + 0x7FFA;
+        data[vectors..vectors + 6].copy_from_slice(&[0x00, 0x04, 0x00, 0x00, 0x00, 0x05]);
+
+        let mut cpu = Cpu::new(SystemVersion::NTSC);
+        cpu.bus.cartridge = Cartridge::new(Rom::new(data));
+        cpu.bus.write(0x2000, 0x80);
+        cpu.bus.ppu.scanline = scanline;
+        cpu.bus.ppu.cycle = cycle;
+        cpu.pc = 0x0200;
+        cpu.sp = 0xFD;
+        cpu.step();
+        cpu
+    }
+
+    #[test]
+    fn nmi_hijacks_brk_before_vector_fetch() {
+        let mut cpu = brk_with_nmi_at(241, 0);
+        assert_eq!(cpu.pc, 0x0400);
+        assert!(!cpu.nmi_pending);
+        // B is still set in the pushed status
+        assert_ne!(cpu.bus.read(0x01FB) & StatusFlag::Break as u8, 0);
+    }
+
+    #[test]
+    fn late_nmi_runs_after_brk() {
+        let cpu = brk_with_nmi_at(240, 327);
+        assert_eq!(cpu.pc, 0x0500);
+        assert!(cpu.nmi_pending);
+    }
+
+    fn run_with(program: &[u8], setup: impl FnOnce(&mut Cpu)) -> Cpu {
+        let mut cpu = Cpu::new(SystemVersion::NTSC);
+        for (i, byte) in program.iter().enumerate() {
+            cpu.bus.write(0x0200 + i as u16, *byte);
+        }
+        cpu.pc = 0x0200;
+        cpu.p |= StatusFlag::InterruptDisable as u8;
+        setup(&mut cpu);
+        cpu.step();
+        cpu
+    }
+
+    // NOP after a DMA from `page`, started on an odd cycle or not. Returns
+    // the cycles both took.
+    fn dma_from(cpu: &mut Cpu, page: u8, odd: bool) -> u64 {
+        cpu.bus.write(0x0200, 0xEA);
+        cpu.pc = 0x0200;
+        cpu.bus.cycles = odd as u64;
+        cpu.bus.write(0x4014, page);
+        cpu.step();
+        cpu.bus.cycles - odd as u64
+    }
+
+    fn dma_cpu() -> Cpu {
+        let mut cpu = Cpu::new(SystemVersion::NTSC);
+        cpu.bus.cartridge = Cartridge::new(Rom::new(TestRom::new(0).bytes()));
+        cpu
+    }
+
+    #[test]
+    fn dma_reads_any_page_through_the_bus() {
+        let mut cpu = dma_cpu();
+        for i in 0..=255u8 {
+            cpu.bus.write(0x7000 + i as u16, i ^ 0x5A);
+            cpu.bus.write(0x0300 + i as u16, !i);
+        }
+        assert_eq!(dma_from(&mut cpu, 0x70, false), 513 + 2);
+        assert!(cpu.bus.ppu.oam_bytes().iter().enumerate().all(|(i, &byte)| byte == i as u8 ^ 0x5A));
+        // Mirror of $0300
+        assert_eq!(dma_from(&mut cpu, 0x0B, true), 514 + 2);
+        assert!(cpu.bus.ppu.oam_bytes().iter().enumerate().all(|(i, &byte)| byte == !(i as u8)));
+        // Second 8KB of PRG ROM
+        dma_from(&mut cpu, 0xA0, false);
+        assert_eq!(cpu.bus.ppu.oam_bytes(), [1; 256]);
+    }
+
+    #[test]
+    fn dma_from_ppu_registers_has_side_effects() {
+        let mut cpu = dma_cpu();
+        cpu.bus.write(0x2006, 0x21);
+        cpu.bus.write(0x2006, 0x00);
+        // Each of the 32 reads of a $2007 mirror steps the VRAM address
+        dma_from(&mut cpu, 0x20, false);
+        cpu.bus.write(0x2007, 0xAB);
+        assert_eq!(cpu.bus.ppu_read(0x2120), 0xAB);
+    }
+
+    #[test]
+    fn ane_and_lxa_use_magic_constant() {
+        let cpu = run_with(&[0x8B, 0xFF], |cpu| { cpu.a = 0x01; cpu.x = 0x0F; cpu.unstable_magic = 0xEE; });
+        assert_eq!(cpu.a, 0x0F);
+        assert_eq!(cpu.pc, 0x0202);
+
+        let cpu = run_with(&[0xAB, 0xF0], |cpu| { cpu.a = 0x00; cpu.unstable_magic = 0xFF; });
+        assert_eq!((cpu.a, cpu.x), (0xF0, 0xF0));
+    }
+
+    #[test]
+    fn shx_corrupts_high_byte_on_page_cross() {
+        // SHX $02FF,Y with Y=2: value = X & $03 lands on $(value)01
+        let mut cpu = run_with(&[0x9E, 0xFF, 0x02], |cpu| { cpu.x = 0x01; cpu.y = 0x02; });
+        assert_eq!(cpu.bus.read(0x0101), 0x01);
+        assert_eq!(cpu.bus.read(0x0301), 0x00);
+
+        // No page cross: plain store of X & (H + 1)
+        let mut cpu = run_with(&[0x9E, 0x10, 0x02], |cpu| { cpu.x = 0xFF; cpu.y = 0x02; });
+        assert_eq!(cpu.bus.read(0x0212), 0x03);
+    }
+
+    #[test]
+    fn jam_halts_until_reset() {
+        let mut cpu = run_with(&[0x02, 0xEA], |_| {});
+        assert!(cpu.jammed);
+        assert_eq!(cpu.pc, 0x0200);
+
+        let cycles = cpu.bus.cycles;
+        cpu.step();
+        assert_eq!(cpu.pc, 0x0200);
+        assert!(cpu.bus.cycles > cycles);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,85 @@
+use super::{bus::BusInterface, Cpu};
+
+// 64KB of RAM and nothing else: every address reads back what was last
+// written to it, with no registers, mirrors or interrupts. For running the
+// CPU on its own.
+pub struct FlatBus {
+    pub memory: Box<[u8; 0x10000]>,
+    pub cycles: u64,
+    // Every (address, value, write) access in order, while Some
+    pub log: Option<Vec<(u16, u8, bool)>>,
+}
+
+impl FlatBus {
+    pub fn new() -> Self {
+        FlatBus { memory: Box::new([0; 0x10000]), cycles: 0, log: None }
+    }
+
+    pub fn load(&mut self, addr: u16, bytes: &[u8]) {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.memory[addr.wrapping_add(i as u16) as usize] = byte;
+        }
+    }
+}
+
+impl Default for FlatBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BusInterface for FlatBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.memory[addr as usize];
+        if let Some(log) = &mut self.log {
+            log.push((addr, data, false));
+        }
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        if let Some(log) = &mut self.log {
+            log.push((addr, data, true));
+        }
+        self.memory[addr as usize] = data;
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.cycles += u64::from(cycles);
+    }
+}
+
+impl Cpu<FlatBus> {
+    // One instruction, as nothing on the bus can interrupt it
+    pub fn step(&mut self) {
+        if self.jammed {
+            self.bus.tick(1);
+            return;
+        }
+        let cycles = self.run_instruction();
+        self.bus.tick(cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SystemVersion;
+    use super::*;
+
+    #[test]
+    fn runs_programs_without_the_console() {
+        let mut cpu = Cpu::with_bus(SystemVersion::NTSC, FlatBus::new());
+        // Reset vector $C000; LDA #$07, STA $2000, JMP $C000
+        cpu.bus.load(0xFFFC, &[0x00, 0xC0]);
+        cpu.bus.load(0xC000, &[0xA9, 0x07, 0x8D, 0x00, 0x20, 0x4C, 0x00, 0xC0]);
+        cpu.reset();
+        assert_eq!((cpu.pc, cpu.bus.cycles), (0xC000, 7));
+
+        for _ in 0..3 {
+            cpu.step();
+        }
+        // A plain byte, not a PPU register
+        assert_eq!(cpu.bus.read(0x2000), 0x07);
+        assert_eq!((cpu.pc, cpu.bus.cycles), (0xC000, 7 + 2 + 4 + 3));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,144 @@
+use std::{collections::VecDeque, fmt};
+
+use super::{breakpoint::CpuContext, profiler::CodeAddress};
+
+// One executed instruction, with the registers as it began
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceEntry {
+    pub context: CpuContext,
+    // The instruction's address and its operand's, with the banks mapped in
+    // when it ran
+    pub location: CodeAddress,
+    pub target: Option<CodeAddress>,
+    pub opcode: u8,
+    operands: [u8; 2],
+    operand_count: u8,
+    // The mapper's PRG bank size, for the bank in the trace line
+    prg_window: u32,
+}
+
+impl TraceEntry {
+    pub(crate) fn new(context: CpuContext, location: CodeAddress, target: Option<CodeAddress>, opcode: u8, operands: &[u8], prg_window: u32) -> Self {
+        let mut bytes = [0; 2];
+        let count = operands.len().min(2);
+        bytes[..count].copy_from_slice(&operands[..count]);
+        TraceEntry { context, location, target, opcode, operands: bytes, operand_count: count as u8, prg_window }
+    }
+
+    // PRG bank the instruction ran from, None for code in RAM
+    pub fn bank(&self) -> Option<u32> {
+        self.location.bank(self.prg_window)
+    }
+
+    pub fn operands(&self) -> &[u8] {
+        &self.operands[..self.operand_count as usize]
+    }
+}
+
+// Same columns as the trace log, cartridge code as bank:address
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let context = &self.context;
+        let mut bytes = format!("{:02X}", self.opcode);
+        for operand in self.operands() {
+            bytes += &format!(" {:02X}", operand);
+        }
+        write!(f, "{}  {:<8}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:3},{:3} CYC:{}",
+            self.location.banked(self.prg_window), bytes, context.a, context.x, context.y, context.p, context.sp, context.scanline, context.dot, context.cycle)
+    }
+}
+
+// The last `capacity` instructions, kept in memory so a frontend can show
+// how execution reached a breakpoint or a crash
+pub struct InstructionHistory {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl InstructionHistory {
+    pub fn new(capacity: usize) -> Self {
+        InstructionHistory { entries: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Oldest first
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &TraceEntry> + '_ {
+        self.entries.iter()
+    }
+
+    pub fn last(&self) -> Option<&TraceEntry> {
+        self.entries.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn push(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{console, console_with, TestRom};
+
+    #[test]
+    fn keeps_the_most_recent_instructions() {
+        let mut nes = console();
+        // $0200: LDA #$07; STA $0300; JMP $0200
+        for (i, byte) in [0xA9, 0x07, 0x8D, 0x00, 0x03, 0x4C, 0x00, 0x02].iter().enumerate() {
+            nes.cpu.bus.write(0x0200 + i as u16, *byte);
+        }
+        nes.set_start(0x0200);
+        nes.record_history(4);
+        for _ in 0..6 {
+            nes.step();
+        }
+
+        let history = nes.history().unwrap();
+        assert_eq!(history.len(), 4);
+        let pcs: Vec<u16> = history.entries().map(|entry| entry.context.pc).collect();
+        assert_eq!(pcs, [0x0205, 0x0200, 0x0202, 0x0205]);
+
+        let store = history.entries().nth(2).unwrap();
+        assert_eq!((store.opcode, store.operands()), (0x8D, &[0x00, 0x03][..]));
+        assert_eq!(store.context.a, 0x07);
+        assert!(store.to_string().starts_with("0202  8D 00 03  A:07"));
+    }
+
+    #[test]
+    fn cartridge_code_is_traced_by_bank() {
+        let mut nes = console_with(&TestRom::new(66).prg_16k(4));
+        nes.record_history(4);
+        // Every PRG byte is its 8KB bank number: BRK in bank 0, NOP zp in bank 1
+        nes.set_start(0x8000);
+        nes.step();
+        nes.poke(0x8000, 0x10);
+        nes.set_start(0x8000);
+        nes.step();
+
+        let lines: Vec<_> = nes.history().unwrap().entries().map(|entry| (entry.bank(), entry.to_string())).collect();
+        assert_eq!(lines[0].0, Some(0));
+        assert!(lines[0].1.starts_with("00:8000  00"));
+        assert_eq!(lines[1].0, Some(1));
+        assert!(lines[1].1.starts_with("01:8000  04 04"));
+    }
+}
\ No newline at end of file
@@ -1,4 +1,4 @@
-use super::{cpu::{Interrupt, StatusFlag}, Cpu};
+use super::{bus::{Bus, BusInterface}, cpu::{Interrupt, StatusFlag}, Cpu};
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum AddressingMode {
@@ -17,277 +17,312 @@ pub enum AddressingMode {
     Relative
 }
 
-type InstructionHandler = fn(&mut Cpu, AddressingMode) -> u8;
+impl AddressingMode {
+    // Instruction length in bytes, opcode included
+    pub fn size(self) -> u16 {
+        match self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 1,
+            AddressingMode::Absolute | AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::Indirect => 3,
+            _ => 2
+        }
+    }
+
+    // The address an instruction at `pc` names in its operand bytes, before
+    // any indexing or indirection
+    pub fn target(self, pc: u16, operands: &[u8]) -> Option<u16> {
+        match (self, operands) {
+            (AddressingMode::Relative, &[offset, ..]) => Some(pc.wrapping_add(2).wrapping_add(offset as i8 as u16)),
+            (AddressingMode::Absolute | AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::Indirect, &[lo, hi, ..]) => {
+                Some(u16::from_le_bytes([lo, hi]))
+            }
+            (AddressingMode::ZeroPage | AddressingMode::ZeroPageX | AddressingMode::ZeroPageY
+                | AddressingMode::IndirectX | AddressingMode::IndirectY, &[addr, ..]) => Some(addr as u16),
+            _ => None,
+        }
+    }
+}
 
-#[derive(Clone, Copy)]
-pub struct Instruction {
-    pub function: InstructionHandler,
+type InstructionHandler<B> = fn(&mut Cpu<B>, AddressingMode) -> u8;
+
+pub struct Instruction<B = Bus> {
+    pub function: InstructionHandler<B>,
     pub mode: AddressingMode,
     pub min_cycles: u8,
 }
 
-pub static OPCODE_TABLE: [Instruction; 256] = [
-    Instruction { function: brk, mode: AddressingMode::Implied, min_cycles: 7 }, // x00
-    Instruction { function: ora, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x01
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x02
-    Instruction { function: slo, mode: AddressingMode::IndirectX, min_cycles: 8 }, // x03
-    Instruction { function: nop, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x04
-    Instruction { function: ora, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x05
-    Instruction { function: asl, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x06
-    Instruction { function: slo, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x07
-    Instruction { function: php, mode: AddressingMode::Implied, min_cycles: 3 }, // x08
-    Instruction { function: ora, mode: AddressingMode::Immediate, min_cycles: 2 }, // x09
-    Instruction { function: asl, mode: AddressingMode::Accumulator, min_cycles: 2 }, // x0A
-    Instruction { function: anc, mode: AddressingMode::Immediate, min_cycles: 4 }, // x0B
-    Instruction { function: nop, mode: AddressingMode::Absolute, min_cycles: 4 }, // x0C
-    Instruction { function: ora, mode: AddressingMode::Absolute, min_cycles: 4 }, // x0D
-    Instruction { function: asl, mode: AddressingMode::Absolute, min_cycles: 6 }, // x0E
-    Instruction { function: slo, mode: AddressingMode::Absolute, min_cycles: 6 }, // x0F
-    Instruction { function: bpl, mode: AddressingMode::Relative, min_cycles: 2 }, // x10
-    Instruction { function: ora, mode: AddressingMode::IndirectY, min_cycles: 5 }, // x11
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x12
-    Instruction { function: slo, mode: AddressingMode::IndirectY, min_cycles: 8 }, // x13
-    Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x14
-    Instruction { function: ora, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x15
-    Instruction { function: asl, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x16
-    Instruction { function: slo, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x17
-    Instruction { function: clc, mode: AddressingMode::Implied, min_cycles: 2 }, // x18
-    Instruction { function: ora, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // x19
-    Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // x1A
-    Instruction { function: slo, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // x1B
-    Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x1C
-    Instruction { function: ora, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x1D
-    Instruction { function: asl, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x1E
-    Instruction { function: slo, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x1F
-    Instruction { function: jsr, mode: AddressingMode::Absolute, min_cycles: 6 }, // x20
-    Instruction { function: and, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x21
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x22
-    Instruction { function: rla, mode: AddressingMode::IndirectX, min_cycles: 8 }, // x23
-    Instruction { function: bit, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x24
-    Instruction { function: and, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x25
-    Instruction { function: rol, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x26
-    Instruction { function: rla, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x27
-    Instruction { function: plp, mode: AddressingMode::Implied, min_cycles: 4 }, // x28
-    Instruction { function: and, mode: AddressingMode::Immediate, min_cycles: 2 }, // x29
-    Instruction { function: rol, mode: AddressingMode::Accumulator, min_cycles: 2 }, // x2A
-    Instruction { function: anc, mode: AddressingMode::Immediate, min_cycles: 2 }, // x2B
-    Instruction { function: bit, mode: AddressingMode::Absolute, min_cycles: 4 }, // x2C
-    Instruction { function: and, mode: AddressingMode::Absolute, min_cycles: 4 }, // x2D
-    Instruction { function: rol, mode: AddressingMode::Absolute, min_cycles: 6 }, // x2E
-    Instruction { function: rla, mode: AddressingMode::Absolute, min_cycles: 6 }, // x2F
-    Instruction { function: bmi, mode: AddressingMode::Relative, min_cycles: 2 }, // x30
-    Instruction { function: and, mode: AddressingMode::IndirectY, min_cycles: 5 }, // x31
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x32
-    Instruction { function: rla, mode: AddressingMode::IndirectY, min_cycles: 8 }, // x33
-    Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x34
-    Instruction { function: and, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x35
-    Instruction { function: rol, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x36
-    Instruction { function: rla, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x37
-    Instruction { function: sec, mode: AddressingMode::Implied, min_cycles: 2 }, // x38
-    Instruction { function: and, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // x39
-    Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // x3A
-    Instruction { function: rla, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // x3B
-    Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x3C
-    Instruction { function: and, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x3D
-    Instruction { function: rol, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x3E
-    Instruction { function: rla, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x3F
-    Instruction { function: rti, mode: AddressingMode::Implied, min_cycles: 6 }, // x40
-    Instruction { function: eor, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x41
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x42
-    Instruction { function: sre, mode: AddressingMode::IndirectX, min_cycles: 8 }, // x43
-    Instruction { function: nop, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x44
-    Instruction { function: eor, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x45
-    Instruction { function: lsr, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x46
-    Instruction { function: sre, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x47
-    Instruction { function: pha, mode: AddressingMode::Implied, min_cycles: 3 }, // x48
-    Instruction { function: eor, mode: AddressingMode::Immediate, min_cycles: 2 }, // x49
-    Instruction { function: lsr, mode: AddressingMode::Accumulator, min_cycles: 2 }, // x4A
-    Instruction { function: alr, mode: AddressingMode::Immediate, min_cycles: 2 }, // x4B
-    Instruction { function: jmp, mode: AddressingMode::Absolute, min_cycles: 3 }, // x4C
-    Instruction { function: eor, mode: AddressingMode::Absolute, min_cycles: 4 }, // x4D
-    Instruction { function: lsr, mode: AddressingMode::Absolute, min_cycles: 6 }, // x4E
-    Instruction { function: sre, mode: AddressingMode::Absolute, min_cycles: 6 }, // x4F
-    Instruction { function: bvc, mode: AddressingMode::Relative, min_cycles: 2 }, // x50
-    Instruction { function: eor, mode: AddressingMode::IndirectY, min_cycles: 5 }, // x51
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x52
-    Instruction { function: sre, mode: AddressingMode::IndirectY, min_cycles: 8 }, // x53
-    Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x54
-    Instruction { function: eor, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x55
-    Instruction { function: lsr, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x56
-    Instruction { function: sre, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x57
-    Instruction { function: cli, mode: AddressingMode::Implied, min_cycles: 2 }, // x58
-    Instruction { function: eor, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // x59
-    Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // x5A
-    Instruction { function: sre, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // x5B
-    Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x5C
-    Instruction { function: eor, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x5D
-    Instruction { function: lsr, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x5E
-    Instruction { function: sre, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x5F
-    Instruction { function: rts, mode: AddressingMode::Implied, min_cycles: 6 }, // x60
-    Instruction { function: adc, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x61
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x62
-    Instruction { function: rra, mode: AddressingMode::IndirectX, min_cycles: 8 }, // x63
-    Instruction { function: nop, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x64
-    Instruction { function: adc, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x65
-    Instruction { function: ror, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x66
-    Instruction { function: rra, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x67
-    Instruction { function: pla, mode: AddressingMode::Implied, min_cycles: 4 }, // x68
-    Instruction { function: adc, mode: AddressingMode::Immediate, min_cycles: 2 }, // x69
-    Instruction { function: ror, mode: AddressingMode::Accumulator, min_cycles: 2 }, // x6A
-    Instruction { function: arr, mode: AddressingMode::Immediate, min_cycles: 2 }, // x6B
-    Instruction { function: jmp, mode: AddressingMode::Indirect, min_cycles: 5 }, // x6C
-    Instruction { function: adc, mode: AddressingMode::Absolute, min_cycles: 4 }, // x6D
-    Instruction { function: ror, mode: AddressingMode::Absolute, min_cycles: 6 }, // x6E
-    Instruction { function: rra, mode: AddressingMode::Absolute, min_cycles: 6 }, // x6F
-    Instruction { function: bvs, mode: AddressingMode::Relative, min_cycles: 2 }, // x70
-    Instruction { function: adc, mode: AddressingMode::IndirectY, min_cycles: 5 }, // x71
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x72
-    Instruction { function: rra, mode: AddressingMode::IndirectY, min_cycles: 8 }, // x73
-    Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x74
-    Instruction { function: adc, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x75
-    Instruction { function: ror, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x76
-    Instruction { function: rra, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x77
-    Instruction { function: sei, mode: AddressingMode::Implied, min_cycles: 2 }, // x78
-    Instruction { function: adc, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // x79
-    Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // x7A
-    Instruction { function: rra, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // x7B
-    Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x7C
-    Instruction { function: adc, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x7D
-    Instruction { function: ror, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x7E
-    Instruction { function: rra, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x7F
-    Instruction { function: nop, mode: AddressingMode::Immediate, min_cycles: 2 }, // x80
-    Instruction { function: sta, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x81
-    Instruction { function: nop, mode: AddressingMode::Immediate, min_cycles: 2 }, // x82
-    Instruction { function: sax, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x83
-    Instruction { function: sty, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x84
-    Instruction { function: sta, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x85
-    Instruction { function: stx, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x86
-    Instruction { function: sax, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x87
-    Instruction { function: dey, mode: AddressingMode::Implied, min_cycles: 2 }, // x88
-    Instruction { function: nop, mode: AddressingMode::Immediate, min_cycles: 2 }, // x89
-    Instruction { function: txa, mode: AddressingMode::Implied, min_cycles: 2 }, // x8A
-    Instruction { function: ane, mode: AddressingMode::Immediate, min_cycles: 2 }, // x8B
-    Instruction { function: sty, mode: AddressingMode::Absolute, min_cycles: 4 }, // x8C
-    Instruction { function: sta, mode: AddressingMode::Absolute, min_cycles: 4 }, // x8D
-    Instruction { function: stx, mode: AddressingMode::Absolute, min_cycles: 4 }, // x8E
-    Instruction { function: sax, mode: AddressingMode::Absolute, min_cycles: 4 }, // x8F
-    Instruction { function: bcc, mode: AddressingMode::Relative, min_cycles: 2 }, // x90
-    Instruction { function: sta, mode: AddressingMode::IndirectY, min_cycles: 6 }, // x91
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x92
-    Instruction { function: sha, mode: AddressingMode::IndirectY, min_cycles: 6 }, // x93
-    Instruction { function: sty, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x94
-    Instruction { function: sta, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x95
-    Instruction { function: stx, mode: AddressingMode::ZeroPageY, min_cycles: 4 }, // x96
-    Instruction { function: sax, mode: AddressingMode::ZeroPageY, min_cycles: 4 }, // x97
-    Instruction { function: tya, mode: AddressingMode::Implied, min_cycles: 2 }, // x98
-    Instruction { function: sta, mode: AddressingMode::AbsoluteY, min_cycles: 5 }, // x99
-    Instruction { function: txs, mode: AddressingMode::Implied, min_cycles: 2 }, // x9A
-    Instruction { function: tas, mode: AddressingMode::AbsoluteY, min_cycles: 5 }, // x9B
-    Instruction { function: shy, mode: AddressingMode::AbsoluteX, min_cycles: 5 }, // x9C
-    Instruction { function: sta, mode: AddressingMode::AbsoluteX, min_cycles: 5 }, // x9D
-    Instruction { function: shx, mode: AddressingMode::AbsoluteY, min_cycles: 5 }, // x9E
-    Instruction { function: sha, mode: AddressingMode::AbsoluteY, min_cycles: 5 }, // x9F
-    Instruction { function: ldy, mode: AddressingMode::Immediate, min_cycles: 2 }, // xA0
-    Instruction { function: lda, mode: AddressingMode::IndirectX, min_cycles: 6 }, // xA1
-    Instruction { function: ldx, mode: AddressingMode::Immediate, min_cycles: 2 }, // xA2
-    Instruction { function: lax, mode: AddressingMode::IndirectX, min_cycles: 6 }, // xA3
-    Instruction { function: ldy, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xA4
-    Instruction { function: lda, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xA5
-    Instruction { function: ldx, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xA6
-    Instruction { function: lax, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xA7
-    Instruction { function: tay, mode: AddressingMode::Implied, min_cycles: 2 }, // xA8
-    Instruction { function: lda, mode: AddressingMode::Immediate, min_cycles: 2 }, // xA9
-    Instruction { function: tax, mode: AddressingMode::Implied, min_cycles: 2 }, // xAA
-    Instruction { function: lxa, mode: AddressingMode::Immediate, min_cycles: 2 }, // xAB
-    Instruction { function: ldy, mode: AddressingMode::Absolute, min_cycles: 4 }, // xAC
-    Instruction { function: lda, mode: AddressingMode::Absolute, min_cycles: 4 }, // xAD
-    Instruction { function: ldx, mode: AddressingMode::Absolute, min_cycles: 4 }, // xAE
-    Instruction { function: lax, mode: AddressingMode::Absolute, min_cycles: 4 }, // xAF
-    Instruction { function: bcs, mode: AddressingMode::Relative, min_cycles: 2 }, // xB0
-    Instruction { function: lda, mode: AddressingMode::IndirectY, min_cycles: 5 }, // xB1
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // xB2
-    Instruction { function: lax, mode: AddressingMode::IndirectY, min_cycles: 5 }, // xB3
-    Instruction { function: ldy, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xB4
-    Instruction { function: lda, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xB5
-    Instruction { function: ldx, mode: AddressingMode::ZeroPageY, min_cycles: 4 }, // xB6
-    Instruction { function: lax, mode: AddressingMode::ZeroPageY, min_cycles: 4 }, // xB7
-    Instruction { function: clv, mode: AddressingMode::Implied, min_cycles: 2 }, // xB8
-    Instruction { function: lda, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xB9
-    Instruction { function: tsx, mode: AddressingMode::Implied, min_cycles: 2 }, // xBA
-    Instruction { function: las, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xBB
-    Instruction { function: ldy, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xBC
-    Instruction { function: lda, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xBD
-    Instruction { function: ldx, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xBE
-    Instruction { function: lax, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xBF
-    Instruction { function: cpy, mode: AddressingMode::Immediate, min_cycles: 2 }, // xC0
-    Instruction { function: cmp, mode: AddressingMode::IndirectX, min_cycles: 6 }, // xC1
-    Instruction { function: nop, mode: AddressingMode::Immediate, min_cycles: 2 }, // xC2
-    Instruction { function: dcp, mode: AddressingMode::IndirectX, min_cycles: 8 }, // xC3
-    Instruction { function: cpy, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xC4
-    Instruction { function: cmp, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xC5
-    Instruction { function: dec, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // xC6
-    Instruction { function: dcp, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // xC7
-    Instruction { function: iny, mode: AddressingMode::Implied, min_cycles: 2 }, // xC8
-    Instruction { function: cmp, mode: AddressingMode::Immediate, min_cycles: 2 }, // xC9
-    Instruction { function: dex, mode: AddressingMode::Implied, min_cycles: 2 }, // xCA
-    Instruction { function: sbx, mode: AddressingMode::Immediate, min_cycles: 2 }, // xCB
-    Instruction { function: cpy, mode: AddressingMode::Absolute, min_cycles: 4 }, // xCC
-    Instruction { function: cmp, mode: AddressingMode::Absolute, min_cycles: 4 }, // xCD
-    Instruction { function: dec, mode: AddressingMode::Absolute, min_cycles: 6 }, // xCE
-    Instruction { function: dcp, mode: AddressingMode::Absolute, min_cycles: 6 }, // xCF
-    Instruction { function: bne, mode: AddressingMode::Relative, min_cycles: 2 }, // xD0
-    Instruction { function: cmp, mode: AddressingMode::IndirectY, min_cycles: 5 }, // xD1
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // xD2
-    Instruction { function: dcp, mode: AddressingMode::IndirectY, min_cycles: 8 }, // xD3
-    Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xD4
-    Instruction { function: cmp, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xD5
-    Instruction { function: dec, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // xD6
-    Instruction { function: dcp, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // xD7
-    Instruction { function: cld, mode: AddressingMode::Implied, min_cycles: 2 }, // xD8
-    Instruction { function: cmp, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xD9
-    Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // xDA
-    Instruction { function: dcp, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // xDB
-    Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xDC
-    Instruction { function: cmp, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xDD
-    Instruction { function: dec, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // xDE
-    Instruction { function: dcp, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // xDF
-    Instruction { function: cpx, mode: AddressingMode::Immediate, min_cycles: 2 }, // xE0
-    Instruction { function: sbc, mode: AddressingMode::IndirectX, min_cycles: 6 }, // xE1
-    Instruction { function: nop, mode: AddressingMode::Immediate, min_cycles: 2 }, // xE2
-    Instruction { function: isc, mode: AddressingMode::IndirectX, min_cycles: 8 }, // xE3
-    Instruction { function: cpx, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xE4
-    Instruction { function: sbc, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xE5
-    Instruction { function: inc, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // xE6
-    Instruction { function: isc, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // xE7
-    Instruction { function: inx, mode: AddressingMode::Implied, min_cycles: 2 }, // xE8
-    Instruction { function: sbc, mode: AddressingMode::Immediate, min_cycles: 2 }, // xE9
-    Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // xEA
-    Instruction { function: sbc, mode: AddressingMode::Immediate, min_cycles: 2 }, // xEB
-    Instruction { function: cpx, mode: AddressingMode::Absolute, min_cycles: 4 }, // xEC
-    Instruction { function: sbc, mode: AddressingMode::Absolute, min_cycles: 4 }, // xED
-    Instruction { function: inc, mode: AddressingMode::Absolute, min_cycles: 6 }, // xEE
-    Instruction { function: isc, mode: AddressingMode::Absolute, min_cycles: 6 }, // xEF
-    Instruction { function: beq, mode: AddressingMode::Relative, min_cycles: 2 }, // xF0
-    Instruction { function: sbc, mode: AddressingMode::IndirectY, min_cycles: 5 }, // xF1
-    Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // xF2
-    Instruction { function: isc, mode: AddressingMode::IndirectY, min_cycles: 8 }, // xF3
-    Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xF4
-    Instruction { function: sbc, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xF5
-    Instruction { function: inc, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // xF6
-    Instruction { function: isc, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // xF7
-    Instruction { function: sed, mode: AddressingMode::Implied, min_cycles: 2 }, // xF8
-    Instruction { function: sbc, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xF9
-    Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // xFA
-    Instruction { function: isc, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // xFB
-    Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xFC
-    Instruction { function: sbc, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xFD
-    Instruction { function: inc, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // xFE
-    Instruction { function: isc, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // xFF
-];
+// Derived, these would need the bus to be Copy
+impl<B> Clone for Instruction<B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B> Copy for Instruction<B> {}
+
+impl<B: BusInterface> Instruction<B> {
+    pub const OPCODE_TABLE: [Instruction<B>; 256] = [
+        Instruction { function: brk, mode: AddressingMode::Implied, min_cycles: 7 }, // x00
+        Instruction { function: ora, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x01
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x02
+        Instruction { function: slo, mode: AddressingMode::IndirectX, min_cycles: 8 }, // x03
+        Instruction { function: nop, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x04
+        Instruction { function: ora, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x05
+        Instruction { function: asl, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x06
+        Instruction { function: slo, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x07
+        Instruction { function: php, mode: AddressingMode::Implied, min_cycles: 3 }, // x08
+        Instruction { function: ora, mode: AddressingMode::Immediate, min_cycles: 2 }, // x09
+        Instruction { function: asl, mode: AddressingMode::Accumulator, min_cycles: 2 }, // x0A
+        Instruction { function: anc, mode: AddressingMode::Immediate, min_cycles: 4 }, // x0B
+        Instruction { function: nop, mode: AddressingMode::Absolute, min_cycles: 4 }, // x0C
+        Instruction { function: ora, mode: AddressingMode::Absolute, min_cycles: 4 }, // x0D
+        Instruction { function: asl, mode: AddressingMode::Absolute, min_cycles: 6 }, // x0E
+        Instruction { function: slo, mode: AddressingMode::Absolute, min_cycles: 6 }, // x0F
+        Instruction { function: bpl, mode: AddressingMode::Relative, min_cycles: 2 }, // x10
+        Instruction { function: ora, mode: AddressingMode::IndirectY, min_cycles: 5 }, // x11
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x12
+        Instruction { function: slo, mode: AddressingMode::IndirectY, min_cycles: 8 }, // x13
+        Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x14
+        Instruction { function: ora, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x15
+        Instruction { function: asl, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x16
+        Instruction { function: slo, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x17
+        Instruction { function: clc, mode: AddressingMode::Implied, min_cycles: 2 }, // x18
+        Instruction { function: ora, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // x19
+        Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // x1A
+        Instruction { function: slo, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // x1B
+        Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x1C
+        Instruction { function: ora, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x1D
+        Instruction { function: asl, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x1E
+        Instruction { function: slo, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x1F
+        Instruction { function: jsr, mode: AddressingMode::Absolute, min_cycles: 6 }, // x20
+        Instruction { function: and, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x21
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x22
+        Instruction { function: rla, mode: AddressingMode::IndirectX, min_cycles: 8 }, // x23
+        Instruction { function: bit, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x24
+        Instruction { function: and, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x25
+        Instruction { function: rol, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x26
+        Instruction { function: rla, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x27
+        Instruction { function: plp, mode: AddressingMode::Implied, min_cycles: 4 }, // x28
+        Instruction { function: and, mode: AddressingMode::Immediate, min_cycles: 2 }, // x29
+        Instruction { function: rol, mode: AddressingMode::Accumulator, min_cycles: 2 }, // x2A
+        Instruction { function: anc, mode: AddressingMode::Immediate, min_cycles: 2 }, // x2B
+        Instruction { function: bit, mode: AddressingMode::Absolute, min_cycles: 4 }, // x2C
+        Instruction { function: and, mode: AddressingMode::Absolute, min_cycles: 4 }, // x2D
+        Instruction { function: rol, mode: AddressingMode::Absolute, min_cycles: 6 }, // x2E
+        Instruction { function: rla, mode: AddressingMode::Absolute, min_cycles: 6 }, // x2F
+        Instruction { function: bmi, mode: AddressingMode::Relative, min_cycles: 2 }, // x30
+        Instruction { function: and, mode: AddressingMode::IndirectY, min_cycles: 5 }, // x31
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x32
+        Instruction { function: rla, mode: AddressingMode::IndirectY, min_cycles: 8 }, // x33
+        Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x34
+        Instruction { function: and, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x35
+        Instruction { function: rol, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x36
+        Instruction { function: rla, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x37
+        Instruction { function: sec, mode: AddressingMode::Implied, min_cycles: 2 }, // x38
+        Instruction { function: and, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // x39
+        Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // x3A
+        Instruction { function: rla, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // x3B
+        Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x3C
+        Instruction { function: and, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x3D
+        Instruction { function: rol, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x3E
+        Instruction { function: rla, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x3F
+        Instruction { function: rti, mode: AddressingMode::Implied, min_cycles: 6 }, // x40
+        Instruction { function: eor, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x41
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x42
+        Instruction { function: sre, mode: AddressingMode::IndirectX, min_cycles: 8 }, // x43
+        Instruction { function: nop, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x44
+        Instruction { function: eor, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x45
+        Instruction { function: lsr, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x46
+        Instruction { function: sre, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x47
+        Instruction { function: pha, mode: AddressingMode::Implied, min_cycles: 3 }, // x48
+        Instruction { function: eor, mode: AddressingMode::Immediate, min_cycles: 2 }, // x49
+        Instruction { function: lsr, mode: AddressingMode::Accumulator, min_cycles: 2 }, // x4A
+        Instruction { function: alr, mode: AddressingMode::Immediate, min_cycles: 2 }, // x4B
+        Instruction { function: jmp, mode: AddressingMode::Absolute, min_cycles: 3 }, // x4C
+        Instruction { function: eor, mode: AddressingMode::Absolute, min_cycles: 4 }, // x4D
+        Instruction { function: lsr, mode: AddressingMode::Absolute, min_cycles: 6 }, // x4E
+        Instruction { function: sre, mode: AddressingMode::Absolute, min_cycles: 6 }, // x4F
+        Instruction { function: bvc, mode: AddressingMode::Relative, min_cycles: 2 }, // x50
+        Instruction { function: eor, mode: AddressingMode::IndirectY, min_cycles: 5 }, // x51
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x52
+        Instruction { function: sre, mode: AddressingMode::IndirectY, min_cycles: 8 }, // x53
+        Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x54
+        Instruction { function: eor, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x55
+        Instruction { function: lsr, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x56
+        Instruction { function: sre, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x57
+        Instruction { function: cli, mode: AddressingMode::Implied, min_cycles: 2 }, // x58
+        Instruction { function: eor, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // x59
+        Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // x5A
+        Instruction { function: sre, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // x5B
+        Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x5C
+        Instruction { function: eor, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x5D
+        Instruction { function: lsr, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x5E
+        Instruction { function: sre, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x5F
+        Instruction { function: rts, mode: AddressingMode::Implied, min_cycles: 6 }, // x60
+        Instruction { function: adc, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x61
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x62
+        Instruction { function: rra, mode: AddressingMode::IndirectX, min_cycles: 8 }, // x63
+        Instruction { function: nop, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x64
+        Instruction { function: adc, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x65
+        Instruction { function: ror, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x66
+        Instruction { function: rra, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // x67
+        Instruction { function: pla, mode: AddressingMode::Implied, min_cycles: 4 }, // x68
+        Instruction { function: adc, mode: AddressingMode::Immediate, min_cycles: 2 }, // x69
+        Instruction { function: ror, mode: AddressingMode::Accumulator, min_cycles: 2 }, // x6A
+        Instruction { function: arr, mode: AddressingMode::Immediate, min_cycles: 2 }, // x6B
+        Instruction { function: jmp, mode: AddressingMode::Indirect, min_cycles: 5 }, // x6C
+        Instruction { function: adc, mode: AddressingMode::Absolute, min_cycles: 4 }, // x6D
+        Instruction { function: ror, mode: AddressingMode::Absolute, min_cycles: 6 }, // x6E
+        Instruction { function: rra, mode: AddressingMode::Absolute, min_cycles: 6 }, // x6F
+        Instruction { function: bvs, mode: AddressingMode::Relative, min_cycles: 2 }, // x70
+        Instruction { function: adc, mode: AddressingMode::IndirectY, min_cycles: 5 }, // x71
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x72
+        Instruction { function: rra, mode: AddressingMode::IndirectY, min_cycles: 8 }, // x73
+        Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x74
+        Instruction { function: adc, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x75
+        Instruction { function: ror, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x76
+        Instruction { function: rra, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // x77
+        Instruction { function: sei, mode: AddressingMode::Implied, min_cycles: 2 }, // x78
+        Instruction { function: adc, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // x79
+        Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // x7A
+        Instruction { function: rra, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // x7B
+        Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x7C
+        Instruction { function: adc, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // x7D
+        Instruction { function: ror, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x7E
+        Instruction { function: rra, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // x7F
+        Instruction { function: nop, mode: AddressingMode::Immediate, min_cycles: 2 }, // x80
+        Instruction { function: sta, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x81
+        Instruction { function: nop, mode: AddressingMode::Immediate, min_cycles: 2 }, // x82
+        Instruction { function: sax, mode: AddressingMode::IndirectX, min_cycles: 6 }, // x83
+        Instruction { function: sty, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x84
+        Instruction { function: sta, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x85
+        Instruction { function: stx, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x86
+        Instruction { function: sax, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // x87
+        Instruction { function: dey, mode: AddressingMode::Implied, min_cycles: 2 }, // x88
+        Instruction { function: nop, mode: AddressingMode::Immediate, min_cycles: 2 }, // x89
+        Instruction { function: txa, mode: AddressingMode::Implied, min_cycles: 2 }, // x8A
+        Instruction { function: ane, mode: AddressingMode::Immediate, min_cycles: 2 }, // x8B
+        Instruction { function: sty, mode: AddressingMode::Absolute, min_cycles: 4 }, // x8C
+        Instruction { function: sta, mode: AddressingMode::Absolute, min_cycles: 4 }, // x8D
+        Instruction { function: stx, mode: AddressingMode::Absolute, min_cycles: 4 }, // x8E
+        Instruction { function: sax, mode: AddressingMode::Absolute, min_cycles: 4 }, // x8F
+        Instruction { function: bcc, mode: AddressingMode::Relative, min_cycles: 2 }, // x90
+        Instruction { function: sta, mode: AddressingMode::IndirectY, min_cycles: 6 }, // x91
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // x92
+        Instruction { function: sha, mode: AddressingMode::IndirectY, min_cycles: 6 }, // x93
+        Instruction { function: sty, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x94
+        Instruction { function: sta, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // x95
+        Instruction { function: stx, mode: AddressingMode::ZeroPageY, min_cycles: 4 }, // x96
+        Instruction { function: sax, mode: AddressingMode::ZeroPageY, min_cycles: 4 }, // x97
+        Instruction { function: tya, mode: AddressingMode::Implied, min_cycles: 2 }, // x98
+        Instruction { function: sta, mode: AddressingMode::AbsoluteY, min_cycles: 5 }, // x99
+        Instruction { function: txs, mode: AddressingMode::Implied, min_cycles: 2 }, // x9A
+        Instruction { function: tas, mode: AddressingMode::AbsoluteY, min_cycles: 5 }, // x9B
+        Instruction { function: shy, mode: AddressingMode::AbsoluteX, min_cycles: 5 }, // x9C
+        Instruction { function: sta, mode: AddressingMode::AbsoluteX, min_cycles: 5 }, // x9D
+        Instruction { function: shx, mode: AddressingMode::AbsoluteY, min_cycles: 5 }, // x9E
+        Instruction { function: sha, mode: AddressingMode::AbsoluteY, min_cycles: 5 }, // x9F
+        Instruction { function: ldy, mode: AddressingMode::Immediate, min_cycles: 2 }, // xA0
+        Instruction { function: lda, mode: AddressingMode::IndirectX, min_cycles: 6 }, // xA1
+        Instruction { function: ldx, mode: AddressingMode::Immediate, min_cycles: 2 }, // xA2
+        Instruction { function: lax, mode: AddressingMode::IndirectX, min_cycles: 6 }, // xA3
+        Instruction { function: ldy, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xA4
+        Instruction { function: lda, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xA5
+        Instruction { function: ldx, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xA6
+        Instruction { function: lax, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xA7
+        Instruction { function: tay, mode: AddressingMode::Implied, min_cycles: 2 }, // xA8
+        Instruction { function: lda, mode: AddressingMode::Immediate, min_cycles: 2 }, // xA9
+        Instruction { function: tax, mode: AddressingMode::Implied, min_cycles: 2 }, // xAA
+        Instruction { function: lxa, mode: AddressingMode::Immediate, min_cycles: 2 }, // xAB
+        Instruction { function: ldy, mode: AddressingMode::Absolute, min_cycles: 4 }, // xAC
+        Instruction { function: lda, mode: AddressingMode::Absolute, min_cycles: 4 }, // xAD
+        Instruction { function: ldx, mode: AddressingMode::Absolute, min_cycles: 4 }, // xAE
+        Instruction { function: lax, mode: AddressingMode::Absolute, min_cycles: 4 }, // xAF
+        Instruction { function: bcs, mode: AddressingMode::Relative, min_cycles: 2 }, // xB0
+        Instruction { function: lda, mode: AddressingMode::IndirectY, min_cycles: 5 }, // xB1
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // xB2
+        Instruction { function: lax, mode: AddressingMode::IndirectY, min_cycles: 5 }, // xB3
+        Instruction { function: ldy, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xB4
+        Instruction { function: lda, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xB5
+        Instruction { function: ldx, mode: AddressingMode::ZeroPageY, min_cycles: 4 }, // xB6
+        Instruction { function: lax, mode: AddressingMode::ZeroPageY, min_cycles: 4 }, // xB7
+        Instruction { function: clv, mode: AddressingMode::Implied, min_cycles: 2 }, // xB8
+        Instruction { function: lda, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xB9
+        Instruction { function: tsx, mode: AddressingMode::Implied, min_cycles: 2 }, // xBA
+        Instruction { function: las, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xBB
+        Instruction { function: ldy, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xBC
+        Instruction { function: lda, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xBD
+        Instruction { function: ldx, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xBE
+        Instruction { function: lax, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xBF
+        Instruction { function: cpy, mode: AddressingMode::Immediate, min_cycles: 2 }, // xC0
+        Instruction { function: cmp, mode: AddressingMode::IndirectX, min_cycles: 6 }, // xC1
+        Instruction { function: nop, mode: AddressingMode::Immediate, min_cycles: 2 }, // xC2
+        Instruction { function: dcp, mode: AddressingMode::IndirectX, min_cycles: 8 }, // xC3
+        Instruction { function: cpy, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xC4
+        Instruction { function: cmp, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xC5
+        Instruction { function: dec, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // xC6
+        Instruction { function: dcp, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // xC7
+        Instruction { function: iny, mode: AddressingMode::Implied, min_cycles: 2 }, // xC8
+        Instruction { function: cmp, mode: AddressingMode::Immediate, min_cycles: 2 }, // xC9
+        Instruction { function: dex, mode: AddressingMode::Implied, min_cycles: 2 }, // xCA
+        Instruction { function: sbx, mode: AddressingMode::Immediate, min_cycles: 2 }, // xCB
+        Instruction { function: cpy, mode: AddressingMode::Absolute, min_cycles: 4 }, // xCC
+        Instruction { function: cmp, mode: AddressingMode::Absolute, min_cycles: 4 }, // xCD
+        Instruction { function: dec, mode: AddressingMode::Absolute, min_cycles: 6 }, // xCE
+        Instruction { function: dcp, mode: AddressingMode::Absolute, min_cycles: 6 }, // xCF
+        Instruction { function: bne, mode: AddressingMode::Relative, min_cycles: 2 }, // xD0
+        Instruction { function: cmp, mode: AddressingMode::IndirectY, min_cycles: 5 }, // xD1
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // xD2
+        Instruction { function: dcp, mode: AddressingMode::IndirectY, min_cycles: 8 }, // xD3
+        Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xD4
+        Instruction { function: cmp, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xD5
+        Instruction { function: dec, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // xD6
+        Instruction { function: dcp, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // xD7
+        Instruction { function: cld, mode: AddressingMode::Implied, min_cycles: 2 }, // xD8
+        Instruction { function: cmp, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xD9
+        Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // xDA
+        Instruction { function: dcp, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // xDB
+        Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xDC
+        Instruction { function: cmp, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xDD
+        Instruction { function: dec, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // xDE
+        Instruction { function: dcp, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // xDF
+        Instruction { function: cpx, mode: AddressingMode::Immediate, min_cycles: 2 }, // xE0
+        Instruction { function: sbc, mode: AddressingMode::IndirectX, min_cycles: 6 }, // xE1
+        Instruction { function: nop, mode: AddressingMode::Immediate, min_cycles: 2 }, // xE2
+        Instruction { function: isc, mode: AddressingMode::IndirectX, min_cycles: 8 }, // xE3
+        Instruction { function: cpx, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xE4
+        Instruction { function: sbc, mode: AddressingMode::ZeroPage, min_cycles: 3 }, // xE5
+        Instruction { function: inc, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // xE6
+        Instruction { function: isc, mode: AddressingMode::ZeroPage, min_cycles: 5 }, // xE7
+        Instruction { function: inx, mode: AddressingMode::Implied, min_cycles: 2 }, // xE8
+        Instruction { function: sbc, mode: AddressingMode::Immediate, min_cycles: 2 }, // xE9
+        Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // xEA
+        Instruction { function: sbc, mode: AddressingMode::Immediate, min_cycles: 2 }, // xEB
+        Instruction { function: cpx, mode: AddressingMode::Absolute, min_cycles: 4 }, // xEC
+        Instruction { function: sbc, mode: AddressingMode::Absolute, min_cycles: 4 }, // xED
+        Instruction { function: inc, mode: AddressingMode::Absolute, min_cycles: 6 }, // xEE
+        Instruction { function: isc, mode: AddressingMode::Absolute, min_cycles: 6 }, // xEF
+        Instruction { function: beq, mode: AddressingMode::Relative, min_cycles: 2 }, // xF0
+        Instruction { function: sbc, mode: AddressingMode::IndirectY, min_cycles: 5 }, // xF1
+        Instruction { function: jam, mode: AddressingMode::Implied, min_cycles: 0 }, // xF2
+        Instruction { function: isc, mode: AddressingMode::IndirectY, min_cycles: 8 }, // xF3
+        Instruction { function: nop, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xF4
+        Instruction { function: sbc, mode: AddressingMode::ZeroPageX, min_cycles: 4 }, // xF5
+        Instruction { function: inc, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // xF6
+        Instruction { function: isc, mode: AddressingMode::ZeroPageX, min_cycles: 6 }, // xF7
+        Instruction { function: sed, mode: AddressingMode::Implied, min_cycles: 2 }, // xF8
+        Instruction { function: sbc, mode: AddressingMode::AbsoluteY, min_cycles: 4 }, // xF9
+        Instruction { function: nop, mode: AddressingMode::Implied, min_cycles: 2 }, // xFA
+        Instruction { function: isc, mode: AddressingMode::AbsoluteY, min_cycles: 7 }, // xFB
+        Instruction { function: nop, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xFC
+        Instruction { function: sbc, mode: AddressingMode::AbsoluteX, min_cycles: 4 }, // xFD
+        Instruction { function: inc, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // xFE
+        Instruction { function: isc, mode: AddressingMode::AbsoluteX, min_cycles: 7 }, // xFF
+    ];
+}
 
 // // Official Instructions
 //Access Instructions
-fn lda(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn lda<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let (data, cycles) = if mode == AddressingMode::Immediate {
         (cpu.fetch_operand(), 0)
     }else{
@@ -301,13 +336,13 @@ fn lda(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     cycles
 }
 
-fn sta(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
+fn sta<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
     cpu.bus.write(addr, cpu.a);
     cycles
 }
 
-fn ldx(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn ldx<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let (data, cycles) = if mode == AddressingMode::Immediate {
         (cpu.fetch_operand(), 0)
     }else {
@@ -319,13 +354,13 @@ fn ldx(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     cycles
 }
 
-fn stx(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
+fn stx<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
     cpu.bus.write(addr, cpu.x);
     cycles
 }
 
-fn ldy(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn ldy<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let (data, cycles) = if mode == AddressingMode::Immediate {
         (cpu.fetch_operand(), 0)
     }else {
@@ -337,39 +372,39 @@ fn ldy(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     cycles
 }
 
-fn sty(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
+fn sty<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
     cpu.bus.write(addr, cpu.y);
     cycles
 }
 
 //Transfer Instructions
-fn tax(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn tax<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.x = cpu.a;
     cpu.set_zero_negative_flag(cpu.a);
     0
 }
 
-fn txa(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn txa<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.a = cpu.x;
     cpu.set_zero_negative_flag(cpu.x);
     0
 }
 
-fn tay(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn tay<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.y = cpu.a;
     cpu.set_zero_negative_flag(cpu.a);
     0
 }
 
-fn tya(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn tya<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.a = cpu.y;
     cpu.set_zero_negative_flag(cpu.y);
     0
 }
 
 //Arithmetic Instructions
-fn adc(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn adc<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let (data, cycles) = if mode == AddressingMode::Immediate {
         (cpu.fetch_operand(), 0)
     }else {
@@ -387,7 +422,7 @@ fn adc(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     cycles
 }
 
-fn sbc(cpu: &mut Cpu, mode: AddressingMode) -> u8 {
+fn sbc<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8 {
     let mut total_cycles: u8 = 0;
     
     let data = if mode == AddressingMode::Immediate {
@@ -421,46 +456,46 @@ fn sbc(cpu: &mut Cpu, mode: AddressingMode) -> u8 {
     total_cycles
 }
 
-fn inc(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
+fn inc<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
     let data = cpu.bus.read(addr);
     let result = data.wrapping_add(1);
     cpu.set_zero_negative_flag(result);
-    cpu.bus.write(addr, result);
+    cpu.rmw_write(addr, data, result);
     cycles
 }
 
-fn dec(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
+fn dec<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
     let data = cpu.bus.read(addr);
     let result = data.wrapping_sub(1);
     cpu.set_zero_negative_flag(result);
-    cpu.bus.write(addr, result);
+    cpu.rmw_write(addr, data, result);
     cycles
 }
 
-fn inx(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn inx<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     let result = cpu.x.wrapping_add(1);
     cpu.set_zero_negative_flag(result);
     cpu.x = result;
     0
 }
 
-fn dex(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn dex<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     let result = cpu.x.wrapping_sub(1);
     cpu.set_zero_negative_flag(result);
     cpu.x = result;
     0
 }
 
-fn iny(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn iny<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     let result = cpu.y.wrapping_add(1);
     cpu.set_zero_negative_flag(result);
     cpu.y = result;
     0
 }
 
-fn dey(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn dey<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     let result = cpu.y.wrapping_sub(1);
     cpu.set_zero_negative_flag(result);
     cpu.y = result;
@@ -468,7 +503,7 @@ fn dey(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
 }
 
 //Shift Instructions
-fn asl(cpu: &mut Cpu, mode: AddressingMode) -> u8 {
+fn asl<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8 {
     if mode == AddressingMode::Accumulator {
         cpu.set_flag(StatusFlag::Carry, cpu.a & 0x80 != 0);
         let result = cpu.a << 1;
@@ -476,17 +511,17 @@ fn asl(cpu: &mut Cpu, mode: AddressingMode) -> u8 {
         cpu.a = result;
         0
     }else{
-        let (addr, cycles) = cpu.fetch_operand_addr(mode);
+        let (addr, cycles) = cpu.fetch_write_addr(mode);
         let data = cpu.bus.read(addr);
         cpu.set_flag(StatusFlag::Carry, data & 0x80 != 0);
         let result = data << 1;
         cpu.set_zero_negative_flag(result);
-        cpu.bus.write(addr, result);
+        cpu.rmw_write(addr, data, result);
         cycles
     }
 }
 
-fn lsr(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn lsr<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     if mode == AddressingMode::Accumulator {
         cpu.set_flag(StatusFlag::Carry, cpu.a & 0x1u8 != 0);
         let result = cpu.a >> 1;
@@ -494,17 +529,17 @@ fn lsr(cpu: &mut Cpu, mode: AddressingMode) -> u8{
         cpu.a = result;
         0
     }else{
-        let (addr, cycles) = cpu.fetch_operand_addr(mode);
+        let (addr, cycles) = cpu.fetch_write_addr(mode);
         let data = cpu.bus.read(addr);
         cpu.set_flag(StatusFlag::Carry, data & 0x1u8 != 0);
         let result = data >> 1;
-        cpu.bus.write(addr, result);
+        cpu.rmw_write(addr, data, result);
         cpu.set_zero_negative_flag(result);
         cycles
     }
 }
 
-fn rol(cpu: &mut Cpu, mode: AddressingMode) -> u8 {
+fn rol<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8 {
     let mut total_cycles: u8 = 0;
     
     if mode == AddressingMode::Accumulator {
@@ -520,7 +555,7 @@ fn rol(cpu: &mut Cpu, mode: AddressingMode) -> u8 {
         cpu.set_flag(StatusFlag::Negative, result & 0x80 != 0);
         cpu.a = result;
     } else {
-        let (addr, cycles) = cpu.fetch_operand_addr(mode);
+        let (addr, cycles) = cpu.fetch_write_addr(mode);
         total_cycles += cycles;
         let data = cpu.bus.read(addr);
         
@@ -533,13 +568,13 @@ fn rol(cpu: &mut Cpu, mode: AddressingMode) -> u8 {
         
         cpu.set_flag(StatusFlag::Zero, result == 0);
         cpu.set_flag(StatusFlag::Negative, result & 0x80 != 0);
-        cpu.bus.write(addr, result);
+        cpu.rmw_write(addr, data, result);
     }
     
     total_cycles
 }
 
-fn ror(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn ror<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let mut total_cycles: u8 = 0;
     if mode == AddressingMode::Accumulator {
         let data = cpu.a;
@@ -550,7 +585,7 @@ fn ror(cpu: &mut Cpu, mode: AddressingMode) -> u8{
         cpu.set_flag(StatusFlag::Negative, result & 0x80 != 0);
         cpu.a = result;
     }else{
-        let (addr, cycles) = cpu.fetch_operand_addr(mode);
+        let (addr, cycles) = cpu.fetch_write_addr(mode);
         total_cycles += cycles;
         let data = cpu.bus.read(addr);
         let old_carry: u8 = if cpu.p & 0x1u8 != 0 { 0x80 } else { 0 };
@@ -558,13 +593,13 @@ fn ror(cpu: &mut Cpu, mode: AddressingMode) -> u8{
         let result = (data >> 1) | old_carry;
         cpu.set_flag(StatusFlag::Zero, result == 0);
         cpu.set_flag(StatusFlag::Negative, result & 0x80 != 0);
-        cpu.bus.write(addr, result);
+        cpu.rmw_write(addr, data, result);
     }
     total_cycles
 }
 
 //Bitwise Instructions
-fn and(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn and<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let (data, cycles) = if mode == AddressingMode::Immediate {
         (cpu.fetch_operand(), 0)
     }else {
@@ -577,7 +612,7 @@ fn and(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     cycles
 }
 
-fn ora(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn ora<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let mut total_cycles: u8 = 0;
     let data = if mode == AddressingMode::Immediate {
         cpu.fetch_operand()
@@ -594,7 +629,7 @@ fn ora(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     total_cycles
 }
 
-fn eor(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn eor<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let mut total_cycles: u8 = 0;
     let data = if mode == AddressingMode::Immediate {
         cpu.fetch_operand()
@@ -611,7 +646,7 @@ fn eor(cpu: &mut Cpu, mode: AddressingMode) -> u8{
 
 }
 
-fn bit(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn bit<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let (addr, cycles) = cpu.fetch_operand_addr(mode);
     let data = cpu.bus.read(addr);
     let result = cpu.a & data;
@@ -622,7 +657,7 @@ fn bit(cpu: &mut Cpu, mode: AddressingMode) -> u8{
 }
 
 //Compare Instructions
-fn cmp(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn cmp<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let mut total_cycles = 0;
     let data = if mode == AddressingMode::Immediate {
         cpu.fetch_operand()
@@ -639,7 +674,7 @@ fn cmp(cpu: &mut Cpu, mode: AddressingMode) -> u8{
 
 }
 
-fn cpx(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn cpx<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let mut total_cycles = 0;
     let data = if mode == AddressingMode::Immediate {
         cpu.fetch_operand()
@@ -655,7 +690,7 @@ fn cpx(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     total_cycles
 }
 
-fn cpy(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn cpy<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let mut total_cycles = 0;
     let data = if mode == AddressingMode::Immediate {
         cpu.fetch_operand()
@@ -672,90 +707,105 @@ fn cpy(cpu: &mut Cpu, mode: AddressingMode) -> u8{
 }
 
 //Branch Instructions
-pub fn branch(cpu: &mut Cpu, mode: AddressingMode, condition: bool) -> u8 {
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
-    let mut cycles_took = cycles;
-    if condition {
-        cpu.pc = addr;
-        cycles_took += 1;
+// +1 cycle when taken, +1 more when the target is on a different page than
+// the next instruction. Both read from PC, the second before its high byte
+// is fixed up.
+pub fn branch<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode, condition: bool) -> u8 {
+    let (addr, _) = cpu.fetch_operand_addr(mode);
+    if !condition {
+        return 0;
     }
-    cycles_took
+    cpu.bus.dummy_read(cpu.pc);
+    let page_cross = if (cpu.pc & 0xFF00) != (addr & 0xFF00) {
+        cpu.bus.dummy_read((cpu.pc & 0xFF00) | (addr & 0x00FF));
+        1
+    } else {
+        0
+    };
+    cpu.pc = addr;
+    1 + page_cross
 }
 
-fn bcc(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn bcc<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let carry = cpu.p & 0x1u8 == 0;
     branch(cpu, mode, carry)
 }
 
-fn bcs(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn bcs<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let carry = cpu.p & 0x1u8 != 0;
     branch(cpu, mode, carry)
 }
 
-fn beq(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn beq<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let zero = cpu.p & 0x2u8 != 0;
     branch(cpu, mode, zero)
 }
 
-fn bne(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn bne<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let zero = cpu.p & 0x2u8 == 0;
     branch(cpu, mode, zero)
 }
 
-fn bpl(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn bpl<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let negative = cpu.p & 0x80 == 0;
     branch(cpu, mode, negative)
 }
 
-fn bmi(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn bmi<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let negative = cpu.p & 0x80 != 0;
     branch(cpu, mode, negative)
 }
 
-fn bvc(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn bvc<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let overflow = cpu.p & 0x40 == 0;
     branch(cpu, mode, overflow)
 }
 
-fn bvs(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn bvs<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let overflow = cpu.p & 0x40 != 0;
     branch(cpu, mode, overflow)
 }
 
 //Jump Instructions
-fn jmp(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn jmp<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let (addr, cycles) = cpu.fetch_operand_addr(mode);
     cpu.pc = addr;
     cycles
 }
 
-fn jsr(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (target_address, cycles) = cpu.fetch_operand_addr(mode);
-    
-    let return_addr = cpu.pc.wrapping_sub(1);
+fn jsr<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
+    // The target's high byte is only read once the return address, which
+    // points at it, is pushed
+    let lo = cpu.fetch_operand() as u16;
+    cpu.stack_peek();
+
+    let return_addr = cpu.pc;
     cpu.stack_push((return_addr >> 8) as u8);
     cpu.stack_push(return_addr as u8);
 
-    cpu.pc = target_address;
-    cycles
+    let hi = cpu.fetch_operand() as u16;
+    cpu.pc = (hi << 8) | lo;
+    0
 }
 
-fn rts(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn rts<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
+    cpu.stack_peek();
     let lo = cpu.stack_pop() as u16; 
     let hi = cpu.stack_pop() as u16;
 
     let return_address = (hi << 8) | lo;
+    cpu.bus.dummy_read(return_address);
     cpu.pc = return_address.wrapping_add(1);
     0
 }
 
-fn brk(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn brk<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.interrupt(Interrupt::BRK);
     0
 }
 
-fn rti(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
-
+fn rti<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
+    cpu.stack_peek();
     cpu.p = cpu.stack_pop();
     cpu.set_flag(StatusFlag::Break, false);
     let lo = cpu.stack_pop() as u16;
@@ -765,14 +815,15 @@ fn rti(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
 }
 
 //Stack Instructions
-fn pha(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn pha<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     let addr = 0x0100 + cpu.sp as u16;
     cpu.bus.write(addr, cpu.a);
     cpu.sp = cpu.sp.wrapping_sub(1);
     0
 }
 
-fn pla(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn pla<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
+    cpu.stack_peek();
     cpu.sp = cpu.sp.wrapping_add(1);
     let addr = 0x0100 + cpu.sp as u16;
     let data = cpu.bus.read(addr);
@@ -782,7 +833,7 @@ fn pla(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
     0
 }
 
-fn php(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn php<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     let addr = 0x0100 + cpu.sp as u16;
     let value = cpu.p | 0x30u8;
     cpu.bus.write(addr, value);
@@ -790,7 +841,8 @@ fn php(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
     0
 }
 
-fn plp(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn plp<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
+    cpu.stack_peek();
     cpu.sp = cpu.sp.wrapping_add(1);
     let addr = 0x0100 + cpu.sp as u16;
     let data = cpu.bus.read(addr);
@@ -805,12 +857,12 @@ fn plp(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
     0
 }
 
-fn txs(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn txs<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.sp = cpu.x;
     0
 }
 
-fn tsx(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn tsx<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.x = cpu.sp;
     cpu.set_flag(StatusFlag::Zero, cpu.sp == 0);
     cpu.set_flag(StatusFlag::Negative, cpu.sp & 0x80u8 != 0);
@@ -818,80 +870,86 @@ fn tsx(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
 }
 
 //Flag Instructions
-fn clc(cpu: &mut Cpu, _mode: AddressingMode) -> u8{ 
+fn clc<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{ 
     cpu.set_flag(StatusFlag::Carry, false);
     0
 }
 
-fn sec(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn sec<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.set_flag(StatusFlag::Carry, true);
     0
 }
 
-fn cli(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn cli<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.set_flag(StatusFlag::InterruptDisable, false);
     0
 }
 
-fn sei(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn sei<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.update_interrupt_disable = (true, 1);
     0
 }
 
-fn cld(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn cld<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.set_flag(StatusFlag::Decimal, false);
     0
 }
 
-fn sed(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn sed<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.set_flag(StatusFlag::Decimal, true);
     0
 }
 
-fn clv(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn clv<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     cpu.set_flag(StatusFlag::Overflow, false);
     0
 }
 
 // // Unofficial Opcodes
-fn nop(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn nop<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     if mode == AddressingMode::Implied {
         0
     }else if mode == AddressingMode::Immediate {
         cpu.fetch_operand();
         0
     }else{
-        let (_addr, cycles) = cpu.fetch_operand_addr(mode);
+        // Reads its operand like LDA, for nothing
+        let (addr, cycles) = cpu.fetch_operand_addr(mode);
+        cpu.bus.read(addr);
         return cycles
     }
 }
 
-fn jam(_cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn jam<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
+    // Locks up until RESET; PC stays on the JAM opcode
+    cpu.pc = cpu.pc.wrapping_sub(1);
+    log::warn!(target: "cpu", "JAM at ${:04X}", cpu.pc);
+    cpu.jammed = true;
     0
-    //panic!("CPU halted due to JAM instruction at PC: {:X}", cpu.pc);
 }
 
-fn slo(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
-    let mut data = cpu.bus.read(addr);
+fn slo<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
+    let old = cpu.bus.read(addr);
+    let mut data = old;
     cpu.set_flag(StatusFlag::Carry, data & 0x80u8 != 0);
     data <<= 1;
-    cpu.bus.write(addr, data);
+    cpu.rmw_write(addr, old, data);
     cpu.a |= data;
     cpu.set_flag(StatusFlag::Zero, cpu.a == 0);
     cpu.set_flag(StatusFlag::Negative, cpu.a & 0x80u8 != 0);
     cycles
 }
 
-fn ane(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
-    //Unstable, recommended to use operand 0.
-    cpu.a = 0;
-    cpu.set_flag(StatusFlag::Zero, true);
-    cpu.set_flag(StatusFlag::Negative, false);
+fn ane<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
+    // Unstable: the result depends on a chip-specific constant ORed into A
+    let operand = cpu.fetch_operand();
+    cpu.a = (cpu.a | cpu.unstable_magic) & cpu.x & operand;
+    cpu.set_zero_negative_flag(cpu.a);
     0
 }
 
-fn anc(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn anc<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     let operand = cpu.fetch_operand();
     cpu.a &= operand;
     cpu.set_flag(StatusFlag::Carry, cpu.a & 0x80u8 != 0);
@@ -900,26 +958,28 @@ fn anc(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
     0
 }
 
-fn sre(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
-    let mut data = cpu.bus.read(addr);
+fn sre<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
+    let old = cpu.bus.read(addr);
+    let mut data = old;
     cpu.set_flag(StatusFlag::Carry, data & 0x01 != 0);
     data >>= 1;
-    cpu.bus.write(addr, data);
+    cpu.rmw_write(addr, old, data);
     cpu.a ^= data;
     cpu.set_flag(StatusFlag::Zero, cpu.a == 0);
     cpu.set_flag(StatusFlag::Negative, cpu.a & 0x80 != 0);
     cycles
 }
 
-fn rla(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
-    let mut data = cpu.bus.read(addr);
+fn rla<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
+    let old = cpu.bus.read(addr);
+    let mut data = old;
     let carry_in = cpu.p & 0x1u8;
     cpu.set_flag(StatusFlag::Carry, data & 0x80 != 0);
     data = (data << 1) | carry_in;
 
-    cpu.bus.write(addr, data);
+    cpu.rmw_write(addr, old, data);
     cpu.a &= data;
 
     cpu.set_flag(StatusFlag::Zero, cpu.a == 0);
@@ -927,17 +987,18 @@ fn rla(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     cycles
 }
 
-fn sax(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
+fn sax<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
     cpu.bus.write(addr, cpu.a & cpu.x);
     cycles
 }
 
-fn rra(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, extra_cycles) = cpu.fetch_operand_addr(mode);
+fn rra<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, extra_cycles) = cpu.fetch_write_addr(mode);
     
     // First do ROR
-    let mut data = cpu.bus.read(addr);
+    let old = cpu.bus.read(addr);
+    let mut data = old;
     let old_carry = cpu.get_carry_bit();
     
     // Set new carry from bit 0
@@ -945,7 +1006,7 @@ fn rra(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     
     // Rotate right, putting old carry in bit 7
     data = (data >> 1) | (old_carry << 7);
-    cpu.bus.write(addr, data);
+    cpu.rmw_write(addr, old, data);
     
     // Then do ADC
     let carry_in = cpu.get_carry_bit();
@@ -964,11 +1025,12 @@ fn rra(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     extra_cycles
 }
 
-fn dcp(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
-    let mut data = cpu.bus.read(addr);
+fn dcp<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
+    let old = cpu.bus.read(addr);
+    let mut data = old;
     data = data.wrapping_sub(1);
-    cpu.bus.write(addr, data);
+    cpu.rmw_write(addr, old, data);
     let result = cpu.a.wrapping_sub(data);
     cpu.set_flag(StatusFlag::Carry, cpu.a >= data);
     cpu.set_flag(StatusFlag::Zero, result == 0);
@@ -976,13 +1038,14 @@ fn dcp(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     cycles
 }
 
-fn isc(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, extra_cycles) = cpu.fetch_operand_addr(mode);
+fn isc<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let (addr, extra_cycles) = cpu.fetch_write_addr(mode);
     
     // First increment memory
-    let mut data = cpu.bus.read(addr);
+    let old = cpu.bus.read(addr);
+    let mut data = old;
     data = data.wrapping_add(1);
-    cpu.bus.write(addr, data);
+    cpu.rmw_write(addr, old, data);
     
     // Then do SBC
     let carry = cpu.get_carry_bit();
@@ -1005,14 +1068,16 @@ fn isc(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     extra_cycles
 }
 
-fn lxa(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    
-    let mut total_cycles = lda(cpu, mode);
-    total_cycles += tax(cpu, mode);
-    total_cycles
+fn lxa<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
+    // Unstable like ANE, with the same constant
+    let operand = cpu.fetch_operand();
+    cpu.a = (cpu.a | cpu.unstable_magic) & operand;
+    cpu.x = cpu.a;
+    cpu.set_zero_negative_flag(cpu.a);
+    0
 }
 
-fn las(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn las<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let (addr, cycles) = cpu.fetch_operand_addr(mode);
     let data = cpu.bus.read(addr);
     let result = data & cpu.sp;
@@ -1025,7 +1090,7 @@ fn las(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     cycles
 }
 
-fn lax(cpu: &mut Cpu, mode: AddressingMode) -> u8{
+fn lax<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     let (addr, cycles) = cpu.fetch_operand_addr(mode);
     let data = cpu.bus.read(addr);
     cpu.a = data;
@@ -1034,7 +1099,7 @@ fn lax(cpu: &mut Cpu, mode: AddressingMode) -> u8{
     cycles
 }
 
-fn sbx(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn sbx<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     let operand = cpu.fetch_operand();
     let temp = cpu.a & cpu.x;
     let result = (temp).wrapping_sub(operand);
@@ -1046,58 +1111,42 @@ fn sbx(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
     0
 }
 
-fn sha(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
-    let value = cpu.a & cpu.x & ((addr >> 8) as u8 + 1);
-    cpu.bus.write(addr, value);
-    cycles
+fn sha<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
+    let value = cpu.a & cpu.x;
+    unstable_store(cpu, mode, cpu.y, value)
 }
 
-fn shx(cpu: &mut Cpu, mode: AddressingMode) -> u8 {
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
-    let high_byte = (addr >> 8) as u8;
-    let value = cpu.x & (high_byte.wrapping_add(1));
-    
-    // Calculate actual address, which can be affected by page crossing
-    let effective_addr = if (addr & 0xFF) + (cpu.y as u16) > 0xFF {
-        // Page boundary crossed - high byte becomes unstable
-        (value as u16) << 8 | (addr & 0xFF) + (cpu.y as u16)
+// SHA/SHX/SHY/TAS AND the stored value with the base address high byte + 1.
+// When indexing carries into the high byte, the value replaces it as well.
+fn unstable_store<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode, index: u8, value: u8) -> u8 {
+    let (addr, cycles) = cpu.fetch_write_addr(mode);
+    let base_addr = addr.wrapping_sub(index as u16);
+    let value = value & ((base_addr >> 8) as u8).wrapping_add(1);
+
+    let effective_addr = if (base_addr & 0xFF00) != (addr & 0xFF00) {
+        ((value as u16) << 8) | (addr & 0x00FF)
     } else {
-        // No page boundary crossing
         addr
     };
-    
+
     cpu.bus.write(effective_addr, value);
     cycles
 }
 
-fn shy(cpu: &mut Cpu, mode: AddressingMode) -> u8 {
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
-    let high_byte = (addr >> 8) as u8;
-    let value = cpu.y & (high_byte.wrapping_add(1));
-    
-    // Calculate actual address, which can be affected by page crossing
-    let effective_addr = if (addr & 0xFF) + (cpu.x as u16) > 0xFF {
-        // Page boundary crossed - high byte becomes unstable
-        (value as u16) << 8 | (addr & 0xFF) + (cpu.x as u16)
-    } else {
-        // No page boundary crossing
-        addr
-    };
-    
-    cpu.bus.write(effective_addr, value);
-    cycles
+fn shx<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8 {
+    unstable_store(cpu, mode, cpu.y, cpu.x)
 }
 
-fn tas(cpu: &mut Cpu, mode: AddressingMode) -> u8{
-    let (addr, cycles) = cpu.fetch_operand_addr(mode);
+fn shy<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8 {
+    unstable_store(cpu, mode, cpu.x, cpu.y)
+}
+
+fn tas<B: BusInterface>(cpu: &mut Cpu<B>, mode: AddressingMode) -> u8{
     cpu.sp = cpu.a & cpu.x;
-    let value = cpu.sp & ((addr >> 8) as u8 + 1);
-    cpu.bus.write(addr, value);
-    cycles
+    unstable_store(cpu, mode, cpu.y, cpu.sp)
 }
 
-fn arr(cpu: &mut Cpu, _mode: AddressingMode) -> u8 {
+fn arr<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8 {
     // Step 1: AND the accumulator with the operand
     let operand = cpu.fetch_operand();
     cpu.a &= operand;
@@ -1119,7 +1168,7 @@ fn arr(cpu: &mut Cpu, _mode: AddressingMode) -> u8 {
     0  // cycles
 }
 
-fn alr(cpu: &mut Cpu, _mode: AddressingMode) -> u8{
+fn alr<B: BusInterface>(cpu: &mut Cpu<B>, _mode: AddressingMode) -> u8{
     let operand = cpu.fetch_operand();
     cpu.a &= operand;
     cpu.set_flag(StatusFlag::Carry, (cpu.a & 0x1) != 0);
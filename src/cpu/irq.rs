@@ -0,0 +1,79 @@
+// Sources sharing the CPU's /IRQ pin. Each one drives the line on its own and
+// the CPU sees them wired-OR'd together.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IrqSource {
+    Mapper = 0b0001,
+    FrameCounter = 0b0010,
+    Dmc = 0b0100,
+    External = 0b1000,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IrqLine {
+    sources: u8,
+}
+
+impl IrqLine {
+    pub fn new() -> Self {
+        IrqLine::default()
+    }
+
+    pub fn assert(&mut self, source: IrqSource) {
+        self.sources |= source as u8;
+    }
+
+    pub fn deassert(&mut self, source: IrqSource) {
+        self.sources &= !(source as u8);
+    }
+
+    pub fn set(&mut self, source: IrqSource, asserted: bool) {
+        if asserted {
+            self.assert(source);
+        } else {
+            self.deassert(source);
+        }
+    }
+
+    pub fn is_asserted_by(&self, source: IrqSource) -> bool {
+        self.sources & source as u8 != 0
+    }
+
+    // Level of the line as the CPU samples it
+    pub fn is_asserted(&self) -> bool {
+        self.sources != 0
+    }
+
+    pub fn clear(&mut self) {
+        self.sources = 0;
+    }
+}
+
+// What the CPU will see when it next polls for interrupts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingInterrupts {
+    // An NMI edge waiting to be taken
+    pub nmi: bool,
+    // The /IRQ line and who's holding it. It's only taken with the I flag
+    // clear.
+    pub irq: IrqLine,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_stays_low_until_every_source_releases() {
+        let mut irq = IrqLine::new();
+        irq.assert(IrqSource::Mapper);
+        irq.assert(IrqSource::Dmc);
+        irq.deassert(IrqSource::Mapper);
+        assert!(irq.is_asserted());
+        assert!(irq.is_asserted_by(IrqSource::Dmc));
+        assert!(!irq.is_asserted_by(IrqSource::Mapper));
+
+        irq.set(IrqSource::Dmc, false);
+        assert!(!irq.is_asserted());
+    }
+}
\ No newline at end of file
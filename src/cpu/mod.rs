@@ -1,5 +1,14 @@
 pub mod cpu;
+pub mod breakpoint;
 pub mod bus;
+pub mod bus_trace;
+pub mod condition;
+pub mod flat_bus;
+pub mod history;
 pub mod instructions;
+pub mod irq;
+pub mod profiler;
+#[cfg(test)]
+mod single_step;
 
 pub use cpu::Cpu;
\ No newline at end of file
@@ -0,0 +1,165 @@
+use std::{collections::HashMap, fmt};
+
+// Calls nested deeper than this are assumed to be stack tricks (JSR then
+// PLA PLA) and the oldest frames are dropped.
+const MAX_CALL_DEPTH: usize = 64;
+
+// Where an instruction ran. Code in cartridge ROM also carries its PRG
+// offset, so the same CPU address in two banks is profiled separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CodeAddress {
+    pub pc: u16,
+    pub prg_offset: Option<u32>,
+}
+
+impl CodeAddress {
+    // Which `window` sized piece of PRG the code is in, as the mapper's
+    // debug state counts them
+    pub fn bank(&self, window: u32) -> Option<u32> {
+        self.prg_offset.map(|offset| offset / window.max(1))
+    }
+
+    // FCEUX style bank:address for cartridge code, like 07:C123, so traces
+    // still say which bank ran after it's switched out. RAM is just 0300.
+    pub fn banked(&self, window: u32) -> String {
+        match self.bank(window) {
+            Some(bank) => format!("{:02X}:{:04X}", bank, self.pc),
+            None => format!("{:04X}", self.pc),
+        }
+    }
+}
+
+impl fmt::Display for CodeAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.prg_offset {
+            Some(offset) => write!(f, "${:04X} (PRG ${:06X})", self.pc, offset),
+            None => write!(f, "${:04X}", self.pc)
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ProfileEntry {
+    pub cycles: u64,
+    // Instructions executed at an address, or calls made to a function
+    pub count: u64,
+}
+
+// Cycle counts per instruction address and per function. A function is
+// whatever a JSR, BRK or interrupt entered; its cycles are the ones spent
+// in its own code, not in what it called.
+#[derive(Default)]
+pub struct Profiler {
+    addresses: HashMap<CodeAddress, ProfileEntry>,
+    functions: HashMap<CodeAddress, ProfileEntry>,
+    call_stack: Vec<CodeAddress>,
+    total_cycles: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    pub(crate) fn record(&mut self, address: CodeAddress, cycles: u64) {
+        let entry = self.addresses.entry(address).or_default();
+        entry.cycles += cycles;
+        entry.count += 1;
+        self.record_cycles(cycles);
+    }
+
+    // Cycles not tied to an instruction, like the interrupt sequence itself
+    pub(crate) fn record_cycles(&mut self, cycles: u64) {
+        self.total_cycles += cycles;
+        if let Some(function) = self.call_stack.last() {
+            self.functions.entry(*function).or_default().cycles += cycles;
+        }
+    }
+
+    pub(crate) fn enter(&mut self, function: CodeAddress) {
+        if self.call_stack.len() == MAX_CALL_DEPTH {
+            self.call_stack.remove(0);
+        }
+        self.call_stack.push(function);
+        self.functions.entry(function).or_default().count += 1;
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.call_stack.pop();
+    }
+
+    // Instruction addresses, most cycles first
+    pub fn hot_spots(&self) -> Vec<(CodeAddress, ProfileEntry)> {
+        Self::sorted(&self.addresses)
+    }
+
+    // Function entry points, most cycles first
+    pub fn functions(&self) -> Vec<(CodeAddress, ProfileEntry)> {
+        Self::sorted(&self.functions)
+    }
+
+    fn sorted(entries: &HashMap<CodeAddress, ProfileEntry>) -> Vec<(CodeAddress, ProfileEntry)> {
+        let mut sorted: Vec<_> = entries.iter().map(|(&address, &entry)| (address, entry)).collect();
+        sorted.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        sorted
+    }
+
+    // Plain text report of the `limit` hottest functions and addresses
+    pub fn report(&self, limit: usize) -> String {
+        let mut out = format!("Profiled {} CPU cycles\n", self.total_cycles);
+        let sections = [("Functions", "calls", self.functions()), ("Addresses", "executed", self.hot_spots())];
+        for (title, count_name, entries) in sections {
+            out += &format!("\n{}\n{:>12} {:>7} {:>10}  address\n", title, "cycles", "%", count_name);
+            for (address, entry) in entries.iter().take(limit) {
+                let percent = entry.cycles as f64 * 100.0 / self.total_cycles.max(1) as f64;
+                out += &format!("{:>12} {:>6.2}% {:>10}  {}\n", entry.cycles, percent, entry.count, address);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::console;
+    use super::*;
+
+    #[test]
+    fn counts_cycles_per_address_and_function() {
+        let mut nes = console();
+        // $0200: JSR $0210; JMP $0200    $0210: NOP; RTS
+        for (i, byte) in [0x20, 0x10, 0x02, 0x4C, 0x00, 0x02].iter().enumerate() {
+            nes.cpu.bus.write(0x0200 + i as u16, *byte);
+        }
+        nes.cpu.bus.write(0x0210, 0xEA);
+        nes.cpu.bus.write(0x0211, 0x60);
+        nes.set_start(0x0200);
+
+        nes.start_profiling();
+        for _ in 0..40 {
+            nes.step();
+        }
+        let profiler = nes.stop_profiling().unwrap();
+
+        let subroutine = CodeAddress { pc: 0x0210, prg_offset: None };
+        let functions = profiler.functions();
+        assert_eq!(functions[0].0, subroutine);
+        assert_eq!(functions[0].1, ProfileEntry { cycles: 10 * 8, count: 10 });
+
+        let hot_spots = profiler.hot_spots();
+        assert_eq!(hot_spots[0].0, CodeAddress { pc: 0x0200, prg_offset: None });
+        assert_eq!(hot_spots[0].1, ProfileEntry { cycles: 10 * 6, count: 10 });
+        assert_eq!(profiler.total_cycles(), 10 * (6 + 3 + 2 + 6));
+        assert!(profiler.report(5).contains("$0210"));
+    }
+
+    #[test]
+    fn cartridge_code_is_keyed_by_prg_offset() {
+        let address = CodeAddress { pc: 0xC123, prg_offset: Some(0x1C123) };
+        assert_eq!(address.to_string(), "$C123 (PRG $01C123)");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,190 @@
+use std::{fmt, panic::{self, AssertUnwindSafe}};
+
+use crate::{cpu::breakpoint::CpuContext, mapper::{BankWindow, MapperDebugInfo}, watch::MemoryRegion, Nes};
+
+// What to attach to a bug report when the emulator falls over: the ROM,
+// where the CPU was, how it got there and the memory it was working with.
+// The Display form is the report as a user would send it.
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    // The panic message, or whatever the frontend gave crash_report()
+    pub reason: String,
+    pub title: Option<String>,
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+    pub mapper_number: u16,
+    pub submapper: u8,
+    pub mapper: MapperDebugInfo,
+    pub registers: CpuContext,
+    pub state_hash: u64,
+    // Oldest first, annotated from the symbol table if there is one. Empty
+    // unless Nes::record_history was on.
+    pub history: Vec<String>,
+    pub system_ram: Vec<u8>,
+    pub prg_ram: Vec<u8>,
+    // The whole console as save_state() wrote it, to load and replay the
+    // crash from. None if it couldn't be serialized.
+    #[cfg(feature = "save-states")]
+    pub save_state: Option<Vec<u8>>,
+}
+
+impl Nes {
+    // A report on the console as it is now, for a frontend to save on a
+    // JAM or anything else it considers a crash
+    pub fn crash_report(&self, reason: &str) -> CrashReport {
+        let cartridge = &self.cpu.bus.cartridge;
+        let metadata = cartridge.metadata();
+        let history = self.history().map_or_else(Vec::new, |history| {
+            history.entries()
+                .map(|entry| match self.symbols() {
+                    Some(symbols) => symbols.annotate(entry),
+                    None => entry.to_string(),
+                })
+                .collect()
+        });
+        CrashReport {
+            reason: reason.to_string(),
+            title: metadata.title.clone(),
+            crc32: metadata.crc32,
+            sha1: metadata.sha1,
+            mapper_number: cartridge.header().mapper_number,
+            submapper: cartridge.header().submapper,
+            mapper: self.mapper_debug_info(),
+            registers: self.cpu.context(),
+            state_hash: self.state_hash(),
+            history,
+            system_ram: self.memory(MemoryRegion::SystemRam).to_vec(),
+            prg_ram: self.memory(MemoryRegion::PrgRam).to_vec(),
+            #[cfg(feature = "save-states")]
+            save_state: self.save_state().ok(),
+        }
+    }
+
+    // Runs `f`, turning a panic inside it into a CrashReport instead of
+    // taking the frontend down with it, e.g. around each run_frame(). The
+    // console is left as the panic found it, so power cycle or load
+    // another ROM before running it again.
+    pub fn catch_crash<T>(&mut self, f: impl FnOnce(&mut Nes) -> T) -> Result<T, Box<CrashReport>> {
+        panic::catch_unwind(AssertUnwindSafe(|| f(self))).map_err(|payload| {
+            let reason = payload.downcast_ref::<&str>().map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "panic".to_string());
+            Box::new(self.crash_report(&reason))
+        })
+    }
+}
+
+// 16 bytes to a line, offset first
+fn hex_dump(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        write!(f, "{:04X}:", line * 16)?;
+        for byte in chunk {
+            write!(f, " {:02X}", byte)?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Crash report: {}", self.reason)?;
+        writeln!(f)?;
+        writeln!(f, "ROM: {}", self.title.as_deref().unwrap_or("(not in the database)"))?;
+        writeln!(f, "CRC32: {:08X}", self.crc32)?;
+        let sha1: String = self.sha1.iter().map(|byte| format!("{:02X}", byte)).collect();
+        writeln!(f, "SHA-1: {}", sha1)?;
+        writeln!(f, "Mapper: {}.{} ({})", self.mapper_number, self.submapper, self.mapper.name)?;
+        let banks = |windows: &[BankWindow]| windows.iter()
+            .map(|window| format!("{:04X}:{:02X}", window.addr, window.bank))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(f, "PRG banks: {}", banks(&self.mapper.prg_banks))?;
+        writeln!(f, "CHR banks: {}", banks(&self.mapper.chr_banks))?;
+        for (name, value) in &self.mapper.registers {
+            writeln!(f, "{}: {:02X}", name, value)?;
+        }
+        writeln!(f)?;
+
+        let cpu = &self.registers;
+        writeln!(f, "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}", cpu.pc, cpu.a, cpu.x, cpu.y, cpu.p, cpu.sp)?;
+        writeln!(f, "Frame {} scanline {} dot {}, CPU cycle {}", cpu.frame, cpu.scanline, cpu.dot, cpu.cycle)?;
+        writeln!(f, "State hash: {:016X}", self.state_hash)?;
+        #[cfg(feature = "save-states")]
+        if let Some(state) = &self.save_state {
+            writeln!(f, "Save state: {} bytes, sent separately", state.len())?;
+        }
+
+        writeln!(f)?;
+        if self.history.is_empty() {
+            writeln!(f, "No instruction history recorded")?;
+        } else {
+            writeln!(f, "Last {} instructions:", self.history.len())?;
+            for line in &self.history {
+                writeln!(f, "{}", line)?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(f, "System RAM:")?;
+        hex_dump(f, &self.system_ram)?;
+        if !self.prg_ram.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "PRG RAM:")?;
+            hex_dump(f, &self.prg_ram)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::builder::RomBuilder, SystemVersion};
+    use super::*;
+
+    #[test]
+    fn panics_become_reports() {
+        // LDA #$42; STA $10; JMP $8004
+        let program = [0xA9, 0x42, 0x85, 0x10, 0x4C, 0x04, 0x80];
+        let rom = RomBuilder::new(0).prg(&program).vectors(0x8004, 0x8000, 0x8004).build().unwrap();
+        let crc32 = rom.crc32();
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(rom);
+        nes.record_history(4);
+        nes.on();
+
+        assert_eq!(nes.catch_crash(|nes| nes.step().pc).ok(), Some(0x8002));
+        let report = nes.catch_crash(|nes| {
+            nes.step();
+            panic!("PPU on fire at {}", nes.total_cycles());
+        }).unwrap_err();
+        assert!(report.reason.starts_with("PPU on fire at "));
+        assert_eq!((report.crc32, report.registers.pc, report.system_ram[0x10]), (crc32, 0x8004, 0x42));
+        assert_eq!(report.history.len(), 2);
+
+        let text = report.to_string();
+        assert!(text.contains("Mapper: 0.0 (NROM)"));
+        assert!(text.contains("0010: 42 00"));
+    }
+
+    #[test]
+    #[cfg(feature = "save-states")]
+    fn reports_carry_a_loadable_state() {
+        // LDA #$42; STA $10; JMP $8004
+        let program = [0xA9, 0x42, 0x85, 0x10, 0x4C, 0x04, 0x80];
+        let rom = RomBuilder::new(0).prg(&program).vectors(0x8004, 0x8000, 0x8004).build().unwrap();
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(rom);
+        nes.on();
+        let report = nes.catch_crash(|nes| {
+            nes.step();
+            nes.step();
+            panic!("crash");
+        }).unwrap_err();
+
+        nes.reset();
+        nes.load_state(report.save_state.as_ref().unwrap()).unwrap();
+        assert_eq!(nes.state_hash(), report.state_hash);
+        assert!(report.to_string().contains("Save state: "));
+    }
+}
\ No newline at end of file
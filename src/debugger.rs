@@ -0,0 +1,154 @@
+use crate::{cpu::breakpoint::{BreakHit, CpuContext}, Nes};
+
+// Instructions a run may take before giving up, a few seconds of play
+const DEFAULT_LIMIT: u64 = 10_000_000;
+
+const JSR: u8 = 0x20;
+const RTS: u8 = 0x60;
+const RTI: u8 = 0x40;
+
+// Why an execution command returned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    // Got where it was going
+    Finished,
+    Breakpoint(BreakHit),
+    // A JAM opcode halted the CPU
+    Jammed,
+    // Ran out of instructions, see Debugger::set_limit
+    Limit,
+}
+
+// Execution controls on top of breakpoints: single steps, stepping over and
+// out of subroutines, and running to an address. Every command stops early
+// when a breakpoint hits.
+pub struct Debugger<'a> {
+    nes: &'a mut Nes,
+    limit: u64,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(nes: &'a mut Nes) -> Self {
+        Debugger { nes, limit: DEFAULT_LIMIT }
+    }
+
+    pub fn nes(&mut self) -> &mut Nes {
+        self.nes
+    }
+
+    pub fn context(&self) -> CpuContext {
+        self.nes.cpu.context()
+    }
+
+    pub fn set_limit(&mut self, instructions: u64) {
+        self.limit = instructions;
+    }
+
+    // One instruction, or one interrupt if one is due
+    pub fn step(&mut self) -> StopReason {
+        let info = self.nes.step();
+        match info.breakpoint {
+            Some(hit) => StopReason::Breakpoint(hit),
+            None if info.jammed => StopReason::Jammed,
+            None => StopReason::Finished,
+        }
+    }
+
+    // Like step, but a JSR runs until its subroutine returns
+    pub fn step_over(&mut self) -> StopReason {
+        let (pc, sp) = (self.nes.cpu.pc, self.nes.cpu.sp);
+        let reason = self.step();
+        // Anything else that pushes two bytes is an interrupt's three
+        let called = self.nes.cpu.opcode == JSR && sp.wrapping_sub(self.nes.cpu.sp) == 2;
+        if reason != StopReason::Finished || !called {
+            return reason;
+        }
+        let return_pc = pc.wrapping_add(3);
+        self.run_while(|nes| nes.cpu.pc != return_pc || nes.cpu.sp != sp)
+    }
+
+    // Runs until the current subroutine or interrupt handler returns
+    pub fn step_out(&mut self) -> StopReason {
+        let sp = self.nes.cpu.sp;
+        self.run_while(|nes| !matches!(nes.cpu.opcode, RTS | RTI) || nes.cpu.sp <= sp)
+    }
+
+    // Runs until the next instruction is the one at `pc`
+    pub fn run_to(&mut self, pc: u16) -> StopReason {
+        if self.nes.cpu.pc == pc {
+            return StopReason::Finished;
+        }
+        self.run_while(|nes| nes.cpu.pc != pc)
+    }
+
+    fn run_while<F: Fn(&Nes) -> bool>(&mut self, running: F) -> StopReason {
+        for _ in 0..self.limit {
+            let reason = self.step();
+            if reason != StopReason::Finished {
+                return reason;
+            }
+            if !running(self.nes) {
+                return StopReason::Finished;
+            }
+        }
+        StopReason::Limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cpu::breakpoint::Breakpoint, test_support};
+    use super::*;
+
+    // $0200: JSR $0210; INX; JMP $0200
+    // $0210: INY; JSR $0220; RTS
+    // $0220: INY; RTS
+    fn console() -> Nes {
+        let mut nes = test_support::console();
+        let code: [(u16, &[u8]); 3] = [
+            (0x0200, &[0x20, 0x10, 0x02, 0xE8, 0x4C, 0x00, 0x02]),
+            (0x0210, &[0xC8, 0x20, 0x20, 0x02, 0x60]),
+            (0x0220, &[0xC8, 0x60]),
+        ];
+        for (addr, bytes) in code {
+            for (i, byte) in bytes.iter().enumerate() {
+                nes.cpu.bus.write(addr + i as u16, *byte);
+            }
+        }
+        nes.set_start(0x0200);
+        nes
+    }
+
+    #[test]
+    fn steps_over_and_out_of_subroutines() {
+        let mut nes = console();
+        let mut debugger = nes.debugger();
+
+        assert_eq!(debugger.step_over(), StopReason::Finished);
+        assert_eq!((debugger.context().pc, debugger.context().y), (0x0203, 2));
+
+        assert_eq!(debugger.run_to(0x0220), StopReason::Finished);
+        assert_eq!(debugger.context().y, 3);
+        assert_eq!(debugger.step_out(), StopReason::Finished);
+        assert_eq!(debugger.context().pc, 0x0214);
+        assert_eq!(debugger.step_out(), StopReason::Finished);
+        assert_eq!(debugger.context().pc, 0x0203);
+
+        assert_eq!(debugger.step(), StopReason::Finished);
+        assert_eq!(debugger.context().x, 2);
+    }
+
+    #[test]
+    fn breakpoints_and_limits_stop_runs() {
+        let mut nes = console();
+        nes.add_breakpoint(Breakpoint::Execute(0x0220));
+        let mut debugger = nes.debugger();
+
+        // Stepping over the JSR still stops inside it
+        let StopReason::Breakpoint(hit) = debugger.step_over() else { panic!("expected a breakpoint") };
+        assert_eq!(hit.context.pc, 0x0220);
+
+        debugger.set_limit(3);
+        assert_eq!(debugger.run_to(0x1234), StopReason::Limit);
+    }
+}
\ No newline at end of file
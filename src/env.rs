@@ -0,0 +1,200 @@
+use crate::{config::NesConfig, controller::Button, rom::{Rom, RomError}, threaded::Frame, watch::MemoryRegion, Nes};
+
+const NOOP: u8 = 0;
+const A: u8 = Button::A as u8;
+const B: u8 = Button::B as u8;
+const LEFT: u8 = Button::Left as u8;
+const RIGHT: u8 = Button::Right as u8;
+
+type DoneCheck = Box<dyn FnMut(&Nes) -> bool>;
+
+// How an agent's action number turns into buttons held on controller 1
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActionSpace {
+    // The action is the button byte itself, 256 actions: A, B, Select,
+    // Start, Up, Down, Left, Right from bit 0
+    Buttons,
+    // The action indexes a list of button combinations
+    Discrete(Vec<u8>),
+}
+
+impl ActionSpace {
+    // Enough for most platformers: nothing, right, right+A, right+B,
+    // right+A+B, A and left
+    pub fn simple() -> Self {
+        ActionSpace::Discrete(vec![NOOP, RIGHT, RIGHT | A, RIGHT | B, RIGHT | A | B, A, LEFT])
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            ActionSpace::Buttons => 256,
+            ActionSpace::Discrete(combos) => combos.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Actions outside the space press nothing
+    pub fn buttons(&self, action: usize) -> u8 {
+        match self {
+            ActionSpace::Buttons => u8::try_from(action).unwrap_or(NOOP),
+            ActionSpace::Discrete(combos) => combos.get(action).copied().unwrap_or(NOOP),
+        }
+    }
+}
+
+// What the agent sees after a reset or step
+pub struct Observation {
+    // 256x240 RGB24 of the last frame run
+    pub pixels: Frame,
+    // The 2KB of system RAM, where games keep score, lives and positions
+    pub ram: Vec<u8>,
+    // Frames since the reset
+    pub frame: u64,
+}
+
+// A Gym style environment for reinforcement learning. Every reset powers a
+// fresh console on from the same ROM and configuration, so with a fixed
+// seed (see seed) an episode replays exactly from the same actions.
+pub struct Env {
+    nes: Nes,
+    rom: Vec<u8>,
+    config: NesConfig,
+    actions: ActionSpace,
+    frame_skip: u32,
+    max_frames: Option<u64>,
+    done: Option<DoneCheck>,
+    frames: u64,
+}
+
+impl Env {
+    pub fn new(rom: Vec<u8>, config: NesConfig) -> Result<Self, RomError> {
+        let mut nes = Nes::with_config(config.clone());
+        nes.set_rom(Rom::from_bytes(rom.clone())?);
+        Ok(Env {
+            nes,
+            rom,
+            config,
+            actions: ActionSpace::Buttons,
+            frame_skip: 1,
+            max_frames: None,
+            done: None,
+            frames: 0,
+        })
+    }
+
+    pub fn nes(&self) -> &Nes {
+        &self.nes
+    }
+
+    // Random power-on state for every later reset comes from this seed.
+    // Without one each reset picks its own.
+    pub fn seed(&mut self, seed: u64) {
+        self.config.seed = Some(seed);
+    }
+
+    pub fn set_actions(&mut self, actions: ActionSpace) {
+        self.actions = actions;
+    }
+
+    pub fn actions(&self) -> &ActionSpace {
+        &self.actions
+    }
+
+    // Frames each step runs with its action held, at least 1
+    pub fn set_frame_skip(&mut self, frames: u32) {
+        self.frame_skip = frames.max(1);
+    }
+
+    // Ends episodes after this many frames
+    pub fn set_max_frames(&mut self, frames: Option<u64>) {
+        self.max_frames = frames;
+    }
+
+    // Ends an episode when it returns true, checked after every step. A
+    // jammed CPU always ends one.
+    pub fn set_done<F: FnMut(&Nes) -> bool + 'static>(&mut self, done: F) {
+        self.done = Some(Box::new(done));
+    }
+
+    pub fn reset(&mut self) -> Observation {
+        let rom = Rom::from_bytes(self.rom.clone()).expect("ROM was parsed when the Env was made");
+        self.nes = Nes::with_config(self.config.clone());
+        self.nes.set_rom(rom);
+        self.nes.on();
+        self.frames = 0;
+        self.observe()
+    }
+
+    pub fn step(&mut self, action: usize) -> (Observation, bool) {
+        self.nes.set_port_buttons(0, self.actions.buttons(action));
+        for _ in 0..self.frame_skip {
+            self.nes.run_frame();
+            self.frames += 1;
+        }
+
+        let mut done = self.nes.is_jammed() || self.max_frames.is_some_and(|max| self.frames >= max);
+        if let Some(check) = &mut self.done {
+            done |= check(&self.nes);
+        }
+        (self.observe(), done)
+    }
+
+    fn observe(&mut self) -> Observation {
+        Observation {
+            pixels: Box::new(self.nes.frame()),
+            ram: self.nes.memory(MemoryRegion::SystemRam).to_vec(),
+            frame: self.frames,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::TestRom, SystemVersion};
+    use super::*;
+
+    // The test ROM's reset vector points into RAM, so only the PPU's power
+    // on state is randomized
+    fn env() -> Env {
+        let config = NesConfig { random_ppu_state: true, ..NesConfig::new(SystemVersion::NTSC) };
+        Env::new(TestRom::new(0).bytes(), config).unwrap()
+    }
+
+    #[test]
+    fn seeded_resets_repeat() {
+        let mut env = env();
+        env.seed(7);
+        env.reset();
+        let first = env.step(0).0.pixels;
+        env.reset();
+        assert_eq!(env.step(0).0.pixels, first);
+        env.seed(8);
+        env.reset();
+        assert_ne!(env.step(0).0.pixels, first);
+    }
+
+    #[test]
+    fn steps_skip_frames_and_end_episodes() {
+        let mut env = env();
+        env.set_actions(ActionSpace::simple());
+        env.set_frame_skip(4);
+        env.set_max_frames(Some(12));
+        env.set_done(|nes| nes.memory(MemoryRegion::SystemRam)[0x10] == 0x55);
+        env.reset();
+
+        let (observation, done) = env.step(2);
+        assert_eq!(observation.frame, 4);
+        assert!(!done);
+        assert_eq!(env.nes().cpu.bus.controller1.buttons(), RIGHT | A);
+        assert!(!env.step(0).1);
+        assert!(env.step(0).1);
+
+        env.reset();
+        env.nes.poke(0x0010, 0x55);
+        assert!(env.step(99).1);
+        assert_eq!(env.nes().cpu.bus.controller1.buttons(), NOOP);
+    }
+}
\ No newline at end of file
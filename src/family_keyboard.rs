@@ -0,0 +1,130 @@
+// Family BASIC keyboard (HVC-007) on the Famicom expansion port. The 72 keys
+// form a 9 row x 2 column matrix of 4 keys each. Writes to $4016 select the
+// row/column, and $4017 bits 1-4 return the selected keys, 0 meaning pressed.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Key {
+    F1, F2, F3, F4, F5, F6, F7, F8,
+    Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M,
+    N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Minus, Caret, Yen, At, LeftBracket, RightBracket, Semicolon, Colon,
+    Comma, Period, Slash, Underscore,
+    Escape, Ctrl, LeftShift, RightShift, Graph, Kana, Stop, Return, Space,
+    ClrHome, Insert, Delete, Up, Down, Left, Right,
+}
+
+const ROWS: usize = 9;
+
+impl Key {
+    // Row, and bit within the row: bits 0-3 are column 0, bits 4-7 column 1
+    fn position(self) -> (usize, u8) {
+        use Key::*;
+        match self {
+            RightBracket => (0, 0), LeftBracket => (0, 1), Return => (0, 2), F8 => (0, 3),
+            Stop => (0, 4), Yen => (0, 5), RightShift => (0, 6), Kana => (0, 7),
+            Semicolon => (1, 0), Colon => (1, 1), At => (1, 2), F7 => (1, 3),
+            Caret => (1, 4), Minus => (1, 5), Slash => (1, 6), Underscore => (1, 7),
+            K => (2, 0), L => (2, 1), O => (2, 2), F6 => (2, 3),
+            Num0 => (2, 4), P => (2, 5), Comma => (2, 6), Period => (2, 7),
+            J => (3, 0), U => (3, 1), I => (3, 2), F5 => (3, 3),
+            Num8 => (3, 4), Num9 => (3, 5), N => (3, 6), M => (3, 7),
+            H => (4, 0), G => (4, 1), Y => (4, 2), F4 => (4, 3),
+            Num6 => (4, 4), Num7 => (4, 5), V => (4, 6), B => (4, 7),
+            D => (5, 0), R => (5, 1), T => (5, 2), F3 => (5, 3),
+            Num4 => (5, 4), Num5 => (5, 5), C => (5, 6), F => (5, 7),
+            A => (6, 0), S => (6, 1), W => (6, 2), F2 => (6, 3),
+            Num3 => (6, 4), E => (6, 5), Z => (6, 6), X => (6, 7),
+            Ctrl => (7, 0), Q => (7, 1), Escape => (7, 2), F1 => (7, 3),
+            Num2 => (7, 4), Num1 => (7, 5), Graph => (7, 6), LeftShift => (7, 7),
+            Left => (8, 0), Right => (8, 1), Up => (8, 2), ClrHome => (8, 3),
+            Insert => (8, 4), Delete => (8, 5), Space => (8, 6), Down => (8, 7),
+        }
+    }
+}
+
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FamilyKeyboard {
+    keys: [u8; ROWS],
+    row: usize,
+    column: usize,
+    enabled: bool,
+}
+
+impl FamilyKeyboard {
+    pub fn new() -> Self {
+        FamilyKeyboard::default()
+    }
+
+    pub fn set_key(&mut self, key: Key, pressed: bool) {
+        let (row, bit) = key.position();
+        if pressed {
+            self.keys[row] |= 1 << bit;
+        } else {
+            self.keys[row] &= !(1 << bit);
+        }
+    }
+
+    // $4016: bit 0 resets to row 0, bit 1 selects the column and moves to
+    // the next row on its falling edge, bit 2 enables the matrix.
+    pub fn write(&mut self, data: u8) {
+        let column = ((data >> 1) & 1) as usize;
+        if self.column == 1 && column == 0 {
+            self.row += 1;
+        }
+        self.column = column;
+        if data & 0x01 != 0 {
+            self.row = 0;
+        }
+        self.enabled = data & 0x04 != 0;
+    }
+
+    // $4017 bits 1-4
+    pub fn read(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let keys = match self.keys.get(self.row) {
+            Some(row) => (row >> (self.column * 4)) & 0x0F,
+            None => 0
+        };
+        (!keys & 0x0F) << 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scans_rows_and_columns() {
+        let mut keyboard = FamilyKeyboard::new();
+        keyboard.set_key(Key::Return, true);
+        keyboard.set_key(Key::Space, true);
+
+        // Row 0, column 0: RETURN is bit 3 of $4017
+        keyboard.write(0x05);
+        assert_eq!(keyboard.read(), 0x1E & !0x08);
+
+        // Column 1, then the falling edge moves on to row 1
+        keyboard.write(0x06);
+        assert_eq!(keyboard.read(), 0x1E);
+        keyboard.write(0x04);
+        assert_eq!(keyboard.read(), 0x1E);
+
+        for _ in 1..8 {
+            keyboard.write(0x06);
+            keyboard.write(0x04);
+        }
+        keyboard.write(0x06);
+        assert_eq!(keyboard.read(), 0x1E & !0x08);
+    }
+
+    #[test]
+    fn disabled_matrix_reads_zero() {
+        let mut keyboard = FamilyKeyboard::new();
+        keyboard.write(0x01);
+        assert_eq!(keyboard.read(), 0);
+    }
+}
\ No newline at end of file
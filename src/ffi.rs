@@ -0,0 +1,258 @@
+// C interface for embedding the core in non-Rust frontends. build.rs
+// generates include/nes.h from this file with cbindgen, so the `///`
+// comments and constants here are what C callers see. Every function
+// taking a Nes pointer needs one from nes_create that hasn't been
+// destroyed; buffers must hold at least the length passed with them.
+#![allow(clippy::missing_safety_doc)]
+
+use std::{panic::{self, AssertUnwindSafe}, ptr, slice};
+
+use crate::{rom::Rom, save_state::state_preview, Nes, SystemVersion};
+
+pub const NES_REGION_NTSC: u32 = 0;
+pub const NES_REGION_PAL: u32 = 1;
+pub const NES_REGION_DENDY: u32 = 2;
+/// Picked from each loaded ROM's header
+pub const NES_REGION_AUTO: u32 = 3;
+
+pub const NES_WIDTH: usize = 256;
+pub const NES_HEIGHT: usize = 240;
+
+/// Saved states carry a picture of the screen a quarter the size each way
+pub const NES_THUMBNAIL_WIDTH: usize = 64;
+pub const NES_THUMBNAIL_HEIGHT: usize = 60;
+
+/// Controller bits for nes_set_input
+pub const NES_BUTTON_A: u8 = 1 << 0;
+pub const NES_BUTTON_B: u8 = 1 << 1;
+pub const NES_BUTTON_SELECT: u8 = 1 << 2;
+pub const NES_BUTTON_START: u8 = 1 << 3;
+pub const NES_BUTTON_UP: u8 = 1 << 4;
+pub const NES_BUTTON_DOWN: u8 = 1 << 5;
+pub const NES_BUTTON_LEFT: u8 = 1 << 6;
+pub const NES_BUTTON_RIGHT: u8 = 1 << 7;
+
+// A panic can't unwind into C, so every function runs its body through
+// this and returns `error` instead. The console may be left mid-step.
+fn guard<T>(error: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(error)
+}
+
+/// A console for NES_REGION_*, or NULL if it couldn't be made
+#[no_mangle]
+pub extern "C" fn nes_create(region: u32) -> *mut Nes {
+    guard(ptr::null_mut(), || {
+        let version = match region {
+            NES_REGION_PAL => SystemVersion::PAL,
+            NES_REGION_DENDY => SystemVersion::Dendy,
+            NES_REGION_AUTO => SystemVersion::Auto,
+            _ => SystemVersion::NTSC,
+        };
+        Box::into_raw(Box::new(Nes::new(version)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nes_destroy(nes: *mut Nes) {
+    guard((), || {
+        if !nes.is_null() {
+            drop(Box::from_raw(nes));
+        }
+    })
+}
+
+/// An iNES or NES 2.0 image, copied. Powers the console on, or power cycles
+/// it. Returns 0, or -1 if the data isn't a ROM.
+#[no_mangle]
+pub unsafe extern "C" fn nes_load_rom(nes: *mut Nes, data: *const u8, len: usize) -> i32 {
+    guard(-1, || {
+        let (Some(nes), false) = (nes.as_mut(), data.is_null()) else {
+            return -1;
+        };
+        match Rom::from_bytes(slice::from_raw_parts(data, len).to_vec()) {
+            Ok(rom) => {
+                nes.load_rom(rom);
+                if !nes.is_on() {
+                    nes.on();
+                }
+                0
+            }
+            Err(_) => -1,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nes_reset(nes: *mut Nes) {
+    guard((), || {
+        if let Some(nes) = nes.as_mut() {
+            nes.reset();
+        }
+    })
+}
+
+/// Runs until the next frame is complete
+#[no_mangle]
+pub unsafe extern "C" fn nes_run_frame(nes: *mut Nes) {
+    guard((), || {
+        if let Some(nes) = nes.as_mut() {
+            nes.run_frame();
+        }
+    })
+}
+
+/// NES_WIDTH x NES_HEIGHT RGB24, rows top to bottom. Valid until the Nes is
+/// destroyed, loaded states included, and updated as frames are run.
+#[no_mangle]
+pub unsafe extern "C" fn nes_framebuffer(nes: *const Nes) -> *const u8 {
+    guard(ptr::null(), || match nes.as_ref() {
+        Some(nes) => nes.cpu.bus.ppu.frame_buffer.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// NES_BUTTON_* bits held on controller 0 or 1
+#[no_mangle]
+pub unsafe extern "C" fn nes_set_input(nes: *mut Nes, port: u32, buttons: u8) {
+    guard((), || {
+        if let Some(nes) = nes.as_mut() {
+            nes.set_port_buttons(port as usize, buttons);
+        }
+    })
+}
+
+/// Mono 44.1kHz samples since the last call. Copies up to capacity of them
+/// and returns how many; any more are dropped.
+#[no_mangle]
+pub unsafe extern "C" fn nes_audio_samples(nes: *mut Nes, out: *mut f32, capacity: usize) -> usize {
+    guard(0, || {
+        let Some(nes) = nes.as_mut() else {
+            return 0;
+        };
+        let samples = nes.take_audio_samples();
+        let count = samples.len().min(capacity);
+        if !out.is_null() {
+            ptr::copy_nonoverlapping(samples.as_ptr(), out, count);
+        }
+        count
+    })
+}
+
+/// Returns the saved state's size, writing it only when capacity is enough:
+/// call with NULL first to size the buffer. 0 if it couldn't be saved. The
+/// ROM is left out, so a state only loads back with the same game.
+#[no_mangle]
+pub unsafe extern "C" fn nes_save_state(nes: *const Nes, out: *mut u8, capacity: usize) -> usize {
+    guard(0, || {
+        let Some(state) = nes.as_ref().and_then(|nes| nes.save_state().ok()) else {
+            return 0;
+        };
+        if !out.is_null() && state.len() <= capacity {
+            ptr::copy_nonoverlapping(state.as_ptr(), out, state.len());
+        }
+        state.len()
+    })
+}
+
+/// Returns 0, or -1 if the data isn't a saved state of the loaded ROM,
+/// leaving the console as it was
+#[no_mangle]
+pub unsafe extern "C" fn nes_load_state(nes: *mut Nes, data: *const u8, len: usize) -> i32 {
+    guard(-1, || {
+        let (Some(nes), false) = (nes.as_mut(), data.is_null()) else {
+            return -1;
+        };
+        match nes.load_state(slice::from_raw_parts(data, len)) {
+            Ok(()) => 0,
+            Err(_) => -1,
+        }
+    })
+}
+
+/// Copies a saved state's NES_THUMBNAIL_WIDTH x NES_THUMBNAIL_HEIGHT RGB24
+/// thumbnail into out and returns when it was saved, in seconds since the
+/// Unix epoch. -1 if the data isn't a saved state or its thumbnail isn't
+/// that size.
+#[no_mangle]
+pub unsafe extern "C" fn nes_state_thumbnail(data: *const u8, len: usize, out: *mut u8) -> i64 {
+    guard(-1, || {
+        if data.is_null() || out.is_null() {
+            return -1;
+        }
+        match state_preview(slice::from_raw_parts(data, len)) {
+            Ok(preview) => {
+                let thumbnail = &preview.thumbnail;
+                if (thumbnail.width, thumbnail.height) != (NES_THUMBNAIL_WIDTH, NES_THUMBNAIL_HEIGHT) || thumbnail.rgb.len() != NES_THUMBNAIL_WIDTH * NES_THUMBNAIL_HEIGHT * 3 {
+                    return -1;
+                }
+                ptr::copy_nonoverlapping(thumbnail.rgb.as_ptr(), out, thumbnail.rgb.len());
+                preview.saved_at as i64
+            }
+            Err(_) => -1,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestRom;
+    use super::*;
+
+    #[test]
+    fn header_declares_every_function() {
+        let header = include_str!("../include/nes.h");
+        let exported: Vec<&str> = include_str!("ffi.rs").lines()
+            .filter_map(|line| line.split("extern \"C\" fn ").nth(1))
+            .filter_map(|rest| rest.split('(').next())
+            .collect();
+        assert!(exported.len() >= 10);
+        for name in exported {
+            assert!(header.contains(&format!(" {}(", name)) || header.contains(&format!("*{}(", name)), "{} missing from nes.h", name);
+        }
+    }
+
+    #[test]
+    fn panics_come_back_as_errors() {
+        assert_eq!(guard(-1, || panic!("caught")), -1);
+        assert_eq!(guard(-1, || 0), 0);
+    }
+
+    #[test]
+    fn runs_and_restores_through_the_c_interface() {
+        unsafe {
+            let nes = nes_create(0);
+            let rom = TestRom::new(0).bytes();
+            assert_eq!(nes_load_rom(nes, [0u8; 4].as_ptr(), 4), -1);
+            assert_eq!(nes_load_rom(nes, rom.as_ptr(), rom.len()), 0);
+            nes_set_input(nes, 0, 0b1001);
+            assert_eq!((*nes).cpu.bus.controller1.buttons(), 0b1001);
+            nes_run_frame(nes);
+            let framebuffer = nes_framebuffer(nes);
+            assert!(!framebuffer.is_null());
+
+            let size = nes_save_state(nes, ptr::null_mut(), 0);
+            let mut state = vec![0; size];
+            assert_eq!(nes_save_state(nes, state.as_mut_ptr(), size), size);
+            let cycles = (*nes).total_cycles();
+            nes_run_frame(nes);
+            assert_eq!(nes_load_state(nes, state.as_ptr(), size), 0);
+            assert_eq!((*nes).total_cycles(), cycles);
+            assert_eq!(nes_framebuffer(nes), framebuffer);
+            assert_eq!(slice::from_raw_parts(framebuffer, 256 * 240 * 3), &(*nes).frame()[..]);
+            assert_eq!(nes_load_state(nes, state.as_ptr(), 3), -1);
+            let mut thumbnail = vec![0; 64 * 60 * 3];
+            assert!(nes_state_thumbnail(state.as_ptr(), size, thumbnail.as_mut_ptr()) > 0);
+            assert_eq!(nes_state_thumbnail(state.as_ptr(), 3, thumbnail.as_mut_ptr()), -1);
+
+            // A state can't make the copy overrun a thumbnail-sized buffer
+            let rgb = vec!["0"; 64 * 60 * 3 + 64].join(",");
+            let crafted = format!(r#"{{"thumbnail":{{"width":64,"height":60,"rgb":[{}]}},"saved_at":1}}"#, rgb);
+            assert_eq!(nes_state_thumbnail(crafted.as_ptr(), crafted.len(), thumbnail.as_mut_ptr()), -1);
+
+            let mut samples = [0.0; 4096];
+            nes_run_frame(nes);
+            assert!(nes_audio_samples(nes, samples.as_mut_ptr(), samples.len()) > 0);
+            nes_destroy(nes);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,74 @@
+use crate::{cpu::{flat_bus::FlatBus, Cpu}, rom::{header::HEADER_SIZE, Rom, RomError}, SystemVersion};
+
+// Entry points for the fuzz targets in fuzz/, usable from AFL just the
+// same. Each takes whatever bytes the fuzzer comes up with and returns;
+// a panic reached from here is a bug in the emulator, not bad input.
+
+// Mapper writes replayed from one image, to keep runs short
+const MAX_WRITES: usize = 256;
+
+// PC low, PC high, S, A, X, Y and P, ahead of the program in instruction()
+pub const REGISTER_BYTES: usize = 7;
+
+// Parses `data` as a ROM and then pokes the mapper with it: everything
+// after the header, taken as address low, address high and value triples,
+// is written to the mapper, followed by a read of the whole address space.
+pub fn rom(data: &[u8]) -> Result<(), RomError> {
+    let mut rom = Rom::try_new(data)?;
+    for write in data[HEADER_SIZE..].chunks_exact(3).take(MAX_WRITES) {
+        let addr = u16::from_le_bytes([write[0], write[1]]);
+        rom.mapper.write(addr, write[2]);
+        rom.mapper.read(addr);
+        rom.mapper.clock(1);
+    }
+    for addr in 0..=0xFFFF {
+        rom.mapper.read(addr);
+        rom.mapper.map(addr);
+    }
+    rom.mapper.debug_state();
+    Ok(())
+}
+
+// Runs one instruction on a flat bus. `data` is REGISTER_BYTES of
+// registers, then the bytes loaded from PC on; the rest of memory is zero.
+pub fn instruction(data: &[u8]) -> Result<Cpu<FlatBus>, String> {
+    let Some((registers, program)) = data.split_first_chunk::<REGISTER_BYTES>() else {
+        return Err(format!("need {} bytes of registers, got {}", REGISTER_BYTES, data.len()));
+    };
+    let [pc_low, pc_high, sp, a, x, y, p] = *registers;
+
+    let mut cpu = Cpu::with_bus(SystemVersion::NTSC, FlatBus::new());
+    cpu.pc = u16::from_le_bytes([pc_low, pc_high]);
+    (cpu.sp, cpu.a, cpu.x, cpu.y, cpu.p) = (sp, a, x, y, p);
+    cpu.bus.load(cpu.pc, &program[..program.len().min(0x10000)]);
+    cpu.step();
+    Ok(cpu)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rom::builder::RomBuilder;
+    use super::*;
+
+    #[test]
+    fn malformed_roms_are_errors() {
+        assert_eq!(rom(&[]), Err(RomError::MissingHeader));
+        let mut image = RomBuilder::new(0).bytes();
+        image.truncate(HEADER_SIZE + 100);
+        assert!(matches!(rom(&image), Err(RomError::Truncated { .. })));
+
+        // Writes over NROM's PRG ROM, which debug builds used to assert on
+        let mut image = RomBuilder::new(0).bytes();
+        image[HEADER_SIZE..HEADER_SIZE + 3].copy_from_slice(&[0x00, 0x80, 0x42]);
+        assert_eq!(rom(&image), Ok(()));
+    }
+
+    #[test]
+    fn runs_one_instruction_from_raw_bytes() {
+        assert!(instruction(&[0x00, 0xC0]).is_err());
+
+        // PC $FFFF, so the operand of LDA # wraps around to $0000
+        let cpu = instruction(&[0xFF, 0xFF, 0xFD, 0, 0, 0, 0x24, 0xA9, 0x42]).unwrap();
+        assert_eq!((cpu.a, cpu.pc, cpu.bus.cycles), (0x42, 0x0001, 2));
+    }
+}
\ No newline at end of file
@@ -1,54 +1,570 @@
 pub mod cpu;
+pub mod apu;
+#[cfg(feature = "audit")]
+pub mod audit;
 pub mod ppu;
 pub mod mapper;
 pub mod mappers;
 pub mod rom;
 pub mod memory;
 pub mod controller;
+pub mod vs_system;
+pub mod vaus;
+pub mod family_keyboard;
+pub mod config;
+#[cfg(feature = "control")]
+pub mod control;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod cheat;
+pub mod cartridge;
+pub mod cdl;
+pub mod crash;
+pub mod debugger;
+pub mod env;
+pub mod threaded;
+pub mod tile_changes;
+pub mod scroll_splits;
+pub mod pixel_sources;
+pub mod overlay;
+pub mod pacer;
+pub mod png;
+pub mod ppu_viewer;
+#[cfg(feature = "save-states")]
+pub mod save_state;
+pub mod state_hash;
+pub mod stats;
+pub mod symbols;
+pub mod watch;
+pub mod movie;
+pub mod fuzz;
+pub(crate) mod rng;
+#[cfg(feature = "serde")]
+mod serde_arrays;
+#[cfg(test)]
+mod test_support;
 
-use std::fs;
+use std::{io::Write, ops::{ControlFlow, Range}, time::Duration};
 
-use controller::Button;
-use cpu::Cpu;
-use rom::Rom;
+use apu::Channel;
+use cartridge::Cartridge;
+use cdl::CodeDataLog;
+use cheat::GameGenie;
+use debugger::Debugger;
+use config::NesConfig;
+use controller::{Button, PortLayout};
+use family_keyboard::{FamilyKeyboard, Key};
+use mapper::MapperDebugInfo;
+use movie::{Movie, MovieMode, MovieSession};
+use cpu::{breakpoint::{BreakHit, Breakpoint}, bus_trace::BusTrace, history::InstructionHistory, irq::{IrqSource, PendingInterrupts}, profiler::Profiler, Cpu};
+use rng::Rng;
+use rom::{header::{Console, ExpansionDevice, TvSystem}, Rom};
+use pixel_sources::PixelSources;
+use scroll_splits::ScrollCapture;
+use stats::{SpeedStats, SpeedTracker};
+use symbols::SymbolTable;
+use tile_changes::TileChanges;
+use vaus::Vaus;
+use vs_system::VsSystem;
+use watch::{MemoryChange, MemoryChanges, MemoryRegion, MemoryWatch};
+
+// CPU cycles in an NTSC frame, averaging the short odd frames in
+const NTSC_FRAME_CYCLES: f64 = 29780.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SystemVersion {
     NTSC,
     PAL,
     Dendy,
     RGB,
     BrazilFamiclone,
-    ArgentinaFamiclone
+    ArgentinaFamiclone,
+    // Whatever the inserted ROM's header, or the database entry for it, is
+    // made for. Dual region games and an empty slot get NTSC.
+    Auto
+}
+
+// Outcome of a single Nes::step().
+#[derive(Debug, Clone, Copy)]
+pub struct StepInfo {
+    pub pc: u16,
+    pub cycles: u64,
+    pub jammed: bool,
+    pub breakpoint: Option<BreakHit>,
+}
+
+// Timing of a completed frame, in CPU cycles.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTiming {
+    pub frame: u64,
+    pub cycles: u64,
+    pub total_cycles: u64,
 }
 
+// What Nes::run_until stopped for before handing over to its callback
+#[derive(Debug, Clone, Copy)]
+pub enum FrameEvent {
+    // The PPU finished a frame: time to show it, queue the audio and
+    // read input
+    Frame(FrameTiming),
+    // A breakpoint was hit; running on resumes past it
+    Breakpoint(BreakHit),
+    // The CPU hit a JAM opcode. Only a reset gets it going again, but the
+    // PPU and APU keep running, so frames still follow.
+    Jammed,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Nes {
-    cpu: Cpu
+    cpu: Cpu,
+    config: NesConfig,
+    // The configured region, with Auto resolved
+    region: SystemVersion,
+    powered: bool,
+    frame_start_cycle: u64,
+    last_frame_cycles: u64,
+    seen_frames: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    frame_callback: Option<Box<dyn FnMut(FrameTiming) + Send>>,
+    // Kept in save states, so a state made mid-movie can resume it
+    movie: Option<MovieSession>,
+    // Buttons the frontend is holding, which a playing movie overrides
+    live_buttons: [u8; 2],
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watches: Vec<MemoryWatch>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    memory_changes: MemoryChanges,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    speed: SpeedTracker,
 }
 
 impl Nes {
     pub fn new(version: SystemVersion) -> Self {
+        Nes::with_config(NesConfig::new(version))
+    }
+
+    pub fn with_config(mut config: NesConfig) -> Self {
+        config.seed.get_or_insert_with(Rng::time_seed);
+
+        let region = match config.region {
+            SystemVersion::Auto => SystemVersion::NTSC,
+            region => region,
+        };
+        let mut cpu = Cpu::new(region);
+        cpu.bus.ppu.sprite_limit = config.sprite_limit;
+        cpu.bus.ppu.extra_scanlines = config.overclock_scanlines;
+        cpu.unstable_magic = config.unstable_magic;
+        cpu.bus.apu.set_region(region);
+        cpu.bus.apu.set_output(config.audio_synthesis, config.sample_rate);
+
         Nes {
-            cpu: Cpu::new(version)
+            cpu,
+            config,
+            region,
+            powered: false,
+            frame_start_cycle: 0,
+            last_frame_cycles: 0,
+            seen_frames: 0,
+            frame_callback: None,
+            movie: None,
+            live_buttons: [0; 2],
+            watches: Vec::new(),
+            memory_changes: MemoryChanges::default(),
+            speed: SpeedTracker::default(),
         }
     }
 
+    // Power on, or power cycle if already running. The cartridge stays
+    // inserted; everything else comes up in its power-on state, with random
+    // contents drawn from the configured seed so a power cycle is repeatable.
     pub fn on(&mut self){
-        self.cpu.interrupt(cpu::cpu::Interrupt::RESET);
+        let mut rng = Rng::new(self.config.seed.unwrap_or(0));
+        self.cpu.power_on();
+        self.cpu.bus.fill_ram(self.config.ram_pattern, &mut rng);
+        if self.config.random_ppu_state {
+            self.cpu.bus.ppu.randomize_power_on(&mut rng);
+        }
+        if let Some(addr) = self.config.start_pc {
+            self.cpu.pc = addr;
+        }
+
+        self.frame_start_cycle = self.cpu.bus.cycles;
+        self.seen_frames = self.cpu.bus.ppu.frame_count;
+        self.speed.restart();
+        self.powered = true;
     }
 
-    pub fn off(&mut self){
+    // Puts another console's emulated state in place of this one's, such as
+    // a loaded save state. What save states leave out stays as it was:
+    // the ROM, callbacks, watches, breakpoints, history, symbols, traces,
+    // captures and debug views. A state of another game is refused, since
+    // it has no ROM to run.
+    pub fn restore(&mut self, state: Nes) -> Result<(), String> {
+        let (crc32, loaded) = (state.cpu.bus.cartridge.metadata().crc32, self.cpu.bus.cartridge.metadata().crc32);
+        if crc32 != loaded {
+            return Err(format!("State is for ROM {:08X}, not the loaded {:08X}", crc32, loaded));
+        }
+        let mut old = std::mem::replace(self, state);
+        self.frame_callback = old.frame_callback.take();
+        self.watches = std::mem::take(&mut old.watches);
+        self.speed = std::mem::take(&mut old.speed);
+        self.cpu.keep_attachments(&mut old.cpu);
+        Ok(())
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.powered
+    }
 
+    pub fn config(&self) -> &NesConfig {
+        &self.config
     }
 
+    // Per-game region override, e.g. from a frontend's saved settings
+    pub fn set_region(&mut self, region: SystemVersion) {
+        self.config.region = region;
+        self.update_region();
+    }
+
+    // The region being emulated, never Auto
+    pub fn region(&self) -> SystemVersion {
+        self.region
+    }
+
+    fn update_region(&mut self) {
+        self.region = match self.config.region {
+            SystemVersion::Auto => match self.cpu.bus.cartridge.header().tv {
+                TvSystem::PAL => SystemVersion::PAL,
+                TvSystem::Dendy => SystemVersion::Dendy,
+                TvSystem::NTSC | TvSystem::DualCompatible => SystemVersion::NTSC,
+            },
+            region => region,
+        };
+        self.cpu.set_region(self.region);
+        self.cpu.bus.apu.set_region(self.region);
+    }
+
+    // Stops execution; step() does nothing until the next on().
+    pub fn off(&mut self){
+        self.powered = false;
+    }
+
+    // The RESET button: CPU registers other than SP and P are kept, the PPU
+    // and APU are partially cleared, and RAM is left alone.
     pub fn reset(&mut self){
         self.cpu.reset();
     }
 
-    pub fn step(&mut self){
-        self.cpu.step();
+    pub fn step(&mut self) -> StepInfo {
+        if !self.powered {
+            return StepInfo { pc: self.cpu.pc, cycles: 0, jammed: self.cpu.jammed, breakpoint: None };
+        }
+        let start_cycles = self.cpu.bus.cycles;
+        self.speed.emulating();
+        let breakpoint = self.cpu.step();
+
+        let frames = self.cpu.bus.ppu.frame_count;
+        if frames != self.seen_frames {
+            self.seen_frames = frames;
+            let total_cycles = self.cpu.bus.cycles;
+            self.last_frame_cycles = total_cycles.saturating_sub(self.frame_start_cycle);
+            self.frame_start_cycle = total_cycles;
+            self.speed.frame_done(total_cycles);
+
+            self.apply_movie_input(frames);
+            self.cpu.bus.end_tile_change_frame();
+            self.update_watches(frames);
+            if let Some(callback) = &mut self.frame_callback {
+                callback(FrameTiming { frame: frames, cycles: self.last_frame_cycles, total_cycles });
+            }
+        }
+
+        StepInfo {
+            pc: self.cpu.pc,
+            cycles: self.cpu.bus.cycles.saturating_sub(start_cycles),
+            jammed: self.cpu.jammed,
+            breakpoint,
+        }
+    }
+
+    // Runs until the PPU completes a frame, for frontends without a display
+    // to pace them. Does nothing while the console is off.
+    pub fn run_frame(&mut self) {
+        if !self.powered {
+            return;
+        }
+        while !self.poll_frame() {
+            self.step();
+        }
+    }
+
+    pub fn is_jammed(&self) -> bool {
+        self.cpu.jammed
+    }
+
+    // Forces an NMI before the next instruction, whatever PPUCTRL says, for
+    // exercising interrupt handlers without setting up PPU timing
+    pub fn raise_nmi(&mut self) {
+        self.cpu.raise_nmi();
+    }
+
+    // Holds /IRQ until the CPU takes the interrupt, which waits for the I
+    // flag to be clear
+    pub fn raise_irq(&mut self) {
+        self.cpu.bus.irq.assert(IrqSource::External);
+    }
+
+    pub fn pending_interrupts(&self) -> PendingInterrupts {
+        PendingInterrupts { nmi: self.cpu.nmi_pending(), irq: self.cpu.bus.irq }
+    }
+
+    // CPU cycles since power on, counted at instruction granularity.
+    pub fn total_cycles(&self) -> u64 {
+        self.cpu.bus.cycles
+    }
+
+    // CPU cycles elapsed since the last frame completed.
+    pub fn cycles_this_frame(&self) -> u64 {
+        self.cpu.bus.cycles.saturating_sub(self.frame_start_cycle)
+    }
+
+    pub fn last_frame_cycles(&self) -> u64 {
+        self.last_frame_cycles
+    }
+
+    // Real time the last frame would take on the console, from its cycle
+    // count and the region's clock. Before the first frame, an NTSC one's.
+    pub fn frame_period(&self) -> Duration {
+        let cycles = match self.last_frame_cycles {
+            0 => NTSC_FRAME_CYCLES,
+            cycles => cycles as f64,
+        };
+        Duration::from_secs_f64(cycles / (cpu::cpu::clock_mhz(self.region) as f64 * 1_000_000.0))
+    }
+
+    // Frame rate, emulation speed and host frame time over the last second
+    // or so of frames, for a performance HUD
+    pub fn stats(&self) -> SpeedStats {
+        self.speed.stats(cpu::cpu::clock_mhz(self.region) as f64 * 1_000_000.0)
+    }
+
+    // The frontend's audio queue, which only it can see, in samples; shows
+    // up as SpeedStats::audio_fill
+    pub fn report_audio_queue(&mut self, queued: usize, capacity: usize) {
+        self.speed.set_audio_fill(queued as f32 / capacity.max(1) as f32);
+    }
+
+    // Called from step() whenever the PPU finishes a frame.
+    pub fn set_frame_callback<F: FnMut(FrameTiming) + Send + 'static>(&mut self, callback: F) {
+        self.frame_callback = Some(Box::new(callback));
+    }
+
+    pub fn clear_frame_callback(&mut self) {
+        self.frame_callback = None;
+    }
+
+    // Called as each visible scanline finishes, with its number and its 256
+    // RGB24 pixels, so beam racing frontends can show it before the rest of
+    // the frame is drawn. Runs in the middle of step(), so keep it quick.
+    pub fn set_scanline_callback<F: FnMut(usize, &[u8]) + Send + 'static>(&mut self, callback: F) {
+        self.cpu.bus.ppu.scanline_callback = Some(Box::new(callback));
+    }
+
+    pub fn clear_scanline_callback(&mut self) {
+        self.cpu.bus.ppu.scanline_callback = None;
+    }
+
+    // Set up PPU state directly, for tests and tile editors. Nothing goes
+    // through the registers, so the game sees no difference but the data.
+    // `addr` is in the PPU's address space, where CHR ROM ignores writes.
+    pub fn write_ppu(&mut self, addr: u16, data: u8) {
+        self.cpu.bus.ppu.poke(&mut self.cpu.bus.cartridge, addr, data);
+    }
+
+    // Sprite `index` of 64: Y, tile, attributes, X
+    pub fn write_oam(&mut self, index: usize, sprite: [u8; 4]) {
+        self.cpu.bus.ppu.set_oam_entry(index, sprite);
+    }
+
+    // Entry `index` of 32, mirrored like writes to $3F00-$3F1F are
+    pub fn write_palette(&mut self, index: usize, value: u8) {
+        self.write_ppu(0x3F00 + (index % 32) as u16, value);
+    }
+
+    // Leaves the background or sprites out of the picture to isolate
+    // rendering problems. Games can't tell: PPUMASK and sprite 0 hits work
+    // as they would.
+    pub fn set_layer_visibility(&mut self, background: bool, sprites: bool) {
+        self.cpu.bus.ppu.hide_background = !background;
+        self.cpu.bus.ppu.hide_sprites = !sprites;
+    }
+
+    // Background, then sprites
+    pub fn layer_visibility(&self) -> (bool, bool) {
+        (!self.cpu.bus.ppu.hide_background, !self.cpu.bus.ppu.hide_sprites)
+    }
+
+    // Tracks which pattern table tiles and nametable bytes change each frame
+    pub fn track_tile_changes(&mut self, enabled: bool) {
+        self.cpu.bus.ppu.tile_changes = if enabled { Some(TileChanges::new()) } else { None };
+    }
+
+    // What changed during the last completed frame
+    pub fn tile_changes(&self) -> Option<&TileChanges> {
+        self.cpu.bus.ppu.tile_changes.as_ref()
+    }
+
+    // Records the scroll each scanline starts from, to find raster splits
+    pub fn capture_scroll(&mut self, enabled: bool) {
+        self.cpu.bus.ppu.scroll_capture = if enabled { Some(ScrollCapture::new()) } else { None };
+    }
+
+    // Scroll per scanline of the last completed frame
+    pub fn scroll_capture(&self) -> Option<&ScrollCapture> {
+        self.cpu.bus.ppu.scroll_capture.as_ref()
+    }
+
+    // Records which layer, sprite, palette entry and nametable each pixel
+    // came from
+    pub fn track_pixel_sources(&mut self, enabled: bool) {
+        self.cpu.bus.ppu.pixel_sources = if enabled { Some(PixelSources::new()) } else { None };
+    }
+
+    pub fn pixel_sources(&self) -> Option<&PixelSources> {
+        self.cpu.bus.ppu.pixel_sources.as_ref()
+    }
+
+    pub fn memory(&self, region: MemoryRegion) -> &[u8] {
+        match region {
+            MemoryRegion::SystemRam => self.cpu.bus.ram(),
+            MemoryRegion::PrgRam => self.cpu.bus.cartridge.prg_ram(),
+            MemoryRegion::Nametables => self.cpu.bus.ppu.vram(),
+        }
+    }
+
+    // Fills PRG-RAM from a battery save, as much of it as fits. Goes in
+    // after the cartridge, since a new one comes with its RAM cleared.
+    pub fn load_prg_ram(&mut self, data: &[u8]) {
+        let ram = self.cpu.bus.cartridge.prg_ram_mut();
+        let len = ram.len().min(data.len());
+        ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    // Reports changes to `range` of the region, compared once per frame.
+    // Offsets past the end of the region are ignored.
+    pub fn watch_memory(&mut self, region: MemoryRegion, range: Range<usize>) {
+        let watch = MemoryWatch::new(region, range, self.memory(region));
+        self.watches.push(watch);
+    }
+
+    pub fn clear_memory_watches(&mut self) {
+        self.watches.clear();
+        self.memory_changes.clear();
+    }
+
+    // Changes seen at the end of each frame since the last call, oldest first.
+    // Only the last MEMORY_CHANGE_LIMIT are kept.
+    pub fn take_memory_changes(&mut self) -> Vec<MemoryChange> {
+        self.memory_changes.take()
+    }
+
+    // Changes dropped since the last take_memory_changes for going over
+    // MEMORY_CHANGE_LIMIT
+    pub fn dropped_memory_changes(&self) -> u64 {
+        self.memory_changes.dropped()
+    }
+
+    fn update_watches(&mut self, frame: u64) {
+        let mut watches = std::mem::take(&mut self.watches);
+        let mut changes = std::mem::take(&mut self.memory_changes);
+        for watch in &mut watches {
+            watch.update(self.memory(watch.region()), frame, &mut changes);
+        }
+        self.watches = watches;
+        self.memory_changes = changes;
+    }
+
+    // Side-effect free read of CPU address space, for tools. Only system RAM
+    // and cartridge RAM can be read without disturbing anything.
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        let memory = match addr {
+            0x0000..0x2000 => self.memory(MemoryRegion::SystemRam),
+            0x6000..0x8000 => self.memory(MemoryRegion::PrgRam),
+            _ => return None,
+        };
+        memory.get(addr as usize % memory.len().max(1)).copied()
+    }
+
+    // Writes through the CPU bus, so registers and mappers see it too
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.cpu.bus.write(addr, value);
     }
 
     pub fn set_rom(&mut self, rom: Rom){
-        self.cpu.bus.ppu.rom = rom;
+        self.movie = None;
+        // Codes patch one game's PRG; on another they'd corrupt it
+        self.clear_cheats();
+        self.cpu.bus.vs_system = match rom.header.console {
+            Console::VsSystem => Some(VsSystem::new()),
+            _ => None
+        };
+        self.cpu.bus.ppu.set_vs_ppu(rom.header.vs_ppu);
+        let device = rom.header.expansion_device;
+        self.cpu.bus.cartridge = Cartridge::new(rom);
+        self.update_region();
+        if self.config.auto_input_devices {
+            self.connect_expansion_device(device);
+        }
+    }
+
+    // There's no Zapper to plug in, so it's left like any other. Devices
+    // made for one console also pick its port layout; standard controllers
+    // fit either.
+    fn connect_expansion_device(&mut self, device: ExpansionDevice) {
+        match device {
+            ExpansionDevice::ArkanoidVaus => self.set_port_layout(PortLayout::Nes),
+            ExpansionDevice::FamicomArkanoidVaus | ExpansionDevice::FamilyBasicKeyboard => self.set_port_layout(PortLayout::Famicom),
+            ExpansionDevice::StandardControllers => {}
+            _ => return
+        }
+        self.connect_vaus(matches!(device, ExpansionDevice::ArkanoidVaus | ExpansionDevice::FamicomArkanoidVaus));
+        self.connect_keyboard(device == ExpansionDevice::FamilyBasicKeyboard);
+    }
+
+    // Swap cartridges without rebuilding the Nes. A running console is power
+    // cycled so the new game starts from a clean CPU, PPU and mapper state.
+    pub fn load_rom(&mut self, rom: Rom) {
+        self.set_rom(rom);
+        if self.powered {
+            self.on();
+        }
+    }
+
+    // Replaces the 64 color RGB palette with the first 192 bytes of a .pal
+    // file. Files with emphasis variants appended are accepted.
+    pub fn set_palette(&mut self, pal: &[u8]) -> Result<(), String> {
+        if pal.len() < 192 {
+            return Err(format!("Palette needs 192 bytes, got {}", pal.len()));
+        }
+        self.cpu.bus.ppu.rgb_palette.copy_from_slice(&pal[..192]);
+        Ok(())
+    }
+
+    pub fn reset_palette(&mut self) {
+        self.cpu.bus.ppu.rgb_palette = ppu::PALETTE;
+    }
+
+    // Adds a Game Genie code, applied to cartridge reads until cleared or
+    // another cartridge goes in
+    pub fn add_cheat(&mut self, code: &str) -> Result<(), String> {
+        let cheat = GameGenie::decode(code)?;
+        self.cpu.bus.cheats.push(cheat);
+        Ok(())
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cpu.bus.cheats.clear();
     }
 
     pub fn set_start(&mut self, addr: u16){
@@ -56,11 +572,297 @@ impl Nes {
     }
 
     pub fn set_button(&mut self, button: Button, pressed: bool) {
-        self.cpu.bus.controller1.set_button(button, pressed);
+        self.set_port_button(0, button, pressed);
+    }
+
+    pub fn set_button2(&mut self, button: Button, pressed: bool) {
+        self.set_port_button(1, button, pressed);
+    }
+
+    // All eight buttons of a port at once, one bit each as in Button
+    pub fn set_port_buttons(&mut self, port: usize, buttons: u8) {
+        for button in Button::ALL {
+            self.set_port_button(port, button, buttons & button as u8 != 0);
+        }
+    }
+
+    // Port 0 is read through $4016, port 1 through $4017.
+    pub fn set_port_button(&mut self, port: usize, button: Button, pressed: bool) {
+        if let Some(live) = self.live_buttons.get_mut(port) {
+            *live &= !(button as u8);
+            if pressed {
+                *live |= button as u8;
+            }
+        }
+        // With a movie running, input only changes on frame boundaries
+        if self.movie.is_some() {
+            return;
+        }
+        match port {
+            0 => self.cpu.bus.controller1.set_button(button, pressed),
+            1 => self.cpu.bus.controller2.set_button(button, pressed),
+            _ => {}
+        }
+    }
+    
+    // Power cycles and records controller input from the first frame on.
+    pub fn start_recording(&mut self) {
+        let seed = self.config.seed.unwrap_or(0);
+        let movie = Movie::new(self.cpu.bus.cartridge.metadata().crc32, seed);
+        self.start_movie(movie, MovieMode::Recording);
+    }
+
+    // Power cycles with the movie's seed and plays it back read-only.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), String> {
+        let crc32 = self.cpu.bus.cartridge.metadata().crc32;
+        if movie.rom_crc32 != crc32 {
+            return Err(format!("Movie was recorded on ROM {:08X}, this is {:08X}", movie.rom_crc32, crc32));
+        }
+        self.config.seed = Some(movie.seed);
+        self.start_movie(movie, MovieMode::Playback);
+        Ok(())
+    }
+
+    fn start_movie(&mut self, movie: Movie, mode: MovieMode) {
+        self.on();
+        let frame = self.cpu.bus.ppu.frame_count;
+        self.movie = Some(MovieSession { movie, mode, start_frame: frame });
+        self.apply_movie_input(frame);
+    }
+
+    // Switches between read-only replay and rerecording, e.g. right after
+    // loading a save state made during a movie.
+    pub fn set_movie_mode(&mut self, mode: MovieMode) {
+        if let Some(session) = &mut self.movie {
+            session.mode = mode;
+        }
+    }
+
+    pub fn movie(&self) -> Option<&Movie> {
+        self.movie.as_ref().map(|session| &session.movie)
+    }
+
+    // Playback has used up every frame and live input is back in control
+    pub fn movie_finished(&self) -> bool {
+        self.movie.as_ref().is_some_and(|session| session.finished(self.cpu.bus.ppu.frame_count))
+    }
+
+    // Ends recording or playback and hands back the movie
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        let session = self.movie.take()?;
+        self.cpu.bus.controller1.set_buttons(self.live_buttons[0]);
+        self.cpu.bus.controller2.set_buttons(self.live_buttons[1]);
+        Some(session.movie)
+    }
+
+    fn apply_movie_input(&mut self, frame: u64) {
+        if let Some(session) = &mut self.movie {
+            let input = session.next_input(frame, self.live_buttons);
+            self.cpu.bus.controller1.set_buttons(input[0]);
+            self.cpu.bus.controller2.set_buttons(input[1]);
+        }
+    }
+
+    // Swaps controller 2 for an Arkanoid Vaus paddle, or back. With the
+    // Famicom layout it goes in the expansion port, next to both controllers.
+    pub fn connect_vaus(&mut self, connected: bool) {
+        self.cpu.bus.vaus = if connected { Some(Vaus::new()) } else { None };
+    }
+
+    // 0.0-1.0 across the paddle's travel, e.g. mouse X over the window width.
+    pub fn set_paddle(&mut self, fraction: f32) {
+        if let Some(vaus) = &mut self.cpu.bus.vaus {
+            vaus.set_position_fraction(fraction);
+        }
+    }
+
+    pub fn set_paddle_fire(&mut self, pressed: bool) {
+        if let Some(vaus) = &mut self.cpu.bus.vaus {
+            vaus.set_fire(pressed);
+        }
+    }
+
+    // The keyboard only fits a Famicom, so plugging it in switches the
+    // ports to that layout. Unplugging leaves the layout alone.
+    pub fn connect_keyboard(&mut self, connected: bool) {
+        self.cpu.bus.keyboard = if connected { Some(FamilyKeyboard::new()) } else { None };
+        if connected {
+            self.cpu.bus.port_layout = PortLayout::Famicom;
+        }
+    }
+
+    pub fn set_port_layout(&mut self, layout: PortLayout) {
+        self.cpu.bus.port_layout = layout;
+    }
+
+    pub fn port_layout(&self) -> PortLayout {
+        self.cpu.bus.port_layout
+    }
+
+    // Whether anyone is making noise into the Famicom's microphone, as
+    // Pols Voice in The Legend of Zelda listens for. Only heard with the
+    // Famicom port layout.
+    pub fn set_mic_level(&mut self, loud: bool) {
+        self.cpu.bus.mic = loud;
+    }
+
+    pub fn set_keyboard_key(&mut self, key: Key, pressed: bool) {
+        if let Some(keyboard) = &mut self.cpu.bus.keyboard {
+            keyboard.set_key(key, pressed);
+        }
+    }
+
+    // The VS System cabinet's switches, or the cartridge's on boards with
+    // them (the NWC timer); ignored otherwise. Bit 0 is switch 1.
+    pub fn set_dip_switches(&mut self, value: u8) {
+        if let Some(vs) = &mut self.cpu.bus.vs_system {
+            vs.set_dip_switches(value);
+        }
+        self.cpu.bus.cartridge.set_dip_switches(value);
+    }
+
+    // None when neither the console nor the cartridge has any
+    pub fn dip_switches(&self) -> Option<u8> {
+        match &self.cpu.bus.vs_system {
+            Some(vs) => Some(vs.dip_switches()),
+            None => self.cpu.bus.cartridge.dip_switches(),
+        }
+    }
+
+    pub fn set_coin(&mut self, slot: usize, inserted: bool) {
+        if let Some(vs) = &mut self.cpu.bus.vs_system {
+            vs.set_coin(slot, inserted);
+        }
+    }
+
+    pub fn set_service_button(&mut self, pressed: bool) {
+        if let Some(vs) = &mut self.cpu.bus.vs_system {
+            vs.set_service(pressed);
+        }
     }
     
-    pub fn set_debug_mode(&mut self){
-        self.cpu.debug_mode = true;
+    // Starts counting cycles per instruction address and function, replacing
+    // any profile in progress
+    pub fn start_profiling(&mut self) {
+        self.cpu.profiler = Some(Profiler::new());
+    }
+
+    pub fn stop_profiling(&mut self) -> Option<Profiler> {
+        self.cpu.profiler.take()
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.cpu.profiler.as_ref()
+    }
+
+    // Code/Data Logger over the inserted cartridge's PRG and CHR ROM
+    pub fn start_code_data_log(&mut self) {
+        self.cpu.bus.cartridge.start_cdl(None);
+    }
+
+    // Continues logging into the contents of a .cdl file
+    pub fn resume_code_data_log(&mut self, cdl: &[u8]) -> Result<(), String> {
+        let header = self.cpu.bus.cartridge.header();
+        let log = CodeDataLog::from_cdl(cdl, header.prg_rom_size as usize, header.chr_rom_size as usize)?;
+        self.cpu.bus.cartridge.start_cdl(Some(log));
+        Ok(())
+    }
+
+    pub fn stop_code_data_log(&mut self) -> Option<CodeDataLog> {
+        self.cpu.bus.cartridge.stop_cdl()
+    }
+
+    pub fn code_data_log(&self) -> Option<&CodeDataLog> {
+        self.cpu.bus.cartridge.cdl()
+    }
+
+    // Banks, mirroring and registers of the cartridge board, for debuggers
+    pub fn mapper_debug_info(&self) -> MapperDebugInfo {
+        self.cpu.bus.cartridge.mapper_debug_info()
+    }
+
+    // Step over/out and run-to controls, stopping at breakpoints
+    pub fn debugger(&mut self) -> Debugger<'_> {
+        Debugger::new(self)
+    }
+
+    // Reported through StepInfo::breakpoint from the step that hits one
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.cpu.bus.breakpoints.add(breakpoint);
+    }
+
+    // Stops only when the condition holds, e.g. "A == 0x20 && scanline > 200"
+    pub fn add_conditional_breakpoint(&mut self, breakpoint: Breakpoint, condition: &str) -> Result<(), String> {
+        self.cpu.bus.breakpoints.add_conditional(breakpoint, condition)
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.cpu.bus.breakpoints.remove(breakpoint);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.cpu.bus.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        self.cpu.bus.breakpoints.list()
+    }
+
+    // Keeps the last `capacity` executed instructions in memory, replacing
+    // any history so far
+    pub fn record_history(&mut self, capacity: usize) {
+        self.cpu.history = Some(InstructionHistory::new(capacity));
+    }
+
+    pub fn stop_history(&mut self) -> Option<InstructionHistory> {
+        self.cpu.history.take()
+    }
+
+    pub fn history(&self) -> Option<&InstructionHistory> {
+        self.cpu.history.as_ref()
+    }
+
+    // Records bus accesses into `trace`, replacing any trace so far
+    pub fn trace_bus(&mut self, trace: BusTrace) {
+        self.cpu.bus.set_trace(Some(trace));
+    }
+
+    pub fn stop_bus_trace(&mut self) -> Option<BusTrace> {
+        self.cpu.bus.take_trace()
+    }
+
+    pub fn bus_trace(&self) -> Option<&BusTrace> {
+        self.cpu.bus.trace()
+    }
+
+    // Labels for the trace log and SymbolTable::annotate, from ca65
+    // .dbg or FCEUX .nl files. Each file loaded adds to the table.
+    pub fn load_symbols<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<usize, String> {
+        self.cpu.symbols.get_or_insert_with(SymbolTable::new).load(path)
+    }
+
+    pub fn set_symbols(&mut self, symbols: Option<SymbolTable>) {
+        self.cpu.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> Option<&SymbolTable> {
+        self.cpu.symbols.as_ref()
+    }
+
+    // A line per instruction executed, with registers, PPU position and
+    // cycle count, nestest.log style. Slows emulation down a lot.
+    pub fn set_trace_log<W: Write + Send + 'static>(&mut self, log: W) {
+        self.cpu.set_trace_log(Some(Box::new(log)), false);
+    }
+
+    // set_trace_log with cartridge addresses as FCEUX style bank:address,
+    // like 07:C123, for following code across bank switches
+    pub fn set_banked_trace_log<W: Write + Send + 'static>(&mut self, log: W) {
+        self.cpu.set_trace_log(Some(Box::new(log)), true);
+    }
+
+    pub fn clear_trace_log(&mut self) {
+        self.cpu.set_trace_log(None, false);
     }
     
     pub fn poll_frame(&mut self) -> bool{
@@ -69,116 +871,470 @@ impl Nes {
         ret
     }
 
+    // Mixed audio since the last call, mono f32 at the configured sample rate
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.cpu.bus.apu.take_samples()
+    }
+
+    // Mute or unmute one APU channel in the mix. The channel keeps running,
+    // so only the audio output changes.
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.cpu.bus.apu.set_channel_enabled(channel, enabled);
+    }
+
+    pub fn channel_enabled(&self, channel: Channel) -> bool {
+        self.cpu.bus.apu.channel_enabled(channel)
+    }
+
+    // Records the mixed output to a 16-bit mono WAV file at the configured
+    // sample rate, independent of take_audio_samples
+    pub fn start_audio_capture<P: AsRef<std::path::Path>>(&mut self, path: P) -> std::io::Result<()> {
+        self.cpu.bus.apu.start_capture(path.as_ref())
+    }
+
+    // Finishes the WAV header; a no-op when nothing is being captured
+    pub fn stop_audio_capture(&mut self) -> std::io::Result<()> {
+        self.cpu.bus.apu.stop_capture()
+    }
+
+    pub fn is_capturing_audio(&self) -> bool {
+        self.cpu.bus.apu.is_capturing()
+    }
+
     pub fn frame(&mut self) -> [u8;256 * 240 * 3] {
-        self.cpu.bus.ppu.frame_buffer
-    }
-
-    pub fn dump_ppu(&mut self) -> std::io::Result<()> {
-        use std::fs::File;
-        use std::io::Write;
-        
-        let mut file = File::create("nametable_dump.txt")?;
-        
-        // Write header
-        writeln!(file, "NES PPU Memory Dump")?;
-        writeln!(file, "==================")?;
-        
-        // Dump all four nametables (0x2000-0x2FFF)
-        for nt in 0..4 {
-            let base_addr = 0x2000 + (nt * 0x400);
-            writeln!(file, "\nNametable {}", nt)?;
-            writeln!(file, "-------------")?;
-            
-            // Print each row of the 32x30 nametable
-            for y in 0..30 {
-                // Write row number
-                write!(file, "{:02X}: ", y)?;
-                
-                // Write tile values for this row
-                for x in 0..32 {
-                    let addr = base_addr + y * 32 + x;
-                    let tile = self.cpu.bus.ppu.read(addr as u16);
-                    write!(file, "{:02X} ", tile)?;
-                }
-                
-                // Add ASCII representation
-                write!(file, "| ")?;
-                for x in 0..32 {
-                    let addr = base_addr + y * 32 + x;
-                    let tile = self.cpu.bus.ppu.read(addr as u16);
-                    // Convert to ASCII if printable, otherwise use a dot
-                    let ch = if tile >= 0x20 && tile < 0x7F {
-                        tile as char
-                    } else {
-                        '.'
-                    };
-                    write!(file, "{}", ch)?;
-                }
-                writeln!(file)?;
-            }
-            
-            // Print attribute table for this nametable
-            writeln!(file, "\nAttribute Table:")?;
-            let attr_base = base_addr + 0x3C0;
-            for y in 0..8 {
-                write!(file, "    ")?;
-                for x in 0..8 {
-                    let addr = attr_base + y * 8 + x;
-                    let attr = self.cpu.bus.ppu.read(addr as u16);
-                    write!(file, "{:02X} ", attr)?;
-                }
-                writeln!(file)?;
+        *self.cpu.bus.ppu.frame_buffer
+    }
+
+    // Runs the console until `callback` breaks, calling it for each frame,
+    // breakpoint and jam. The callback gets the console to pull video and
+    // audio, set input or anything else between frames. None if the console
+    // is off, or gets turned off by the callback.
+    pub fn run_until<B, F: FnMut(&mut Nes, FrameEvent) -> ControlFlow<B>>(&mut self, mut callback: F) -> Option<B> {
+        let mut jammed = self.cpu.jammed;
+        while self.powered {
+            let step = self.step();
+            let newly_jammed = step.jammed && !jammed;
+            jammed = step.jammed;
+            let event = if let Some(hit) = step.breakpoint {
+                FrameEvent::Breakpoint(hit)
+            } else if newly_jammed {
+                FrameEvent::Jammed
+            } else if self.poll_frame() {
+                FrameEvent::Frame(FrameTiming { frame: self.seen_frames, cycles: self.last_frame_cycles, total_cycles: self.frame_start_cycle })
+            } else {
+                continue;
+            };
+            if let ControlFlow::Break(result) = callback(self, event) {
+                return Some(result);
             }
         }
-        
-
-        // Add palette data section
-        writeln!(file, "\nPalette Data")?;
-        writeln!(file, "============")?;
-        
-        // Background palettes (0x3F00-0x3F0F)
-        writeln!(file, "\nBackground Palettes:")?;
-        for i in 0..4 {
-            write!(file, "Palette {}: ", i)?;
-            for j in 0..4 {
-                let addr = 0x3F00 + i * 4 + j;
-                let color = self.cpu.bus.ppu.read(addr as u16);
-                write!(file, "{:02X} ", color)?;
-            }
-            writeln!(file)?;
-        }
-        
-        // Sprite palettes (0x3F10-0x3F1F)
-        writeln!(file, "\nSprite Palettes:")?;
-        for i in 0..4 {
-            write!(file, "Palette {}: ", i)?;
-            for j in 0..4 {
-                let addr = 0x3F10 + i * 4 + j;
-                let color = self.cpu.bus.ppu.read(addr as u16);
-                write!(file, "{:02X} ", color)?;
-            }
-            writeln!(file)?;
+        None
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use rom::builder::RomBuilder;
+    use test_support::TestRom;
+    use super::*;
+
+    #[test]
+    fn seed_reproduces_the_power_on_state() {
+        let power_on = |seed| {
+            let mut config = NesConfig::new(SystemVersion::NTSC);
+            config.ram_pattern = config::RamPattern::Random;
+            config.random_ppu_state = true;
+            config.seed = Some(seed);
+            let mut nes = Nes::with_config(config);
+            nes.set_rom(Rom::new(TestRom::new(0).bytes()));
+            nes.on();
+            let hashes = nes.state_hashes();
+            (hashes.ram, hashes.ppu, hashes.vram, hashes.oam)
+        };
+        assert_eq!(power_on(1), power_on(1));
+
+        let (a, b) = (power_on(1), power_on(2));
+        assert!(a.0 != b.0 && a.2 != b.2 && a.3 != b.3, "{:?} {:?}", a, b);
+    }
+
+    #[test]
+    fn auto_region_follows_the_rom() {
+        let mut nes = Nes::new(SystemVersion::Auto);
+        assert_eq!(nes.region(), SystemVersion::NTSC);
+        nes.set_rom(Rom::new(TestRom::new(0).pal().bytes()));
+        assert_eq!(nes.region(), SystemVersion::PAL);
+        nes.load_rom(Rom::new(TestRom::new(0).submapper(0).pal().bytes()));
+        assert_eq!(nes.region(), SystemVersion::PAL);
+        nes.load_rom(Rom::new(TestRom::new(0).bytes()));
+        assert_eq!(nes.region(), SystemVersion::NTSC);
+
+        nes.set_region(SystemVersion::Dendy);
+        nes.load_rom(Rom::new(TestRom::new(0).pal().bytes()));
+        assert_eq!(nes.region(), SystemVersion::Dendy);
+        assert_eq!(nes.config().region, SystemVersion::Dendy);
+    }
+
+    #[test]
+    fn power_on_keeps_the_audio_settings() {
+        let mut config = NesConfig::new(SystemVersion::PAL);
+        config.audio_synthesis = config::AudioSynthesis::BandLimited;
+        config.sample_rate = 48_000;
+        let mut nes = Nes::with_config(config);
+        nes.set_rom(Rom::new(TestRom::new(0).bytes()));
+        nes.on();
+        nes.run_frame();
+        nes.run_frame();
+        nes.take_audio_samples();
+        nes.run_frame();
+        let expected = nes.frame_period().as_secs_f64() * 48_000.0;
+        let made = nes.take_audio_samples().len() as f64;
+        assert!((made - expected).abs() <= 1.0, "{} samples, expected {}", made, expected);
+    }
+
+    #[test]
+    fn overclocked_frames_keep_stock_audio() {
+        let samples = |overclock_scanlines| {
+            let mut config = NesConfig::new(SystemVersion::NTSC);
+            config.overclock_scanlines = overclock_scanlines;
+            config.sample_rate = 48_000;
+            let mut nes = Nes::with_config(config);
+            nes.set_rom(Rom::new(TestRom::new(0).bytes()));
+            nes.on();
+            nes.run_frame();
+            nes.run_frame();
+            nes.take_audio_samples();
+            nes.run_frame();
+            nes.take_audio_samples().len() as i64
+        };
+        let (stock, overclocked) = (samples(0), samples(100));
+        assert!((stock - overclocked).abs() <= 1, "{} samples, expected {}", overclocked, stock);
+    }
+
+    #[test]
+    fn band_limited_audio_reaches_a_powered_console() {
+        let run = |synthesis| {
+            let mut config = NesConfig::new(SystemVersion::NTSC);
+            config.audio_synthesis = synthesis;
+            config.seed = Some(1);
+            let mut nes = Nes::with_config(config);
+            nes.set_rom(Rom::new(TestRom::new(0).bytes()));
+            nes.on();
+            nes.run_frame();
+            nes.cpu.bus.apu.write(0x4011, 0x40);
+            nes.take_audio_samples();
+            nes.run_frame();
+            nes.take_audio_samples()
+        };
+        // Point sampling jumps straight to the new DMC level, band limiting
+        // rings on the way there
+        let (sampled, band_limited) = (run(config::AudioSynthesis::Sampled), run(config::AudioSynthesis::BandLimited));
+        assert_eq!(sampled.len(), band_limited.len());
+        assert_ne!(sampled, band_limited);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn restored_console_runs_identically() {
+        // INC $00; JMP $8000
+        let console = || {
+            let mut nes = Nes::new(SystemVersion::NTSC);
+            nes.set_rom(RomBuilder::new(0).prg(&[0xE6, 0x00, 0x4C, 0x00, 0x80]).vectors(0x8000, 0x8000, 0x8000).build().unwrap());
+            nes.on();
+            nes
+        };
+        let mut nes = console();
+        for _ in 0..1000 {
+            nes.step();
         }
-        
-        Ok(())
+
+        // States leave the ROM out, so only a console with the game can
+        // take one
+        let json = serde_json::to_string(&nes).unwrap();
+        assert!(Nes::new(SystemVersion::NTSC).restore(serde_json::from_str(&json).unwrap()).is_err());
+        let mut restored = console();
+        restored.restore(serde_json::from_str(&json).unwrap()).unwrap();
+        for _ in 0..30000 {
+            nes.step();
+            restored.step();
+        }
+        assert_eq!(restored.total_cycles(), nes.total_cycles());
+        assert_eq!(restored.memory(MemoryRegion::SystemRam), nes.memory(MemoryRegion::SystemRam));
+        assert_ne!(nes.memory(MemoryRegion::SystemRam)[0], 0);
+        assert_eq!(restored.frame()[..], nes.frame()[..]);
     }
 
-    pub fn run(&mut self){
-        if self.cpu.debug_mode {
-            if fs::metadata("debug.log").is_ok() {
-                let _= fs::remove_file("debug.log");  // Delete the file
-            }
+    #[test]
+    fn battery_saves_load_into_prg_ram() {
+        let mut nes = test_support::console_with(&TestRom::new(1).battery());
+        nes.load_prg_ram(&[0x12, 0x34]);
+        assert_eq!(nes.memory(MemoryRegion::PrgRam)[..3], [0x12, 0x34, 0]);
+        assert_eq!(nes.peek(0x6001), Some(0x34));
+    }
+
+    #[test]
+    fn cheats_come_out_with_the_cartridge() {
+        let mut nes = test_support::console();
+        nes.add_cheat("SXIOPO").unwrap();
+        nes.load_rom(Rom::new(TestRom::new(0).bytes()));
+        assert!(nes.cpu.bus.cheats.is_empty());
+    }
+
+    #[test]
+    fn writes_ppu_state_directly() {
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(Rom::new(TestRom::new(0).chr_8k(0).bytes()));
+        nes.cpu.bus.ppu.open_bus = 0x55;
+        nes.write_ppu(0x0010, 0xAA);
+        nes.write_ppu(0x2400, 0x12);
+        nes.write_palette(0x10, 0x2C);
+        nes.write_oam(65, [0x20, 0x01, 0x40, 0x30]);
+
+        assert_eq!(nes.cpu.bus.ppu_read(0x0010), 0xAA);
+        assert_eq!(nes.cpu.bus.ppu_read(0x2400), 0x12);
+        // $3F10 mirrors the backdrop
+        assert_eq!(nes.cpu.bus.ppu_read(0x3F00), 0x2C);
+        assert_eq!(nes.cpu.bus.ppu.oam_bytes()[4..8], [0x20, 0x01, 0x40, 0x30]);
+        assert_eq!(nes.cpu.bus.ppu.open_bus, 0x55);
+    }
+
+    // Past the PPU's warm up with tile 0 solid in color 1, spinning in a
+    // JMP loop at $0200
+    fn raster_console() -> Nes {
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(Rom::new(TestRom::new(0).chr_8k(0).bytes()));
+        nes.on();
+        nes.run_frame();
+        nes.run_frame();
+        for row in 0..8 {
+            nes.write_ppu(row, 0xFF);
         }
+        for (i, byte) in [0x4C, 0x00, 0x02].into_iter().enumerate() {
+            nes.poke(0x0200 + i as u16, byte);
+        }
+        nes.set_start(0x0200);
+        nes
+    }
 
-        if self.cpu.debug_mode {
-            if fs::metadata("nt.log").is_ok() {
-                let _= fs::remove_file("nt.log");  // Delete the file
-            }
+    // Runs `program` with A = `a` from dot 60-70 of line 100 and finishes the
+    // line. Returns the first pixel that saw the program's last write.
+    fn raster_write(nes: &mut Nes, a: u8, program: &[u8]) -> usize {
+        for (i, byte) in program.iter().enumerate() {
+            nes.poke(0x0210 + i as u16, *byte);
+        }
+        while !(nes.cpu.bus.ppu.scanline == 100 && nes.cpu.bus.ppu.cycle >= 60) {
+            nes.step();
+        }
+        let dot = nes.cpu.bus.ppu.cycle;
+        nes.cpu.a = a;
+        nes.set_start(0x0210);
+        nes.step();
+        nes.set_start(0x0200);
+        while nes.cpu.bus.ppu.scanline == 100 {
+            nes.step();
         }
+        // A 4 cycle store writes after 9 dots, and pixel x is drawn on dot x + 2
+        dot + 9 - 2
+    }
 
-        loop{
-            self.step();
+    fn colors_around(nes: &Nes, x: usize) -> [&[u8]; 2] {
+        let row = &nes.cpu.bus.ppu.frame_buffer[100 * 256 * 3..101 * 256 * 3];
+        [&row[(x - 1) * 3..x * 3], &row[x * 3..(x + 1) * 3]]
+    }
+
+    fn rgb(nes: &Nes, color: u8) -> &[u8] {
+        &nes.cpu.bus.ppu.rgb_palette[color as usize * 3..color as usize * 3 + 3]
+    }
+
+    #[test]
+    fn mask_writes_land_mid_scanline() {
+        let mut nes = raster_console();
+        nes.write_palette(0, 0x0F);
+        nes.write_palette(1, 0x16);
+        nes.poke(0x2001, 0x0A);
+        // STA $2001 with the background off
+        let x = raster_write(&mut nes, 0x00, &[0x8D, 0x01, 0x20]);
+        assert_eq!(colors_around(&nes, x), [rgb(&nes, 0x16), rgb(&nes, 0x0F)]);
+    }
+
+    #[test]
+    fn palette_writes_land_mid_scanline() {
+        let mut nes = raster_console();
+        nes.write_palette(0, 0x0F);
+        nes.write_palette(1, 0x16);
+        nes.poke(0x2006, 0x3F);
+        nes.poke(0x2006, 0x00);
+        // STA $2007 with rendering off, so the backdrop changes color
+        let x = raster_write(&mut nes, 0x16, &[0x8D, 0x07, 0x20]);
+        assert_eq!(colors_around(&nes, x), [rgb(&nes, 0x0F), rgb(&nes, 0x16)]);
+    }
+
+    #[test]
+    fn forced_interrupts_are_taken_once() {
+        // Both vectors point at $0303, in PRG bank 3
+        let mut nes = raster_console();
+        nes.raise_nmi();
+        assert!(nes.pending_interrupts().nmi);
+        nes.step();
+        assert_eq!(nes.cpu.pc, 0x0303);
+        assert!(!nes.pending_interrupts().nmi);
+
+        nes.set_start(0x0200);
+        nes.cpu.p |= 0x04;
+        nes.raise_irq();
+        nes.step();
+        assert_eq!(nes.cpu.pc, 0x0200);
+        assert!(nes.pending_interrupts().irq.is_asserted_by(IrqSource::External));
+        nes.cpu.p &= !0x04;
+        nes.step();
+        assert_eq!(nes.cpu.pc, 0x0303);
+        assert!(!nes.pending_interrupts().irq.is_asserted_by(IrqSource::External));
+    }
+
+    #[test]
+    fn hidden_layers_still_hit_sprite_zero() {
+        let mut nes = raster_console();
+        nes.write_palette(0, 0x0F);
+        nes.write_palette(1, 0x16);
+        nes.write_palette(0x11, 0x2A);
+        // Sprite 0 over the solid background at (16, 41), in front of it
+        nes.write_oam(0, [40, 0x00, 0x00, 16]);
+        nes.poke(0x2001, 0x1E);
+
+        let frame = |nes: &mut Nes, background, sprites| {
+            nes.set_layer_visibility(background, sprites);
+            nes.run_frame();
+            let hit = nes.cpu.bus.ppu.read_status() & 0x40 != 0;
+            let idx = (41 * 256 + 16) * 3;
+            (nes.cpu.bus.ppu.frame_buffer[idx..idx + 3].to_vec(), hit)
+        };
+        assert_eq!(frame(&mut nes, true, true), (rgb(&nes, 0x2A).to_vec(), true));
+        assert_eq!(frame(&mut nes, true, false), (rgb(&nes, 0x16).to_vec(), true));
+        assert_eq!(frame(&mut nes, false, true), (rgb(&nes, 0x2A).to_vec(), true));
+        assert_eq!(frame(&mut nes, false, false), (rgb(&nes, 0x0F).to_vec(), true));
+        assert_eq!(nes.layer_visibility(), (false, false));
+    }
+
+    fn with_device(device: u8) -> Rom {
+        let mut data = TestRom::new(0).submapper(0).bytes();
+        data[15] = device;
+        Rom::new(data)
+    }
+
+    #[test]
+    fn header_plugs_in_its_device() {
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.connect_keyboard(true);
+        nes.set_rom(with_device(0x00));
+        assert!(nes.cpu.bus.keyboard.is_some());
+        assert_eq!(nes.port_layout(), PortLayout::Famicom);
+        nes.set_rom(with_device(0x0F));
+        assert!(nes.cpu.bus.vaus.is_some() && nes.cpu.bus.keyboard.is_none());
+        assert_eq!(nes.port_layout(), PortLayout::Nes);
+        nes.set_rom(with_device(0x10));
+        assert!(nes.cpu.bus.vaus.is_some() && nes.port_layout() == PortLayout::Famicom);
+        nes.set_rom(with_device(0x01));
+        assert!(nes.cpu.bus.vaus.is_none() && nes.port_layout() == PortLayout::Famicom);
+
+        let config = NesConfig { auto_input_devices: false, ..NesConfig::new(SystemVersion::NTSC) };
+        let mut nes = Nes::with_config(config);
+        nes.set_rom(with_device(0x23));
+        assert!(nes.cpu.bus.keyboard.is_none());
+    }
+
+    // Collects what's written to it where the test can still see it
+    #[derive(Clone, Default)]
+    struct SharedLog(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn instances_trace_to_their_own_logs() {
+        let logs = [SharedLog::default(), SharedLog::default()];
+        let mut consoles: Vec<Nes> = logs.iter().map(|log| {
+            let mut nes = Nes::new(SystemVersion::NTSC);
+            nes.set_rom(Rom::new(TestRom::new(0).bytes()));
+            nes.set_trace_log(log.clone());
+            nes.on();
+            nes
+        }).collect();
+        for _ in 0..3 {
+            consoles[0].step();
+        }
+        consoles[1].step();
+
+        let lines = logs.map(|log| String::from_utf8(log.0.lock().unwrap().clone()).unwrap().lines().count());
+        assert_eq!(lines, [3, 1]);
+    }
+
+    #[test]
+    fn trace_banks_only_when_asked() {
+        let logs = [SharedLog::default(), SharedLog::default()];
+        for (banked, log) in [false, true].into_iter().zip(&logs) {
+            let mut nes = Nes::new(SystemVersion::NTSC);
+            // NOP; JMP $8000
+            nes.set_rom(RomBuilder::new(0).prg(&[0xEA, 0x4C, 0x00, 0x80]).vectors(0x8000, 0x8000, 0x8000).build().unwrap());
+            if banked {
+                nes.set_banked_trace_log(log.clone());
+            } else {
+                nes.set_trace_log(log.clone());
+            }
+            nes.on();
+            nes.step();
         }
+        let [plain, banked] = logs.map(|log| String::from_utf8(log.0.lock().unwrap().clone()).unwrap());
+        // nestest.log starts each line with the bare address
+        assert!(plain.starts_with("8000  EA"), "{}", plain);
+        assert!(banked.starts_with("00:8000  EA"), "{}", banked);
     }
 
+    #[test]
+    fn run_until_hands_over_each_event() {
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        assert_eq!(nes.run_until(|_, _| ControlFlow::Break(())), None);
+
+        // NOP; JMP $8000
+        let rom = RomBuilder::new(0).prg(&[0xEA, 0x4C, 0x00, 0x80]).vectors(0x8000, 0x8000, 0x8000).build().unwrap();
+        nes.set_rom(rom);
+        nes.on();
+        let mut frames = 0;
+        let last = nes.run_until(|_, event| match event {
+            FrameEvent::Frame(timing) => {
+                frames += 1;
+                if frames == 3 { ControlFlow::Break(timing.frame) } else { ControlFlow::Continue(()) }
+            }
+            _ => ControlFlow::Continue(()),
+        });
+        assert_eq!(last, Some(nes.cpu.bus.ppu.frame_count));
+
+        nes.add_breakpoint(Breakpoint::Execute(0x8001));
+        let hit = nes.run_until(|_, event| match event {
+            FrameEvent::Breakpoint(hit) => ControlFlow::Break(hit.context.pc),
+            _ => ControlFlow::Continue(()),
+        });
+        assert_eq!(hit, Some(0x8001));
+
+        let off = nes.run_until(|nes, _| {
+            nes.off();
+            ControlFlow::<()>::Continue(())
+        });
+        assert_eq!(off, None);
+
+        let rom = RomBuilder::new(0).prg(&[0x02]).vectors(0x8000, 0x8000, 0x8000).build().unwrap();
+        nes.set_rom(rom);
+        nes.on();
+        let jam = nes.run_until(|nes, event| match event {
+            FrameEvent::Jammed => ControlFlow::Break(nes.is_jammed()),
+            _ => ControlFlow::Continue(()),
+        });
+        assert_eq!(jam, Some(true));
+    }
 }
\ No newline at end of file
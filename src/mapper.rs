@@ -1,21 +1,249 @@
-use rom::header::RomHeader;
+use rom::header::{Mirroring, RomHeader};
 
-use crate::{mappers::{m0::Mapper0, m1::Mapper1}, rom};
+use crate::{rom::RomError, mappers::{m0::Mapper0, m1::Mapper1, m10::Mapper10, m11::Mapper11, m21::Mapper21, m34::Mapper34, m38::Mapper38, m60::Mapper60, m64::Mapper64, m66::Mapper66, m71::Mapper71, m79::Mapper79, m99::Mapper99, m105::Mapper105, m206::Mapper206}, rom};
+#[cfg(feature = "serde")]
+use crate::mappers::fallback::FallbackMapper;
 
 #[derive(Clone)]
 pub struct MapperFactory;
 
 impl MapperFactory {
-    pub fn select(header: &RomHeader, data: Vec<u8>) -> Box<dyn Mapper> {
-        match header.mapper_number {
-            0 => Box::new(Mapper0::new(&header, data)),
-            1 => Box::new(Mapper1::new(&header, data)),
-            _ => panic!("Mapper not supported {}", header.mapper_number)
+    pub fn select(header: &RomHeader, data: Vec<u8>) -> Result<Box<dyn Mapper>, RomError> {
+        let mapper: Box<dyn Mapper> = match header.mapper_number {
+            0 => Box::new(Mapper0::new(header, data)),
+            1 => Box::new(Mapper1::new(header, data)),
+            10 => Box::new(Mapper10::new(header, data)),
+            11 => Box::new(Mapper11::new(header, data)),
+            21 | 22 | 23 | 25 => Box::new(Mapper21::new(header, data)),
+            34 => Box::new(Mapper34::new(header, data)),
+            38 => Box::new(Mapper38::new(header, data)),
+            60 => Box::new(Mapper60::new(header, data)),
+            64 => Box::new(Mapper64::new(header, data)),
+            66 => Box::new(Mapper66::new(header, data)),
+            71 => Box::new(Mapper71::new(header, data)),
+            79 => Box::new(Mapper79::new(header, data)),
+            99 => Box::new(Mapper99::new(header, data)),
+            105 => Box::new(Mapper105::new(header, data)),
+            76 | 88 | 95 | 154 | 206 => Box::new(Mapper206::new(header, data)),
+            number => return Err(RomError::UnsupportedMapper(number))
+        };
+        Ok(mapper)
+    }
+}
+// Where one of the four 1KB nametable slots at $2000-$2FFF is served from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NametableSource {
+    Vram(u16), // 1KB page of console VRAM (pages 2-3 only exist on four-screen boards)
+    Mapper     // Cartridge memory, through read_nametable/write_nametable
+}
+
+// The VRAM page nametable slot 0-3 uses under `mirroring`
+pub fn mirrored_nametable(table: u16, mirroring: Mirroring) -> NametableSource {
+    let page = match mirroring {
+        Mirroring::Horizontal => table >> 1,
+        Mirroring::Vertical => table & 0x1,
+        Mirroring::SingleScreen => 0,
+        Mirroring::SingleScreenUpper => 1,
+        Mirroring::FourScreen => table
+    };
+    NametableSource::Vram(page)
+}
+
+// One bank-switched window and the bank currently in it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BankWindow {
+    pub addr: u16,
+    pub size: u32,
+    pub bank: u32,
+}
+
+// Banking snapshot for debugger UIs, see Mapper::debug_state
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapperDebugInfo {
+    pub name: &'static str,
+    // PRG-ROM windows at $8000-$FFFF
+    pub prg_banks: Vec<BankWindow>,
+    // CHR windows at PPU $0000-$1FFF
+    pub chr_banks: Vec<BankWindow>,
+    // None when the board leaves it to the header. Cartridge fills it in.
+    pub mirroring: Option<Mirroring>,
+    // Raw register values, latches and IRQ counters, by name
+    pub registers: Vec<(&'static str, u32)>,
+}
+
+impl MapperDebugInfo {
+    // Splits PRG and CHR space into windows of the given sizes and reads the
+    // bank in each back through map(), so it matches what reads see
+    pub fn new<M: Mapper + ?Sized>(mapper: &M, name: &'static str, prg_window: u32, chr_window: u32) -> Self {
+        let windows = |start: u32, end: u32, size: u32| -> Vec<BankWindow> {
+            (start..end).step_by(size as usize)
+                .map(|addr| BankWindow { addr: addr as u16, size, bank: mapper.map(addr as u16) / size })
+                .collect()
+        };
+        MapperDebugInfo {
+            name,
+            prg_banks: windows(0x8000, 0x10000, prg_window),
+            chr_banks: windows(0x0000, 0x2000, chr_window),
+            mirroring: mapper.mirroring(),
+            registers: Vec::new(),
         }
     }
+
+    pub fn register(mut self, name: &'static str, value: u32) -> Self {
+        self.registers.push((name, value));
+        self
+    }
 }
-pub trait Mapper {
-    fn map(&self, addr: u16) -> u16;
+
+// Send so a Nes can be moved onto an emulation thread
+pub trait Mapper: Send {
+    // Offset an address resolves to inside the chip it selects (CHR, PRG-RAM
+    // or PRG-ROM), with the current banking applied
+    fn map(&self, addr: u16) -> u32;
     fn read(&mut self, addr: u16) -> u8;
     fn write(&mut self, addr: u16, data: u8);
+
+    // CPU-side writes also carry the bus cycle they happened on, for boards
+    // that react to write timing. Most mappers don't care.
+    fn cpu_write(&mut self, addr: u16, data: u8, _cycle: u64) {
+        self.write(addr, data);
+    }
+
+    // Writes to $4016 drive the OUT0-2 pins, which the VS System routes to
+    // the cartridge for bank switching.
+    fn out_latch_write(&mut self, _data: u8) {}
+
+    // Boards with mirroring control override the header's hardwired setting.
+    fn mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
+    // Resolves nametable slot 0-3 for every PPU access to $2000-$2FFF. The
+    // default wires it to VRAM per mirroring(), falling back to the header.
+    // Boards that put nametables in ExRAM or CHR-ROM (MMC5, Namco 163)
+    // override this and return NametableSource::Mapper for those slots.
+    fn nametable_source(&self, table: u16, header_mirroring: Mirroring) -> NametableSource {
+        mirrored_nametable(table, self.mirroring().unwrap_or(header_mirroring))
+    }
+
+    fn read_nametable(&mut self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn write_nametable(&mut self, _addr: u16, _data: u8) {}
+
+    // Level of the board's IRQ output, sampled by the CPU every instruction.
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    // CPU cycles run since the last call, for boards with timers counting
+    // them. Called once per instruction, before the IRQ line is sampled.
+    fn clock(&mut self, _cycles: u8) {}
+
+    // Called on power on and when the console's RESET button is pressed.
+    // Cartridges don't see the reset line itself, but some boards (reset
+    // based multicarts) or timing-sensitive state react to it.
+    fn reset(&mut self) {}
+
+    // Called when the console powers on, after the reset() that comes with
+    // it, so boards counting resets start over
+    fn power_on(&mut self) {}
+
+    // Switches on the board, bit 0 being switch 1. None without any.
+    fn dip_switches(&self) -> Option<u8> {
+        None
+    }
+
+    fn set_dip_switches(&mut self, _value: u8) {}
+
+    // Battery or work RAM at $6000-$7FFF, whole, for memory watching
+    fn prg_ram(&self) -> &[u8] {
+        &[]
+    }
+
+    // The same RAM, for loading a battery save into
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    // CHR-ROM or CHR-RAM, whole, so debug views can read it through map()
+    fn chr(&self) -> &[u8] {
+        &[]
+    }
+
+    // Current banking and registers. Every board implements this so a
+    // debugger can show it whichever cartridge is in.
+    fn debug_state(&self) -> MapperDebugInfo;
+
+    // Snapshot of the board, banks and RAM included. PRG and CHR ROM are
+    // left out and come back through restore_rom.
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState;
+
+    // Reloads the ROM a deserialized board was saved without, from the image
+    // it was built from
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]);
+}
+
+// Serializable form of every supported board, since a Box<dyn Mapper> can't
+// be deserialized without knowing which one it was.
+#[cfg(feature = "serde")]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub enum MapperState {
+    Mapper0(Mapper0),
+    Mapper1(Mapper1),
+    Mapper10(Mapper10),
+    Mapper11(Mapper11),
+    Mapper21(Mapper21),
+    Mapper34(Mapper34),
+    Mapper38(Mapper38),
+    Mapper60(Mapper60),
+    Mapper64(Mapper64),
+    Mapper66(Mapper66),
+    Mapper71(Mapper71),
+    Mapper79(Mapper79),
+    Mapper99(Mapper99),
+    Mapper105(Mapper105),
+    Mapper206(Mapper206),
+    Fallback(FallbackMapper),
+}
+
+#[cfg(feature = "serde")]
+impl MapperState {
+    pub fn into_mapper(self) -> Box<dyn Mapper> {
+        match self {
+            MapperState::Mapper0(mapper) => Box::new(mapper),
+            MapperState::Mapper1(mapper) => Box::new(mapper),
+            MapperState::Mapper10(mapper) => Box::new(mapper),
+            MapperState::Mapper11(mapper) => Box::new(mapper),
+            MapperState::Mapper21(mapper) => Box::new(mapper),
+            MapperState::Mapper34(mapper) => Box::new(mapper),
+            MapperState::Mapper38(mapper) => Box::new(mapper),
+            MapperState::Mapper60(mapper) => Box::new(mapper),
+            MapperState::Mapper64(mapper) => Box::new(mapper),
+            MapperState::Mapper66(mapper) => Box::new(mapper),
+            MapperState::Mapper71(mapper) => Box::new(mapper),
+            MapperState::Mapper79(mapper) => Box::new(mapper),
+            MapperState::Mapper99(mapper) => Box::new(mapper),
+            MapperState::Mapper105(mapper) => Box::new(mapper),
+            MapperState::Mapper206(mapper) => Box::new(mapper),
+            MapperState::Fallback(mapper) => Box::new(mapper),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Box<dyn Mapper> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.state().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Box<dyn Mapper> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        MapperState::deserialize(deserializer).map(MapperState::into_mapper)
+    }
 }
@@ -0,0 +1,97 @@
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::RomHeader};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// Stands in for boards that aren't emulated, NROM style: the last 32KB of
+// PRG, where most boards keep their fixed bank and the vectors, and the
+// first 8KB of CHR. Register writes go nowhere, so games usually get as
+// far as a title screen at best.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FallbackMapper {
+    mapper_number: u16,
+    chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Memory,
+    prg_ram: Memory,
+}
+
+impl FallbackMapper {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+
+        FallbackMapper {
+            mapper_number: header.mapper_number,
+            chr: ChrMemory::new(header, chr_rom_data),
+            prg_rom: Memory::new(prg_rom_data),
+            prg_ram: Memory::new(vec![0; 8 * 1024]),
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        self.prg_rom.capacity().saturating_sub(0x8000) + (addr - 0x8000) as u32
+    }
+}
+
+impl Mapper for FallbackMapper {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => self.chr.read(addr as u32),
+
+            // PRG RAM (0x6000-0x7FFF)
+            0x6000..=0x7FFF => self.prg_ram.read(addr - 0x6000),
+
+            // PRG ROM (0x8000-0xFFFF)
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
+
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.chr.write(addr as u32, data),
+            0x6000..=0x7FFF => self.prg_ram.write(addr - 0x6000, data),
+            _ => {}
+        }
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => self.chr.mirror(addr as u32),
+            0x6000..=0x7FFF => (addr - 0x6000) as u32,
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+            _ => addr as u32
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        self.prg_ram.as_slice()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.prg_ram.as_mut_slice()
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        MapperDebugInfo::new(self, "Unsupported", 0x8000, 0x2000)
+            .register("Mapper", self.mapper_number as u32)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Fallback(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,113 @@
+use std::marker::PhantomData;
+
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::RomHeader};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// Wiring of a discrete-logic board whose only register is a latch on the
+// data bus, selecting a 32KB PRG bank and an 8KB CHR bank. A new board is
+// a unit struct implementing this and a type alias for LatchMapper.
+pub trait LatchBoard: Clone + Send + 'static {
+    const NAME: &'static str;
+
+    // Whether a CPU write to `addr` reaches the latch
+    fn decodes(addr: u16) -> bool {
+        addr >= 0x8000
+    }
+
+    // PRG and CHR bank for a latched value
+    fn banks(latch: u8) -> (u8, u8);
+
+    #[cfg(feature = "serde")]
+    fn state(mapper: &LatchMapper<Self>) -> MapperState;
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound = ""))]
+pub struct LatchMapper<B: LatchBoard> {
+    chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Memory,
+    latch: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    board: PhantomData<B>,
+}
+
+impl<B: LatchBoard> LatchMapper<B> {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+
+        LatchMapper {
+            chr: ChrMemory::new(header, chr_rom_data),
+            prg_rom: Memory::new(prg_rom_data),
+            latch: 0,
+            board: PhantomData,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> u32 {
+        B::banks(self.latch).1 as u32 * 0x2000 + addr as u32
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        B::banks(self.latch).0 as u32 * 0x8000 + (addr - 0x8000) as u32
+    }
+}
+
+impl<B: LatchBoard> Mapper for LatchMapper<B> {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => self.chr.read(self.chr_offset(addr)),
+
+            // PRG ROM (0x8000-0xFFFF)
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
+
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            // CHR RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => {
+                let offset = self.chr_offset(addr);
+                self.chr.write(offset, data);
+            },
+
+            _ if B::decodes(addr) => self.latch = data,
+
+            _ => {}
+        }
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => self.chr.mirror(self.chr_offset(addr)),
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+            _ => addr as u32
+        }
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        MapperDebugInfo::new(self, B::NAME, 0x8000, 0x2000)
+            .register("Latch", self.latch as u32)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        B::state(self)
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
\ No newline at end of file
@@ -1,26 +1,23 @@
-use crate::{mapper::Mapper, memory::Memory, rom::header::{RomHeader, HEADER_SIZE}};
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::RomHeader};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Mapper0 {
-	chr_rom: Memory,
-    chr_ram: Memory,
+	chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
     prg_rom: Memory,
     prg_ram: Memory
 }
 
 impl Mapper0 {
 	pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
-        let prg_rom = Memory::new(data[HEADER_SIZE..HEADER_SIZE + header.prg_rom_size as usize].to_vec());
-        let chr_rom = Memory::new(data[HEADER_SIZE + header.prg_rom_size as usize..HEADER_SIZE + header.prg_rom_size as usize + header.chr_rom_size as usize].to_vec());
-
-
-        let mut chr_ram = Memory::new(vec![0; 0]);
-        if header.chr_rom_size ==0 && header.chr_ram_size == 0 {
-            chr_ram = Memory::new(vec![0;8 * 1024]);
-        }
+        let prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        let chr_rom = data[header.chr_rom_range()].to_vec();
 
 		Mapper0 {
-			chr_rom,
-            chr_ram,
+			chr: ChrMemory::new(header, chr_rom),
             prg_rom,
             prg_ram: Memory::new(vec![0; 8 * 1024]),
 		}
@@ -30,34 +27,17 @@ impl Mapper for Mapper0 {
     fn read(&mut self, addr: u16) -> u8 {
         match addr {
             // CHR ROM/RAM (0x0000-0x1FFF)
-            0x0000..=0x1FFF => {
-                if self.chr_rom.capacity() == 0 {
-                    self.chr_ram.read(addr)
-                }else{
-                    self.chr_rom.read(addr)
-                }
-            }
+            0x0000..=0x1FFF => self.chr.read(addr as u32),
             
             // PRG RAM (0x6000-0x7FFF)
             0x6000..=0x7FFF => self.prg_ram.read(addr - 0x6000),
             
             // PRG ROM (0x8000-0xFFFF)
-            0x8000..=0xFFFF => {
-                let mapped_addr = if addr >= 0xC000 && self.prg_rom.capacity() <= 0x4000 {
-                    // Mirror for 16KB PRG ROM
-                    (addr - 0xC000) % 0x4000
-                } else {
-                    // 32KB PRG ROM or lower bank access
-                    (addr - 0x8000) % self.prg_rom.capacity() as u16
-                };
-                self.prg_rom.read(mapped_addr)
-            },
+            // 16KB PRG ROM mirrors into $C000-$FFFF
+            0x8000..=0xFFFF => self.prg_rom.read(addr - 0x8000),
             
-            // Invalid addresses
-            _ => {
-                debug_assert!(false, "NROM: Invalid read address: ${:04X}", addr);
-                0
-            }
+            // Nothing mapped here
+            _ => 0
         }
     }
 
@@ -67,7 +47,7 @@ impl Mapper for Mapper0 {
             // CHR RAM writes (if present)
             0x0000..=0x1FFF => {
                 // Only write if it's CHR RAM (will be ignored for CHR ROM)
-                self.chr_ram.write(addr, data);
+                self.chr.write(addr as u32, data);
             },
             
             // PRG RAM writes
@@ -75,43 +55,89 @@ impl Mapper for Mapper0 {
                 self.prg_ram.write(addr - 0x6000, data);
             },
             
-            // PRG ROM writes are ignored
-            0x8000..=0xFFFF => {
-                // Ignore writes to PRG ROM
-                debug_assert!(false, "NROM: Attempted write to PRG ROM: ${:04X}", addr);
-            },
-            
-            // Invalid addresses
-            _ => {
-                debug_assert!(false, "NROM: Invalid write address: ${:04X}", addr);
-            }
+            // PRG ROM writes, which some games make, and unmapped
+            // addresses are ignored
+            _ => {}
         }
     }
 
-    fn map(&self, addr: u16) -> u16 {
+    fn map(&self, addr: u16) -> u32 {
         match addr {
             // CHR ROM/RAM mapping
-            0x0000..=0x1FFF => addr,
+            0x0000..=0x1FFF => addr as u32,
             
             // PRG RAM mapping
-            0x6000..=0x7FFF => addr - 0x6000,
+            0x6000..=0x7FFF => (addr - 0x6000) as u32,
             
             // PRG ROM mapping
-            0x8000..=0xFFFF => {
-                if addr >= 0xC000 && self.prg_rom.capacity() <= 0x4000 {
-                    // Mirror for 16KB PRG ROM
-                    (addr - 0xC000) % 0x4000
-                } else {
-                    // 32KB PRG ROM or lower bank access
-                    (addr - 0x8000) % self.prg_rom.capacity() as u16
-                }
-            },
+            0x8000..=0xFFFF => self.prg_rom.mirror((addr - 0x8000) as u32),
             
             // Invalid addresses
-            _ => {
-                debug_assert!(false, "NROM: Invalid map address: ${:04X}", addr);
-                0
-            }
+            _ => 0
         }
     }
-}
\ No newline at end of file
+
+    fn prg_ram(&self) -> &[u8] {
+        self.prg_ram.as_slice()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.prg_ram.as_mut_slice()
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        MapperDebugInfo::new(self, "NROM", 0x4000, 0x2000)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper0(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestRom;
+    use super::*;
+
+    #[test]
+    fn mirrors_16k_prg() {
+        let (header, data) = TestRom::new(0).prg_16k(1).build();
+        let mut mapper = Mapper0::new(&header, data);
+        assert_eq!(mapper.read(0x8000), 0);
+        assert_eq!(mapper.read(0xA000), 1);
+        assert_eq!(mapper.read(0xC000), 0);
+        assert_eq!(mapper.read(0xE000), 1);
+    }
+
+    #[test]
+    fn maps_32k_prg_and_chr_rom() {
+        let (header, data) = TestRom::new(0).prg_16k(2).build();
+        let mut mapper = Mapper0::new(&header, data);
+        assert_eq!(mapper.read(0xC000), 2);
+        assert_eq!(mapper.read(0xFFFF), 3);
+        assert_eq!(mapper.read(0x1C00), 7);
+
+        // CHR-ROM ignores writes
+        mapper.write(0x1C00, 0xAA);
+        assert_eq!(mapper.read(0x1C00), 7);
+    }
+
+    #[test]
+    fn chr_ram_when_no_chr_rom() {
+        let (header, data) = TestRom::new(0).chr_8k(0).build();
+        let mut mapper = Mapper0::new(&header, data);
+        mapper.write(0x0010, 0xAA);
+        assert_eq!(mapper.read(0x0010), 0xAA);
+    }
+}
@@ -1,37 +1,111 @@
-use crate::{mapper::Mapper, memory::Memory, rom::header::{RomHeader, HEADER_SIZE}};
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::{Mirroring, RomHeader}};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Mapper1 {
-    chr_rom: Memory,
-    prg_rom: Memory,
+    pub(crate) chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) prg_rom: Memory,
     prg_ram: Memory,
     shift_register: u8,
     shift_count: u8,
     control: u8,
-    chr_bank_0: u8,
+    pub(crate) chr_bank_0: u8,
     chr_bank_1: u8,
     prg_bank: u8,
-    last_write_cycle: u64, // For detecting consecutive writes
+    last_write_cycle: Option<u64>, // For detecting consecutive writes
 }
 
 impl Mapper1 {
     pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
-        let prg_rom_data = data[HEADER_SIZE..HEADER_SIZE + header.prg_rom_size as usize].to_vec();
-        let chr_rom_data = data[HEADER_SIZE + header.prg_rom_size as usize..HEADER_SIZE + header.prg_rom_size as usize + header.chr_rom_size as usize].to_vec();
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+
+        // 8KB unless the header asks for the 16KB (SOROM) or 32KB (SXROM) boards
+        let prg_ram_size = ((header.prg_ram_size + header.prg_nvram_size) as usize).max(8 * 1024);
 
         Mapper1 {
-            chr_rom: Memory::new(chr_rom_data),
+            chr: ChrMemory::new(header, chr_rom_data),
             prg_rom: Memory::new(prg_rom_data),
-            prg_ram: Memory::new(vec![0; 1024 * 8]), // 8KB PRG RAM
+            prg_ram: Memory::new(vec![0; prg_ram_size]),
             shift_register: 0x10, // Initial state
             shift_count: 0,
             control: 0x0C,       // Initial state: PRG ROM mode 3, CHR ROM mode 0
             chr_bank_0: 0,
             chr_bank_1: 0,
             prg_bank: 0,
-            last_write_cycle: 0,
+            last_write_cycle: None,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> u32 {
+        let chr_mode = (self.control >> 4) & 1;
+        if chr_mode == 0 {
+            // 8KB mode
+            let bank = (self.chr_bank_0 & 0x1E) as u32;
+            addr as u32 + (bank * 0x1000)
+        } else {
+            // 4KB mode
+            let bank = if addr < 0x1000 { self.chr_bank_0 } else { self.chr_bank_1 } as u32;
+            (addr & 0x0FFF) as u32 + (bank * 0x1000)
         }
     }
 
+    pub(crate) fn prg_offset(&self, addr: u16) -> u32 {
+        // SUROM/SXROM carry 512KB of PRG and use CHR bank bit 4 to pick
+        // which 256KB half the regular banking operates in.
+        let outer = if self.prg_rom.capacity() > 0x40000 {
+            (self.chr_bank_0 & 0x10) as u32 * 0x4000
+        } else {
+            0
+        };
+        let last_bank = (self.prg_rom.capacity().min(0x40000) / 0x4000).saturating_sub(1);
+        let bank = (self.prg_bank & 0x0F) as u32;
+
+        let prg_mode = (self.control >> 2) & 0x3;
+        let inner = match prg_mode {
+            0 | 1 => {
+                // 32KB mode
+                (bank & 0x0E) * 0x4000 + (addr - 0x8000) as u32
+            },
+            2 => {
+                // Fix first bank, switch second
+                if addr < 0xC000 {
+                    (addr - 0x8000) as u32
+                } else {
+                    bank * 0x4000 + (addr - 0xC000) as u32
+                }
+            },
+            3 => {
+                // Fix last bank, switch first
+                if addr >= 0xC000 {
+                    last_bank * 0x4000 + (addr - 0xC000) as u32
+                } else {
+                    bank * 0x4000 + (addr - 0x8000) as u32
+                }
+            },
+            _ => unreachable!()
+        };
+        outer + inner
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> u32 {
+        // SXROM selects one of four 8KB PRG-RAM banks with CHR bank bits 2-3,
+        // SOROM one of two with bit 3.
+        let bank = match self.prg_ram.capacity() {
+            0x8000 => (self.chr_bank_0 >> 2) & 0x03,
+            0x4000 => (self.chr_bank_0 >> 3) & 0x01,
+            _ => 0
+        } as u32;
+        bank * 0x2000 + (addr - 0x6000) as u32
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+
     fn write_register(&mut self, addr: u16, data: u8) {
         // Reset shift register if bit 7 is set
         if data & 0x80 != 0 {
@@ -67,59 +141,20 @@ impl Mapper for Mapper1 {
     fn read(&mut self, addr: u16) -> u8 {
         match addr {
             // CHR ROM (0x0000-0x1FFF)
-            0x0000..=0x1FFF => {
-                let chr_mode = (self.control >> 4) & 1;
-                let bank = if chr_mode == 0 {
-                    // 8KB mode
-                    let bank = (self.chr_bank_0 & 0x1E) as u16;
-                    (addr + (bank * 0x1000)) % self.chr_rom.capacity() as u16
-                } else {
-                    // 4KB mode
-                    if addr < 0x1000 {
-                        let bank = self.chr_bank_0 as u16;
-                        (addr + (bank * 0x1000)) % self.chr_rom.capacity() as u16
-                    } else {
-                        let bank = self.chr_bank_1 as u16;
-                        ((addr - 0x1000) + (bank * 0x1000)) % self.chr_rom.capacity() as u16
-                    }
-                };
-                self.chr_rom.read(bank)
-            },
+            0x0000..=0x1FFF => self.chr.read(self.chr_offset(addr)),
 
             // PRG RAM (0x6000-0x7FFF)
             0x6000..=0x7FFF => {
-                self.prg_ram.read(addr - 0x6000)
+                if self.prg_ram_enabled() {
+                    self.prg_ram.read_wrapped(self.prg_ram_offset(addr))
+                } else {
+                    // Open bus
+                    (addr >> 8) as u8
+                }
             },
 
             // PRG ROM (0x8000-0xFFFF)
-            0x8000..=0xFFFF => {
-                let prg_mode = (self.control >> 2) & 0x3;
-                let mapped_addr = match prg_mode {
-                    0 | 1 => {
-                        // 32KB mode
-                        let bank = (self.prg_bank & 0x0E) as u32;
-                        ((addr - 0x8000) as u32 + (bank * 0x4000)) as u16
-                    },
-                    2 => {
-                        // Fix first bank, switch second
-                        if addr < 0xC000 {
-                            addr - 0x8000
-                        } else {
-                            ((addr - 0xC000) as u32 + (self.prg_bank as u32 * 0x4000)) as u16
-                        }
-                    },
-                    3 => {
-                        // Fix last bank, switch first
-                        if addr >= 0xC000 {
-                            (addr - 0xC000) + (self.prg_rom.capacity() as u16 - 0x4000)
-                        } else {
-                            ((addr - 0x8000) as u32 + (self.prg_bank as u32 * 0x4000)) as u16
-                        }
-                    },
-                    _ => unreachable!()
-                };
-                self.prg_rom.read(mapped_addr % self.prg_rom.capacity() as u16)
-            },
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
 
             _ => 0
         }
@@ -129,12 +164,14 @@ impl Mapper for Mapper1 {
         match addr {
             // CHR ROM/RAM (0x0000-0x1FFF)
             0x0000..=0x1FFF => {
-                self.chr_rom.write(addr, data); // Will be ignored if ROM
+                let offset = self.chr_offset(addr);
+                self.chr.write(offset, data); // Will be ignored if ROM
             },
 
-            // PRG RAM (0x6000-0x7FFF)
-            0x6000..=0x7FFF => {
-                self.prg_ram.write(addr - 0x6000, data);
+            // PRG RAM (0x6000-0x7FFF), dropped while the chip is disabled
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                let offset = self.prg_ram_offset(addr);
+                self.prg_ram.write_wrapped(offset, data);
             },
 
             // Register writes (0x8000-0xFFFF)
@@ -146,60 +183,256 @@ impl Mapper for Mapper1 {
         }
     }
 
-    fn map(&self, addr: u16) -> u16 {
+    fn cpu_write(&mut self, addr: u16, data: u8, cycle: u64) {
+        if addr >= 0x8000 {
+            // The serial port ignores a write landing on the cycle right after
+            // another one, e.g. the dummy write of a read-modify-write.
+            let consecutive = self.last_write_cycle.is_some_and(|last| cycle.wrapping_sub(last) <= 1);
+            self.last_write_cycle = Some(cycle);
+            if consecutive {
+                return;
+            }
+        }
+        self.write(addr, data);
+    }
+
+    // The bus cycle counter restarts on reset
+    fn reset(&mut self) {
+        self.last_write_cycle = None;
+    }
+
+    // Registers lose their contents with the power; PRG-RAM may have a
+    // battery and is left alone
+    fn power_on(&mut self) {
+        self.shift_register = 0x10;
+        self.shift_count = 0;
+        self.control = 0x0C;
+        self.chr_bank_0 = 0;
+        self.chr_bank_1 = 0;
+        self.prg_bank = 0;
+        self.last_write_cycle = None;
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match self.control & 0x03 {
+            0 => Mirroring::SingleScreen,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal
+        })
+    }
+
+    fn map(&self, addr: u16) -> u32 {
         match addr {
             // CHR ROM/RAM mapping
-            0x0000..=0x1FFF => {
-                let chr_mode = (self.control >> 4) & 1;
-                if chr_mode == 0 {
-                    // 8KB mode
-                    let bank = (self.chr_bank_0 & 0x1E) as u16;
-                    (addr + (bank * 0x1000)) % self.chr_rom.capacity() as u16
-                } else {
-                    // 4KB mode
-                    if addr < 0x1000 {
-                        let bank = self.chr_bank_0 as u16;
-                        (addr + (bank * 0x1000)) % self.chr_rom.capacity() as u16
-                    } else {
-                        let bank = self.chr_bank_1 as u16;
-                        ((addr - 0x1000) + (bank * 0x1000)) % self.chr_rom.capacity() as u16
-                    }
-                }
-            },
+            0x0000..=0x1FFF => self.chr.mirror(self.chr_offset(addr)),
 
             // PRG RAM mapping
-            0x6000..=0x7FFF => addr - 0x6000,
+            0x6000..=0x7FFF => self.prg_ram_offset(addr),
 
             // PRG ROM mapping
-            0x8000..=0xFFFF => {
-                let prg_mode = (self.control >> 2) & 0x3;
-                match prg_mode {
-                    0 | 1 => {
-                        // 32KB mode
-                        let bank = (self.prg_bank & 0x0E) as u32;
-                        ((addr - 0x8000) as u32 + (bank * 0x4000)) as u16
-                    },
-                    2 => {
-                        // Fix first bank, switch second
-                        if addr < 0xC000 {
-                            addr - 0x8000
-                        } else {
-                            ((addr - 0xC000) as u32 + (self.prg_bank as u32 * 0x4000)) as u16
-                        }
-                    },
-                    3 => {
-                        // Fix last bank, switch first
-                        if addr >= 0xC000 {
-                            (addr - 0xC000) + (self.prg_rom.capacity() as u16 - 0x4000)
-                        } else {
-                            ((addr - 0x8000) as u32 + (self.prg_bank as u32 * 0x4000)) as u16
-                        }
-                    },
-                    _ => unreachable!()
-                }
-            },
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+
+            _ => addr as u32
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        self.prg_ram.as_slice()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.prg_ram.as_mut_slice()
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        MapperDebugInfo::new(self, "MMC1", 0x4000, 0x1000)
+            .register("Control", self.control as u32)
+            .register("CHR bank 0", self.chr_bank_0 as u32)
+            .register("CHR bank 1", self.chr_bank_1 as u32)
+            .register("PRG bank", self.prg_bank as u32)
+            .register("Shift register", self.shift_register as u32)
+            .register("Shift count", self.shift_count as u32)
+            .register("PRG-RAM bank", self.prg_ram_offset(0x6000) / 0x2000)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper1(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mapper::{BankWindow, NametableSource}, test_support::TestRom};
+    use super::*;
 
-            _ => addr
+    fn write_serial(mapper: &mut Mapper1, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.write(addr, (value >> bit) & 1);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn power_on_fixes_last_prg_bank() {
+        let (header, data) = TestRom::new(1).prg_16k(8).chr_8k(2).build();
+        let mut mapper = Mapper1::new(&header, data);
+        assert_eq!(mapper.read(0x8000), 0);
+        assert_eq!(mapper.read(0xC000), 14);
+
+        write_serial(&mut mapper, 0xE000, 3);
+        assert_eq!(mapper.read(0x8000), 6);
+        assert_eq!(mapper.read(0xC000), 14);
+    }
+
+    #[test]
+    fn prg_modes() {
+        let (header, data) = TestRom::new(1).prg_16k(8).chr_8k(2).build();
+        let mut mapper = Mapper1::new(&header, data);
+        write_serial(&mut mapper, 0xE000, 5);
+
+        // Fix first bank, switch second
+        write_serial(&mut mapper, 0x8000, 0x08);
+        assert_eq!(mapper.read(0x8000), 0);
+        assert_eq!(mapper.read(0xC000), 10);
+
+        // 32KB mode ignores the low bank bit
+        write_serial(&mut mapper, 0x8000, 0x00);
+        assert_eq!(mapper.read(0x8000), 8);
+        assert_eq!(mapper.read(0xC000), 10);
+    }
+
+    #[test]
+    fn debug_state_reports_current_banks() {
+        let (header, data) = TestRom::new(1).prg_16k(8).chr_8k(4).build();
+        let mut mapper = Mapper1::new(&header, data);
+        write_serial(&mut mapper, 0x8000, 0x1F);
+        write_serial(&mut mapper, 0xA000, 3);
+        write_serial(&mut mapper, 0xC000, 6);
+        write_serial(&mut mapper, 0xE000, 2);
+
+        let info = mapper.debug_state();
+        let banks = |windows: &[BankWindow]| windows.iter().map(|w| (w.addr, w.bank)).collect::<Vec<_>>();
+        assert_eq!(banks(&info.prg_banks), [(0x8000, 2), (0xC000, 7)]);
+        assert_eq!(banks(&info.chr_banks), [(0x0000, 3), (0x1000, 6)]);
+        assert_eq!(info.mirroring, Some(Mirroring::Horizontal));
+        assert!(info.registers.contains(&("Control", 0x1F)));
+    }
+
+    #[test]
+    fn chr_4k_mode() {
+        let (header, data) = TestRom::new(1).chr_8k(4).build();
+        let mut mapper = Mapper1::new(&header, data);
+        write_serial(&mut mapper, 0x8000, 0x1C);
+        write_serial(&mut mapper, 0xA000, 3);
+        write_serial(&mut mapper, 0xC000, 6);
+        assert_eq!(mapper.read(0x0000), 12);
+        assert_eq!(mapper.read(0x1000), 24);
+    }
+
+    #[test]
+    fn reset_bit_discards_partial_write() {
+        let (header, data) = TestRom::new(1).prg_16k(8).chr_8k(2).build();
+        let mut mapper = Mapper1::new(&header, data);
+        mapper.write(0xE000, 1);
+        mapper.write(0xE000, 1);
+        mapper.write(0x8000, 0x80);
+        write_serial(&mut mapper, 0xE000, 2);
+        assert_eq!(mapper.read(0x8000), 4);
+    }
+
+    #[test]
+    fn consecutive_cycle_writes_are_ignored() {
+        let (header, data) = TestRom::new(1).prg_16k(8).chr_8k(2).build();
+        let mut mapper = Mapper1::new(&header, data);
+        mapper.cpu_write(0xE000, 1, 10);
+        mapper.cpu_write(0xE000, 0, 11);
+        for (bit, cycle) in (1..5).zip((20..).step_by(4)) {
+            mapper.cpu_write(0xE000, (1 >> bit) & 1, cycle);
+        }
+        assert_eq!(mapper.read(0x8000), 2);
+    }
+
+    #[test]
+    fn prg_ram_disable_bit() {
+        let (header, data) = TestRom::new(1).build();
+        let mut mapper = Mapper1::new(&header, data);
+        mapper.write(0x6000, 0x42);
+        assert_eq!(mapper.read(0x6000), 0x42);
+
+        write_serial(&mut mapper, 0xE000, 0x10);
+        mapper.write(0x6000, 0x24);
+        assert_ne!(mapper.read(0x6000), 0x42);
+
+        write_serial(&mut mapper, 0xE000, 0x00);
+        assert_eq!(mapper.read(0x6000), 0x42);
+    }
+
+    #[test]
+    fn surom_outer_prg_bank() {
+        let (header, data) = TestRom::new(1).prg_16k(32).chr_8k(0).build();
+        let mut mapper = Mapper1::new(&header, data);
+        assert_eq!(mapper.read(0xC000), 30);
+
+        write_serial(&mut mapper, 0xA000, 0x10);
+        assert_eq!(mapper.read(0x8000), 32);
+        assert_eq!(mapper.read(0xC000), 62);
+    }
+
+    #[test]
+    fn sxrom_prg_ram_banks() {
+        let (header, data) = TestRom::new(1).prg_16k(32).chr_8k(0).prg_ram(32 * 1024).chr_ram(8 * 1024).build();
+        let mut mapper = Mapper1::new(&header, data);
+        for bank in 0..4 {
+            write_serial(&mut mapper, 0xA000, bank << 2);
+            mapper.write(0x6000, bank);
+        }
+        for bank in 0..4 {
+            write_serial(&mut mapper, 0xA000, bank << 2);
+            assert_eq!(mapper.read(0x6000), bank);
+        }
+    }
+
+    #[test]
+    fn control_selects_nametable_layout() {
+        let (header, data) = TestRom::new(1).build();
+        let mut mapper = Mapper1::new(&header, data);
+        write_serial(&mut mapper, 0x8000, 0x0E);
+        assert_eq!(mapper.nametable_source(1, Mirroring::Horizontal), NametableSource::Vram(1));
+        assert_eq!(mapper.nametable_source(2, Mirroring::Horizontal), NametableSource::Vram(0));
+
+        write_serial(&mut mapper, 0x8000, 0x0F);
+        assert_eq!(mapper.nametable_source(1, Mirroring::Vertical), NametableSource::Vram(0));
+        assert_eq!(mapper.nametable_source(2, Mirroring::Vertical), NametableSource::Vram(1));
+
+        write_serial(&mut mapper, 0x8000, 0x0D);
+        assert_eq!(mapper.nametable_source(0, Mirroring::Vertical), NametableSource::Vram(1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn state_round_trips_through_box() {
+        let (header, data) = TestRom::new(1).prg_16k(8).chr_8k(2).build();
+        let mut mapper = Mapper1::new(&header, data.clone());
+        write_serial(&mut mapper, 0xE000, 3);
+
+        let boxed: Box<dyn Mapper> = Box::new(mapper);
+        let json = serde_json::to_string(&boxed).unwrap();
+        // 144KB of ROM stays out of the state, only the 8KB of PRG-RAM goes in
+        assert!(json.len() < 32 * 1024);
+        let mut restored: Box<dyn Mapper> = serde_json::from_str(&json).unwrap();
+        restored.restore_rom(&header, &data);
+        assert_eq!(restored.read(0x8000), 6);
+        assert_eq!(restored.read(0xC000), 14);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::{Mirroring, RomHeader}};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// MMC4 (FxROM). Each 4KB CHR window has two bank registers and a latch that
+// flips between them when the PPU fetches tile $FD or $FE from that window.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper10 {
+    chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Memory,
+    prg_ram: Memory,
+    prg_bank: u8,
+    chr_banks: [[u8; 2]; 2], // [window][latch]: latch 0 = $FD, latch 1 = $FE
+    latches: [usize; 2],
+    mirroring: Mirroring,
+}
+
+impl Mapper10 {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+
+        Mapper10 {
+            chr: ChrMemory::new(header, chr_rom_data),
+            prg_rom: Memory::new(prg_rom_data),
+            prg_ram: Memory::new(vec![0; 8 * 1024]),
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [1, 1],
+            mirroring: header.mirroring,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> u32 {
+        let window = (addr >> 12) as usize & 1;
+        let bank = self.chr_banks[window][self.latches[window]] as u32;
+        bank * 0x1000 + (addr & 0x0FFF) as u32
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        if addr < 0xC000 {
+            self.prg_bank as u32 * 0x4000 + (addr - 0x8000) as u32
+        } else {
+            // Fixed to the last bank
+            self.prg_rom.capacity().saturating_sub(0x4000) + (addr - 0xC000) as u32
+        }
+    }
+
+    // The latch switches after the triggering fetch, so this runs post-read.
+    fn update_latch(&mut self, addr: u16) {
+        let window = (addr >> 12) as usize & 1;
+        match addr & 0x0FF8 {
+            0x0FD8 => self.latches[window] = 0,
+            0x0FE8 => self.latches[window] = 1,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mapper10 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => {
+                let data = self.chr.read(self.chr_offset(addr));
+                self.update_latch(addr);
+                data
+            },
+
+            // PRG RAM (0x6000-0x7FFF)
+            0x6000..=0x7FFF => self.prg_ram.read(addr - 0x6000),
+
+            // PRG ROM (0x8000-0xFFFF)
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
+
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            // CHR RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => {
+                let offset = self.chr_offset(addr);
+                self.chr.write(offset, data);
+            },
+
+            // PRG RAM (0x6000-0x7FFF)
+            0x6000..=0x7FFF => self.prg_ram.write(addr - 0x6000, data),
+
+            // Registers (0xA000-0xFFFF)
+            0xA000..=0xAFFF => self.prg_bank = data & 0x0F,
+            0xB000..=0xBFFF => self.chr_banks[0][0] = data & 0x1F,
+            0xC000..=0xCFFF => self.chr_banks[0][1] = data & 0x1F,
+            0xD000..=0xDFFF => self.chr_banks[1][0] = data & 0x1F,
+            0xE000..=0xEFFF => self.chr_banks[1][1] = data & 0x1F,
+            0xF000..=0xFFFF => {
+                self.mirroring = if data & 1 != 0 { Mirroring::Horizontal } else { Mirroring::Vertical };
+            },
+
+            _ => {}
+        }
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => self.chr.mirror(self.chr_offset(addr)),
+            0x6000..=0x7FFF => (addr - 0x6000) as u32,
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+            _ => addr as u32
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        self.prg_ram.as_slice()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.prg_ram.as_mut_slice()
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        MapperDebugInfo::new(self, "MMC4", 0x4000, 0x1000)
+            .register("$0000 FD bank", self.chr_banks[0][0] as u32)
+            .register("$0000 FE bank", self.chr_banks[0][1] as u32)
+            .register("$1000 FD bank", self.chr_banks[1][0] as u32)
+            .register("$1000 FE bank", self.chr_banks[1][1] as u32)
+            .register("$0000 latch", self.latches[0] as u32)
+            .register("$1000 latch", self.latches[1] as u32)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper10(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestRom;
+    use super::*;
+
+    #[test]
+    fn switches_low_prg_bank() {
+        let (header, data) = TestRom::new(10).prg_16k(8).chr_8k(8).build();
+        let mut mapper = Mapper10::new(&header, data);
+        mapper.write(0xA000, 5);
+        assert_eq!(mapper.read(0x8000), 10);
+        assert_eq!(mapper.read(0xC000), 14);
+    }
+
+    #[test]
+    fn chr_latch_follows_fd_fe_fetches() {
+        let (header, data) = TestRom::new(10).chr_8k(8).build();
+        let mut mapper = Mapper10::new(&header, data);
+        mapper.write(0xB000, 2);
+        mapper.write(0xC000, 3);
+        mapper.write(0xD000, 4);
+        mapper.write(0xE000, 5);
+
+        // Latches power up selecting the $FE registers
+        assert_eq!(mapper.read(0x0000), 12);
+        assert_eq!(mapper.read(0x1000), 20);
+
+        // The triggering fetch still comes from the old bank
+        assert_eq!(mapper.read(0x0FD8), 15);
+        assert_eq!(mapper.read(0x0000), 8);
+        assert_eq!(mapper.read(0x1000), 20);
+
+        mapper.read(0x1FDF);
+        assert_eq!(mapper.read(0x1000), 16);
+        mapper.read(0x0FE8);
+        assert_eq!(mapper.read(0x0000), 12);
+    }
+
+    #[test]
+    fn mirroring_control() {
+        let (header, data) = TestRom::new(10).chr_8k(8).build();
+        let mut mapper = Mapper10::new(&header, data);
+        mapper.write(0xF000, 1);
+        assert_eq!(mapper.mirroring(), Some(Mirroring::Horizontal));
+        mapper.write(0xF000, 0);
+        assert_eq!(mapper.mirroring(), Some(Mirroring::Vertical));
+    }
+}
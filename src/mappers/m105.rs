@@ -0,0 +1,241 @@
+use crate::{mapper::{Mapper, MapperDebugInfo}, mappers::m1::Mapper1, rom::header::{Mirroring, RomHeader}};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// Nintendo World Championships 1990 (NES-EVENT): an MMC1 whose CHR bank 0
+// register drives the board instead of CHR, which is 8KB of unbanked RAM.
+//   CHR bank 0: ---IMBB-
+//     I: holds the timer in reset and acknowledges its IRQ
+//     M: 0 = 32KB bank BB of the first 128KB PRG chip, 1 = the second chip
+//        with the usual MMC1 PRG banking
+// Until I has been seen clear and then set, the first 32KB stays mapped.
+// The timer runs down (16 + DIP switches) * 2^25 CPU cycles, from about
+// 5 to 9.7 minutes, then raises an IRQ that ends the round.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper105 {
+    mmc1: Mapper1,
+    // 0 until I has been seen clear, 1 until it's seen set, then 2
+    unlock: u8,
+    counter: u32,
+    irq: bool,
+    dip_switches: u8,
+}
+
+impl Mapper105 {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        Mapper105 {
+            mmc1: Mapper1::new(header, data),
+            unlock: 0,
+            counter: 0,
+            irq: false,
+            dip_switches: 0,
+        }
+    }
+
+    fn board(&self) -> u8 {
+        self.mmc1.chr_bank_0
+    }
+
+    fn timer_held(&self) -> bool {
+        self.board() & 0x10 != 0
+    }
+
+    fn timer_length(&self) -> u32 {
+        (0x10 | (self.dip_switches & 0x0F) as u32) << 25
+    }
+
+    // Follows the board register after every write
+    fn update(&mut self) {
+        self.unlock = match (self.unlock, self.timer_held()) {
+            (0, false) => 1,
+            (1, true) => 2,
+            (unlock, _) => unlock,
+        };
+        if self.timer_held() {
+            self.counter = 0;
+            self.irq = false;
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        let board = self.board();
+        match (self.unlock, board & 0x08 != 0) {
+            (2, false) => ((board >> 1) & 0x03) as u32 * 0x8000 + (addr - 0x8000) as u32,
+            (2, true) => 0x20000 | (self.mmc1.prg_offset(addr) & 0x1FFFF),
+            _ => (addr - 0x8000) as u32,
+        }
+    }
+}
+
+impl Mapper for Mapper105 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.mmc1.chr.read(addr as u32),
+            0x8000..=0xFFFF => self.mmc1.prg_rom.read_wrapped(self.prg_offset(addr)),
+            _ => self.mmc1.read(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.mmc1.chr.write(addr as u32, data),
+            _ => {
+                self.mmc1.write(addr, data);
+                self.update();
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8, cycle: u64) {
+        self.mmc1.cpu_write(addr, data, cycle);
+        self.update();
+    }
+
+    fn clock(&mut self, cycles: u8) {
+        if !self.timer_held() && !self.irq {
+            self.counter += cycles as u32;
+            if self.counter >= self.timer_length() {
+                self.irq = true;
+            }
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq
+    }
+
+    fn reset(&mut self) {
+        self.mmc1.reset();
+    }
+
+    // Back to the first 32KB with the timer stopped, as the board boots
+    fn power_on(&mut self) {
+        self.mmc1.power_on();
+        self.unlock = 0;
+        self.counter = 0;
+        self.irq = false;
+    }
+
+    fn dip_switches(&self) -> Option<u8> {
+        Some(self.dip_switches)
+    }
+
+    fn set_dip_switches(&mut self, value: u8) {
+        self.dip_switches = value & 0x0F;
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        self.mmc1.mirroring()
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => self.mmc1.chr.mirror(addr as u32),
+            0x8000..=0xFFFF => self.mmc1.prg_rom.mirror(self.prg_offset(addr)),
+            _ => self.mmc1.map(addr),
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        self.mmc1.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.mmc1.prg_ram_mut()
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.mmc1.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        MapperDebugInfo::new(self, "NES-EVENT", 0x4000, 0x2000)
+            .register("Board", self.board() as u32)
+            .register("Unlock", self.unlock as u32)
+            .register("Timer", self.timer_length().saturating_sub(self.counter))
+            .register("DIP switches", self.dip_switches as u32)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper105(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.mmc1.restore_rom(header, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestRom;
+    use super::*;
+
+    fn write_serial(mapper: &mut Mapper105, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.write(addr, (value >> bit) & 1);
+        }
+    }
+
+    fn mapper() -> Mapper105 {
+        let (header, data) = TestRom::new(105).prg_16k(16).chr_8k(0).build();
+        Mapper105::new(&header, data)
+    }
+
+    #[test]
+    fn prg_unlocks_after_the_timer_bit_toggles() {
+        let mut mapper = mapper();
+        // I powers on clear
+        write_serial(&mut mapper, 0xE000, 2);
+        assert_eq!((mapper.read(0x8000), mapper.read(0xE000)), (0, 3));
+        write_serial(&mut mapper, 0xA000, 0x14);
+        // 32KB bank 2 of the first chip
+        assert_eq!((mapper.read(0x8000), mapper.read(0xE000)), (8, 11));
+
+        // MMC1 banking in the second, last bank fixed at $C000
+        write_serial(&mut mapper, 0xA000, 0x18);
+        assert_eq!((mapper.read(0x8000), mapper.read(0xC000)), (20, 30));
+
+        mapper.write(0x0010, 0x42);
+        assert_eq!(mapper.read(0x0010), 0x42);
+    }
+
+    #[test]
+    fn power_cycling_locks_the_first_32k_again() {
+        let mut mapper = mapper();
+        write_serial(&mut mapper, 0xA000, 0x00);
+        write_serial(&mut mapper, 0xA000, 0x14);
+        mapper.clock(200);
+        assert_eq!(mapper.read(0x8000), 8);
+
+        mapper.reset();
+        mapper.power_on();
+        assert_eq!((mapper.read(0x8000), mapper.read(0xE000)), (0, 3));
+        assert_eq!(mapper.debug_state().registers[2], ("Timer", 16 << 25));
+    }
+
+    #[test]
+    fn timer_length_follows_the_dip_switches() {
+        let mut mapper = mapper();
+        mapper.set_dip_switches(0x03);
+        assert_eq!(mapper.dip_switches(), Some(0x03));
+        write_serial(&mut mapper, 0xA000, 0x00);
+
+        let length = 19 << 25;
+        for _ in 0..length / 200 {
+            mapper.clock(200);
+        }
+        mapper.clock((length % 200) as u8 - 1);
+        assert!(!mapper.irq_pending());
+        mapper.clock(1);
+        assert!(mapper.irq_pending());
+
+        // Holding the timer acknowledges the IRQ and starts it over
+        write_serial(&mut mapper, 0xA000, 0x10);
+        assert!(!mapper.irq_pending());
+        mapper.clock(200);
+        assert_eq!(mapper.debug_state().registers[2], ("Timer", length));
+    }
+}
\ No newline at end of file
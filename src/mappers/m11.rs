@@ -0,0 +1,42 @@
+use crate::mappers::latch::{LatchBoard, LatchMapper};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// Color Dreams: one register selecting a 32KB PRG bank and an 8KB CHR bank.
+#[derive(Clone)]
+pub struct ColorDreams;
+
+impl LatchBoard for ColorDreams {
+    const NAME: &'static str = "Color Dreams";
+
+    // CCCC --PP
+    fn banks(latch: u8) -> (u8, u8) {
+        (latch & 0x03, latch >> 4)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(mapper: &Mapper11) -> MapperState {
+        MapperState::Mapper11(mapper.clone())
+    }
+}
+
+pub type Mapper11 = LatchMapper<ColorDreams>;
+
+#[cfg(test)]
+mod tests {
+    use crate::{mapper::Mapper, test_support::TestRom};
+    use super::*;
+
+    #[test]
+    fn selects_prg_and_chr_banks() {
+        let (header, data) = TestRom::new(11).prg_16k(8).chr_8k(16).build();
+        let mut mapper = Mapper11::new(&header, data);
+        assert_eq!(mapper.read(0x8000), 0);
+
+        mapper.write(0x8000, 0xA3);
+        assert_eq!(mapper.read(0x8000), 12);
+        assert_eq!(mapper.read(0xFFFF), 15);
+        assert_eq!(mapper.read(0x0000), 80);
+        assert_eq!(mapper.read(0x1C00), 87);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{mapper::{mirrored_nametable, Mapper, MapperDebugInfo, NametableSource}, memory::{ChrMemory, Memory}, rom::header::{Mirroring, RomHeader}};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+const REGISTER_NAMES: [&str; 8] = ["R0", "R1", "R2", "R3", "R4", "R5", "R6", "R7"];
+
+// Boards built on the Namco 108, the chip MMC3 grew out of: its bank
+// select/data pair at $8000/$8001, without the IRQ or mirroring control.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Board {
+    // 206: DxROM and Tengen's and Namco's own boards
+    Dxrom,
+    // 76: Namco 3446, R2-R5 are 2KB CHR banks and R0-R1 do nothing
+    Namco3446,
+    // 88: Namco 3443, CHR at $0000-$0FFF from the first 64KB and at
+    // $1000-$1FFF from the second
+    Namco3443,
+    // 95: Namco 3425, bit 5 of R0 and R1 picks the CIRAM page for the top
+    // and bottom nametables
+    Namco3425,
+    // 154: Namco 3453, 88 plus single-screen mirroring from bit 6 of any
+    // write to $8000-$FFFF
+    Namco3453,
+}
+
+// Mappers 206, 76, 88, 95 and 154. R0-R1 are 2KB CHR banks at $0000 and
+// $0800, R2-R5 1KB banks at $1000-$1FFF, R6-R7 8KB PRG banks at $8000 and
+// $A000; $C000-$FFFF holds the last 16KB.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper206 {
+    board: Board,
+    chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Memory,
+    bank_select: u8,
+    registers: [u8; 8],
+    nametable_page: u8,
+}
+
+impl Mapper206 {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+        let board = match header.mapper_number {
+            76 => Board::Namco3446,
+            88 => Board::Namco3443,
+            95 => Board::Namco3425,
+            154 => Board::Namco3453,
+            _ => Board::Dxrom,
+        };
+
+        Mapper206 {
+            board,
+            chr: ChrMemory::new(header, chr_rom_data),
+            prg_rom: Memory::new(prg_rom_data),
+            bank_select: 0,
+            registers: [0; 8],
+            nametable_page: 0,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> u32 {
+        let r = &self.registers;
+        if self.board == Board::Namco3446 {
+            return r[2 + (addr >> 11) as usize] as u32 * 0x800 + (addr & 0x7FF) as u32;
+        }
+        let bank = match addr >> 10 {
+            slot @ 0..=3 => (r[(slot >> 1) as usize] & 0x3E) | (slot & 1) as u8,
+            slot => r[slot as usize - 2],
+        } as u32;
+        let bank = match self.board {
+            Board::Namco3425 => bank & 0x1F,
+            Board::Namco3443 | Board::Namco3453 if addr >= 0x1000 => bank | 0x40,
+            _ => bank,
+        };
+        bank * 0x400 + (addr & 0x3FF) as u32
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        let last_bank = (self.prg_rom.capacity() / 0x2000).saturating_sub(1);
+        let bank = match addr {
+            0x8000..=0x9FFF => (self.registers[6] & 0x0F) as u32,
+            0xA000..=0xBFFF => (self.registers[7] & 0x0F) as u32,
+            0xC000..=0xDFFF => last_bank.saturating_sub(1),
+            _ => last_bank,
+        };
+        bank * 0x2000 + (addr & 0x1FFF) as u32
+    }
+}
+
+impl Mapper for Mapper206 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => self.chr.read(self.chr_offset(addr)),
+
+            // PRG ROM (0x8000-0xFFFF)
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
+
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        if self.board == Board::Namco3453 && addr >= 0x8000 {
+            self.nametable_page = (data >> 6) & 1;
+        }
+        match addr {
+            0x0000..=0x1FFF => {
+                let offset = self.chr_offset(addr);
+                self.chr.write(offset, data);
+            },
+
+            // Bank select on even addresses, bank data on odd ones
+            0x8000..=0x9FFF if addr & 1 == 0 => self.bank_select = data & 0x07,
+            0x8000..=0x9FFF => self.registers[self.bank_select as usize] = data & 0x3F,
+
+            _ => {}
+        }
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        match (self.board, self.nametable_page) {
+            (Board::Namco3453, 0) => Some(Mirroring::SingleScreen),
+            (Board::Namco3453, _) => Some(Mirroring::SingleScreenUpper),
+            _ => None,
+        }
+    }
+
+    fn nametable_source(&self, table: u16, header_mirroring: Mirroring) -> NametableSource {
+        if self.board != Board::Namco3425 {
+            return mirrored_nametable(table, self.mirroring().unwrap_or(header_mirroring));
+        }
+        NametableSource::Vram(((self.registers[(table >> 1) as usize] >> 5) & 1) as u16)
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => self.chr.mirror(self.chr_offset(addr)),
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+            _ => addr as u32
+        }
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        let name = match self.board {
+            Board::Dxrom => "Namco 108",
+            Board::Namco3446 => "Namco 3446",
+            Board::Namco3443 => "Namco 3443",
+            Board::Namco3425 => "Namco 3425",
+            Board::Namco3453 => "Namco 3453",
+        };
+        let chr_window = if self.board == Board::Namco3446 { 0x800 } else { 0x400 };
+        let info = MapperDebugInfo::new(self, name, 0x2000, chr_window)
+            .register("Bank select", self.bank_select as u32);
+        REGISTER_NAMES.iter().zip(self.registers).fold(info, |info, (name, value)| info.register(name, value as u32))
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper206(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestRom;
+    use super::*;
+
+    fn board(number: u16) -> Mapper206 {
+        let (header, data) = TestRom::new(number).prg_16k(8).chr_8k(16).build();
+        Mapper206::new(&header, data)
+    }
+
+    fn set_banks(mapper: &mut Mapper206, banks: [u8; 8]) {
+        for (register, bank) in banks.into_iter().enumerate() {
+            mapper.write(0x8000, register as u8);
+            mapper.write(0x8001, bank);
+        }
+    }
+
+    fn chr_banks(mapper: &mut Mapper206) -> Vec<u8> {
+        (0..8).map(|slot| mapper.read(slot * 0x400)).collect()
+    }
+
+    #[test]
+    fn dxrom_banking() {
+        let mut mapper = board(206);
+        set_banks(&mut mapper, [5, 8, 10, 11, 12, 13, 3, 4]);
+        assert_eq!(chr_banks(&mut mapper), [4, 5, 8, 9, 10, 11, 12, 13]);
+        assert_eq!([0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mapper.read(addr)), [3, 4, 14, 15]);
+
+        // Only $8000-$9FFF decodes
+        mapper.write(0xA001, 7);
+        assert_eq!(mapper.read(0xA000), 4);
+        assert_eq!(mapper.mirroring(), None);
+    }
+
+    #[test]
+    fn namco_3446_has_2k_chr_banks() {
+        let mut mapper = board(76);
+        set_banks(&mut mapper, [9, 9, 1, 2, 3, 4, 0, 0]);
+        assert_eq!(chr_banks(&mut mapper), [2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn namco_3443_splits_chr_by_pattern_table() {
+        for number in [88, 154] {
+            let mut mapper = board(number);
+            set_banks(&mut mapper, [0x42, 2, 0, 1, 2, 3, 0, 0]);
+            assert_eq!(chr_banks(&mut mapper), [2, 3, 2, 3, 64, 65, 66, 67]);
+        }
+    }
+
+    #[test]
+    fn namco_3425_and_3453_pick_nametables() {
+        let mut mapper = board(95);
+        set_banks(&mut mapper, [0x20, 0x02, 0, 0, 0, 0, 0, 0]);
+        let pages = |mapper: &Mapper206| (0..4).map(|table| mapper.nametable_source(table, Mirroring::Vertical)).collect::<Vec<_>>();
+        assert_eq!(pages(&mapper), [1, 1, 0, 0].map(NametableSource::Vram));
+        assert_eq!(mapper.read(0x0000), 0);
+
+        let mut mapper = board(154);
+        mapper.write(0xC000, 0x40);
+        assert_eq!(pages(&mapper), [1; 4].map(NametableSource::Vram));
+        mapper.write(0x8000, 0x06);
+        assert_eq!(pages(&mapper), [0; 4].map(NametableSource::Vram));
+    }
+}
\ No newline at end of file
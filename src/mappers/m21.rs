@@ -0,0 +1,372 @@
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::{Mirroring, RomHeader}};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// PPU dots per scanline, which the IRQ prescaler counts down 3 a CPU cycle
+const PRESCALER_PERIOD: i16 = 341;
+
+// How a board wires the chip. The register pairs are selected by the
+// chip's A0 and A1 pins, which each board connects to different CPU
+// address lines; without a submapper both candidates are decoded at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Variant {
+    a0: u16,
+    a1: u16,
+    // VRC4 adds PRG swapping, one-screen mirroring and the IRQ
+    vrc4: bool,
+    // VRC2a ignores the low bit of CHR banks
+    chr_shift: u8,
+}
+
+impl Variant {
+    fn new(header: &RomHeader) -> Self {
+        let (a0, a1, vrc4) = match (header.mapper_number, header.submapper) {
+            // VRC4a, VRC4c
+            (21, 1) => (0x02, 0x04, true),
+            (21, 2) => (0x40, 0x80, true),
+            (21, _) => (0x42, 0x84, true),
+            // VRC2a
+            (22, _) => (0x02, 0x01, false),
+            // VRC4f, VRC4e, VRC2b
+            (23, 1) => (0x01, 0x02, true),
+            (23, 2) => (0x04, 0x08, true),
+            (23, 3) => (0x01, 0x02, false),
+            (23, _) => (0x05, 0x0A, true),
+            // VRC4b, VRC4d, VRC2c
+            (25, 1) => (0x02, 0x01, true),
+            (25, 2) => (0x08, 0x04, true),
+            (25, 3) => (0x02, 0x01, false),
+            _ => (0x0A, 0x05, true),
+        };
+        let chr_shift = if header.mapper_number == 22 { 1 } else { 0 };
+        Variant { a0, a1, vrc4, chr_shift }
+    }
+
+    // The address with the board's lines moved to A0 and A1
+    fn register(&self, addr: u16) -> u16 {
+        let a0 = (addr & self.a0 != 0) as u16;
+        let a1 = (addr & self.a1 != 0) as u16;
+        (addr & 0xF000) | (a1 << 1) | a0
+    }
+}
+
+// Konami VRC2 and VRC4, mappers 21, 22, 23 and 25. Two 8KB PRG banks at
+// $8000 and $A000 (swapped with the fixed second-last bank at $C000 in
+// VRC4's swap mode), the last bank at $E000, and eight 1KB CHR banks each
+// written a nibble at a time through $B000-$E003.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper21 {
+    variant: Variant,
+    chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Memory,
+    // VRC2 boards only have a 1-bit latch here, which RAM stands in for
+    prg_ram: Memory,
+    prg_banks: [u8; 2],
+    chr_banks: [u16; 8],
+    prg_swap: bool,
+    mirroring: u8,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_enabled: bool,
+    irq_enable_after_ack: bool,
+    irq_cycle_mode: bool,
+    irq: bool,
+    prescaler: i16,
+}
+
+impl Mapper21 {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+
+        Mapper21 {
+            variant: Variant::new(header),
+            chr: ChrMemory::new(header, chr_rom_data),
+            prg_rom: Memory::new(prg_rom_data),
+            prg_ram: Memory::new(vec![0; 8 * 1024]),
+            prg_banks: [0; 2],
+            chr_banks: [0; 8],
+            prg_swap: false,
+            mirroring: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_enable_after_ack: false,
+            irq_cycle_mode: false,
+            irq: false,
+            prescaler: PRESCALER_PERIOD,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> u32 {
+        let bank = self.chr_banks[(addr >> 10) as usize] >> self.variant.chr_shift;
+        bank as u32 * 0x400 + (addr & 0x3FF) as u32
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        let last_bank = (self.prg_rom.capacity() / 0x2000).saturating_sub(1);
+        let bank = match ((addr >> 13) & 3, self.prg_swap) {
+            (0, false) | (2, true) => self.prg_banks[0] as u32,
+            (1, _) => self.prg_banks[1] as u32,
+            (0, true) | (2, false) => last_bank.saturating_sub(1),
+            _ => last_bank,
+        };
+        bank * 0x2000 + (addr & 0x1FFF) as u32
+    }
+
+    // $B000-$E003: the even register of each pair is a bank's low nibble,
+    // the odd one its high bits
+    fn write_chr_bank(&mut self, register: u16, data: u8) {
+        let slot = (((register - 0xB000) >> 12) * 2 + ((register >> 1) & 1)) as usize;
+        let bank = &mut self.chr_banks[slot];
+        *bank = match register & 1 {
+            0 => (*bank & 0x1F0) | (data & 0x0F) as u16,
+            _ => (*bank & 0x0F) | (((data & 0x1F) as u16) << 4),
+        };
+    }
+
+    fn clock_counter(&mut self) {
+        if self.irq_counter == 0xFF {
+            self.irq_counter = self.irq_latch;
+            self.irq = true;
+        } else {
+            self.irq_counter += 1;
+        }
+    }
+}
+
+impl Mapper for Mapper21 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => self.chr.read(self.chr_offset(addr)),
+
+            // PRG RAM (0x6000-0x7FFF)
+            0x6000..=0x7FFF => self.prg_ram.read(addr - 0x6000),
+
+            // PRG ROM (0x8000-0xFFFF)
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
+
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        let vrc4 = self.variant.vrc4;
+        match (self.variant.register(addr), addr) {
+            (_, 0x0000..=0x1FFF) => {
+                let offset = self.chr_offset(addr);
+                self.chr.write(offset, data);
+            }
+            (_, 0x6000..=0x7FFF) => self.prg_ram.write(addr - 0x6000, data),
+            (0x8000..=0x8003, _) => self.prg_banks[0] = data & 0x1F,
+            (0x9000..=0x9001, _) if vrc4 => self.mirroring = data & 3,
+            (0x9002, _) if vrc4 => self.prg_swap = data & 2 != 0,
+            (0x9000..=0x9003, _) if !vrc4 => self.mirroring = data & 1,
+            (0xA000..=0xA003, _) => self.prg_banks[1] = data & 0x1F,
+            (register @ 0xB000..=0xE003, _) => self.write_chr_bank(register, data),
+            (0xF000, _) if vrc4 => self.irq_latch = (self.irq_latch & 0xF0) | (data & 0x0F),
+            (0xF001, _) if vrc4 => self.irq_latch = (self.irq_latch & 0x0F) | (data << 4),
+            (0xF002, _) if vrc4 => {
+                self.irq_enable_after_ack = data & 1 != 0;
+                self.irq_enabled = data & 2 != 0;
+                self.irq_cycle_mode = data & 4 != 0;
+                self.irq = false;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_latch;
+                    self.prescaler = PRESCALER_PERIOD;
+                }
+            }
+            (0xF003, _) if vrc4 => {
+                self.irq = false;
+                self.irq_enabled = self.irq_enable_after_ack;
+            }
+            _ => {}
+        }
+    }
+
+    // Scanline mode clocks the counter every 113 2/3 CPU cycles, the
+    // prescaler counting down 3 a cycle from 341
+    fn clock(&mut self, cycles: u8) {
+        if !self.irq_enabled {
+            return;
+        }
+        for _ in 0..cycles {
+            if self.irq_cycle_mode {
+                self.clock_counter();
+                continue;
+            }
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.clock_counter();
+            }
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match self.mirroring {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreen,
+            _ => Mirroring::SingleScreenUpper,
+        })
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => self.chr.mirror(self.chr_offset(addr)),
+            0x6000..=0x7FFF => (addr - 0x6000) as u32,
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+            _ => addr as u32
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        self.prg_ram.as_slice()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.prg_ram.as_mut_slice()
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        let name = if self.variant.vrc4 { "VRC4" } else { "VRC2" };
+        let info = MapperDebugInfo::new(self, name, 0x2000, 0x400)
+            .register("PRG 0", self.prg_banks[0] as u32)
+            .register("PRG 1", self.prg_banks[1] as u32)
+            .register("PRG swap", self.prg_swap as u32)
+            .register("Mirroring", self.mirroring as u32);
+        if !self.variant.vrc4 {
+            return info;
+        }
+        info.register("IRQ latch", self.irq_latch as u32)
+            .register("IRQ counter", self.irq_counter as u32)
+            .register("IRQ enabled", self.irq_enabled as u32)
+            .register("IRQ cycle mode", self.irq_cycle_mode as u32)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper21(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestRom;
+    use super::*;
+
+    fn board(number: u16, submapper: u8) -> Mapper21 {
+        let (header, data) = TestRom::new(number).submapper(submapper).prg_16k(8).chr_8k(8).build();
+        Mapper21::new(&header, data)
+    }
+
+    fn prg_banks(mapper: &mut Mapper21) -> [u8; 4] {
+        [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mapper.read(addr))
+    }
+
+    #[test]
+    fn decodes_each_boards_address_lines() {
+        // The board's addresses for registers $B000-$B003
+        let boards: [(u16, u8, [u16; 4]); 9] = [
+            (21, 1, [0xB000, 0xB002, 0xB004, 0xB006]),
+            (21, 2, [0xB000, 0xB040, 0xB080, 0xB0C0]),
+            (21, 0, [0xB000, 0xB040, 0xB004, 0xB0C0]),
+            (22, 0, [0xB000, 0xB002, 0xB001, 0xB003]),
+            (23, 2, [0xB000, 0xB004, 0xB008, 0xB00C]),
+            (23, 3, [0xB000, 0xB001, 0xB002, 0xB003]),
+            (25, 1, [0xB000, 0xB002, 0xB001, 0xB003]),
+            (25, 2, [0xB000, 0xB008, 0xB004, 0xB00C]),
+            (25, 0, [0xB000, 0xB002, 0xB004, 0xB00C]),
+        ];
+        for (number, submapper, registers) in boards {
+            let mut mapper = board(number, submapper);
+            for (addr, data) in registers.into_iter().zip([0x0A, 0x02, 0x05, 0x01]) {
+                mapper.write(addr, data);
+            }
+            let expected = if number == 22 { [0x15, 0x0A] } else { [0x2A, 0x15] };
+            assert_eq!([mapper.read(0x0000), mapper.read(0x0400)], expected, "mapper {} submapper {}", number, submapper);
+        }
+    }
+
+    #[test]
+    fn vrc4_swaps_prg_and_mirrors_one_screen() {
+        let mut mapper = board(21, 1);
+        mapper.write(0x8000, 3);
+        mapper.write(0xA000, 5);
+        assert_eq!(prg_banks(&mut mapper), [3, 5, 14, 15]);
+        mapper.write(0x9004, 2);
+        assert_eq!(prg_banks(&mut mapper), [14, 5, 3, 15]);
+
+        mapper.write(0x9000, 3);
+        assert_eq!(mapper.mirroring(), Some(Mirroring::SingleScreenUpper));
+        mapper.write(0x6123, 0x42);
+        assert_eq!(mapper.read(0x6123), 0x42);
+    }
+
+    #[test]
+    fn vrc2_has_no_swap_mode_or_irq() {
+        let mut mapper = board(22, 0);
+        mapper.write(0x8000, 3);
+        // Mirroring on VRC2, PRG swapping on VRC4
+        mapper.write(0x9001, 2);
+        assert_eq!(prg_banks(&mut mapper), [3, 0, 14, 15]);
+        assert_eq!(mapper.mirroring(), Some(Mirroring::Vertical));
+        mapper.write(0x9000, 1);
+        assert_eq!(mapper.mirroring(), Some(Mirroring::Horizontal));
+
+        mapper.write(0xF001, 0x02);
+        mapper.write(0xF003, 0x06);
+        mapper.clock(255);
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn irq_counts_cycles_or_scanlines() {
+        let mut mapper = board(23, 1);
+        // Latch $FE, cycle mode, re-enabled by acks
+        mapper.write(0xF000, 0x0E);
+        mapper.write(0xF001, 0x0F);
+        mapper.write(0xF002, 0x07);
+        mapper.clock(1);
+        assert!(!mapper.irq_pending());
+        mapper.clock(1);
+        assert!(mapper.irq_pending());
+        mapper.write(0xF003, 0);
+        assert!(!mapper.irq_pending());
+        mapper.clock(2);
+        assert!(mapper.irq_pending());
+
+        // Scanline mode from $FF, one clock a scanline
+        mapper.write(0xF000, 0x0F);
+        mapper.write(0xF001, 0x0F);
+        mapper.write(0xF002, 0x03);
+        assert!(!mapper.irq_pending());
+        mapper.clock(113);
+        assert!(!mapper.irq_pending());
+        mapper.clock(1);
+        assert!(mapper.irq_pending());
+        mapper.write(0xF003, 0);
+        mapper.clock(114);
+        assert!(mapper.irq_pending());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,165 @@
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::RomHeader};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// Two boards share mapper 34. BNROM latches a 32KB PRG bank from writes to
+// $8000-$FFFF and has CHR RAM. AVE's NINA-001 has 8KB of PRG RAM with its
+// registers over the top of it: $7FFD the PRG bank, $7FFE and $7FFF 4KB
+// CHR banks. Submapper 1 is NINA-001 and 2 BNROM; without one, more than
+// 8KB of CHR ROM means NINA-001.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper34 {
+    chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Memory,
+    prg_ram: Memory,
+    nina: bool,
+    prg_bank: u8,
+    chr_banks: [u8; 2],
+}
+
+impl Mapper34 {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+        let nina = match header.submapper {
+            1 => true,
+            2 => false,
+            _ => header.chr_rom_size > 8 * 1024,
+        };
+
+        Mapper34 {
+            chr: ChrMemory::new(header, chr_rom_data),
+            prg_rom: Memory::new(prg_rom_data),
+            prg_ram: Memory::new(vec![0; if nina { 8 * 1024 } else { 0 }]),
+            nina,
+            prg_bank: 0,
+            chr_banks: [0, 1],
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> u32 {
+        self.chr_banks[(addr >> 12) as usize] as u32 * 0x1000 + (addr & 0x0FFF) as u32
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        self.prg_bank as u32 * 0x8000 + (addr - 0x8000) as u32
+    }
+}
+
+impl Mapper for Mapper34 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => self.chr.read(self.chr_offset(addr)),
+
+            // PRG RAM (0x6000-0x7FFF)
+            0x6000..=0x7FFF if self.nina => self.prg_ram.read(addr - 0x6000),
+
+            // PRG ROM (0x8000-0xFFFF)
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
+
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            // CHR RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => {
+                let offset = self.chr_offset(addr);
+                self.chr.write(offset, data);
+            },
+
+            // The RAM under the registers is written too
+            0x6000..=0x7FFF if self.nina => {
+                self.prg_ram.write(addr - 0x6000, data);
+                match addr {
+                    0x7FFD => self.prg_bank = data & 0x01,
+                    0x7FFE => self.chr_banks[0] = data & 0x0F,
+                    0x7FFF => self.chr_banks[1] = data & 0x0F,
+                    _ => {}
+                }
+            },
+
+            0x8000..=0xFFFF if !self.nina => self.prg_bank = data,
+
+            _ => {}
+        }
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => self.chr.mirror(self.chr_offset(addr)),
+            0x6000..=0x7FFF => (addr - 0x6000) as u32,
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+            _ => addr as u32
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        self.prg_ram.as_slice()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.prg_ram.as_mut_slice()
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        let (name, chr_window) = if self.nina { ("NINA-001", 0x1000) } else { ("BNROM", 0x2000) };
+        MapperDebugInfo::new(self, name, 0x8000, chr_window)
+            .register("PRG bank", self.prg_bank as u32)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper34(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestRom;
+    use super::*;
+
+    #[test]
+    fn bnrom_switches_32k_prg() {
+        let (header, data) = TestRom::new(34).prg_16k(8).chr_8k(0).chr_ram(8 * 1024).build();
+        let mut mapper = Mapper34::new(&header, data);
+        mapper.write(0x7FFD, 1);
+        assert_eq!(mapper.read(0x8000), 0);
+
+        mapper.write(0x8000, 2);
+        assert_eq!([mapper.read(0x8000), mapper.read(0xFFFF)], [8, 11]);
+        mapper.write(0x1000, 0x42);
+        assert_eq!(mapper.read(0x1000), 0x42);
+        assert!(mapper.prg_ram().is_empty());
+    }
+
+    #[test]
+    fn nina_001_registers_sit_over_prg_ram() {
+        let (header, data) = TestRom::new(34).prg_16k(4).chr_8k(8).build();
+        let mut mapper = Mapper34::new(&header, data);
+        assert_eq!([mapper.read(0x0000), mapper.read(0x1000)], [0, 4]);
+        mapper.write(0x8000, 1);
+        assert_eq!(mapper.read(0x8000), 0);
+
+        mapper.write(0x7FFD, 1);
+        mapper.write(0x7FFE, 5);
+        mapper.write(0x7FFF, 14);
+        assert_eq!(mapper.read(0x8000), 4);
+        assert_eq!([mapper.read(0x0000), mapper.read(0x1000)], [20, 56]);
+        assert_eq!(mapper.read(0x7FFE), 5);
+    }
+}
\ No newline at end of file
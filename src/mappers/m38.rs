@@ -0,0 +1,47 @@
+use crate::mappers::latch::{LatchBoard, LatchMapper};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// Bit Corp's PCI556 (Crime Busters): the latch sits at $7000-$7FFF rather
+// than over the ROM.
+#[derive(Clone)]
+pub struct Pci556;
+
+impl LatchBoard for Pci556 {
+    const NAME: &'static str = "PCI556";
+
+    fn decodes(addr: u16) -> bool {
+        (0x7000..=0x7FFF).contains(&addr)
+    }
+
+    // ---- CCPP
+    fn banks(latch: u8) -> (u8, u8) {
+        (latch & 0x03, (latch >> 2) & 0x03)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(mapper: &Mapper38) -> MapperState {
+        MapperState::Mapper38(mapper.clone())
+    }
+}
+
+pub type Mapper38 = LatchMapper<Pci556>;
+
+#[cfg(test)]
+mod tests {
+    use crate::{mapper::Mapper, test_support::TestRom};
+    use super::*;
+
+    #[test]
+    fn latches_writes_to_7000() {
+        let (header, data) = TestRom::new(38).prg_16k(8).chr_8k(4).build();
+        let mut mapper = Mapper38::new(&header, data);
+        mapper.write(0x8000, 0x0F);
+        assert_eq!(mapper.read(0x8000), 0);
+
+        mapper.write(0x7123, 0x0E);
+        assert_eq!(mapper.read(0x8000), 8);
+        assert_eq!(mapper.read(0xFFFF), 11);
+        assert_eq!(mapper.read(0x0000), 24);
+    }
+}
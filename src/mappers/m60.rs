@@ -0,0 +1,132 @@
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::RomHeader};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// Reset-based NROM-128 multicart (the 4-in-1 boards): no registers, just a
+// counter stepped by the console's RESET button. Game n is 16KB PRG bank n,
+// mirrored like NROM-128, with 8KB CHR bank n.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper60 {
+    chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Memory,
+    game: u8,
+}
+
+impl Mapper60 {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+
+        Mapper60 {
+            chr: ChrMemory::new(header, chr_rom_data),
+            prg_rom: Memory::new(prg_rom_data),
+            game: 0,
+        }
+    }
+
+    // Up to 256, so a 4MB PRG doesn't wrap to 0
+    fn games(&self) -> u16 {
+        (self.prg_rom.capacity() / 0x4000).clamp(1, 0x100) as u16
+    }
+
+    fn chr_offset(&self, addr: u16) -> u32 {
+        self.game as u32 * 0x2000 + addr as u32
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        self.game as u32 * 0x4000 + (addr & 0x3FFF) as u32
+    }
+}
+
+impl Mapper for Mapper60 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => self.chr.read(self.chr_offset(addr)),
+
+            // PRG ROM (0x8000-0xFFFF)
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
+
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        // CHR RAM (0x0000-0x1FFF)
+        if addr < 0x2000 {
+            let offset = self.chr_offset(addr);
+            self.chr.write(offset, data);
+        }
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => self.chr.mirror(self.chr_offset(addr)),
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+            _ => addr as u32
+        }
+    }
+
+    fn reset(&mut self) {
+        self.game = ((self.game as u16 + 1) % self.games()) as u8;
+    }
+
+    fn power_on(&mut self) {
+        self.game = 0;
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        MapperDebugInfo::new(self, "Reset-based NROM multicart", 0x4000, 0x2000)
+            .register("Game", self.game as u32)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper60(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::Rom, test_support::TestRom, Nes, SystemVersion};
+    use super::*;
+
+    #[test]
+    fn each_reset_selects_the_next_game() {
+        let (header, data) = TestRom::new(60).prg_16k(4).chr_8k(4).build();
+        let mut mapper = Mapper60::new(&header, data);
+        assert_eq!((mapper.read(0x8000), mapper.read(0xC000), mapper.read(0x0000)), (0, 0, 0));
+
+        mapper.reset();
+        assert_eq!((mapper.read(0x8000), mapper.read(0xE000), mapper.read(0x0000)), (2, 3, 8));
+        for _ in 0..3 {
+            mapper.reset();
+        }
+        assert_eq!(mapper.read(0x8000), 0);
+    }
+
+    #[test]
+    fn power_cycling_starts_from_the_first_game() {
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(Rom::new(TestRom::new(60).prg_16k(4).chr_8k(4).bytes()));
+        nes.on();
+        assert_eq!(nes.cpu.bus.cartridge.cpu_read(0x8000), 0);
+        nes.reset();
+        nes.reset();
+        assert_eq!(nes.cpu.bus.cartridge.cpu_read(0x8000), 4);
+        nes.on();
+        assert_eq!(nes.cpu.bus.cartridge.cpu_read(0x8000), 0);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,305 @@
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::{Mirroring, RomHeader}};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+const REGISTER_NAMES: [&str; 16] = [
+    "R0", "R1", "R2", "R3", "R4", "R5", "R6", "R7", "R8", "R9", "RA", "RB", "RC", "RD", "RE", "RF",
+];
+
+// PPU reads with A12 low needed before a rise counts, standing in for the
+// chip's filter so the alternating tables of 8x16 sprites don't clock it
+const A12_FILTER: u8 = 3;
+
+// Tengen RAMBO-1, an MMC3 relative. Bank select at $8000 is CPxxRRRR:
+//   C: swaps the CHR halves, P: swaps RF and R6 in the PRG layout,
+//   x (bit 5): R0 and R1 become 1KB banks, with R8 and R9 after them
+// R6, R7 and RF are 8KB PRG banks at $8000, $A000 and $C000 (RF, R6, R7
+// with P set); the last bank stays at $E000. The IRQ counter is clocked by
+// PPU A12 rising once a scanline, or every 4 CPU cycles in cycle mode.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper64 {
+    chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Memory,
+    bank_select: u8,
+    registers: [u8; 16],
+    horizontal: bool,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_cycle_mode: bool,
+    irq: bool,
+    prescaler: u8,
+    a12_low: u8,
+}
+
+impl Mapper64 {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+
+        Mapper64 {
+            chr: ChrMemory::new(header, chr_rom_data),
+            prg_rom: Memory::new(prg_rom_data),
+            bank_select: 0,
+            registers: [0; 16],
+            horizontal: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_cycle_mode: false,
+            irq: false,
+            prescaler: 0,
+            a12_low: 0,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> u32 {
+        let r = &self.registers;
+        let addr = if self.bank_select & 0x80 != 0 { addr ^ 0x1000 } else { addr };
+        let full = self.bank_select & 0x20 != 0;
+        let bank = match (addr >> 10, full) {
+            (0, true) => r[0],
+            (1, true) => r[8],
+            (2, true) => r[1],
+            (3, true) => r[9],
+            (slot @ 0..=3, false) => (r[(slot >> 1) as usize] & 0xFE) | (slot & 1) as u8,
+            (slot, _) => r[slot as usize - 2],
+        };
+        bank as u32 * 0x400 + (addr & 0x3FF) as u32
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        let r = &self.registers;
+        let last_bank = (self.prg_rom.capacity() / 0x2000).saturating_sub(1);
+        let swapped = self.bank_select & 0x40 != 0;
+        let bank = match ((addr >> 13) & 3, swapped) {
+            (0, false) | (1, true) => r[6] as u32,
+            (1, false) | (2, true) => r[7] as u32,
+            (2, false) | (0, true) => r[15] as u32,
+            _ => last_bank,
+        };
+        bank * 0x2000 + (addr & 0x1FFF) as u32
+    }
+
+    // A reload takes effect on the next clock, counting one (two for
+    // latches above 1) further than MMC3 would, as Hard Drivin' expects
+    fn clock_counter(&mut self) {
+        if self.irq_reload {
+            self.irq_reload = false;
+            self.irq_counter = self.irq_latch.wrapping_add(if self.irq_latch <= 1 { 1 } else { 2 });
+        } else if self.irq_counter == 0 {
+            self.irq_counter = self.irq_latch.wrapping_add(1);
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq = true;
+        }
+    }
+
+    fn watch_a12(&mut self, addr: u16) {
+        if addr & 0x1000 == 0 {
+            self.a12_low = self.a12_low.saturating_add(1);
+            return;
+        }
+        if self.a12_low >= A12_FILTER && !self.irq_cycle_mode {
+            self.clock_counter();
+        }
+        self.a12_low = 0;
+    }
+}
+
+impl Mapper for Mapper64 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => {
+                self.watch_a12(addr);
+                self.chr.read(self.chr_offset(addr))
+            }
+
+            // PRG ROM (0x8000-0xFFFF)
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
+
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match (addr & 0xE001, addr) {
+            (_, 0x0000..=0x1FFF) => {
+                self.watch_a12(addr);
+                let offset = self.chr_offset(addr);
+                self.chr.write(offset, data);
+            }
+            (0x8000, _) => self.bank_select = data,
+            (0x8001, _) => self.registers[(self.bank_select & 0x0F) as usize] = data,
+            (0xA000, _) => self.horizontal = data & 1 != 0,
+            (0xC000, _) => self.irq_latch = data,
+            (0xC001, _) => {
+                self.irq_cycle_mode = data & 1 != 0;
+                self.irq_reload = true;
+                self.prescaler = 0;
+            }
+            (0xE000, _) => {
+                self.irq_enabled = false;
+                self.irq = false;
+            }
+            (0xE001, _) => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self, cycles: u8) {
+        if !self.irq_cycle_mode {
+            return;
+        }
+        for _ in 0..cycles {
+            self.prescaler = (self.prescaler + 1) & 3;
+            if self.prescaler == 0 {
+                self.clock_counter();
+            }
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(if self.horizontal { Mirroring::Horizontal } else { Mirroring::Vertical })
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => self.chr.mirror(self.chr_offset(addr)),
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+            _ => addr as u32
+        }
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        let info = MapperDebugInfo::new(self, "RAMBO-1", 0x2000, 0x400)
+            .register("Bank select", self.bank_select as u32)
+            .register("IRQ latch", self.irq_latch as u32)
+            .register("IRQ counter", self.irq_counter as u32)
+            .register("IRQ enabled", self.irq_enabled as u32)
+            .register("IRQ cycle mode", self.irq_cycle_mode as u32);
+        // RA-RE don't exist
+        (0..10).chain([15]).fold(info, |info, i| info.register(REGISTER_NAMES[i], self.registers[i] as u32))
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper64(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::Rom, test_support::TestRom, Nes, SystemVersion};
+    use super::*;
+
+    fn mapper() -> Mapper64 {
+        let (header, data) = TestRom::new(64).prg_16k(8).chr_8k(16).build();
+        Mapper64::new(&header, data)
+    }
+
+    fn set_banks(mapper: &mut Mapper64, banks: &[(u8, u8)]) {
+        for &(register, bank) in banks {
+            mapper.write(0x8000, register);
+            mapper.write(0x8001, bank);
+        }
+    }
+
+    fn chr_banks(mapper: &mut Mapper64) -> Vec<u8> {
+        (0..8).map(|slot| mapper.read(slot * 0x400)).collect()
+    }
+
+    #[test]
+    fn prg_and_chr_modes() {
+        let mut mapper = mapper();
+        set_banks(&mut mapper, &[(0, 10), (1, 20), (2, 2), (3, 3), (4, 4), (5, 5), (6, 1), (7, 2), (8, 30), (9, 40), (15, 3)]);
+        assert_eq!(chr_banks(&mut mapper), [10, 11, 20, 21, 2, 3, 4, 5]);
+        assert_eq!([0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mapper.read(addr)), [1, 2, 3, 15]);
+
+        // 1KB banks, halves swapped
+        mapper.write(0x8000, 0xA0);
+        assert_eq!(chr_banks(&mut mapper), [2, 3, 4, 5, 10, 30, 20, 40]);
+        mapper.write(0x8000, 0x40);
+        assert_eq!([0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mapper.read(addr)), [3, 1, 2, 15]);
+
+        mapper.write(0xA000, 1);
+        assert_eq!(mapper.mirroring(), Some(Mirroring::Horizontal));
+    }
+
+    #[test]
+    fn cycle_mode_counts_every_four_cycles() {
+        let mut mapper = mapper();
+        mapper.write(0xC000, 3);
+        mapper.write(0xC001, 1);
+        mapper.write(0xE001, 0);
+        // The reload makes it 5 clocks
+        mapper.clock(19);
+        assert!(!mapper.irq_pending());
+        mapper.clock(1);
+        assert!(mapper.irq_pending());
+        mapper.write(0xE000, 0);
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn scanline_mode_counts_filtered_a12_rises() {
+        let mut mapper = mapper();
+        mapper.write(0xC000, 1);
+        mapper.write(0xC001, 0);
+        mapper.write(0xE001, 0);
+        let line = |mapper: &mut Mapper64, reads: &[u16]| {
+            for &addr in reads {
+                mapper.read(addr);
+            }
+            mapper.irq_pending()
+        };
+        // 8x16 sprites alternating tables
+        assert!(!line(&mut mapper, &[0x0000, 0x0008, 0x1000, 0x1008, 0x0000, 0x0008, 0x1000]));
+        assert!(!line(&mut mapper, &[0x0000, 0x0008, 0x0010, 0x1000]));
+        assert!(line(&mut mapper, &[0x0000, 0x0008, 0x0010, 0x1000]));
+    }
+
+    #[test]
+    fn forced_blank_stops_the_scanline_counter() {
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(Rom::new(TestRom::new(64).prg_16k(8).chr_8k(16).bytes()));
+        nes.on();
+        nes.run_frame();
+        nes.run_frame();
+        for (i, byte) in [0x4C, 0x00, 0x02].into_iter().enumerate() {
+            nes.poke(0x0200 + i as u16, byte);
+        }
+        nes.set_start(0x0200);
+        nes.poke(0x2000, 0x08);
+        nes.poke(0xC000, 100);
+        nes.poke(0xC001, 0);
+        nes.poke(0xE001, 0);
+
+        nes.run_frame();
+        assert!(!nes.cpu.bus.cartridge.irq_pending());
+        nes.poke(0x2001, 0x18);
+        nes.run_frame();
+        assert!(nes.cpu.bus.cartridge.irq_pending());
+    }
+}
\ No newline at end of file
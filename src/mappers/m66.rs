@@ -0,0 +1,43 @@
+use crate::mappers::latch::{LatchBoard, LatchMapper};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// GxROM / MHROM: one register selecting a 32KB PRG bank and an 8KB CHR bank.
+#[derive(Clone)]
+pub struct Gxrom;
+
+impl LatchBoard for Gxrom {
+    const NAME: &'static str = "GxROM";
+
+    // --PP --CC
+    fn banks(latch: u8) -> (u8, u8) {
+        ((latch >> 4) & 0x03, latch & 0x03)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(mapper: &Mapper66) -> MapperState {
+        MapperState::Mapper66(mapper.clone())
+    }
+}
+
+pub type Mapper66 = LatchMapper<Gxrom>;
+
+#[cfg(test)]
+mod tests {
+    use crate::{mapper::Mapper, test_support::TestRom};
+    use super::*;
+
+    #[test]
+    fn selects_prg_and_chr_banks() {
+        let (header, data) = TestRom::new(66).prg_16k(8).chr_8k(4).build();
+        let mut mapper = Mapper66::new(&header, data);
+        assert_eq!(mapper.read(0x8000), 0);
+        assert_eq!(mapper.read(0xFFFF), 3);
+
+        mapper.write(0x8000, 0x23);
+        assert_eq!(mapper.read(0x8000), 8);
+        assert_eq!(mapper.read(0xFFFF), 11);
+        assert_eq!(mapper.read(0x0000), 24);
+        assert_eq!(mapper.read(0x1FFF), 31);
+    }
+}
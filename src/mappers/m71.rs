@@ -0,0 +1,152 @@
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::{Mirroring, RomHeader}};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// Camerica / Codemasters (BF909x): UNROM-like 16KB PRG switching with the last
+// bank fixed. The Fire Hawk board (submapper 1) adds single-screen control.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper71 {
+    chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Memory,
+    prg_bank: u8,
+    mirroring: Option<Mirroring>,
+    // Submapper 1. On other boards $9000-$9FFF is just more of the ROM.
+    fire_hawk: bool,
+}
+
+impl Mapper71 {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+
+        Mapper71 {
+            chr: ChrMemory::new(header, chr_rom_data),
+            prg_rom: Memory::new(prg_rom_data),
+            prg_bank: 0,
+            mirroring: None,
+            fire_hawk: header.submapper == 1,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        if addr < 0xC000 {
+            self.prg_bank as u32 * 0x4000 + (addr - 0x8000) as u32
+        } else {
+            // Fixed to the last bank
+            self.prg_rom.capacity().saturating_sub(0x4000) + (addr - 0xC000) as u32
+        }
+    }
+}
+
+impl Mapper for Mapper71 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // CHR RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => self.chr.read(addr as u32),
+
+            // PRG ROM (0x8000-0xFFFF)
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
+
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            // CHR RAM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => self.chr.write(addr as u32, data),
+
+            // Mirroring (0x9000-0x9FFF), only wired up on the Fire Hawk board
+            0x9000..=0x9FFF if self.fire_hawk => {
+                self.mirroring = Some(if data & 0x10 != 0 {
+                    Mirroring::SingleScreenUpper
+                } else {
+                    Mirroring::SingleScreen
+                });
+            },
+
+            // PRG bank select (0xC000-0xFFFF)
+            0xC000..=0xFFFF => self.prg_bank = data & 0x0F,
+
+            _ => {}
+        }
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        self.mirroring
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => addr as u32,
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+            _ => addr as u32
+        }
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        MapperDebugInfo::new(self, "Camerica", 0x4000, 0x2000)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper71(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestRom;
+    use super::*;
+
+    #[test]
+    fn switches_low_bank_and_fixes_last() {
+        let (header, data) = TestRom::new(71).prg_16k(8).chr_8k(0).build();
+        let mut mapper = Mapper71::new(&header, data);
+        assert_eq!(mapper.read(0xC000), 14);
+
+        mapper.write(0xC000, 3);
+        assert_eq!(mapper.read(0x8000), 6);
+        assert_eq!(mapper.read(0xFFFF), 15);
+    }
+
+    #[test]
+    fn uses_chr_ram() {
+        let (header, data) = TestRom::new(71).chr_8k(0).build();
+        let mut mapper = Mapper71::new(&header, data);
+        mapper.write(0x1234, 0x5A);
+        assert_eq!(mapper.read(0x1234), 0x5A);
+    }
+
+    #[test]
+    fn fire_hawk_single_screen_select() {
+        let (header, data) = TestRom::new(71).chr_8k(0).submapper(1).build();
+        let mut mapper = Mapper71::new(&header, data);
+        assert_eq!(mapper.mirroring(), None);
+
+        mapper.write(0x9000, 0x10);
+        assert_eq!(mapper.mirroring(), Some(Mirroring::SingleScreenUpper));
+        mapper.write(0x9000, 0x00);
+        assert_eq!(mapper.mirroring(), Some(Mirroring::SingleScreen));
+    }
+
+    #[test]
+    fn other_boards_keep_their_mirroring() {
+        let (header, data) = TestRom::new(71).chr_8k(0).submapper(0).build();
+        let mut mapper = Mapper71::new(&header, data);
+        mapper.write(0x9000, 0x10);
+        assert_eq!(mapper.mirroring(), None);
+    }
+}
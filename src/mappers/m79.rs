@@ -0,0 +1,47 @@
+use crate::mappers::latch::{LatchBoard, LatchMapper};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// AVE's NINA-03 and NINA-06: the latch answers at $4100-$5FFF wherever A8
+// is set.
+#[derive(Clone)]
+pub struct Nina03;
+
+impl LatchBoard for Nina03 {
+    const NAME: &'static str = "NINA-03/06";
+
+    fn decodes(addr: u16) -> bool {
+        addr & 0xE100 == 0x4100
+    }
+
+    // ---- PCCC
+    fn banks(latch: u8) -> (u8, u8) {
+        ((latch >> 3) & 0x01, latch & 0x07)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(mapper: &Mapper79) -> MapperState {
+        MapperState::Mapper79(mapper.clone())
+    }
+}
+
+pub type Mapper79 = LatchMapper<Nina03>;
+
+#[cfg(test)]
+mod tests {
+    use crate::{mapper::Mapper, test_support::TestRom};
+    use super::*;
+
+    #[test]
+    fn latches_writes_with_a8_set() {
+        let (header, data) = TestRom::new(79).prg_16k(4).chr_8k(8).build();
+        let mut mapper = Mapper79::new(&header, data);
+        mapper.write(0x4000, 0x0F);
+        mapper.write(0x8000, 0x0F);
+        assert_eq!(mapper.read(0x8000), 0);
+
+        mapper.write(0x5F00, 0x0D);
+        assert_eq!(mapper.read(0x8000), 4);
+        assert_eq!(mapper.read(0x0000), 40);
+    }
+}
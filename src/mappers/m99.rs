@@ -0,0 +1,145 @@
+use crate::{mapper::{Mapper, MapperDebugInfo}, memory::{ChrMemory, Memory}, rom::header::RomHeader};
+#[cfg(feature = "serde")]
+use crate::mapper::MapperState;
+
+// VS System default board. The CPU's OUT2 line ($4016 bit 2) selects the 8KB
+// CHR bank, and on 40KB PRG boards (Vs. Gumshoe) also the 8KB bank at $8000.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapper99 {
+    chr: ChrMemory,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Memory,
+    prg_ram: Memory,
+    bank: u8,
+}
+
+impl Mapper99 {
+    pub fn new(header: &RomHeader, data: Vec<u8>) -> Self {
+        let prg_rom_data = data[header.prg_rom_range()].to_vec();
+        let chr_rom_data = data[header.chr_rom_range()].to_vec();
+
+        Mapper99 {
+            chr: ChrMemory::new(header, chr_rom_data),
+            prg_rom: Memory::new(prg_rom_data),
+            prg_ram: Memory::new(vec![0; 2 * 1024]),
+            bank: 0,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> u32 {
+        self.bank as u32 * 0x2000 + addr as u32
+    }
+
+    fn prg_offset(&self, addr: u16) -> u32 {
+        if addr < 0xA000 && self.prg_rom.capacity() > 0x8000 {
+            self.bank as u32 * 0x8000 + (addr - 0x8000) as u32
+        } else {
+            (addr - 0x8000) as u32
+        }
+    }
+}
+
+impl Mapper for Mapper99 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM (0x0000-0x1FFF)
+            0x0000..=0x1FFF => self.chr.read(self.chr_offset(addr)),
+
+            // 2KB PRG RAM mirrored through 0x6000-0x7FFF
+            0x6000..=0x7FFF => self.prg_ram.read(addr & 0x07FF),
+
+            // PRG ROM (0x8000-0xFFFF)
+            0x8000..=0xFFFF => self.prg_rom.read_wrapped(self.prg_offset(addr)),
+
+            _ => 0
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x1FFF => {
+                let offset = self.chr_offset(addr);
+                self.chr.write(offset, data);
+            },
+            0x6000..=0x7FFF => self.prg_ram.write(addr & 0x07FF, data),
+            _ => {}
+        }
+    }
+
+    fn out_latch_write(&mut self, data: u8) {
+        self.bank = (data >> 2) & 1;
+    }
+
+    fn map(&self, addr: u16) -> u32 {
+        match addr {
+            0x0000..=0x1FFF => self.chr.mirror(self.chr_offset(addr)),
+            0x6000..=0x7FFF => (addr & 0x07FF) as u32,
+            0x8000..=0xFFFF => self.prg_rom.mirror(self.prg_offset(addr)),
+            _ => addr as u32
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        self.prg_ram.as_slice()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.prg_ram.as_mut_slice()
+    }
+
+    fn chr(&self) -> &[u8] {
+        self.chr.as_slice()
+    }
+
+    fn debug_state(&self) -> MapperDebugInfo {
+        MapperDebugInfo::new(self, "VS System", 0x2000, 0x2000)
+            .register("OUT2", self.bank as u32)
+    }
+
+    #[cfg(feature = "serde")]
+    fn state(&self) -> MapperState {
+        MapperState::Mapper99(self.clone())
+    }
+
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, header: &RomHeader, data: &[u8]) {
+        self.prg_rom = Memory::new(data[header.prg_rom_range()].to_vec());
+        self.chr.restore_rom(&data[header.chr_rom_range()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::header::HEADER_SIZE, test_support::TestRom};
+    use super::*;
+
+    #[test]
+    fn out2_selects_chr_bank() {
+        let (header, data) = TestRom::new(99).chr_8k(2).four_screen().build();
+        let mut mapper = Mapper99::new(&header, data);
+        assert_eq!(mapper.read(0x0000), 0);
+
+        mapper.out_latch_write(0x04);
+        assert_eq!(mapper.read(0x0000), 8);
+        assert_eq!(mapper.read(0x8000), 0);
+    }
+
+    #[test]
+    fn gumshoe_40k_prg() {
+        let mut data = TestRom::new(99).prg_16k(3).chr_8k(2).bytes();
+        data.truncate(HEADER_SIZE + 0xA000);
+        data.extend(vec![0xCC; 0x4000]);
+        let mut header = RomHeader::new(data[0..HEADER_SIZE].to_vec());
+        header.prg_rom_size = 0xA000;
+        header.chr_rom_size = 0x4000;
+
+        let mut mapper = Mapper99::new(&header, data);
+        assert_eq!(mapper.read(0x8000), 0);
+        assert_eq!(mapper.read(0xA000), 1);
+
+        mapper.out_latch_write(0x04);
+        assert_eq!(mapper.read(0x8000), 4);
+        assert_eq!(mapper.read(0xA000), 1);
+    }
+}
@@ -1,2 +1,17 @@
+pub mod fallback;
+pub mod latch;
+pub mod m10;
 pub mod m0;
-pub mod m1;
\ No newline at end of file
+pub mod m1;
+pub mod m11;
+pub mod m21;
+pub mod m34;
+pub mod m38;
+pub mod m60;
+pub mod m64;
+pub mod m66;
+pub mod m71;
+pub mod m79;
+pub mod m99;
+pub mod m105;
+pub mod m206;
\ No newline at end of file
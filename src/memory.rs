@@ -1,28 +1,177 @@
+use crate::rom::header::RomHeader;
+
+const DEFAULT_CHR_RAM_SIZE: usize = 8 * 1024;
+
+// A block of RAM or ROM. Every access mirrors across the chip size, the
+// same way unconnected high address lines do on real boards, so an address
+// past the end can't panic. Power-of-two sizes (all real chips) are masked,
+// anything else falls back to a modulo; an empty chip reads as 0.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Memory {
-	pub data: Vec<u8>
+	data: Vec<u8>,
+	mask: Option<usize>
 }
 
 impl Memory {
 	pub fn new(vec: Vec<u8>) -> Self {
-		Memory{ data: vec }
+		let len = vec.len();
+		let mask = if len.is_power_of_two() { Some(len - 1) } else { None };
+		Memory{ data: vec, mask }
 	}
 
 	pub fn clear(&mut self) {
-		for i in 0..self.capacity() {
-			self.data[i as usize] = 0;
-		}
+		self.data.fill(0);
 	}
 
 	pub fn capacity(&self) -> u32 {
 		self.data.len() as u32
 	}
 
+	pub fn as_slice(&self) -> &[u8] {
+		&self.data
+	}
+
+	pub fn as_mut_slice(&mut self) -> &mut [u8] {
+		&mut self.data
+	}
+
+	// Where `offset` lands inside the chip
+	pub fn mirror(&self, offset: u32) -> u32 {
+		match self.mask {
+			Some(mask) => offset & mask as u32,
+			None if self.data.is_empty() => 0,
+			None => offset % self.capacity()
+		}
+	}
+
 	pub fn read(&self, address: u16) -> u8 {
-		self.data[address as usize]
+		self.read_wrapped(address as u32)
 	}
 
 	pub fn write(&mut self, address: u16, value: u8) {
-		self.data[address as usize] = value;
+		self.write_wrapped(address as u32, value);
+	}
+
+	pub fn read_wrapped(&self, offset: u32) -> u8 {
+		let offset = self.mirror(offset) as usize;
+		self.data.get(offset).copied().unwrap_or(0)
+	}
+
+	pub fn write_wrapped(&mut self, offset: u32, value: u8) {
+		let offset = self.mirror(offset) as usize;
+		if let Some(byte) = self.data.get_mut(offset) {
+			*byte = value;
+		}
+	}
+}
+
+// Pattern table storage for a cartridge. Boards either ship CHR-ROM or
+// provide CHR-RAM instead, and mappers should not have to care which.
+// Save states only carry CHR-RAM; ROM comes back with restore_rom.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "ChrState", into = "ChrState"))]
+pub struct ChrMemory {
+	memory: Memory,
+	writable: bool
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ChrState {
+	ram: Option<Memory>
+}
+
+#[cfg(feature = "serde")]
+impl From<ChrState> for ChrMemory {
+	fn from(state: ChrState) -> Self {
+		match state.ram {
+			Some(memory) => ChrMemory { memory, writable: true },
+			None => ChrMemory { memory: Memory::default(), writable: false },
+		}
+	}
+}
+
+#[cfg(feature = "serde")]
+impl From<ChrMemory> for ChrState {
+	fn from(chr: ChrMemory) -> Self {
+		ChrState { ram: chr.writable.then_some(chr.memory) }
+	}
+}
+
+impl ChrMemory {
+	pub fn new(header: &RomHeader, chr_rom: Vec<u8>) -> Self {
+		if !chr_rom.is_empty() {
+			return ChrMemory { memory: Memory::new(chr_rom), writable: false };
+		}
+
+		// NES 2.0 headers give an explicit CHR-RAM size, iNES 1.0 boards
+		// without CHR-ROM are assumed to carry 8KB.
+		let mut size = (header.chr_ram_size + header.chr_nvram_size) as usize;
+		if size == 0 {
+			size = DEFAULT_CHR_RAM_SIZE;
+		}
+
+		ChrMemory { memory: Memory::new(vec![0; size]), writable: true }
+	}
+
+	pub fn is_ram(&self) -> bool {
+		self.writable
+	}
+
+	// Puts CHR-ROM back after a save state; CHR-RAM is in the state
+	#[cfg(feature = "serde")]
+	pub fn restore_rom(&mut self, chr_rom: &[u8]) {
+		if !self.writable {
+			self.memory = Memory::new(chr_rom.to_vec());
+		}
+	}
+
+	pub fn capacity(&self) -> u32 {
+		self.memory.capacity()
+	}
+
+	pub fn mirror(&self, offset: u32) -> u32 {
+		self.memory.mirror(offset)
+	}
+
+	pub fn as_slice(&self) -> &[u8] {
+		self.memory.as_slice()
+	}
+
+	pub fn read(&self, offset: u32) -> u8 {
+		self.memory.read_wrapped(offset)
+	}
+
+	pub fn write(&mut self, offset: u32, value: u8) {
+		if self.writable {
+			self.memory.write_wrapped(offset, value);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn accesses_mirror_across_the_chip() {
+		let mut memory = Memory::new(vec![0; 0x800]);
+		memory.write(0x0801, 0x42);
+		assert_eq!(memory.read(0x0001), 0x42);
+		assert_eq!(memory.read_wrapped(0x1_0001), 0x42);
+
+		// Odd sizes wrap too
+		let memory = Memory::new(vec![1, 2, 3]);
+		assert_eq!(memory.read(4), 2);
+	}
+
+	#[test]
+	fn empty_memory_reads_zero() {
+		let mut memory = Memory::new(Vec::new());
+		memory.write(0x1234, 0xFF);
+		assert_eq!(memory.read(0x1234), 0);
+		assert_eq!(memory.mirror(0x1234), 0);
 	}
-	
 }
\ No newline at end of file
@@ -0,0 +1,154 @@
+// Input movies for TAS tooling. A movie is the controller state for every
+// frame since a power on with a known seed, so replaying it on the same ROM
+// reproduces the run exactly. The active movie travels inside save states
+// (with the serde feature), and loading one mid-movie resumes from there.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Movie {
+    // CRC32 of the ROM it was recorded on
+    pub rom_crc32: u32,
+    // Power-on seed, see NesConfig::seed
+    pub seed: u64,
+    // Buttons held on ports 0 and 1, one entry per frame
+    pub frames: Vec<[u8; 2]>,
+}
+
+impl Movie {
+    pub fn new(rom_crc32: u32, seed: u64) -> Self {
+        Movie { rom_crc32, seed, frames: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MovieMode {
+    // Live input is appended each frame. After loading a state, frames past
+    // it are discarded and recording branches off from there.
+    Recording,
+    // Read-only: input comes from the movie and live input is ignored until
+    // it runs out
+    Playback,
+}
+
+// The movie a console is running along with where it started
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct MovieSession {
+    pub movie: Movie,
+    pub mode: MovieMode,
+    // PPU frame count movie frame 0 began at
+    pub start_frame: u64,
+}
+
+impl MovieSession {
+    // Input for the frame starting now. Recording truncates anything after
+    // it first, so rerecording from an older state overwrites the rest.
+    pub fn next_input(&mut self, frame: u64, live: [u8; 2]) -> [u8; 2] {
+        let index = frame.saturating_sub(self.start_frame) as usize;
+        match self.mode {
+            MovieMode::Recording => {
+                self.movie.frames.resize(index, live);
+                self.movie.frames.push(live);
+                live
+            }
+            MovieMode::Playback => self.movie.frames.get(index).copied().unwrap_or(live)
+        }
+    }
+
+    pub fn finished(&self, frame: u64) -> bool {
+        self.mode == MovieMode::Playback && frame.saturating_sub(self.start_frame) as usize >= self.movie.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{controller::Button, test_support::console, Nes};
+    use super::*;
+
+    // Controller 1 as the game saw it over one frame
+    fn frame_buttons(nes: &mut Nes) -> u8 {
+        nes.run_frame();
+        nes.cpu.bus.controller1.buttons()
+    }
+
+    fn record(nes: &mut Nes) -> Vec<u8> {
+        nes.start_recording();
+        let mut seen = Vec::new();
+        for frame in 0..6 {
+            nes.set_button(Button::A, frame % 2 == 0);
+            nes.set_button(Button::Start, frame == 3);
+            seen.push(frame_buttons(nes));
+        }
+        seen
+    }
+
+    #[test]
+    fn playback_reproduces_recorded_input() {
+        let mut nes = console();
+        let recorded = record(&mut nes);
+        let movie = nes.stop_movie().unwrap();
+        assert_eq!(movie.len(), 7);
+
+        let mut replay = console();
+        replay.play_movie(movie).unwrap();
+        replay.set_button(Button::B, true);
+        let played: Vec<u8> = (0..6).map(|_| frame_buttons(&mut replay)).collect();
+        assert_eq!(played, recorded);
+        assert!(!replay.movie_finished());
+
+        // Live input takes over once the movie runs out
+        assert_eq!(frame_buttons(&mut replay), Button::B as u8);
+        assert!(replay.movie_finished());
+    }
+
+    #[test]
+    fn movies_only_play_on_their_rom() {
+        let mut nes = console();
+        record(&mut nes);
+        let mut movie = nes.stop_movie().unwrap();
+        movie.rom_crc32 ^= 1;
+        assert!(console().play_movie(movie).is_err());
+    }
+
+    #[test]
+    fn rerecording_branches_from_earlier_frame() {
+        let mut session = MovieSession { movie: Movie::new(0, 0), mode: MovieMode::Recording, start_frame: 10 };
+        for frame in 10..15 {
+            session.next_input(frame, [1, 0]);
+        }
+        // Back at frame 12, as if a state had been loaded
+        assert_eq!(session.next_input(12, [2, 0]), [2, 0]);
+        assert_eq!(session.movie.frames, vec![[1, 0], [1, 0], [2, 0]]);
+
+        session.mode = MovieMode::Playback;
+        assert_eq!(session.next_input(11, [4, 4]), [1, 0]);
+        assert!(!session.finished(12));
+        assert!(session.finished(13));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn save_state_resumes_the_movie() {
+        let mut nes = console();
+        let recorded = record(&mut nes);
+        let movie = nes.stop_movie().unwrap();
+
+        let mut replay = console();
+        replay.play_movie(movie).unwrap();
+        frame_buttons(&mut replay);
+        frame_buttons(&mut replay);
+        let state = serde_json::to_string(&replay).unwrap();
+
+        let mut loaded = console();
+        loaded.restore(serde_json::from_str(&state).unwrap()).unwrap();
+        let played: Vec<u8> = (0..4).map(|_| frame_buttons(&mut loaded)).collect();
+        assert_eq!(played, recorded[2..]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,131 @@
+use crate::{scroll_splits::{next_line, ScrollLine}, Nes};
+
+const WIDTH: usize = 256;
+const HEIGHT: usize = 240;
+
+const TILE_COLOR: [u8; 3] = [0x70, 0x70, 0x70];
+const ATTRIBUTE_COLOR: [u8; 3] = [0xF8, 0xD8, 0x00];
+const SEAM_COLOR: [u8; 3] = [0xF8, 0x38, 0x38];
+
+// Debug overlays for studying how a game lays out its background
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Overlays {
+    // Boundaries between the 8x8 background tiles
+    pub tiles: bool,
+    // Boundaries between the 16x16 areas sharing an attribute palette
+    pub attributes: bool,
+    // Where the picture crosses from one nametable into the next
+    pub scroll_seam: bool,
+}
+
+impl Overlays {
+    pub fn any(&self) -> bool {
+        self.tiles || self.attributes || self.scroll_seam
+    }
+
+    // Draws onto a 256x240 RGB24 frame. `lines` holds the scroll each
+    // scanline was drawn with, so the grids stay on the background through
+    // scrolling and splits. Lines drawn with rendering off get none.
+    pub fn draw(&self, frame: &mut [u8], lines: &[ScrollLine]) {
+        for (y, line) in lines.iter().enumerate().take(HEIGHT) {
+            if !line.rendering {
+                continue;
+            }
+            // Down the nametable, 0-255 as coarse Y can run into the
+            // attribute rows
+            let table_y = ((line.v & 0x3E0) >> 2 | (line.v >> 12) & 7) as usize;
+            for x in 0..WIDTH {
+                let world_x = line.scroll_x() as usize + x;
+                let seam = (x > 0 && world_x & 0xFF == 0) || (y > 0 && table_y == 0);
+                let color = if self.scroll_seam && seam {
+                    SEAM_COLOR
+                } else if self.attributes && (world_x & 0xF == 0 || table_y & 0xF == 0) {
+                    ATTRIBUTE_COLOR
+                } else if self.tiles && (world_x & 7 == 0 || table_y & 7 == 0) {
+                    TILE_COLOR
+                } else {
+                    continue;
+                };
+                let idx = (y * WIDTH + x) * 3;
+                if let Some(pixel) = frame.get_mut(idx..idx + 3) {
+                    pixel.copy_from_slice(&color);
+                }
+            }
+        }
+    }
+}
+
+impl Nes {
+    // A copy of the frame buffer with `overlays` drawn on. The scroll comes
+    // from capture_scroll when it's on, which follows mid-frame splits;
+    // otherwise from the scroll registers, as if the whole frame used them.
+    pub fn frame_with_overlays(&self, overlays: Overlays) -> [u8; WIDTH * HEIGHT * 3] {
+        let mut frame = *self.cpu.bus.ppu.frame_buffer;
+        if overlays.any() {
+            overlays.draw(&mut frame, &self.scroll_lines());
+        }
+        frame
+    }
+
+    fn scroll_lines(&self) -> Vec<ScrollLine> {
+        if let Some(capture) = self.scroll_capture().filter(|capture| !capture.lines().is_empty()) {
+            return capture.lines().to_vec();
+        }
+        let mut line = self.cpu.bus.ppu.frame_scroll();
+        (0..HEIGHT)
+            .map(|_| {
+                let current = line;
+                line.v = next_line(line.v);
+                current
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(frame: &[u8], x: usize, y: usize) -> [u8; 3] {
+        let idx = (y * WIDTH + x) * 3;
+        frame[idx..idx + 3].try_into().unwrap()
+    }
+
+    // Scrolled 4 pixels right and 1 down into the top left nametable
+    fn lines(rendering: bool) -> Vec<ScrollLine> {
+        let mut v = 0x1000;
+        (0..HEIGHT)
+            .map(|_| {
+                let line = ScrollLine { v, t: 0x1000, x: 4, rendering };
+                v = next_line(v);
+                line
+            })
+            .collect()
+    }
+
+    #[test]
+    fn grids_follow_the_scroll() {
+        let mut frame = vec![0; WIDTH * HEIGHT * 3];
+        let overlays = Overlays { tiles: true, attributes: true, scroll_seam: true };
+        overlays.draw(&mut frame, &lines(true));
+
+        // Columns at 16n - 4, rows at 16n - 1
+        assert_eq!(pixel(&frame, 4, 100), TILE_COLOR);
+        assert_eq!(pixel(&frame, 12, 100), ATTRIBUTE_COLOR);
+        assert_eq!(pixel(&frame, 13, 100), [0; 3]);
+        assert_eq!(pixel(&frame, 100, 7), TILE_COLOR);
+        assert_eq!(pixel(&frame, 100, 15), ATTRIBUTE_COLOR);
+        // Into the next nametable across at x 252, and the one below at
+        // line 239
+        assert_eq!(pixel(&frame, 252, 100), SEAM_COLOR);
+        assert_eq!(pixel(&frame, 100, 239), SEAM_COLOR);
+        assert_eq!(pixel(&frame, 101, 238), [0; 3]);
+
+        let mut frame = vec![0; WIDTH * HEIGHT * 3];
+        Overlays { scroll_seam: true, ..Overlays::default() }.draw(&mut frame, &lines(true));
+        assert_eq!(pixel(&frame, 12, 100), [0; 3]);
+        assert_eq!(pixel(&frame, 252, 100), SEAM_COLOR);
+        Overlays { tiles: true, ..Overlays::default() }.draw(&mut frame, &lines(false));
+        assert_eq!(pixel(&frame, 4, 100), [0; 3]);
+    }
+}
\ No newline at end of file
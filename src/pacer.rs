@@ -0,0 +1,121 @@
+use std::{thread, time::{Duration, Instant}};
+
+// Paces a frontend's frame loop off its audio device instead of a timer.
+// The console makes exactly as many samples as the device plays in the
+// time a frame takes on the region's CPU clock, so holding the device's
+// queue at a fixed depth runs emulation at the audio clock's speed: no
+// drift from it over a long session, and none of the periodic stutter of
+// a sleep that disagrees with the sound card. Without audio it falls back
+// to a fixed-step clock.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    sample_rate: u32,
+    // Samples to keep queued, which is also the audio latency
+    target: usize,
+    // For the clock fallback, see Nes::frame_period
+    frame_period: Duration,
+    // When the next frame is due by the clock
+    deadline: Option<Instant>,
+}
+
+impl Pacer {
+    pub fn new(sample_rate: u32, latency: Duration, frame_period: Duration) -> Self {
+        Pacer {
+            sample_rate,
+            target: (latency.as_secs_f64() * sample_rate as f64) as usize,
+            frame_period,
+            deadline: None,
+        }
+    }
+
+    // The region can change with the ROM
+    pub fn set_frame_period(&mut self, frame_period: Duration) {
+        self.frame_period = frame_period;
+    }
+
+    // How long to wait before emulating the next frame, given the samples
+    // still queued on the device after this frame's went in. None means
+    // there's no audio to follow, so the clock decides.
+    pub fn delay(&mut self, queued: Option<usize>) -> Duration {
+        match queued {
+            Some(queued) => {
+                self.deadline = None;
+                let excess = queued.saturating_sub(self.target);
+                Duration::from_secs_f64(excess as f64 / self.sample_rate as f64)
+            }
+            None => self.clock_delay(Instant::now()),
+        }
+    }
+
+    // Sleeps for delay()
+    pub fn wait(&mut self, queued: Option<usize>) {
+        let delay = self.delay(queued);
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+
+    // Frames are due a period apart from the first, not a period after the
+    // last one finished, so time spent emulating doesn't add up. Falling a
+    // whole frame behind starts again from now rather than rushing.
+    fn clock_delay(&mut self, now: Instant) -> Duration {
+        let deadline = match self.deadline {
+            Some(deadline) if deadline + self.frame_period >= now => deadline + self.frame_period,
+            _ => now + self.frame_period,
+        };
+        self.deadline = Some(deadline);
+        deadline - now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::Rom, test_support::TestRom, Nes, SystemVersion};
+    use super::*;
+
+    #[test]
+    fn waits_for_the_queue_to_drain_to_the_target() {
+        let mut pacer = Pacer::new(48_000, Duration::from_millis(250), Duration::from_millis(16));
+        assert_eq!(pacer.delay(Some(1_000)), Duration::ZERO);
+        assert_eq!(pacer.delay(Some(12_000)), Duration::ZERO);
+        assert_eq!(pacer.delay(Some(36_000)), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn frames_last_as_long_as_on_the_console() {
+        let period = Nes::new(SystemVersion::NTSC).frame_period();
+        assert!(period > Duration::from_micros(16_630) && period < Duration::from_micros(16_650));
+    }
+
+    #[test]
+    fn a_frame_makes_a_frames_worth_of_samples() {
+        // Auto picks PAL from the header
+        for (region, rom) in [(SystemVersion::NTSC, TestRom::new(0)), (SystemVersion::PAL, TestRom::new(0)), (SystemVersion::Auto, TestRom::new(0).pal())] {
+            let mut nes = Nes::new(region);
+            nes.set_rom(Rom::new(rom.bytes()));
+            nes.on();
+            nes.run_frame();
+            nes.run_frame();
+            nes.take_audio_samples();
+            nes.run_frame();
+            let expected = nes.frame_period().as_secs_f64() * nes.config().sample_rate as f64;
+            let made = nes.take_audio_samples().len() as f64;
+            assert!((made - expected).abs() <= 1.0, "{:?}: {} samples, expected {}", region, made, expected);
+        }
+    }
+
+    #[test]
+    fn clock_keeps_to_its_own_deadlines() {
+        let period = Duration::from_millis(16);
+        let mut pacer = Pacer::new(48_000, Duration::from_millis(50), period);
+        let start = Instant::now();
+        assert_eq!(pacer.clock_delay(start), period);
+        // A slow frame comes out of the next wait, not on top of it
+        assert_eq!(pacer.clock_delay(start + Duration::from_millis(20)), Duration::from_millis(12));
+        // More than a frame late: no catching up
+        assert_eq!(pacer.clock_delay(start + Duration::from_millis(100)), period);
+
+        pacer.delay(Some(0));
+        assert_eq!(pacer.clock_delay(start + Duration::from_millis(200)), period);
+    }
+}
\ No newline at end of file
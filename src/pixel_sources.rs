@@ -0,0 +1,105 @@
+const WIDTH: usize = 256;
+const HEIGHT: usize = 240;
+
+// What drew a pixel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PixelLayer {
+    // Palette entry 0, or the entry v points at with rendering off
+    #[default]
+    Backdrop,
+    Background,
+    // The OAM index, 0-63, of the sprite in front
+    Sprite(u8),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PixelSource {
+    pub layer: PixelLayer,
+    // The palette RAM entry drawn, 0-31
+    pub palette: u8,
+    // The nametable, 0-3, the background tile under the pixel was fetched
+    // from, whichever layer ended up in front. 0 with the background off.
+    pub nametable: u8,
+}
+
+// Where every pixel of the frame buffer came from, for debug overlays like
+// highlighting sprite 0 or a layer. Filled in as pixels are drawn, so after
+// a frame it lines up with the frame buffer.
+pub struct PixelSources {
+    pixels: Vec<PixelSource>,
+}
+
+impl Default for PixelSources {
+    fn default() -> Self {
+        PixelSources::new()
+    }
+}
+
+impl PixelSources {
+    pub fn new() -> Self {
+        PixelSources { pixels: vec![PixelSource::default(); WIDTH * HEIGHT] }
+    }
+
+    // Rows top to bottom, the same order as the frame buffer
+    pub fn pixels(&self) -> &[PixelSource] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<PixelSource> {
+        if x < WIDTH {
+            self.pixels.get(y * WIDTH + x).copied()
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn record(&mut self, x: usize, y: usize, source: PixelSource) {
+        if let Some(pixel) = self.pixels.get_mut(y * WIDTH + x) {
+            *pixel = source;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::Rom, test_support::TestRom, Nes, SystemVersion};
+    use super::*;
+
+    #[test]
+    fn records_layer_palette_and_nametable() {
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(Rom::new(TestRom::new(0).chr_8k(0).bytes()));
+        nes.on();
+        nes.run_frame();
+        nes.run_frame();
+        // Tile 0, on every nametable entry, solid in color 1
+        for row in 0..8 {
+            nes.write_ppu(row, 0xFF);
+        }
+        nes.write_oam(5, [40, 0x00, 0x00, 16]);
+        nes.write_oam(6, [79, 0x00, 0x20, 100]);
+        nes.track_pixel_sources(true);
+        // Scrolled halfway into the second nametable, with the background
+        // left out of the leftmost 8 pixels
+        nes.cpu.bus.read(0x2002);
+        nes.poke(0x2005, 128);
+        nes.poke(0x2005, 0);
+        nes.poke(0x2001, 0x1C);
+        nes.run_frame();
+        nes.run_frame();
+
+        let sources = nes.pixel_sources().unwrap();
+        let source = |layer, palette, nametable| Some(PixelSource { layer, palette, nametable });
+        assert_eq!(sources.get(0, 50), source(PixelLayer::Backdrop, 0, 0));
+        assert_eq!(sources.get(20, 50), source(PixelLayer::Background, 1, 0));
+        assert_eq!(sources.get(200, 50), source(PixelLayer::Background, 1, 1));
+        assert_eq!(sources.get(16, 41), source(PixelLayer::Sprite(5), 0x11, 0));
+        // Behind the background
+        assert_eq!(sources.get(100, 80), source(PixelLayer::Background, 1, 0));
+        assert_eq!(sources.get(256, 0), None);
+        assert_eq!(sources.pixels().len(), 256 * 240);
+
+        nes.track_pixel_sources(false);
+        assert!(nes.pixel_sources().is_none());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+
+use crate::rom::crc32::crc32;
+
+// Just enough PNG to save 8-bit RGB images without pulling in a codec. The
+// pixels go in stored (uncompressed) deflate blocks, so a file comes out a
+// little over the size of the raw image, which is fine for debug dumps.
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+// Most a stored deflate block can hold
+const STORED_BLOCK: usize = 0xFFFF;
+
+// `rgb` is `width` * `height` pixels of R, G and B, row by row
+pub fn write_rgb<W: Write>(out: &mut W, width: u32, height: u32, rgb: &[u8]) -> io::Result<()> {
+    if width == 0 || height == 0 || rgb.len() != width as usize * height as usize * 3 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("{} bytes is not a {}x{} RGB image", rgb.len(), width, height)));
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // 8 bits per channel, truecolor, deflate, no filtering or interlace
+    header.extend([8, 2, 0, 0, 0]);
+
+    // Every row starts with its filter type, 0 for none
+    let mut rows = Vec::with_capacity(rgb.len() + height as usize);
+    for row in rgb.chunks(width as usize * 3) {
+        rows.push(0);
+        rows.extend_from_slice(row);
+    }
+
+    out.write_all(&SIGNATURE)?;
+    write_chunk(out, b"IHDR", &header)?;
+    write_chunk(out, b"IDAT", &zlib_stored(&rows))?;
+    write_chunk(out, b"IEND", &[])
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut chunk = Vec::with_capacity(data.len() + 4);
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(&chunk)?;
+    out.write_all(&crc32(&chunk).to_be_bytes())
+}
+
+// A zlib stream of `data` in stored blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(STORED_BLOCK).max(1);
+    let mut stream = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    // Deflate with a 32KB window, no preset dictionary
+    stream.extend([0x78, 0x01]);
+    let mut chunks = data.chunks(STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        stream.extend([1, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = block.len() as u16;
+        stream.push(last as u8);
+        stream.extend(len.to_le_bytes());
+        stream.extend((!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend(adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MOD;
+        b = (b + a) % MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adler32_check_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(&[]), 1);
+    }
+
+    #[test]
+    fn writes_chunks_around_stored_rows() {
+        let mut png = Vec::new();
+        write_rgb(&mut png, 2, 1, &[1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(png[..8], SIGNATURE);
+        assert_eq!(png[8..16], [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(png[16..29], [0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        assert_eq!(png[29..33], crc32(&png[12..29]).to_be_bytes());
+
+        // zlib header, one final stored block of the filtered row, checksum
+        let idat = &png[33..];
+        assert_eq!(idat[..8], [0, 0, 0, 18, b'I', b'D', b'A', b'T']);
+        assert_eq!(idat[8..15], [0x78, 0x01, 1, 7, 0, 0xF8, 0xFF]);
+        assert_eq!(idat[15..22], [0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(idat[22..26], adler32(&[0, 1, 2, 3, 4, 5, 6]).to_be_bytes());
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+
+        assert!(write_rgb(&mut Vec::new(), 2, 2, &[0; 6]).is_err());
+    }
+
+    #[test]
+    fn splits_large_images_into_blocks() {
+        let stream = zlib_stored(&[7; STORED_BLOCK + 1]);
+        assert_eq!(stream[2..7], [0, 0xFF, 0xFF, 0x00, 0x00]);
+        let second = 7 + STORED_BLOCK;
+        assert_eq!(stream[second..second + 6], [1, 1, 0, 0xFE, 0xFF, 7]);
+        assert_eq!(stream.len(), second + 6 + 4);
+    }
+}
\ No newline at end of file
@@ -1,7 +1,6 @@
 use core::panic;
-use std::{fs::OpenOptions, io::{self, Write}, iter::Scan};
 
-use crate::{memory::Memory, rom::{header::{Mirroring, HEADER_SIZE}, Rom}};
+use crate::{cartridge::Cartridge, cpu::bus_trace::{BusAccess, BusOrigin}, mapper::NametableSource, memory::Memory, pixel_sources::{PixelLayer, PixelSource, PixelSources}, rng::Rng, rom::header::VsPpu, scroll_splits::{ScrollCapture, ScrollLine}, state_hash::StateHasher, tile_changes::TileChanges};
 
 const fn nth_bit(x: u16, n: u8) -> u16 {
     (x >> n) & 1
@@ -35,7 +34,37 @@ pub static PALETTE: [u8; 192] = [
     184, 248, 216, 0, 252, 252, 248, 216, 248, 0, 0, 0, 0, 0, 0,
 ];
 
+// RP2C04 palette scrambles: maps each color index a VS game writes to the
+// standard palette entry it displays as.
+static RP2C04_PALETTES: [[u8; 64]; 4] = [
+    [
+        0x35, 0x23, 0x16, 0x22, 0x1C, 0x09, 0x1D, 0x15, 0x20, 0x00, 0x27, 0x05, 0x04, 0x28, 0x08, 0x20,
+        0x21, 0x3E, 0x1F, 0x29, 0x3C, 0x32, 0x36, 0x12, 0x3F, 0x2B, 0x2E, 0x1E, 0x3D, 0x2D, 0x24, 0x01,
+        0x0E, 0x31, 0x33, 0x2A, 0x2C, 0x0C, 0x1B, 0x14, 0x2E, 0x07, 0x34, 0x06, 0x13, 0x02, 0x26, 0x2E,
+        0x2E, 0x19, 0x10, 0x0A, 0x39, 0x03, 0x37, 0x17, 0x0F, 0x11, 0x0B, 0x0D, 0x38, 0x25, 0x18, 0x3A,
+    ],
+    [
+        0x2E, 0x27, 0x18, 0x39, 0x3A, 0x25, 0x1C, 0x31, 0x16, 0x13, 0x38, 0x34, 0x20, 0x23, 0x3C, 0x0B,
+        0x0F, 0x21, 0x06, 0x3D, 0x1B, 0x29, 0x1E, 0x22, 0x1D, 0x24, 0x0E, 0x2B, 0x32, 0x08, 0x2E, 0x03,
+        0x04, 0x36, 0x26, 0x33, 0x11, 0x1F, 0x10, 0x02, 0x14, 0x3F, 0x00, 0x09, 0x12, 0x2E, 0x28, 0x20,
+        0x3E, 0x0D, 0x2A, 0x17, 0x0C, 0x01, 0x15, 0x19, 0x2E, 0x2C, 0x07, 0x37, 0x35, 0x05, 0x0A, 0x2D,
+    ],
+    [
+        0x14, 0x25, 0x3A, 0x10, 0x0B, 0x20, 0x31, 0x09, 0x01, 0x2E, 0x36, 0x08, 0x15, 0x3D, 0x3E, 0x3C,
+        0x22, 0x1C, 0x05, 0x12, 0x19, 0x18, 0x17, 0x1B, 0x00, 0x03, 0x2E, 0x02, 0x16, 0x06, 0x34, 0x35,
+        0x23, 0x0F, 0x0E, 0x37, 0x0D, 0x27, 0x26, 0x20, 0x29, 0x04, 0x21, 0x24, 0x11, 0x2D, 0x2E, 0x1F,
+        0x2C, 0x1E, 0x39, 0x33, 0x07, 0x2A, 0x28, 0x1D, 0x0A, 0x2E, 0x32, 0x38, 0x13, 0x2B, 0x3F, 0x0C,
+    ],
+    [
+        0x18, 0x03, 0x1C, 0x28, 0x2E, 0x35, 0x01, 0x17, 0x10, 0x1F, 0x2A, 0x0E, 0x36, 0x37, 0x1A, 0x39,
+        0x25, 0x1E, 0x12, 0x34, 0x2E, 0x1D, 0x06, 0x26, 0x3E, 0x1B, 0x22, 0x19, 0x04, 0x2E, 0x3A, 0x21,
+        0x05, 0x0A, 0x07, 0x02, 0x13, 0x14, 0x00, 0x15, 0x0C, 0x3D, 0x11, 0x0F, 0x0D, 0x38, 0x2D, 0x24,
+        0x33, 0x20, 0x08, 0x16, 0x3F, 0x2B, 0x20, 0x3C, 0x2E, 0x27, 0x23, 0x31, 0x29, 0x32, 0x2C, 0x09,
+    ],
+];
+
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sprite {
     id: u8,
     y: u8,
@@ -76,10 +105,53 @@ impl Sprite {
     }
 }
 
-const PPU_VRAM_SIZE: usize = 0x800; 
+// Where sprite evaluation is in dots 65-256
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum SpriteEval {
+    // Reading Y bytes for a sprite on the next line
+    Scan,
+    // Bytes of a found sprite still to copy
+    Copy(u8),
+    // Secondary OAM is full; looking for a ninth sprite, buggily
+    Overflow,
+    // Bytes still to read past the ninth sprite
+    OverflowCopy(u8),
+    // All 64 looked at
+    Done,
+}
+
+pub type ScanlineCallback = Box<dyn FnMut(usize, &[u8]) + Send>;
+
+// 2KB on the console, plus the 2KB four-screen boards add on the cartridge
+const PPU_VRAM_SIZE: usize = 0x1000;
 const NUM_SCANLINES: usize = 262;
 const CYCLERS_PER_SCANLINE: usize = 341;
 
+// What a PPU bus address selects. Only 14 address lines are wired, and
+// every one of those addresses decodes to something, so there's no
+// unmapped case to fall into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PpuTarget {
+    // Pattern tables, $0000-$1FFF
+    Chr(u16),
+    // $2000-$3EFF, the cartridge deciding what backs $2000-$2FFF and
+    // $3000 up mirroring it
+    Nametable(u16),
+    // One of the 32 palette bytes, repeated through $3F00-$3FFF
+    Palette(usize),
+}
+
+impl PpuTarget {
+    fn decode(addr: u16) -> Self {
+        match addr & 0x3FFF {
+            addr @ 0x0000..=0x1FFF => PpuTarget::Chr(addr),
+            addr @ 0x2000..=0x3EFF => PpuTarget::Nametable(addr),
+            addr => PpuTarget::Palette((addr & 0x1F) as usize),
+        }
+    }
+}
+
 #[derive(PartialEq)]
 pub enum Scanline{
     PreRender,
@@ -99,6 +171,7 @@ impl Scanline {
         }
     }
 }
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ppu {
 
     ctrl: u8,
@@ -116,12 +189,33 @@ pub struct Ppu {
     vram_buffer: u8,
     pub open_bus: u8,
     vram: Memory,
-    pub rom: Rom,
     palette: [u8; 32],
+    // Index into RP2C04_PALETTES for VS System PPUs with scrambled colors
+    color_lut: Option<usize>,
+    // RGB triplet for each of the 64 colors, PALETTE unless replaced
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_arrays"))]
+    pub rgb_palette: [u8; 192],
 
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_arrays"))]
     pub oam: [Sprite; 64],
-    pub secondary_oam: [Sprite; 8], 
-    pub sprite_cache: [Sprite; 8],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_arrays"))]
+    pub secondary_oam: [Sprite; 64],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_arrays"))]
+    pub sprite_cache: [Sprite; 64],
+    pub sprite_limit: bool,
+    // Debugging overrides that leave a layer out of the picture, whatever
+    // PPUMASK says. Sprite 0 hits still happen.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub hide_background: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub hide_sprites: bool,
+    sprite_eval: SpriteEval,
+    // OAM byte read on the last odd dot of evaluation
+    oam_latch: u8,
+    // Next secondary OAM byte evaluation writes
+    secondary_addr: u8,
+    pub extra_scanlines: u16,
+    idle_dots: u32,
     pub sprites: Vec<Sprite>,
     pub trigger_nmi: bool,
 
@@ -129,7 +223,22 @@ pub struct Ppu {
     pub scanline: usize,
 
     pub frame_ready: bool,
-    pub frame_buffer: [u8; 256 * 240 * 3],
+    pub frame_count: u64,
+    // Boxed to keep the console small enough to move between threads
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_arrays::boxed"))]
+    pub frame_buffer: Box<[u8; 256 * 240 * 3]>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub tile_changes: Option<TileChanges>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub scroll_capture: Option<ScrollCapture>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub pixel_sources: Option<PixelSources>,
+    // Handed each visible scanline's number and RGB24 pixels as it finishes
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub scanline_callback: Option<ScanlineCallback>,
+    // Accesses on the PPU bus, collected for the bus trace while it wants them
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) access_log: Option<Vec<BusAccess>>,
 
     addr_latch: u16,
 
@@ -139,11 +248,16 @@ pub struct Ppu {
     at_latch_hi: u8,
     pt_latch_lo: u8,
     pt_latch_hi: u8,
+    // Nametable bits of v at the tile's fetch, shifted alongside the pattern
+    // bits for PixelSource::nametable
+    nt_latch: u8,
 
     at_shifter_lo: u8,
     at_shifter_hi: u8,
     pt_shifter_lo: u16,
     pt_shifter_hi: u16,
+    nt_shifter_lo: u16,
+    nt_shifter_hi: u16,
 }
 
 impl Ppu {
@@ -171,20 +285,35 @@ impl Ppu {
             vram_buffer: 0,
             open_bus: 0,
             vram: Memory::new(vec![0; PPU_VRAM_SIZE]),
-            rom: Rom::new(vec![0; HEADER_SIZE]),
             palette: [0; 32],
+            color_lut: None,
+            rgb_palette: PALETTE,
 
             oam: [Sprite::new(); 64],
-            secondary_oam: [Sprite::new(); 8],
-            sprite_cache: [Sprite::new(); 8],
+            secondary_oam: [Sprite::new(); 64],
+            sprite_cache: [Sprite::new(); 64],
+            sprite_limit: true,
+            hide_background: false,
+            hide_sprites: false,
+            sprite_eval: SpriteEval::Done,
+            oam_latch: 0,
+            secondary_addr: 0,
+            extra_scanlines: 0,
+            idle_dots: 0,
             sprites,
             trigger_nmi: false,
 
             cycle: 0,
             scanline: 0,
 
-            frame_buffer: [0; 256 * 240 * 3],
+            tile_changes: None,
+            scroll_capture: None,
+            pixel_sources: None,
+            scanline_callback: None,
+            access_log: None,
+            frame_buffer: vec![0; 256 * 240 * 3].into_boxed_slice().try_into().unwrap(),
             frame_ready: false,
+            frame_count: 0,
 
             addr_latch: 0,
 
@@ -194,15 +323,33 @@ impl Ppu {
             at_latch_hi: 0,
             pt_latch_lo: 0,
             pt_latch_hi: 0,
+            nt_latch: 0,
 
             at_shifter_lo: 0,
             at_shifter_hi: 0,
             pt_shifter_lo: 0,
             pt_shifter_hi: 0,
+            nt_shifter_lo: 0,
+            nt_shifter_hi: 0,
         }
     }
 
-    fn cycle(&mut self, s: Scanline) {
+    // Takes the debug views and layer toggles of the PPU a loaded state
+    // replaces. The frame buffer stays where it was too, as frontends may
+    // hold a pointer to it.
+    pub(crate) fn keep_attachments(&mut self, old: &mut Ppu) {
+        std::mem::swap(&mut self.frame_buffer, &mut old.frame_buffer);
+        *self.frame_buffer = *old.frame_buffer;
+        self.hide_background = old.hide_background;
+        self.hide_sprites = old.hide_sprites;
+        self.tile_changes = old.tile_changes.take();
+        self.scroll_capture = old.scroll_capture.take();
+        self.pixel_sources = old.pixel_sources.take();
+        self.scanline_callback = old.scanline_callback.take();
+        self.access_log = old.access_log.take();
+    }
+
+    fn cycle(&mut self, cart: &mut Cartridge, s: Scanline) {
         let cycle = self.cycle;
         if s == Scanline::VBlank && cycle == 1 {
             self.status |= 0x80;
@@ -211,19 +358,20 @@ impl Ppu {
             }
         }else if s == Scanline::PostRender && cycle == 0 {
             self.frame_ready = true;
+            self.frame_count += 1;
+            if let Some(capture) = &mut self.scroll_capture {
+                capture.end_frame();
+            }
         }else if s == Scanline::PreRender || s == Scanline::Visible {
             
             match cycle {
-                1 => {
-                    self.clear_oam();
-                    if s == Scanline::PreRender {
-                        self.status &= 0x1F; 
-                    }
-                },
-                257 => self.eval_sprites(),
-                321 => self.load_sprites(),
+                1 if s == Scanline::PreRender => self.status &= 0x1F,
+                321 => self.load_sprites(cart),
                 _ => {}
             }
+            if self.is_rendering_enabled() {
+                self.sprite_evaluation(cycle);
+            }
 
             
             match cycle {
@@ -234,18 +382,21 @@ impl Ppu {
                             self.addr_latch = self.nt_addr();
                             self.reload_shifters();
                         },
-                        2 => self.nt_byte = self.read(self.addr_latch),
+                        2 => {
+                            self.nt_byte = self.fetch(cart, self.addr_latch);
+                            self.nt_latch = ((self.addr_latch >> 10) & 3) as u8;
+                        }
                         3 => self.addr_latch = self.at_addr(),
                         4 => {
-                            self.at_byte = self.read(self.addr_latch);
+                            self.at_byte = self.fetch(cart, self.addr_latch);
                             if self.coarse_y() & 2 != 0 { self.at_byte >>= 4 }
                             if self.coarse_x() & 2 != 0 { self.at_byte >>= 2 }
                         }
                         5 => self.addr_latch = self.pt_addr(),
-                        6 => self.pt_latch_lo = self.read(self.addr_latch),
+                        6 => self.pt_latch_lo = self.fetch(cart, self.addr_latch),
                         7 => self.addr_latch += 8,
                         0 => {
-                            self.pt_latch_hi = self.read(self.addr_latch);
+                            self.pt_latch_hi = self.fetch(cart, self.addr_latch);
                             self.increment_h();
                         },
                         _ => unreachable!()
@@ -253,7 +404,7 @@ impl Ppu {
                 },
                 256 => {
                     self.load_pixel();
-                    self.pt_latch_hi = self.read(self.addr_latch);
+                    self.pt_latch_hi = self.fetch(cart, self.addr_latch);
                     self.increment_v();
                 },
                 257 => {
@@ -270,31 +421,68 @@ impl Ppu {
                     }
                 },
                 321 | 339 => self.addr_latch = self.nt_addr(),
-                338 => self.nt_byte = self.read(self.addr_latch),
-                340 => {
-                    self.nt_byte = self.read(self.addr_latch);
-                    if s == Scanline::PreRender && self.odd_frame {
-                        self.cycle += 1;
-                    }
-                },
+                338 => self.nt_byte = self.fetch(cart, self.addr_latch),
+                340 => self.nt_byte = self.fetch(cart, self.addr_latch),
                 _ => {}
             }
+
+            if s == Scanline::Visible && cycle == 257 {
+                self.finish_scanline();
+            }
+
+            if self.scroll_capture.is_some() {
+                match (s, cycle) {
+                    (Scanline::PreRender, 304) => self.capture_scroll(0),
+                    (Scanline::Visible, 257) => self.capture_scroll(self.scanline + 1),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Dot 257 draws the line's last pixel
+    fn finish_scanline(&mut self) {
+        if let Some(callback) = &mut self.scanline_callback {
+            let start = self.scanline * 256 * 3;
+            callback(self.scanline, &self.frame_buffer[start..start + 256 * 3]);
         }
     }
 
-    pub fn step(&mut self){
+    fn capture_scroll(&mut self, scanline: usize) {
+        let line = ScrollLine { v: self.v, t: self.t, x: self.x, rendering: self.is_rendering_enabled() };
+        if let Some(capture) = &mut self.scroll_capture {
+            capture.record(scanline, line);
+        }
+    }
+
+    // The scroll a frame starts from, once the pre-render line copies t
+    pub(crate) fn frame_scroll(&self) -> ScrollLine {
+        ScrollLine { v: self.t, t: self.t, x: self.x, rendering: self.is_rendering_enabled() }
+    }
+
+    pub fn step(&mut self, cart: &mut Cartridge){
+        // Overclock: the PPU stands still at the end of vblank while the CPU
+        // keeps running, giving games extra time without touching rendering.
+        if self.idle_dots > 0 {
+            self.idle_dots -= 1;
+            return;
+        }
+
         match self.scanline {
-            0..=239 => self.cycle(Scanline::Visible),
-            240 => self.cycle(Scanline::PostRender),
-            241 => self.cycle(Scanline::VBlank),
-            261 => self.cycle(Scanline::PreRender),
+            0..=239 => self.cycle(cart, Scanline::Visible),
+            240 => self.cycle(cart, Scanline::PostRender),
+            241 => self.cycle(cart, Scanline::VBlank),
+            261 => self.cycle(cart, Scanline::PreRender),
             _ => {}
         }
 
         self.cycle += 1;
-        if self.cycle > 340 {
-            self.cycle %= CYCLERS_PER_SCANLINE;
+        if self.cycle >= self.scanline_length() {
+            self.cycle = 0;
             self.scanline += 1;
+            if self.scanline == NUM_SCANLINES - 1 {
+                self.idle_dots = self.extra_scanlines as u32 * CYCLERS_PER_SCANLINE as u32;
+            }
             if self.scanline >= NUM_SCANLINES {
                 self.scanline = 0;
                 self.odd_frame = !self.odd_frame
@@ -302,15 +490,40 @@ impl Ppu {
         }
     }
 
+    // Dots left of the overclock's idle scanlines, 0 outside them
+    pub fn idle_dots(&self) -> u32 {
+        self.idle_dots
+    }
+
+    // Odd frames drop the pre-render line's last dot while rendering is on,
+    // so frames alternate between 89342 and 89341 dots
+    fn scanline_length(&self) -> usize {
+        if self.scanline == NUM_SCANLINES - 1 && self.odd_frame && self.is_rendering_enabled() {
+            CYCLERS_PER_SCANLINE - 1
+        }else{
+            CYCLERS_PER_SCANLINE
+        }
+    }
+
     fn shift(&mut self){
         self.at_shifter_lo = (self.at_shifter_lo << 1) | self.at_latch_lo;
         self.at_shifter_hi = (self.at_shifter_hi << 1) | self.at_latch_hi;
         self.pt_shifter_lo <<= 1;
         self.pt_shifter_hi <<= 1;
+        self.nt_shifter_lo <<= 1;
+        self.nt_shifter_hi <<= 1;
+    }
+
+    // Sprites drawn per scanline. Secondary OAM is sized for all 64 so the
+    // hardware limit of 8 can be lifted.
+    fn sprite_slots(&self) -> usize {
+        if self.sprite_limit { 8 } else { 64 }
     }
 
     fn clear_oam(&mut self) {
-        for i in 0..8 {
+        for i in 0..self.sprite_slots() {
+            // Slots left empty by evaluation fetch tile $FF but draw nothing
+            self.secondary_oam[i].id = 64;
             self.secondary_oam[i].y = 0xFF;
             self.secondary_oam[i].tile = 0xFF;
             self.secondary_oam[i].attr = 0xFF;
@@ -318,13 +531,138 @@ impl Ppu {
         }
     }
 
+    // Secondary OAM is cleared over dots 1-64 and filled over 65-256, with an
+    // OAM read on each odd dot and a secondary OAM write on each even one.
+    // Evaluation walks OAM with OAMADDR itself, so a write to $2003 mid-line
+    // moves it, and OAMADDR is held at 0 while sprite tiles are fetched.
+    fn sprite_evaluation(&mut self, cycle: usize) {
+        if !self.sprite_limit {
+            // More sprites than the dots allow for, so all at once
+            match cycle {
+                1 => self.clear_oam(),
+                257 => self.eval_sprites(),
+                _ => {}
+            }
+            return;
+        }
+        match cycle {
+            1..=64 if cycle & 1 == 0 => self.write_secondary_oam(cycle as u8 / 2 - 1, 0xFF),
+            65..=256 if cycle & 1 == 1 => {
+                if cycle == 65 {
+                    self.secondary_addr = 0;
+                    self.sprite_eval = SpriteEval::Scan;
+                }
+                self.oam_latch = self.oam_byte(self.oamaddr);
+            }
+            65..=256 => self.eval_step(),
+            257..=320 => self.oamaddr = 0,
+            _ => {}
+        }
+    }
+
+    // One even dot of evaluation, on the byte the odd dot before it read
+    fn eval_step(&mut self) {
+        let data = self.oam_latch;
+        match self.sprite_eval {
+            SpriteEval::Scan => {
+                // The Y byte goes into the free slot whether it's in range or not
+                self.write_secondary_oam(self.secondary_addr, data);
+                if self.sprite_in_range(data) {
+                    self.secondary_oam[self.secondary_addr as usize / 4].id = self.oamaddr / 4;
+                    self.secondary_addr += 1;
+                    self.sprite_eval = SpriteEval::Copy(3);
+                    self.next_oam_byte();
+                }else{
+                    self.next_oam_sprite();
+                }
+            }
+            SpriteEval::Copy(left) => {
+                self.write_secondary_oam(self.secondary_addr, data);
+                self.secondary_addr += 1;
+                self.sprite_eval = match left {
+                    1 if self.secondary_addr as usize >= 8 * 4 => SpriteEval::Overflow,
+                    1 => SpriteEval::Scan,
+                    _ => SpriteEval::Copy(left - 1),
+                };
+                self.next_oam_byte();
+            }
+            SpriteEval::Overflow => {
+                if self.sprite_in_range(data) {
+                    self.status |= 0x20;
+                    self.sprite_eval = SpriteEval::OverflowCopy(3);
+                    self.next_oam_byte();
+                }else{
+                    // The hardware bug: the byte index steps along with the
+                    // sprite index, so later sprites are checked by their
+                    // tile, attribute or X byte instead of Y
+                    let (n, m) = (self.oamaddr / 4 + 1, self.oamaddr.wrapping_add(1) % 4);
+                    if n == 64 {
+                        self.sprite_eval = SpriteEval::Done;
+                    }
+                    self.oamaddr = (n % 64) * 4 + m;
+                }
+            }
+            SpriteEval::OverflowCopy(left) => {
+                self.sprite_eval = if left == 1 { SpriteEval::Done } else { SpriteEval::OverflowCopy(left - 1) };
+                self.next_oam_byte();
+                if left == 1 {
+                    self.oamaddr &= !3;
+                }
+            }
+            // Keeps reading Y bytes with nowhere to put them
+            SpriteEval::Done => self.oamaddr = self.oamaddr.wrapping_add(4),
+        }
+    }
+
+    // Evaluation stops once OAMADDR wraps past the last sprite
+    fn next_oam_byte(&mut self) {
+        let (addr, wrapped) = self.oamaddr.overflowing_add(1);
+        self.oamaddr = addr;
+        if wrapped {
+            self.sprite_eval = SpriteEval::Done;
+        }
+    }
+
+    fn next_oam_sprite(&mut self) {
+        let (addr, wrapped) = self.oamaddr.overflowing_add(4);
+        self.oamaddr = addr;
+        if wrapped {
+            self.sprite_eval = SpriteEval::Done;
+        }
+    }
+
+    // Whether a sprite with this Y byte shows on the next line. Nothing does
+    // after the pre-render line.
+    fn sprite_in_range(&self, y: u8) -> bool {
+        let line = if self.scanline == 261 { -1 } else { self.scanline as i16 } - y as i16;
+        line >= 0 && line < self.sprite_height() as i16
+    }
+
+    fn oam_byte(&self, addr: u8) -> u8 {
+        let sprite = &self.oam[addr as usize / 4];
+        [sprite.y, sprite.tile, sprite.attr, sprite.x][addr as usize % 4]
+    }
+
+    fn write_secondary_oam(&mut self, addr: u8, data: u8) {
+        let sprite = &mut self.secondary_oam[addr as usize / 4];
+        match addr % 4 {
+            0 => {
+                // Slots left empty by evaluation fetch tile $FF but draw nothing
+                sprite.id = 64;
+                sprite.y = data;
+            }
+            1 => sprite.tile = data,
+            2 => sprite.attr = data,
+            _ => sprite.x = data,
+        }
+    }
+
+    // Without the sprite limit: every sprite on the next line, in one go
     fn eval_sprites(&mut self) {
 
         let mut n = 0;
         for i in 0..64 {
-            let line: i16 = if self.scanline == 261 { -1 } else { self.scanline as i16 } - self.oam[i].y as i16;
-            let spr_height = self.sprite_height() as i16;
-            if line >= 0 && line < spr_height {
+            if self.sprite_in_range(self.oam[i].y) {
                 self.secondary_oam[n].id = i as u8;
                 self.secondary_oam[n].y = self.oam[i].y;
                 self.secondary_oam[n].tile = self.oam[i].tile;
@@ -334,34 +672,42 @@ impl Ppu {
                 n += 1;
                 if n >= 8 {
                     self.status |= 0x20;
+                }
+                if n >= self.sprite_slots() {
                     return;
                 }
             }
         }
     }
 
-    fn load_sprites(&mut self) {
-        for i in 0..8 {
-
+    fn load_sprites(&mut self, cart: &mut Cartridge) {
+        for i in 0..self.sprite_slots() {
             self.sprite_cache[i] = self.secondary_oam[i];
 
-            let mut addr: u16;
-            let sprite_height = self.sprite_height();
-
-            if sprite_height == 16 {
-                addr = ((self.sprite_cache[i].tile as u16 & 1) * 0x1000) + ((self.sprite_cache[i].tile as u16 & !1) * 16);
-            }else{
-                addr = self.sprite_pattern_table_address() + (self.sprite_cache[i].tile as u16 * 16);
+            let sprite_height = self.sprite_height() as usize;
+            let mut row = self.scanline.wrapping_sub(self.sprite_cache[i].y as usize) % sprite_height;
+            if self.sprite_cache[i].is_v_flipped() {
+                row ^= sprite_height - 1;
             }
 
-            let mut sprite_y = self.scanline.wrapping_sub(self.sprite_cache[i].y as usize) % sprite_height as usize;
-            if self.sprite_cache[i].attr & 0x80 != 0 {
-                sprite_y ^= sprite_height as usize - 1;
-            }
-            addr += sprite_y as u16 + (sprite_y as u16 & 8);
+            let addr = self.sprite_pattern_addr(self.sprite_cache[i].tile, row);
+            self.sprite_cache[i].pt_lo = self.fetch(cart, addr);
+            self.sprite_cache[i].pt_hi = self.fetch(cart, addr + 8);
+        }
+    }
 
-            self.sprite_cache[i].pt_lo = self.read(addr);
-            self.sprite_cache[i].pt_hi = self.read(addr + 8);
+    // Pattern address of a row of a sprite, counted from its top after any
+    // flip. 8x16 sprites take their table from bit 0 of the tile number and
+    // ignore PPUCTRL bit 3; rows 8-15 come from the odd tile of the pair, so
+    // under vertical flip the bottom half is drawn from the even one.
+    fn sprite_pattern_addr(&self, tile: u8, row: usize) -> u16 {
+        let row = row as u16;
+        if self.sprite_height() == 16 {
+            let table = (tile as u16 & 1) * 0x1000;
+            let tile = (tile as u16 & 0xFE) | (row >> 3);
+            table + tile * 16 + (row & 7)
+        }else{
+            self.sprite_pattern_table_address() + tile as u16 * 16 + row
         }
     }
 
@@ -369,6 +715,8 @@ impl Ppu {
     fn reload_shifters(&mut self) {
         self.pt_shifter_lo = (self.pt_shifter_lo & 0xFF00) | self.pt_latch_lo as u16;
         self.pt_shifter_hi = (self.pt_shifter_hi & 0xFF00) | self.pt_latch_hi as u16;
+        self.nt_shifter_lo = (self.nt_shifter_lo & 0xFF00) | if self.nt_latch & 1 != 0 { 0xFF } else { 0 };
+        self.nt_shifter_hi = (self.nt_shifter_hi & 0xFF00) | if self.nt_latch & 2 != 0 { 0xFF } else { 0 };
 
         self.at_latch_lo = self.at_byte & 1;
         self.at_latch_hi = self.at_byte & 2;
@@ -384,6 +732,8 @@ impl Ppu {
         let mut palette = 0u8;
         let mut obj_palette = 0u8;
         let mut obj_priority = false;
+        let mut obj_index = 0u8;
+        let mut nametable = 0u8;
     
         if self.scanline < 240 && x < 256 {
             
@@ -396,10 +746,12 @@ impl Ppu {
                         | nth_bit(self.at_shifter_lo as u16, 7 - fine_x) as u8)
                         << 2;
                 }
+                nametable = (nth_bit(self.nt_shifter_hi, 15 - fine_x) << 1) as u8
+                    | nth_bit(self.nt_shifter_lo, 15 - fine_x) as u8;
             }
     
             if self.is_sprite_rendering_enabled() && (x >= 8 || self.is_leftmost_sprite_rendering_enabled()) {
-                for i in (0..8).rev() {
+                for i in (0..self.sprite_slots()).rev() {
                     if self.sprite_cache[i].id == 64 {
                         continue; 
                     }
@@ -428,26 +780,47 @@ impl Ppu {
                         sprite_palette | ((self.sprite_cache[i].attr & 0x03) << 2);
                     obj_palette = final_sprite_palette + 16;
                     obj_priority = self.sprite_cache[i].attr & 0x20 != 0;
+                    obj_index = self.sprite_cache[i].id;
                 }
             }
     
             
-            if obj_palette != 0 && (palette == 0 || !obj_priority) {
-                palette = obj_palette;
+            if self.hide_background {
+                palette = 0;
+            }
+            if self.hide_sprites {
+                obj_palette = 0;
             }
+            let sprite_in_front = obj_palette != 0 && (palette == 0 || !obj_priority);
+            let mut layer = if sprite_in_front {
+                palette = obj_palette;
+                PixelLayer::Sprite(obj_index)
+            } else if palette != 0 {
+                PixelLayer::Background
+            } else {
+                PixelLayer::Backdrop
+            };
     
             
+            // With rendering off the backdrop comes from the palette entry v
+            // points at, if it points into the palette. Full screen color
+            // fills and raster bars are drawn this way.
             if !self.is_rendering_enabled() {
-                palette = 0;
+                palette = if self.v & 0x3FFF >= 0x3F00 { (self.v & 0x1F) as u8 } else { 0 };
+                layer = PixelLayer::Backdrop;
+            }
+            if let Some(sources) = &mut self.pixel_sources {
+                sources.record(x, self.scanline, PixelSource { layer, palette, nametable });
             }
     
             
-            let color = (self.palette[palette as usize] & 0x3F) as usize;
+            let mut color = (self.palette[palette as usize] & 0x3F) as usize;
+            if let Some(lut) = self.color_lut {
+                color = RP2C04_PALETTES[lut][color] as usize;
+            }
             let idx = (self.scanline * 256 + x) * 3;
     
-            self.frame_buffer[idx] = PALETTE[color * 3];
-            self.frame_buffer[idx + 1] = PALETTE[color * 3 + 1];
-            self.frame_buffer[idx + 2] = PALETTE[color * 3 + 2];
+            self.frame_buffer[idx..idx + 3].copy_from_slice(&self.rgb_palette[color * 3..color * 3 + 3]);
         }
     
         self.shift();
@@ -468,103 +841,153 @@ impl Ppu {
         self.bg_pattern_table_address() + (self.nt_byte as u16 * 16) + self.fine_y()
     }
 
-    pub fn read(&mut self, addr: u16) -> u8 {
-        let mut m_addr = addr & 0x3FFF; 
+    // Nametable RAM: the console's 2KB, then the 2KB four-screen boards add
+    pub fn vram(&self) -> &[u8] {
+        self.vram.as_slice()
+    }
 
-        match m_addr {
-            0x0000..0x2000 => {
-                self.rom.mapper.read(m_addr)
-            }
-            0x2000..0x3F00 => {
-                let v_addr = self.map_vram_addr(m_addr);
-                self.vram.read(v_addr)
-            }
-            0x3F00..0x4000 => {
-                
-                m_addr = (m_addr - 0x3F00) % 0x20;
-                self.palette[m_addr as usize]
-            }
-            _ => self.open_bus
+    // OAM as the CPU writes it: Y, tile, attributes, X for each sprite
+    pub fn oam_bytes(&self) -> [u8; 256] {
+        let mut bytes = [0; 256];
+        for (chunk, sprite) in bytes.chunks_exact_mut(4).zip(&self.oam) {
+            chunk.copy_from_slice(&[sprite.y, sprite.tile, sprite.attr, sprite.x]);
         }
+        bytes
     }
 
-    fn append_to_file(&self, filename: &str, content: &str) -> io::Result<()> {
-        
-        let mut file = OpenOptions::new()
-        .create(true)  
-        .append(true)  
-        .open(filename)?;
-    
-        
-        file.write_all(content.as_bytes())?;
-        Ok(())
+    pub fn set_oam_entry(&mut self, index: usize, bytes: [u8; 4]) {
+        let sprite = &mut self.oam[index % 64];
+        [sprite.y, sprite.tile, sprite.attr, sprite.x] = bytes;
     }
-    
-    fn write(&mut self, addr: u16, data: u8){
-        let mut m_addr = addr & 0x3FFF; 
-        self.open_bus = data; 
 
-        match m_addr {
-            0x0000..0x2000 => {
-                
-            }
-            0x2000..0x3000 => {
-                
-                let mirr_addr = self.map_vram_addr(m_addr);
-                self.vram.write(mirr_addr, data);
-            }
-            0x3000..0x3F00 => {
-                self.write(addr - 0x1000, data);
-            }
-            0x3F00..0x4000 => {
-                
-                m_addr = (m_addr - 0x3F00) % 0x20;
-                
-                
-                self.palette[m_addr as usize] = data;
-                
-                if m_addr % 4 == 0 {
-                    self.palette[m_addr as usize ^ 0x10] = data;
-                }
-            }
-            _ => panic!("PPU write outside of addressable memory: {:X} => 0x{:X}", data, addr)
+    // Writes PPU memory without going through $2006/$2007, so the address,
+    // latch and open bus stay as they were and the bus trace doesn't see it
+    pub fn poke(&mut self, cart: &mut Cartridge, addr: u16, data: u8) {
+        let (open_bus, log) = (self.open_bus, self.access_log.take());
+        self.write(cart, addr, data);
+        self.open_bus = open_bus;
+        self.access_log = log;
+    }
+
+    pub(crate) fn hash_registers(&self, hasher: &mut StateHasher) {
+        hasher.u8(self.ctrl).u8(self.mask).u8(self.status).u8(self.oamaddr)
+            .u16(self.v).u16(self.t).u8(self.x).u8(self.w as u8)
+            .u8(self.odd_frame as u8).u8(self.vram_buffer).u8(self.open_bus)
+            .u16(self.scanline as u16).u16(self.cycle as u16).u64(self.frame_count);
+    }
+
+    pub(crate) fn hash_vram(&self, hasher: &mut StateHasher) {
+        hasher.bytes(self.vram.as_slice()).bytes(&self.palette);
+    }
+
+    pub fn set_vs_ppu(&mut self, vs_ppu: Option<VsPpu>) {
+        self.color_lut = match vs_ppu {
+            Some(VsPpu::RP2C04(revision @ 1..=4)) => Some(revision as usize - 1),
+            _ => None
+        };
+    }
+
+    // Rendering fetches only reach the bus while rendering is on, so boards
+    // counting scanlines off the PPU address lines see nothing in forced
+    // blank
+    fn fetch(&mut self, cart: &mut Cartridge, addr: u16) -> u8 {
+        if self.is_rendering_enabled() {
+            self.read(cart, addr)
+        } else {
+            0
         }
     }
 
-    fn map_vram_addr(&mut self, addr: u16) -> u16 {
-        
-        let addr = addr & 0x3FFF;
-    
-        
-        if addr < 0x2000 {
-            panic!("map_vram_addr called with non-nametable address");
+    pub fn read(&mut self, cart: &mut Cartridge, addr: u16) -> u8 {
+        let data = self.read_mapped(cart, addr);
+        self.log_access(addr, data, false);
+        data
+    }
+
+    fn log_access(&mut self, addr: u16, value: u8, write: bool) {
+        if let Some(log) = &mut self.access_log {
+            log.push(BusAccess {
+                cycle: 0,
+                scanline: self.scanline as u16,
+                dot: self.cycle as u16,
+                addr: addr & 0x3FFF,
+                value,
+                write,
+                origin: BusOrigin::Ppu,
+            });
         }
-        
-        
-        let addr = if addr >= 0x3000 { addr - 0x1000 } else { addr };
-        
-        
-        let nametable = ((addr - 0x2000) >> 10) & 0x3;
-        let offset = (addr - 0x2000) & 0x3FF;  
-        
-        
-        let mapped_table = match self.rom.header.mirroring {
-            Mirroring::Horizontal => {
-                if nametable < 2 { 0 } else { 1 }
+    }
+
+    fn read_mapped(&mut self, cart: &mut Cartridge, addr: u16) -> u8 {
+        match PpuTarget::decode(addr) {
+            PpuTarget::Chr(addr) => cart.chr_read(addr),
+            PpuTarget::Nametable(addr) => self.read_nametable(cart, addr),
+            PpuTarget::Palette(index) => self.palette[index]
+        }
+    }
+
+    // Side-effect free read for debug views: no mapper latches, CDL or
+    // access log. Nametables a board supplies itself read as 0.
+    pub fn peek(&self, cart: &Cartridge, addr: u16) -> u8 {
+        match PpuTarget::decode(addr) {
+            PpuTarget::Chr(addr) => cart.chr_peek(addr),
+            PpuTarget::Nametable(addr) => {
+                let addr = 0x2000 | (addr & 0x0FFF);
+                match cart.nametable_source((addr >> 10) & 0x3) {
+                    NametableSource::Vram(page) => self.vram.read(page * 0x400 + (addr & 0x3FF)),
+                    NametableSource::Mapper => 0
+                }
             }
-            Mirroring::Vertical => {
-                nametable & 0x1
+            PpuTarget::Palette(index) => self.palette[index]
+        }
+    }
+
+    fn write(&mut self, cart: &mut Cartridge, addr: u16, data: u8){
+        self.log_access(addr, data, true);
+        self.open_bus = data; 
+
+        match PpuTarget::decode(addr) {
+            PpuTarget::Chr(addr) => {
+                if let Some(changes) = &mut self.tile_changes {
+                    changes.chr_written(addr);
+                }
+                cart.chr_write(addr, data);
             }
-            Mirroring::SingleScreen => {
-                0
+            PpuTarget::Nametable(addr) => {
+                if let Some(changes) = &mut self.tile_changes {
+                    changes.nametable_written(cart, addr);
+                }
+                self.write_nametable(cart, addr, data)
             }
-            Mirroring::FourScreen => {
-                nametable
+            PpuTarget::Palette(index) => {
+                self.palette[index] = data;
+                // Backdrop entries are shared between the background and
+                // sprite palettes, $3F10 being $3F00 and so on
+                if index & 0x03 == 0 {
+                    self.palette[index ^ 0x10] = data;
+                }
             }
-        };
-    
-        
-        (mapped_table * 0x400) + offset
+        }
+    }
+
+    // $2000-$2FFF (and the $3000 mirror) go through the cartridge, which
+    // decides whether a nametable slot lives in VRAM or in cartridge memory.
+    fn read_nametable(&mut self, cart: &mut Cartridge, addr: u16) -> u8 {
+        let addr = 0x2000 | (addr & 0x0FFF);
+        let table = (addr >> 10) & 0x3;
+        match cart.nametable_source(table) {
+            NametableSource::Vram(page) => self.vram.read(page * 0x400 + (addr & 0x3FF)),
+            NametableSource::Mapper => cart.read_nametable(addr)
+        }
+    }
+
+    fn write_nametable(&mut self, cart: &mut Cartridge, addr: u16, data: u8) {
+        let addr = 0x2000 | (addr & 0x0FFF);
+        let table = (addr >> 10) & 0x3;
+        match cart.nametable_source(table) {
+            NametableSource::Vram(page) => self.vram.write(page * 0x400 + (addr & 0x3FF), data),
+            NametableSource::Mapper => cart.write_nametable(addr, data)
+        }
     }
     
     
@@ -577,17 +1000,64 @@ impl Ppu {
     }
 
     
+    // RESET clears PPUCTRL, PPUMASK, the scroll/address latch and the read
+    // buffer. PPUSTATUS, OAMADDR and the VRAM address survive.
+    pub fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.t = 0;
+        self.x = 0;
+        self.w = false;
+        self.vram_buffer = 0;
+        self.odd_frame = false;
+    }
+
+    // Power-on clears what reset does, plus the registers reset preserves.
+    pub fn power_on(&mut self) {
+        self.reset();
+        self.status = 0;
+        self.oamaddr = 0;
+        self.v = 0;
+        self.cycle = 0;
+        self.scanline = 0;
+        self.idle_dots = 0;
+    }
+
+    // Power-on garbage: OAM and palette RAM contents are undefined, and
+    // vblank and sprite overflow are often already set.
+    pub fn randomize_power_on(&mut self, rng: &mut Rng) {
+        for sprite in self.oam.iter_mut() {
+            sprite.y = rng.next_u8();
+            sprite.tile = rng.next_u8();
+            sprite.attr = rng.next_u8();
+            sprite.x = rng.next_u8();
+        }
+        for entry in self.palette.iter_mut() {
+            *entry = rng.next_u8() & 0x3F;
+        }
+        self.status = rng.next_u8() & 0xA0;
+    }
+
     pub fn read_oam(&self) -> u8{
         0xFF
     }
 
-    pub fn read_data(&mut self) -> u8{
+    pub fn read_data(&mut self, cart: &mut Cartridge) -> u8{
         let data = if (self.v & 0x3FFF) >= 0x3F00 {
-            
-            self.read(self.v)
+            // Palette reads skip the buffer, which still gets loaded from
+            // the nametable address underneath, $2F00-$2FFF
+            self.vram_buffer = self.read(cart, self.v & 0x2FFF);
+            let color = self.read(cart, self.v);
+            if self.mask & 0x01 != 0 { color & 0x30 } else { color }
         }else{
             let previous_buffer = self.vram_buffer;
-            self.vram_buffer = self.read(self.v);
+            if let Some(cdl) = cart.cdl_mut() {
+                cdl.set_chr_by_cpu(true);
+            }
+            self.vram_buffer = self.read(cart, self.v);
+            if let Some(cdl) = cart.cdl_mut() {
+                cdl.set_chr_by_cpu(false);
+            }
             previous_buffer
         };
 
@@ -597,6 +1067,10 @@ impl Ppu {
 
     pub fn write_ctrl(&mut self, data: u8){
         
+        // Bit 6 picks master or slave mode for the EXT pins, which a stock
+        // console grounds. Master mode drives nothing anything listens to,
+        // and slave mode takes the backdrop's palette index from them, which
+        // reads 0 as usual, so the bit is only stored.
         let old_ctrl = self.ctrl;
         self.ctrl = data;
         if old_ctrl & 0x80 == 0 && self.ctrl & 0x80 == 1 && self.status & 0x80 == 1 {
@@ -654,17 +1128,32 @@ impl Ppu {
         self.w = !self.w;
     }
 
-    pub fn write_data(&mut self, data: u8){
-        self.write(self.v, data);
+    pub fn write_data(&mut self, cart: &mut Cartridge, data: u8){
+        self.write(cart, self.v, data);
         self.increment_vram_addr();
     }
 
     
     fn increment_vram_addr(&mut self){
+        // While rendering, v is the scroll position and a $2007 access bumps
+        // it the way the fetch hardware does: coarse X and Y at once, never
+        // the +1 or +32 from PPUCTRL
+        if self.is_rendering_line() {
+            self.increment_h();
+            self.increment_v();
+            return;
+        }
         let increment = if (self.ctrl & 0x04) != 0 { 32 } else { 1 };
         self.v = (self.v + increment) & 0x7FFF;
     }
 
+    // Visible and pre-render lines with rendering on. Overclocked lines
+    // idle ahead of pre-render, where nothing is fetched.
+    fn is_rendering_line(&self) -> bool {
+        self.is_rendering_enabled() && self.idle_dots == 0
+            && (self.scanline < 240 || self.scanline == NUM_SCANLINES - 1)
+    }
+
     fn is_rendering_enabled(&self) -> bool{
         self.mask & 0x18 != 0
     }
@@ -693,7 +1182,7 @@ impl Ppu {
         if self.ctrl & 0x8 != 0 { 0x1000 } else { 0 }
     }
 
-    fn bg_pattern_table_address(&self) -> u16 {
+    pub(crate) fn bg_pattern_table_address(&self) -> u16 {
         if self.ctrl & 0x10 != 0 { 0x1000 } else { 0 }
     }
 
@@ -762,4 +1251,298 @@ impl Ppu {
         let y_mask = 0x7000 | 0x0800 | 0x03E0;
         self.v = (self.v & !y_mask) | (self.t & y_mask);
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{rom::Rom, test_support::TestRom};
+    use super::*;
+
+    const TOP: u8 = 0x16;
+    const BOTTOM: u8 = 0x2A;
+    const BACKDROP: u8 = 0x0F;
+
+    // NROM with CHR RAM, sprite palette 0 set to TOP for color 1 and BOTTOM
+    // for color 2, and sprites shown in the leftmost column
+    fn console() -> (Ppu, Cartridge) {
+        let mut ppu = Ppu::new();
+        let mut cart = Cartridge::new(Rom::new(TestRom::new(0).chr_8k(0).bytes()));
+        for (addr, color) in [(0x3F00, BACKDROP), (0x3F11, TOP), (0x3F12, BOTTOM)] {
+            ppu.write(&mut cart, addr, color);
+        }
+        ppu.write_mask(0x14);
+        (ppu, cart)
+    }
+
+    // Row r of the tile has only pixel r set, in color 1 or 2
+    fn diagonal_tile(ppu: &mut Ppu, cart: &mut Cartridge, addr: u16, color: u8) {
+        for row in 0..8 {
+            let bits = 0x80 >> row;
+            ppu.write(cart, addr + row, if color & 1 != 0 { bits } else { 0 });
+            ppu.write(cart, addr + row + 8, if color & 2 != 0 { bits } else { 0 });
+        }
+    }
+
+    fn set_sprite(ppu: &mut Ppu, index: u8, bytes: [u8; 4]) {
+        ppu.write_oamaddr(index * 4);
+        for byte in bytes {
+            ppu.write_oamdata(byte);
+        }
+    }
+
+    fn render_frame(ppu: &mut Ppu, cart: &mut Cartridge) {
+        ppu.frame_ready = false;
+        while !ppu.frame_ready {
+            ppu.step(cart);
+        }
+    }
+
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> &[u8] {
+        let idx = (y * 256 + x) * 3;
+        &ppu.frame_buffer[idx..idx + 3]
+    }
+
+    fn rgb(ppu: &Ppu, color: u8) -> &[u8] {
+        &ppu.rgb_palette[color as usize * 3..color as usize * 3 + 3]
+    }
+
+    // A sprite at OAM Y shows from scanline Y + 1. Asserts that sprite row
+    // r, drawn on that line, has the diagonal pixel of `colors[r]`.
+    fn assert_rows(ppu: &Ppu, x: usize, y: usize, colors: &[u8]) {
+        for (row, &color) in colors.iter().enumerate() {
+            let line = y + 1 + row;
+            assert_eq!(pixel(ppu, x + row % 8, line), rgb(ppu, color), "row {}", row);
+            assert_eq!(pixel(ppu, x + (row + 1) % 8, line), rgb(ppu, BACKDROP), "row {}", row);
+        }
+    }
+
+    fn tall_sprite(attr: u8) -> (Ppu, Cartridge) {
+        let (mut ppu, mut cart) = console();
+        diagonal_tile(&mut ppu, &mut cart, 0x1020, 1);
+        diagonal_tile(&mut ppu, &mut cart, 0x1030, 2);
+        ppu.write_ctrl(0x20);
+        // Tile 3 is the pair 2 and 3 from the table at $1000
+        set_sprite(&mut ppu, 0, [40, 0x03, attr, 16]);
+        render_frame(&mut ppu, &mut cart);
+        (ppu, cart)
+    }
+
+    #[test]
+    fn tall_sprites_draw_the_even_tile_on_top() {
+        let (ppu, _) = tall_sprite(0x00);
+        let mut colors = vec![TOP; 8];
+        colors.extend([BOTTOM; 8]);
+        assert_rows(&ppu, 16, 40, &colors);
+    }
+
+    #[test]
+    fn flipped_tall_sprites_swap_tiles_and_rows() {
+        let (ppu, _) = tall_sprite(0x80);
+        // Row 0 is the odd tile's last row, whose pixel is 7
+        for row in 0..16 {
+            let line = 41 + row;
+            let color = if row < 8 { BOTTOM } else { TOP };
+            assert_eq!(pixel(&ppu, 16 + 7 - row % 8, line), rgb(&ppu, color), "row {}", row);
+        }
+        assert_eq!(pixel(&ppu, 16, 57), rgb(&ppu, BACKDROP));
+    }
+
+    #[test]
+    fn short_sprites_use_the_ppuctrl_table() {
+        let (mut ppu, mut cart) = console();
+        diagonal_tile(&mut ppu, &mut cart, 0x0030, 2);
+        diagonal_tile(&mut ppu, &mut cart, 0x1030, 1);
+        set_sprite(&mut ppu, 0, [40, 0x03, 0x00, 16]);
+        ppu.write_ctrl(0x08);
+        render_frame(&mut ppu, &mut cart);
+        assert_rows(&ppu, 16, 40, &[TOP; 8]);
+        assert_eq!(pixel(&ppu, 16, 49), rgb(&ppu, BACKDROP));
+    }
+
+    #[test]
+    fn master_slave_bit_leaves_the_picture_alone() {
+        let (mut ppu, mut cart) = tall_sprite(0x00);
+        let frame = ppu.frame_buffer.clone();
+        ppu.write_ctrl(0x60);
+        render_frame(&mut ppu, &mut cart);
+        assert!(ppu.frame_buffer == frame);
+    }
+
+    #[test]
+    fn finished_scanlines_go_to_the_callback() {
+        let (mut ppu, mut cart) = tall_sprite(0x00);
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = lines.clone();
+        ppu.scanline_callback = Some(Box::new(move |line, pixels| sink.lock().unwrap().push((line, pixels.to_vec()))));
+        render_frame(&mut ppu, &mut cart);
+
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 240);
+        for (i, (line, pixels)) in lines.iter().enumerate() {
+            assert_eq!(*line, i);
+            assert_eq!(pixels[..], ppu.frame_buffer[i * 768..(i + 1) * 768]);
+        }
+        assert_eq!(lines[41].1[16 * 3..17 * 3], *rgb(&ppu, TOP));
+    }
+
+    fn run_to(ppu: &mut Ppu, cart: &mut Cartridge, scanline: usize, dot: usize) {
+        while (ppu.scanline, ppu.cycle) != (scanline, dot) {
+            ppu.step(cart);
+        }
+    }
+
+    // Eight sprites on lines 41-48, then `ninth` and `tenth`
+    fn crowded_line(ninth: [u8; 4], tenth: [u8; 4]) -> (Ppu, Cartridge) {
+        let (mut ppu, mut cart) = console();
+        for i in 0..8 {
+            set_sprite(&mut ppu, i, [40, 0x01, 0x00, i * 16]);
+        }
+        set_sprite(&mut ppu, 8, ninth);
+        set_sprite(&mut ppu, 9, tenth);
+        render_frame(&mut ppu, &mut cart);
+        (ppu, cart)
+    }
+
+    #[test]
+    fn overflow_checks_the_wrong_byte_after_eight() {
+        let (ppu, _) = crowded_line([40, 0x01, 0x00, 200], [0xFF; 4]);
+        assert_ne!(ppu.status & 0x20, 0);
+
+        // Past the ninth, the tenth is checked by its tile number
+        let (ppu, _) = crowded_line([0xFF; 4], [0xFF, 40, 0xFF, 0xFF]);
+        assert_ne!(ppu.status & 0x20, 0);
+        let (ppu, _) = crowded_line([0xFF; 4], [40, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(ppu.status & 0x20, 0);
+    }
+
+    #[test]
+    fn oamaddr_writes_move_evaluation() {
+        let (mut ppu, mut cart) = console();
+        diagonal_tile(&mut ppu, &mut cart, 0x0010, 1);
+        set_sprite(&mut ppu, 0, [40, 0x01, 0x00, 16]);
+        set_sprite(&mut ppu, 1, [40, 0x01, 0x00, 32]);
+        render_frame(&mut ppu, &mut cart);
+
+        // Starting line 40's evaluation at sprite 1 misses sprite 0 for a line
+        run_to(&mut ppu, &mut cart, 40, 64);
+        ppu.write_oamaddr(4);
+        render_frame(&mut ppu, &mut cart);
+        assert_eq!(pixel(&ppu, 16, 41), rgb(&ppu, BACKDROP));
+        assert_eq!(pixel(&ppu, 32, 41), rgb(&ppu, TOP));
+        assert_eq!(pixel(&ppu, 17, 42), rgb(&ppu, TOP));
+        assert_eq!(ppu.oamaddr, 0);
+    }
+
+    #[test]
+    fn backdrop_follows_v_into_the_palette() {
+        let (mut ppu, mut cart) = console();
+        ppu.write(&mut cart, 0x3F05, TOP);
+        ppu.write(&mut cart, 0x3F15, BOTTOM);
+        ppu.write_mask(0x00);
+        for (hi, lo, color) in [(0x3F, 0x05, TOP), (0x3F, 0x15, BOTTOM), (0x7F, 0xE5, TOP), (0x23, 0x05, BACKDROP)] {
+            ppu.write_addr(hi);
+            ppu.write_addr(lo);
+            render_frame(&mut ppu, &mut cart);
+            assert_eq!(pixel(&ppu, 0, 0), rgb(&ppu, color));
+            assert_eq!(pixel(&ppu, 255, 239), rgb(&ppu, color));
+        }
+    }
+
+    #[test]
+    fn data_port_bumps_scroll_while_rendering() {
+        let (mut ppu, mut cart) = console();
+        ppu.write_ctrl(0x04);
+        run_to(&mut ppu, &mut cart, 100, 300);
+        // Coarse X 31 wraps into the next nametable; fine Y 7 carries into
+        // coarse Y
+        ppu.v = 0x7000 | (5 << 5) | 31;
+        ppu.write_data(&mut cart, 0);
+        assert_eq!(ppu.v, 0x0400 | (6 << 5));
+        ppu.read_data(&mut cart);
+        assert_eq!(ppu.v, 0x1400 | (6 << 5) | 1);
+
+        // Vblank, or rendering off, is back to PPUCTRL's +32
+        run_to(&mut ppu, &mut cart, 250, 0);
+        ppu.v = 0x2001;
+        ppu.write_data(&mut cart, 0);
+        assert_eq!(ppu.v, 0x2021);
+        run_to(&mut ppu, &mut cart, 10, 0);
+        ppu.write_mask(0x00);
+        ppu.v = 0x2021;
+        ppu.read_data(&mut cart);
+        assert_eq!(ppu.v, 0x2041);
+    }
+
+    #[test]
+    fn palette_reads_fill_the_buffer_from_below() {
+        let (mut ppu, mut cart) = console();
+        ppu.write_mask(0x00);
+        ppu.write(&mut cart, 0x2F05, 0xAB);
+        ppu.write(&mut cart, 0x3F05, 0x2A);
+        for (hi, lo, value) in [(0x3F, 0x05, 0x2A), (0x20, 0x00, 0xAB)] {
+            ppu.write_addr(hi);
+            ppu.write_addr(lo);
+            assert_eq!(ppu.read_data(&mut cart), value);
+        }
+
+        ppu.write_mask(0x01);
+        ppu.write_addr(0x3F);
+        ppu.write_addr(0x05);
+        assert_eq!(ppu.read_data(&mut cart), 0x20);
+    }
+
+    fn frame_lengths(ppu: &mut Ppu, cart: &mut Cartridge, frames: usize) -> Vec<usize> {
+        render_frame(ppu, cart);
+        (0..frames).map(|_| {
+            let mut dots = 0;
+            ppu.frame_ready = false;
+            while !ppu.frame_ready {
+                ppu.step(cart);
+                dots += 1;
+            }
+            dots
+        }).collect()
+    }
+
+    #[test]
+    fn odd_frames_skip_a_dot_while_rendering() {
+        let (mut ppu, mut cart) = console();
+        ppu.write_mask(0x08);
+        let lengths = frame_lengths(&mut ppu, &mut cart, 4);
+        assert!(lengths == [89341, 89342, 89341, 89342] || lengths == [89342, 89341, 89342, 89341], "{:?}", lengths);
+
+        ppu.write_mask(0x00);
+        assert_eq!(frame_lengths(&mut ppu, &mut cart, 2), [89342; 2]);
+    }
+
+    #[test]
+    fn empty_slots_draw_nothing() {
+        let (mut ppu, mut cart) = console();
+        // Tile $FF, what empty slots fetch
+        for addr in 0x0FF0..0x1000 {
+            ppu.write(&mut cart, addr, 0xFF);
+        }
+        set_sprite(&mut ppu, 0, [40, 0x00, 0x00, 0]);
+        render_frame(&mut ppu, &mut cart);
+        for line in 1..240 {
+            assert_eq!(pixel(&ppu, 255, line), rgb(&ppu, BACKDROP), "line {}", line);
+        }
+    }
+
+    #[test]
+    fn every_bus_address_is_decoded() {
+        let (mut ppu, mut cart) = console();
+        for addr in 0..=0xFFFF {
+            ppu.write(&mut cart, addr, addr as u8);
+            ppu.read_mapped(&mut cart, addr);
+        }
+        // Above $3FFF the address wraps, and the palette repeats every 32
+        // bytes with $3F1C mirroring $3F0C
+        ppu.write(&mut cart, 0x7F1C, 0x2C);
+        assert_eq!([ppu.read_mapped(&mut cart, 0x3FEC), ppu.read_mapped(&mut cart, 0x3F1C)], [0x2C, 0x2C]);
+        ppu.write(&mut cart, 0x6123, 0x55);
+        assert_eq!(ppu.read_mapped(&mut cart, 0x3123), 0x55);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,207 @@
+use std::{fs::{self, File}, io::{self, BufWriter, Write}, path::Path};
+
+use crate::{png, Nes};
+
+// Pictures of what's in PPU memory for a debugger or a bug report: the
+// nametables as the background would draw them, both pattern tables and
+// the palettes. Everything is read through the PPU's address space, so
+// mirroring and mapper banking apply, but only peeked: drawing a table
+// mid-game won't flip a latch or clock an IRQ counter.
+
+// Swatch size in palette_image()
+const SWATCH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PpuImage {
+    pub width: usize,
+    pub height: usize,
+    // R, G and B for each pixel, row by row
+    pub rgb: Vec<u8>,
+}
+
+impl PpuImage {
+    fn new(width: usize, height: usize) -> Self {
+        PpuImage { width, height, rgb: vec![0; width * height * 3] }
+    }
+
+    fn set(&mut self, x: usize, y: usize, rgb: &[u8]) {
+        let offset = (y * self.width + x) * 3;
+        self.rgb[offset..offset + 3].copy_from_slice(rgb);
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let offset = (y * self.width + x) * 3;
+        [self.rgb[offset], self.rgb[offset + 1], self.rgb[offset + 2]]
+    }
+
+    pub fn write_png<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        png::write_rgb(out, self.width as u32, self.height as u32, &self.rgb)
+    }
+}
+
+impl Nes {
+    // Nametable 0-3, 256x240, tiles from the background pattern table
+    // PPUCTRL selects and colored by the attribute table. Scroll is ignored.
+    pub fn nametable_image(&self, index: u16) -> PpuImage {
+        let base = 0x2000 + (index & 3) * 0x400;
+        let pattern = self.cpu.bus.ppu.bg_pattern_table_address();
+        let palettes = self.palette_colors();
+        let mut image = PpuImage::new(256, 240);
+        for tile_y in 0..30 {
+            for tile_x in 0..32 {
+                let tile = self.cpu.bus.ppu_peek(base + tile_y * 32 + tile_x);
+                let attribute = self.cpu.bus.ppu_peek(base + 0x3C0 + (tile_y / 4) * 8 + tile_x / 4);
+                let shift = ((tile_y & 2) << 1) | (tile_x & 2);
+                let palette = ((attribute >> shift) & 3) as usize;
+                self.draw_tile(&mut image, pattern + tile as u16 * 16, tile_x as usize * 8, tile_y as usize * 8, &palettes, palette);
+            }
+        }
+        image
+    }
+
+    // Both pattern tables side by side, 256x128, in palette 0-7 (4-7
+    // being the sprite palettes)
+    pub fn pattern_tables_image(&self, palette: usize) -> PpuImage {
+        let palettes = self.palette_colors();
+        let mut image = PpuImage::new(256, 128);
+        for tile in 0..512u16 {
+            let table = (tile / 256) as usize;
+            let (x, y) = (table * 128 + (tile % 16) as usize * 8, (tile % 256 / 16) as usize * 8);
+            self.draw_tile(&mut image, tile * 16, x, y, &palettes, palette & 7);
+        }
+        image
+    }
+
+    // The 32 palette entries as 16x16 swatches, background on top
+    pub fn palette_image(&self) -> PpuImage {
+        let colors = self.palette_colors();
+        let mut image = PpuImage::new(16 * SWATCH, 2 * SWATCH);
+        for (entry, &color) in colors.iter().enumerate() {
+            let rgb = self.color_rgb(color);
+            for y in 0..SWATCH {
+                for x in 0..SWATCH {
+                    image.set((entry % 16) * SWATCH + x, (entry / 16) * SWATCH + y, &rgb);
+                }
+            }
+        }
+        image
+    }
+
+    // Saves nametable_0.png to nametable_3.png, pattern_tables.png (in
+    // background palette 0) and palette.png into `dir`, creating it if
+    // needed.
+    pub fn dump_nametables_png<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let mut images: Vec<_> = (0..4).map(|index| (format!("nametable_{}.png", index), self.nametable_image(index))).collect();
+        images.push(("pattern_tables.png".to_string(), self.pattern_tables_image(0)));
+        images.push(("palette.png".to_string(), self.palette_image()));
+        for (name, image) in images {
+            let mut file = BufWriter::new(File::create(dir.join(name))?);
+            image.write_png(&mut file)?;
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    // Palette RAM, with the mirrors of the backdrop resolved as the PPU would
+    fn palette_colors(&self) -> [u8; 32] {
+        let mut colors = [0; 32];
+        for (entry, color) in colors.iter_mut().enumerate() {
+            *color = self.cpu.bus.ppu_peek(0x3F00 + entry as u16) & 0x3F;
+        }
+        colors
+    }
+
+    fn color_rgb(&self, color: u8) -> [u8; 3] {
+        let offset = color as usize * 3;
+        let palette = &self.cpu.bus.ppu.rgb_palette;
+        [palette[offset], palette[offset + 1], palette[offset + 2]]
+    }
+
+    // The 8x8 tile at `addr` with its top left at (x, y), in palette 0-7.
+    // Pixel value 0 is the shared backdrop, whatever the palette.
+    fn draw_tile(&self, image: &mut PpuImage, addr: u16, x: usize, y: usize, palettes: &[u8; 32], palette: usize) {
+        for row in 0..8 {
+            let low = self.cpu.bus.ppu_peek(addr + row);
+            let high = self.cpu.bus.ppu_peek(addr + row + 8);
+            for column in 0..8 {
+                let bit = 7 - column;
+                let value = ((low >> bit) & 1) | (((high >> bit) & 1) << 1);
+                let color = if value == 0 { palettes[0] } else { palettes[palette * 4 + value as usize] };
+                image.set(x + column, y + row as usize, &self.color_rgb(color));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::builder::RomBuilder, SystemVersion};
+    use super::*;
+
+    fn console() -> Nes {
+        // Tile 1 is solid color 1, tile 2 solid color 3
+        let mut chr = vec![0; 48];
+        chr[16..24].fill(0xFF);
+        chr[32..48].fill(0xFF);
+        let rom = RomBuilder::new(0).chr(&chr).vectors(0x8000, 0x8000, 0x8000).build().unwrap();
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(rom);
+        // Entry 4 differs from the backdrop, and mustn't show through
+        for (entry, color) in [0x0F, 0x01, 0x02, 0x03, 0x20, 0x11, 0x12, 0x13].into_iter().enumerate() {
+            nes.write_ppu(0x3F00 + entry as u16, color);
+        }
+        nes
+    }
+
+    #[test]
+    fn nametables_use_their_attribute_palettes() {
+        let mut nes = console();
+        // Top left tile in palette 0, the one two tiles right in palette 1
+        nes.write_ppu(0x2400, 1);
+        nes.write_ppu(0x2402, 2);
+        nes.write_ppu(0x27C0, 0b0000_0100);
+
+        let image = nes.nametable_image(1);
+        assert_eq!((image.width, image.height), (256, 240));
+        let rgb = |nes: &Nes, color| nes.color_rgb(color);
+        assert_eq!(image.pixel(3, 3), rgb(&nes, 0x01));
+        assert_eq!(image.pixel(19, 3), rgb(&nes, 0x13));
+        assert_eq!(image.pixel(8, 0), rgb(&nes, 0x0F));
+        assert_eq!(image.pixel(24, 0), rgb(&nes, 0x0F));
+        // Horizontal mirroring puts nametable 1 at 0 too
+        assert_eq!(nes.nametable_image(0), image);
+
+        let patterns = nes.pattern_tables_image(1);
+        assert_eq!((patterns.pixel(8, 0), patterns.pixel(16, 7)), (rgb(&nes, 0x11), rgb(&nes, 0x13)));
+        assert_eq!(patterns.pixel(136, 0), rgb(&nes, 0x0F));
+        let palette = nes.palette_image();
+        assert_eq!((palette.pixel(5 * SWATCH, 0), palette.pixel(SWATCH - 1, SWATCH)), (rgb(&nes, 0x11), rgb(&nes, 0x0F)));
+    }
+
+    #[test]
+    fn drawing_leaves_mapper_latches_alone() {
+        // MMC4 switches CHR banks when $0FD8 or $0FE8 is fetched
+        let rom = RomBuilder::new(10).chr(&[0; 0x2000]).vectors(0x8000, 0x8000, 0x8000).build().unwrap();
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(rom);
+        let before = nes.cpu.bus.cartridge.mapper_debug_info();
+        nes.pattern_tables_image(0);
+        nes.nametable_image(0);
+        assert_eq!(nes.cpu.bus.cartridge.mapper_debug_info(), before);
+    }
+
+    #[test]
+    fn dumps_every_image_as_png() {
+        let dir = std::env::temp_dir().join(format!("nes-ppu-dump-{}", std::process::id()));
+        let nes = console();
+        nes.dump_nametables_png(&dir).unwrap();
+        for name in ["nametable_0.png", "nametable_3.png", "pattern_tables.png", "palette.png"] {
+            let png = fs::read(dir.join(name)).unwrap();
+            assert_eq!(png[1..4], *b"PNG");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
\ No newline at end of file
@@ -0,0 +1,156 @@
+use super::{header::{Mirroring, HEADER_SIZE}, Rom, RomError};
+
+const PRG_BANK: usize = 16 * 1024;
+const CHR_BANK: usize = 8 * 1024;
+
+// Assembles an iNES image in memory, so tests and tools can make runnable
+// cartridges without a file. PRG is padded with $FF to whole 16KB banks,
+// the vectors going in its last 6 bytes, and CHR with zeros to 8KB banks;
+// no CHR at all means 8KB of CHR RAM. Mappers past 255 and submappers need
+// a NES 2.0 header, which is written only then.
+#[derive(Debug, Clone)]
+pub struct RomBuilder {
+    mapper: u16,
+    submapper: u8,
+    mirroring: Mirroring,
+    battery: bool,
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+    vectors: Option<[u16; 3]>,
+}
+
+impl RomBuilder {
+    pub fn new(mapper: u16) -> Self {
+        RomBuilder {
+            mapper,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            battery: false,
+            prg: Vec::new(),
+            chr: Vec::new(),
+            vectors: None,
+        }
+    }
+
+    pub fn submapper(mut self, submapper: u8) -> Self {
+        self.submapper = submapper;
+        self
+    }
+
+    // Only what the header can say: horizontal, vertical or four-screen.
+    // Single-screen boards pick their own.
+    pub fn mirroring(mut self, mirroring: Mirroring) -> Self {
+        self.mirroring = mirroring;
+        self
+    }
+
+    pub fn battery(mut self, battery: bool) -> Self {
+        self.battery = battery;
+        self
+    }
+
+    // From the start of PRG ROM, $8000 on most boards
+    pub fn prg(mut self, prg: &[u8]) -> Self {
+        self.prg = prg.to_vec();
+        self
+    }
+
+    pub fn chr(mut self, chr: &[u8]) -> Self {
+        self.chr = chr.to_vec();
+        self
+    }
+
+    // NMI, reset and IRQ handlers, written over the end of PRG
+    pub fn vectors(mut self, nmi: u16, reset: u16, irq: u16) -> Self {
+        self.vectors = Some([nmi, reset, irq]);
+        self
+    }
+
+    fn nes2(&self) -> bool {
+        self.mapper > 0xFF || self.submapper != 0
+    }
+
+    fn header_bytes(&self, prg_banks: usize, chr_banks: usize) -> Vec<u8> {
+        let mut flags_6 = ((self.mapper & 0x0F) << 4) as u8;
+        flags_6 |= match self.mirroring {
+            Mirroring::Vertical => 0x01,
+            Mirroring::FourScreen => 0x08,
+            _ => 0x00,
+        };
+        if self.battery {
+            flags_6 |= 0x02;
+        }
+        let mut header = vec![0; HEADER_SIZE];
+        header[0..4].copy_from_slice(b"NES\x1A");
+        header[4] = prg_banks as u8;
+        header[5] = chr_banks as u8;
+        header[6] = flags_6;
+        header[7] = (self.mapper & 0xF0) as u8;
+        if self.nes2() {
+            header[7] |= 0x08;
+            header[8] = (self.submapper << 4) | ((self.mapper >> 8) & 0x0F) as u8;
+            header[9] = ((prg_banks >> 8) & 0x0F) as u8 | (((chr_banks >> 8) & 0x0F) << 4) as u8;
+            // 8KB of PRG RAM, battery backed or not, and of CHR RAM without CHR ROM
+            header[10] = if self.battery { 0x70 } else { 0x07 };
+            header[11] = if chr_banks == 0 { 0x07 } else { 0x00 };
+        }
+        header
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let prg_banks = self.prg.len().div_ceil(PRG_BANK).max(1);
+        let chr_banks = self.chr.len().div_ceil(CHR_BANK);
+
+        let mut prg = self.prg.clone();
+        prg.resize(prg_banks * PRG_BANK, 0xFF);
+        if let Some(vectors) = self.vectors {
+            let start = prg.len() - 6;
+            for (i, vector) in vectors.iter().enumerate() {
+                prg[start + i * 2..start + i * 2 + 2].copy_from_slice(&vector.to_le_bytes());
+            }
+        }
+        let mut chr = self.chr.clone();
+        chr.resize(chr_banks * CHR_BANK, 0);
+
+        let mut data = self.header_bytes(prg_banks, chr_banks);
+        data.extend(prg);
+        data.extend(chr);
+        data
+    }
+
+    pub fn build(&self) -> Result<Rom, RomError> {
+        Rom::from_bytes(self.bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::header::{INesVersion, RomHeader}, watch::MemoryRegion, Nes, SystemVersion};
+    use super::*;
+
+    #[test]
+    fn builds_a_runnable_cartridge() {
+        // LDA #$42; STA $10; JMP $8004
+        let program = [0xA9, 0x42, 0x85, 0x10, 0x4C, 0x04, 0x80];
+        let rom = RomBuilder::new(0).prg(&program).vectors(0x8004, 0x8000, 0x8004).mirroring(Mirroring::Vertical).build().unwrap();
+        assert_eq!((rom.header.prg_rom_size, rom.header.chr_rom_size), (16 * 1024, 0));
+        assert_eq!(rom.header.mirroring, Mirroring::Vertical);
+
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(rom);
+        nes.on();
+        nes.run_frame();
+        assert_eq!(nes.memory(MemoryRegion::SystemRam)[0x10], 0x42);
+    }
+
+    #[test]
+    fn pads_banks_and_writes_nes_2_only_when_needed() {
+        let rom = RomBuilder::new(0).chr(&[1; 9 * 1024]).battery(true).build().unwrap();
+        assert_eq!((rom.header.chr_rom_size, rom.header.battery), (16 * 1024, true));
+        assert_eq!(rom.header.nes_version, INesVersion::One);
+
+        let header = RomBuilder::new(0x123).submapper(2).bytes();
+        let header = RomHeader::new(header[..HEADER_SIZE].to_vec());
+        assert_eq!((header.mapper_number, header.submapper, header.chr_ram_size), (0x123, 2, 8 * 1024));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use super::header::{INesVersion, Mirroring, RomHeader, TvSystem};
+
+// What a ROM database knows about one dump, keyed by the CRC32 of its
+// PRG+CHR data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameInfo {
+    pub title: String,
+    pub crc32: u32,
+    pub sha1: Option<[u8; 20]>,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Option<Mirroring>,
+    pub tv: Option<TvSystem>,
+    pub battery: bool,
+    pub prg_ram_size: u32,
+    pub prg_nvram_size: u32,
+    pub chr_ram_size: u32,
+    pub chr_nvram_size: u32,
+}
+
+impl GameInfo {
+    // iNES 1.0 headers are often wrong or can't express the board at all;
+    // NES 2.0 headers are trusted as they are.
+    pub fn correct_header(&self, header: &mut RomHeader) {
+        if header.nes_version == INesVersion::Two {
+            return;
+        }
+        header.mapper_number = self.mapper;
+        header.submapper = self.submapper;
+        if let Some(mirroring) = self.mirroring {
+            header.mirroring = mirroring;
+        }
+        if let Some(tv) = self.tv {
+            header.tv = tv;
+        }
+        header.battery = self.battery;
+        header.prg_ram_size = self.prg_ram_size;
+        header.prg_nvram_size = self.prg_nvram_size;
+        header.chr_ram_size = self.chr_ram_size;
+        header.chr_nvram_size = self.chr_nvram_size;
+    }
+}
+
+#[derive(Default)]
+pub struct RomDatabase {
+    games: HashMap<u32, GameInfo>,
+}
+
+impl RomDatabase {
+    // Reads the <game> entries of an nes20db.xml file. Entries without a
+    // <rom> CRC are skipped; the comment inside each entry is its title.
+    pub fn from_nes20db(xml: &str) -> Self {
+        let mut games = HashMap::new();
+        for block in xml.split("<game>").skip(1) {
+            let block = block.split("</game>").next().unwrap_or("");
+            let Some(crc32) = tag(block, "rom").and_then(|rom| attr(rom, "crc32")).and_then(|crc| u32::from_str_radix(crc, 16).ok()) else {
+                continue;
+            };
+
+            let title = block.split("<!--").nth(1)
+                .and_then(|comment| comment.split("-->").next())
+                .map(|comment| {
+                    let name = comment.trim().rsplit(['/', '\\']).next().unwrap_or("");
+                    name.trim_end_matches(".nes").to_string()
+                })
+                .unwrap_or_default();
+
+            let sha1 = tag(block, "rom").and_then(|rom| attr(rom, "sha1")).and_then(parse_sha1);
+            let pcb = tag(block, "pcb").unwrap_or("");
+            let number = |tag_name: &str, attr_name: &str| {
+                tag(block, tag_name).and_then(|t| attr(t, attr_name)).and_then(|value| value.parse::<u32>().ok())
+            };
+            // Without a mapper the entry can't fix a header, only break it
+            let Some(mapper) = number("pcb", "mapper") else {
+                continue;
+            };
+
+            games.insert(crc32, GameInfo {
+                title,
+                crc32,
+                sha1,
+                mapper: mapper as u16,
+                submapper: number("pcb", "submapper").unwrap_or(0) as u8,
+                mirroring: match attr(pcb, "mirroring") {
+                    Some("H") => Some(Mirroring::Horizontal),
+                    Some("V") => Some(Mirroring::Vertical),
+                    Some("4") => Some(Mirroring::FourScreen),
+                    _ => None
+                },
+                tv: match tag(block, "console").and_then(|console| attr(console, "region")) {
+                    Some("0") => Some(TvSystem::NTSC),
+                    Some("1") => Some(TvSystem::PAL),
+                    Some("2") => Some(TvSystem::DualCompatible),
+                    Some("3") => Some(TvSystem::Dendy),
+                    _ => None
+                },
+                battery: attr(pcb, "battery") == Some("1"),
+                prg_ram_size: number("prgram", "size").unwrap_or(0),
+                prg_nvram_size: number("prgnvram", "size").unwrap_or(0),
+                chr_ram_size: number("chrram", "size").unwrap_or(0),
+                chr_nvram_size: number("chrnvram", "size").unwrap_or(0),
+            });
+        }
+        RomDatabase { games }
+    }
+
+    // A SHA-1 listed in the database has to match too, to rule out CRC32
+    // collisions.
+    pub fn lookup(&self, crc32: u32, sha1: &[u8; 20]) -> Option<&GameInfo> {
+        self.games.get(&crc32).filter(|game| game.sha1.is_none_or(|expected| expected == *sha1))
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+}
+
+// Attribute text of the first `<name .../>` tag in the block
+fn tag<'a>(block: &'a str, name: &str) -> Option<&'a str> {
+    let start = block.find(&format!("<{} ", name))? + name.len() + 2;
+    let end = block[start..].find('>')?;
+    Some(&block[start..start + end])
+}
+
+fn attr<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!("{}=\"", name))? + name.len() + 2;
+    let end = tag[start..].find('"')?;
+    Some(&tag[start..start + end])
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut digest = [0u8; 20];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::{crc32::crc32, header::HEADER_SIZE, Rom}, test_support::TestRom};
+    use super::*;
+
+    fn database_for(data: &[u8], pcb: &str) -> RomDatabase {
+        let xml = format!(
+            "<nes20db><game>\n<!-- roms/Test Game (USA).nes -->\n<rom size=\"0\" crc32=\"{:08X}\"/>\n{}\n<prgram size=\"8192\"/>\n</game></nes20db>",
+            crc32(&data[HEADER_SIZE..]), pcb
+        );
+        RomDatabase::from_nes20db(&xml)
+    }
+
+    #[test]
+    fn corrects_ines_1_header() {
+        let data = TestRom::new(0).chr_8k(0).bytes();
+        let db = database_for(&data, "<pcb mapper=\"66\" submapper=\"0\" mirroring=\"V\" battery=\"1\"/>");
+
+        let rom = Rom::with_database(data, &db).unwrap();
+        assert_eq!(rom.header.mapper_number, 66);
+        assert_eq!(rom.header.mirroring, Mirroring::Vertical);
+        assert!(rom.header.battery);
+        assert_eq!(rom.header.prg_ram_size, 8192);
+        assert_eq!(rom.metadata().title.as_deref(), Some("Test Game (USA)"));
+    }
+
+    #[test]
+    fn patched_images_keep_the_fixes() {
+        let data = TestRom::new(0).chr_8k(0).bytes();
+        let db = database_for(&data, "<pcb mapper=\"66\" mirroring=\"V\"/>");
+        let mut rom = Rom::with_database(data, &db).unwrap();
+        let crc = rom.crc32();
+
+        // One PRG byte, so the CRC32 no longer matches the database
+        let mut patch = b"PATCH".to_vec();
+        patch.extend([0x00, 0x00, 0x10, 0x00, 0x01, 0xEA]);
+        patch.extend(b"EOF");
+        rom.apply_ips(&patch).unwrap();
+        assert_ne!(rom.crc32(), crc);
+        assert_eq!((rom.header.mapper_number, rom.header.mirroring), (66, Mirroring::Vertical));
+        assert_eq!(rom.metadata().title.as_deref(), Some("Test Game (USA)"));
+    }
+
+    #[test]
+    fn corrects_tv_system() {
+        let data = TestRom::new(0).bytes();
+        let db = database_for(&data, "<pcb mapper=\"0\"/>\n<console type=\"0\" region=\"1\"/>");
+        assert_eq!(Rom::with_database(data.clone(), &db).unwrap().header.tv, TvSystem::PAL);
+
+        let db = database_for(&data, "<pcb mapper=\"0\"/>");
+        assert_eq!(Rom::with_database(data, &db).unwrap().header.tv, TvSystem::NTSC);
+    }
+
+    #[test]
+    fn skips_entries_without_a_mapper() {
+        let data = TestRom::new(66).chr_8k(0).bytes();
+        let db = database_for(&data, "<pcb mirroring=\"V\"/>");
+        assert_eq!(db.len(), 0);
+        assert_eq!(Rom::with_database(data, &db).unwrap().header.mapper_number, 66);
+    }
+
+    #[test]
+    fn trusts_nes_2_header() {
+        let data = TestRom::new(0).submapper(0).bytes();
+        let db = database_for(&data, "<pcb mapper=\"66\" mirroring=\"V\"/>");
+
+        let rom = Rom::with_database(data, &db).unwrap();
+        assert_eq!(rom.header.mapper_number, 0);
+        assert_eq!(rom.header.mirroring, Mirroring::Horizontal);
+        assert!(rom.metadata().title.is_some());
+    }
+
+    #[test]
+    fn sha1_mismatch_is_not_a_match() {
+        let data = TestRom::new(0).bytes();
+        let xml = format!(
+            "<game><rom crc32=\"{:08X}\" sha1=\"{}\"/><pcb mapper=\"66\"/></game>",
+            crc32(&data[HEADER_SIZE..]), "00".repeat(20)
+        );
+        let rom = Rom::with_database(data, &RomDatabase::from_nes20db(&xml)).unwrap();
+        assert_eq!(rom.header.mapper_number, 0);
+        assert_eq!(rom.metadata().title, None);
+    }
+}
\ No newline at end of file
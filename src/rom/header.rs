@@ -1,13 +1,21 @@
+use std::{fmt, ops::Range};
+
 pub static HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+// PlayChoice-10 dumps carry the 8KB INST-ROM plus 16 bytes of PROM data and
+// 16 bytes of CounterOut after CHR-ROM.
+const PLAYCHOICE_DATA_SIZE: usize = 8 * 1024 + 32;
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum INesVersion{
     Unknown,
     One,
     Two
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Console{
     NES,
     VsSystem,
@@ -15,15 +23,86 @@ pub enum Console{
     Extended
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Mirroring{
     Vertical,
     Horizontal,
     SingleScreen,
+    SingleScreenUpper,
     FourScreen
 }
 
-#[derive(Debug)]
+// PPU fitted to a VS System board (NES 2.0 byte 13). The RP2C04 revisions
+// each scramble the palette differently.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VsPpu {
+    RP2C03,
+    RP2C04(u8),
+    RC2C05
+}
+
+// Input device a game expects plugged in (NES 2.0 byte 15). Only the ones
+// this emulator can tell apart are named.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExpansionDevice {
+    Unspecified,
+    StandardControllers,
+    Zapper,
+    // The Arkanoid paddle, in its NES and Famicom versions
+    ArkanoidVaus,
+    FamicomArkanoidVaus,
+    FamilyBasicKeyboard,
+    Other(u8),
+}
+
+impl ExpansionDevice {
+    fn from_byte(device: u8) -> Self {
+        match device & 0x3F {
+            0x00 => ExpansionDevice::Unspecified,
+            0x01 => ExpansionDevice::StandardControllers,
+            0x08 => ExpansionDevice::Zapper,
+            0x0F => ExpansionDevice::ArkanoidVaus,
+            0x10 => ExpansionDevice::FamicomArkanoidVaus,
+            0x23 => ExpansionDevice::FamilyBasicKeyboard,
+            other => ExpansionDevice::Other(other),
+        }
+    }
+}
+
+// Signs of a bad dump or an old, carelessly written header. None of them
+// stop the ROM loading.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HeaderWarning {
+    // Byte 7 is from before the iNES format defined it, so the mapper's
+    // upper nibble and everything after it is suspect
+    Archaic,
+    // Bytes 12-15 of an iNES 1.0 header should be zero. Old tools left text
+    // like "DiskDude!" there, which corrupts the mapper number.
+    DirtyPadding,
+    // The file runs on past the PRG and CHR data the header describes
+    TrailingData { bytes: usize },
+    // No emulated board has this number, so it runs on a stand-in
+    // (mappers::fallback) that's unlikely to get far
+    UnsupportedMapper(u16),
+}
+
+impl fmt::Display for HeaderWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderWarning::Archaic => write!(f, "Archaic iNES header, the mapper number may be wrong"),
+            HeaderWarning::DirtyPadding => write!(f, "Header padding isn't zero, the mapper number may be wrong"),
+            HeaderWarning::TrailingData { bytes } => write!(f, "{} bytes past the end of the ROM data", bytes),
+            HeaderWarning::UnsupportedMapper(number) => write!(f, "Mapper {} isn't supported, running it as NROM", number),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TvSystem {
     NTSC,
     PAL,
@@ -31,6 +110,7 @@ pub enum TvSystem {
     Dendy
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RomHeader {
     pub nes_version : INesVersion,
     pub prg_rom_banks: u8,
@@ -46,7 +126,10 @@ pub struct RomHeader {
     pub trainer: bool,
     pub mirroring: Mirroring,
     pub console: Console,
-    pub tv: TvSystem
+    pub vs_ppu: Option<VsPpu>,
+    pub tv: TvSystem,
+    pub expansion_device: ExpansionDevice,
+    pub warnings: Vec<HeaderWarning>,
     //TODO: Add remaining iNES2.0 fields
 }
 
@@ -65,6 +148,13 @@ impl RomHeader {
             nes_version = INesVersion::Two;
         }
 
+        let mut warnings = Vec::new();
+        if nes_version == INesVersion::One && flag_7 & 0x0C == 0x04 {
+            warnings.push(HeaderWarning::Archaic);
+        }else if nes_version == INesVersion::One && data[12..16].iter().any(|&byte| byte != 0) {
+            warnings.push(HeaderWarning::DirtyPadding);
+        }
+
         let battery = flag_6 & 0x02 != 0;
         let trainer = (flag_6 & 0x04) != 0;
 
@@ -73,7 +163,7 @@ impl RomHeader {
             INesVersion::One => {
                 let lower_nibble = (flag_6 >> 4) & 0x0F;
                 let upper_nibble = flag_7 & 0xF0;
-                let mapper = ((upper_nibble << 4) | lower_nibble) as u16;
+                let mapper = (upper_nibble | lower_nibble) as u16;
                 (mapper, 0)
             }
             INesVersion::Two => {
@@ -96,6 +186,16 @@ impl RomHeader {
             _ => Console::NES
         };
 
+        let vs_ppu = match console {
+            Console::VsSystem if nes_version == INesVersion::Two => match data[13] & 0x0F {
+                2..=5 => Some(VsPpu::RP2C04((data[13] & 0x0F) - 1)),
+                8..=0xC => Some(VsPpu::RC2C05),
+                _ => Some(VsPpu::RP2C03)
+            },
+            Console::VsSystem => Some(VsPpu::RP2C03),
+            _ => None
+        };
+
         //TODO: Mirroring is determined by mapper for a few mappers.
         let mirroring = if flag_6 & 0x08 != 0 {
             Mirroring::FourScreen
@@ -126,15 +226,10 @@ impl RomHeader {
             }
             INesVersion::Two => {
                 // Calculate sizes using NES 2.0 format
-                let prg_rom = if data[4] != 0 {
-                    let shift = (data[9] >> 4) & 0xF;
-                    (data[4] as u32) * (1 << (shift as u32))
-                } else { 0 };
+                // Byte 9 holds the upper bits of the PRG (low nibble) and CHR (high nibble) bank counts
+                let prg_rom = nes2_rom_size(data[4], data[9] & 0x0F, 16 * 1024);
     
-                let chr_rom = if data[5] != 0 {
-                    let shift = data[9] & 0xF;
-                    (data[5] as u32) * (1 << (shift as u32))
-                } else { 0 };
+                let chr_rom = nes2_rom_size(data[5], data[9] >> 4, 8 * 1024);
     
                 let prg_ram = if (data[10] & 0xF) != 0 {
                     1 << ((data[10] & 0xF) as u32 + 6)
@@ -186,6 +281,11 @@ impl RomHeader {
             INesVersion::Unknown => TvSystem::DualCompatible
         };
 
+        let expansion_device = match nes_version {
+            INesVersion::Two => ExpansionDevice::from_byte(data[15]),
+            _ => ExpansionDevice::Unspecified
+        };
+
         RomHeader{
             nes_version,
             prg_rom_banks,
@@ -194,6 +294,7 @@ impl RomHeader {
             battery,
             trainer,
             console,
+            vs_ppu,
             mirroring,
             prg_rom_size, 
             prg_ram_size, 
@@ -201,7 +302,130 @@ impl RomHeader {
             chr_rom_size, 
             chr_ram_size, 
             chr_nvram_size,
-            tv
+            tv,
+            expansion_device,
+            warnings
         }
     }
-}
\ No newline at end of file
+
+    // The trainer, when present, sits between the header and PRG-ROM.
+    pub fn prg_rom_range(&self) -> Range<usize> {
+        let start = HEADER_SIZE + if self.trainer { TRAINER_SIZE } else { 0 };
+        start..start + self.prg_rom_size as usize
+    }
+
+    pub fn chr_rom_range(&self) -> Range<usize> {
+        let start = self.prg_rom_range().end;
+        start..start + self.chr_rom_size as usize
+    }
+
+    // Extra arcade data of a PlayChoice-10 dump. The cartridge itself runs
+    // like a regular NES board, so this is skipped rather than emulated.
+    pub fn playchoice_range(&self) -> Option<Range<usize>> {
+        if self.console != Console::Playchoice10 {
+            return None;
+        }
+        let start = self.chr_rom_range().end;
+        Some(start..start + PLAYCHOICE_DATA_SIZE)
+    }
+}
+
+// A 12 bit count of `unit`s, or when the upper nibble is $F, an LSB of
+// EEEEEEMM meaning 2^E * (MM * 2 + 1) bytes. Sizes past 4GB saturate, which
+// no file can satisfy.
+fn nes2_rom_size(lsb: u8, msb: u8, unit: u32) -> u32 {
+    if msb == 0x0F {
+        let multiplier = (lsb as u32 & 0x03) * 2 + 1;
+        1u32.checked_shl((lsb >> 2) as u32)
+            .and_then(|size| size.checked_mul(multiplier))
+            .unwrap_or(u32::MAX)
+    }else{
+        ((msb as u32) << 8 | lsb as u32) * unit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::Rom, test_support::TestRom};
+    use super::*;
+
+    #[test]
+    fn parses_ines_header() {
+        let (header, _) = TestRom::new(66).prg_16k(4).chr_8k(2).vertical().battery().build();
+        assert_eq!(header.nes_version, INesVersion::One);
+        assert_eq!(header.mapper_number, 66);
+        assert_eq!(header.prg_rom_size, 64 * 1024);
+        assert_eq!(header.chr_rom_size, 16 * 1024);
+        assert_eq!(header.mirroring, Mirroring::Vertical);
+        assert!(header.battery);
+    }
+
+    #[test]
+    fn parses_nes2_sizes() {
+        let (header, _) = TestRom::new(1).prg_16k(32).chr_8k(0).submapper(5).prg_ram(32 * 1024).chr_ram(8 * 1024).build();
+        assert_eq!(header.nes_version, INesVersion::Two);
+        assert_eq!(header.submapper, 5);
+        assert_eq!(header.prg_rom_size, 512 * 1024);
+        assert_eq!(header.chr_rom_size, 0);
+        assert_eq!(header.prg_ram_size, 32 * 1024);
+        assert_eq!(header.chr_ram_size, 8 * 1024);
+    }
+
+    #[test]
+    fn parses_nes2_exponent_sizes() {
+        let mut data = TestRom::new(5).submapper(0).header_bytes();
+        // 2^20 * 3 of PRG, 2^23 of CHR
+        data[4] = 20 << 2 | 1;
+        data[5] = 23 << 2;
+        data[9] = 0xFF;
+        let header = RomHeader::new(data.clone());
+        assert_eq!(header.prg_rom_size, 3 * 1024 * 1024);
+        assert_eq!(header.chr_rom_size, 8 * 1024 * 1024);
+
+        data[4] = 63 << 2 | 3;
+        assert_eq!(RomHeader::new(data).prg_rom_size, u32::MAX);
+    }
+
+    #[test]
+    fn loads_exponent_sized_prg() {
+        let mut data = TestRom::new(0).submapper(0).bytes();
+        // The 32KB of PRG written as 2^15 * 1
+        data[4] = 15 << 2;
+        data[9] = 0x0F;
+        let rom = Rom::from_bytes(data).unwrap();
+        assert_eq!(rom.header.prg_rom_size, 0x8000);
+        assert!(rom.warnings().is_empty());
+    }
+
+    #[test]
+    fn parses_expansion_device() {
+        let mut data = TestRom::new(0).submapper(0).header_bytes();
+        assert_eq!(RomHeader::new(data.clone()).expansion_device, ExpansionDevice::Unspecified);
+        data[15] = 0x10;
+        assert_eq!(RomHeader::new(data.clone()).expansion_device, ExpansionDevice::FamicomArkanoidVaus);
+        data[15] = 0x2A;
+        assert_eq!(RomHeader::new(data).expansion_device, ExpansionDevice::Other(0x2A));
+    }
+
+    #[test]
+    fn warns_about_old_headers() {
+        let mut data = TestRom::new(0).header_bytes();
+        assert!(RomHeader::new(data.clone()).warnings.is_empty());
+        data[7..16].copy_from_slice(b"DiskDude!");
+        assert_eq!(RomHeader::new(data.clone()).warnings, [HeaderWarning::Archaic]);
+        data[7] = 0;
+        assert_eq!(RomHeader::new(data).warnings, [HeaderWarning::DirtyPadding]);
+    }
+
+    #[test]
+    fn slices_around_trainer_and_playchoice_data() {
+        let mut data = TestRom::new(0).prg_16k(1).build().1;
+        data[6] |= 0x04;
+        data[7] |= 0x02;
+        let header = RomHeader::new(data[0..HEADER_SIZE].to_vec());
+        assert_eq!(header.console, Console::Playchoice10);
+        assert_eq!(header.prg_rom_range(), 528..528 + 0x4000);
+        assert_eq!(header.chr_rom_range(), 528 + 0x4000..528 + 0x6000);
+        assert_eq!(header.playchoice_range(), Some(528 + 0x6000..528 + 0x8020));
+    }
+}
@@ -1,26 +1,210 @@
-
+pub mod builder;
+pub mod crc32;
+pub mod database;
 pub mod header;
+pub mod patch;
+pub mod sha1;
+
+use std::fmt;
 
-use header::{RomHeader, HEADER_SIZE};
+use database::{GameInfo, RomDatabase};
+use header::{HeaderWarning, INesVersion, RomHeader, HEADER_SIZE};
 
-use crate::mapper::{Mapper, MapperFactory};
+use crate::{mapper::{Mapper, MapperFactory}, mappers::fallback::FallbackMapper};
+
+// Hashes of the PRG+CHR data (everything after the header) and, when the
+// ROM database knows the dump, its title
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RomMetadata {
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+    pub title: Option<String>,
+}
 
+// Why an image couldn't be loaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum RomError {
+    MissingHeader,
+    // The file ends before the PRG/CHR sizes in its header say it should
+    Truncated { expected: usize, actual: usize },
+    UnsupportedMapper(u16),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::MissingHeader => write!(f, "ROM is shorter than its 16 byte header"),
+            RomError::Truncated { expected, actual } => write!(f, "ROM is truncated: header needs {} bytes, file has {}", expected, actual),
+            RomError::UnsupportedMapper(number) => write!(f, "Mapper not supported {}", number),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rom {
     pub header: RomHeader,
-    pub mapper: Box<dyn Mapper>
+    pub mapper: Box<dyn Mapper>,
+    metadata: RomMetadata,
+    // The file as loaded, kept so patches can be applied afterwards. Save
+    // states leave it and the mapper's copies out; see rebind.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    data: Vec<u8>,
+    // The database entry the header was corrected from, which a patched
+    // image no longer matches by hash but still needs
+    #[cfg_attr(feature = "serde", serde(skip))]
+    game: Option<GameInfo>,
 }
 
 impl Rom {
 
+    // For images known to be good; anything user supplied should go through
+    // from_bytes or try_new instead.
     pub fn new(data: Vec<u8>) -> Self {
+        Rom::from_bytes(data).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-        let header = RomHeader::new(data[0..HEADER_SIZE].to_vec());
+    // Takes the header as it is; no database is built in, so header fixes
+    // need one loaded with RomDatabase::from_nes20db and with_database
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, RomError> {
+        Rom::with_database(data, &RomDatabase::default())
+    }
 
-        let mapper = MapperFactory::select(&header, data);
+    // from_bytes for a borrowed image. Malformed input of any kind is an
+    // Err, never a panic; the fuzz targets come in through here.
+    pub fn try_new(data: &[u8]) -> Result<Self, RomError> {
+        Rom::from_bytes(data.to_vec())
+    }
+
+    // Known dumps get their iNES 1.0 header fixed from the database before
+    // the mapper is built
+    pub fn with_database(data: Vec<u8>, database: &RomDatabase) -> Result<Self, RomError> {
+        Rom::parse(data, |crc32, sha1| database.lookup(crc32, sha1).cloned())
+    }
+
+    // `lookup` finds the database entry for the image's CRC32 and SHA-1
+    fn parse(data: Vec<u8>, lookup: impl FnOnce(u32, &[u8; 20]) -> Option<GameInfo>) -> Result<Self, RomError> {
+        if data.len() < HEADER_SIZE {
+            return Err(RomError::MissingHeader);
+        }
+
+        let mut header = RomHeader::new(data[0..HEADER_SIZE].to_vec());
+
+        let crc32 = crc32::crc32(&data[HEADER_SIZE..]);
+        let sha1 = sha1::sha1(&data[HEADER_SIZE..]);
+        let game = lookup(crc32, &sha1);
+        if let Some(game) = &game {
+            game.correct_header(&mut header);
+        }
+        let metadata = RomMetadata { crc32, sha1, title: game.as_ref().map(|game| game.title.clone()) };
+
+        let expected = header.chr_rom_range().end;
+        if data.len() < expected {
+            return Err(RomError::Truncated { expected, actual: data.len() });
+        }
+        // NES 2.0 byte 14 counts miscellaneous ROMs stored after CHR
+        let end = header.playchoice_range().map_or(expected, |range| range.end);
+        let misc_roms = header.nes_version == INesVersion::Two && data[14] & 0x03 != 0;
+        if data.len() > end && !misc_roms {
+            header.warnings.push(HeaderWarning::TrailingData { bytes: data.len() - end });
+        }
+        let mapper = match MapperFactory::select(&header, data.clone()) {
+            Err(RomError::UnsupportedMapper(number)) => {
+                header.warnings.push(HeaderWarning::UnsupportedMapper(number));
+                Box::new(FallbackMapper::new(&header, data.clone()))
+            }
+            mapper => mapper?,
+        };
+        log::info!(target: "mapper", "Mapper {}.{}, {}KB PRG, {}KB CHR", header.mapper_number, header.submapper, header.prg_rom_size / 1024, header.chr_rom_size / 1024);
+        for warning in &header.warnings {
+            log::warn!(target: "mapper", "{}", warning);
+        }
 
-        Rom {
+        Ok(Rom {
             header,
             mapper,
-        }
+            metadata,
+            data,
+            game,
+        })
+    }
+
+    // Soft patching. The patched image is parsed again from scratch (header
+    // and mapper), so patch before inserting the cartridge. Header fixes
+    // from the database the ROM was loaded with still apply.
+    pub fn apply_ips(&mut self, patch: &[u8]) -> Result<(), String> {
+        let patched = patch::apply_ips(&self.data, patch)?;
+        self.reload(patched)
+    }
+
+    pub fn apply_bps(&mut self, patch: &[u8]) -> Result<(), String> {
+        let patched = patch::apply_bps(&self.data, patch)?;
+        self.reload(patched)
+    }
+
+    fn reload(&mut self, data: Vec<u8>) -> Result<(), String> {
+        let game = self.game.clone();
+        *self = Rom::parse(data, |_, _| game).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // Gives a deserialized ROM the image of `loaded`, the same game
+    #[cfg(feature = "serde")]
+    pub(crate) fn rebind(&mut self, loaded: &mut Rom) {
+        self.data = std::mem::take(&mut loaded.data);
+        self.game = loaded.game.take();
+        self.mapper.restore_rom(&self.header, &self.data);
+    }
+
+    pub fn metadata(&self) -> &RomMetadata {
+        &self.metadata
+    }
+
+    // Anything odd about the header or file, for "may be a bad dump" notices
+    pub fn warnings(&self) -> &[HeaderWarning] {
+        &self.header.warnings
+    }
+
+    // CRC32 of everything after the header, so the same dump with a fixed
+    // up header still hashes the same
+    pub fn crc32(&self) -> u32 {
+        self.metadata.crc32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestRom;
+    use super::*;
+
+    #[test]
+    fn bad_images_are_errors() {
+        assert_eq!(Rom::from_bytes(vec![0; 8]).err(), Some(RomError::MissingHeader));
+
+        let mut data = TestRom::new(0).bytes();
+        let full = data.len();
+        data.truncate(full - 1);
+        assert_eq!(Rom::from_bytes(data).err(), Some(RomError::Truncated { expected: full, actual: full - 1 }));
+    }
+
+    #[test]
+    fn unsupported_mappers_fall_back_to_nrom() {
+        let mut rom = Rom::from_bytes(TestRom::new(255).prg_16k(8).chr_8k(2).bytes()).unwrap();
+        assert_eq!(rom.warnings(), [HeaderWarning::UnsupportedMapper(255)]);
+        let mapper = &mut rom.mapper;
+        mapper.write(0x8000, 0x01);
+        assert_eq!([mapper.read(0x8000), mapper.read(0xFFFF), mapper.read(0x1FFF)], [12, 15, 7]);
+        mapper.write(0x6000, 0x42);
+        assert_eq!(mapper.read(0x6000), 0x42);
+    }
+
+    #[test]
+    fn extra_data_is_a_warning() {
+        assert!(Rom::new(TestRom::new(0).bytes()).warnings().is_empty());
+        let mut data = TestRom::new(0).bytes();
+        data.extend([0xFF; 16]);
+        assert_eq!(Rom::new(data).warnings(), [HeaderWarning::TrailingData { bytes: 16 }]);
     }
 }
\ No newline at end of file
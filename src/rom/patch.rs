@@ -0,0 +1,213 @@
+use super::crc32::crc32;
+
+// Soft patching: both formats patch the whole .nes file, header included,
+// and return the patched copy.
+
+pub fn apply_ips(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(b"PATCH") {
+        return Err("Not an IPS patch".to_string());
+    }
+
+    let mut output = data.to_vec();
+    let mut pos = 5;
+    let take = |pos: &mut usize, len: usize| -> Result<&[u8], String> {
+        let bytes = patch.get(*pos..*pos + len).ok_or("IPS patch is truncated")?;
+        *pos += len;
+        Ok(bytes)
+    };
+
+    loop {
+        let offset = take(&mut pos, 3)?;
+        if offset == b"EOF" {
+            break;
+        }
+        let offset = (offset[0] as usize) << 16 | (offset[1] as usize) << 8 | offset[2] as usize;
+        let size = take(&mut pos, 2)?;
+        let size = (size[0] as usize) << 8 | size[1] as usize;
+
+        // A zero size marks a run of one repeated byte
+        let bytes = if size == 0 {
+            let run = take(&mut pos, 3)?;
+            vec![run[2]; (run[0] as usize) << 8 | run[1] as usize]
+        } else {
+            take(&mut pos, size)?.to_vec()
+        };
+
+        if output.len() < offset + bytes.len() {
+            output.resize(offset + bytes.len(), 0);
+        }
+        output[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+
+    // Optional truncation extension
+    if let Ok(length) = take(&mut pos, 3) {
+        output.truncate((length[0] as usize) << 16 | (length[1] as usize) << 8 | length[2] as usize);
+    }
+    Ok(output)
+}
+
+pub fn apply_bps(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(b"BPS1") || patch.len() < 16 {
+        return Err("Not a BPS patch".to_string());
+    }
+    let footer = patch.len() - 12;
+    let checksum = |at: usize| u32::from_le_bytes([patch[at], patch[at + 1], patch[at + 2], patch[at + 3]]);
+    if crc32(&patch[..footer + 8]) != checksum(footer + 8) {
+        return Err("BPS patch is corrupt".to_string());
+    }
+    if crc32(data) != checksum(footer) {
+        return Err("BPS patch is for a different ROM".to_string());
+    }
+
+    let mut decoder = BpsDecoder { patch: &patch[..footer], pos: 4, source_offset: 0, target_offset: 0 };
+    let source_size = decoder.number()?;
+    let target_size = decoder.number()?;
+    let metadata_size = decoder.number()?;
+    if source_size != data.len() {
+        return Err("BPS patch is for a different ROM".to_string());
+    }
+    decoder.pos += metadata_size;
+
+    let mut output = Vec::new();
+    while decoder.pos < footer {
+        decoder.action(data, &mut output)?;
+    }
+
+    if output.len() != target_size || crc32(&output) != checksum(footer + 4) {
+        return Err("BPS patch produced the wrong output".to_string());
+    }
+    Ok(output)
+}
+
+struct BpsDecoder<'a> {
+    patch: &'a [u8],
+    pos: usize,
+    source_offset: usize,
+    target_offset: usize,
+}
+
+impl BpsDecoder<'_> {
+    fn number(&mut self) -> Result<usize, String> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = *self.patch.get(self.pos).ok_or("BPS patch is truncated")?;
+            self.pos += 1;
+            value += (byte & 0x7F) as usize * shift;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift <<= 7;
+            value += shift;
+        }
+    }
+
+    // Relative offsets are stored as magnitude << 1 | sign
+    fn relative(&mut self, offset: usize) -> Result<usize, String> {
+        let value = self.number()?;
+        let delta = value >> 1;
+        let result = if value & 1 != 0 { offset.checked_sub(delta) } else { offset.checked_add(delta) };
+        result.ok_or_else(|| "BPS copy offset out of range".to_string())
+    }
+
+    fn action(&mut self, source: &[u8], output: &mut Vec<u8>) -> Result<(), String> {
+        let command = self.number()?;
+        let length = (command >> 2) + 1;
+        let out_of_range = || "BPS copy out of range".to_string();
+        match command & 3 {
+            // SourceRead: same bytes as the source at this position
+            0 => {
+                let start = output.len();
+                output.extend_from_slice(source.get(start..start + length).ok_or_else(out_of_range)?);
+            }
+            // TargetRead: literal bytes from the patch
+            1 => {
+                let bytes = self.patch.get(self.pos..self.pos + length).ok_or_else(out_of_range)?;
+                output.extend_from_slice(bytes);
+                self.pos += length;
+            }
+            // SourceCopy: bytes from anywhere in the source
+            2 => {
+                self.source_offset = self.relative(self.source_offset)?;
+                let bytes = source.get(self.source_offset..self.source_offset + length).ok_or_else(out_of_range)?;
+                output.extend_from_slice(bytes);
+                self.source_offset += length;
+            }
+            // TargetCopy: bytes already written, one at a time since the
+            // ranges may overlap
+            _ => {
+                self.target_offset = self.relative(self.target_offset)?;
+                for _ in 0..length {
+                    let byte = *output.get(self.target_offset).ok_or_else(out_of_range)?;
+                    output.push(byte);
+                    self.target_offset += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::Rom, test_support::TestRom};
+    use super::*;
+
+    #[test]
+    fn ips_records_runs_and_truncation() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend([0x00, 0x00, 0x01, 0x00, 0x02, b'X', b'Y']);
+        patch.extend([0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x03, b'Z']);
+        patch.extend(b"EOF");
+        assert_eq!(apply_ips(b"abcdef", &patch).unwrap(), b"aXYdeZZZ");
+
+        patch.extend([0x00, 0x00, 0x04]);
+        assert_eq!(apply_ips(b"abcdef", &patch).unwrap(), b"aXYd");
+
+        assert!(apply_ips(b"abc", b"PATCH\x00\x00").is_err());
+    }
+
+    fn bps(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        patch.extend([0x80 | source.len() as u8, 0x80 | target.len() as u8, 0x80]);
+        patch.extend(actions);
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn bps_reads_and_copies() {
+        // SourceRead 3, TargetRead 1 "d"
+        let patch = bps(b"abc", b"abcd", &[0x88, 0x81, b'd']);
+        assert_eq!(apply_bps(b"abc", &patch).unwrap(), b"abcd");
+
+        // TargetRead "a", then an overlapping TargetCopy of 3 from offset 0,
+        // then SourceCopy 2 from offset 1
+        let patch = bps(b"abc", b"aaaabc", &[0x81, b'a', 0x8B, 0x80, 0x86, 0x82]);
+        assert_eq!(apply_bps(b"abc", &patch).unwrap(), b"aaaabc");
+    }
+
+    #[test]
+    fn bps_rejects_wrong_source() {
+        let patch = bps(b"abc", b"abcd", &[0x88, 0x81, b'd']);
+        assert!(apply_bps(b"xyz", &patch).is_err());
+    }
+
+    #[test]
+    fn patching_rebuilds_the_rom() {
+        let mut rom = Rom::new(TestRom::new(0).bytes());
+        let crc = rom.crc32();
+
+        // Flags 6 and 7 for mapper 66
+        let mut patch = b"PATCH".to_vec();
+        patch.extend([0x00, 0x00, 0x06, 0x00, 0x02, 0x20, 0x40]);
+        patch.extend(b"EOF");
+        rom.apply_ips(&patch).unwrap();
+        assert_eq!(rom.header.mapper_number, 66);
+        assert_eq!(rom.crc32(), crc);
+
+        assert!(rom.apply_ips(b"PATCH\x00\x00\x00\x00\x00").is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,132 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{de::Error, Deserialize, Serialize};
+
+use crate::{ppu_viewer::PpuImage, Nes};
+
+// A saved state is the whole console plus a small picture of the screen at
+// the time, so a frontend can show what's in each slot without loading it.
+// The thumbnail and timestamp come first and can be read on their own with
+// state_preview().
+
+// Thumbnails are the frame shrunk by this much each way: 64x60
+const THUMBNAIL_SCALE: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatePreview {
+    pub thumbnail: PpuImage,
+    // Seconds since the Unix epoch, 0 if the clock was unreadable
+    pub saved_at: u64,
+}
+
+#[derive(Serialize)]
+struct SaveStateRef<'a> {
+    #[serde(flatten)]
+    preview: StatePreview,
+    console: &'a Nes,
+}
+
+#[derive(Deserialize)]
+struct SaveState {
+    console: Nes,
+}
+
+impl Nes {
+    // The last frame, each pixel the average of a THUMBNAIL_SCALE square
+    pub fn thumbnail(&self) -> PpuImage {
+        let (width, height) = (256 / THUMBNAIL_SCALE, 240 / THUMBNAIL_SCALE);
+        let frame = &self.cpu.bus.ppu.frame_buffer;
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0u32; 3];
+                for dy in 0..THUMBNAIL_SCALE {
+                    for dx in 0..THUMBNAIL_SCALE {
+                        let offset = ((y * THUMBNAIL_SCALE + dy) * 256 + x * THUMBNAIL_SCALE + dx) * 3;
+                        for (total, &channel) in sum.iter_mut().zip(&frame[offset..offset + 3]) {
+                            *total += channel as u32;
+                        }
+                    }
+                }
+                rgb.extend(sum.map(|total| (total / (THUMBNAIL_SCALE * THUMBNAIL_SCALE) as u32) as u8));
+            }
+        }
+        PpuImage { width, height, rgb }
+    }
+
+    pub fn save_state(&self) -> serde_json::Result<Vec<u8>> {
+        let saved_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        let preview = StatePreview { thumbnail: self.thumbnail(), saved_at };
+        serde_json::to_vec(&SaveStateRef { preview, console: self })
+    }
+
+    // Replaces the console with a saved one through restore(), leaving it
+    // as it was if the data isn't a saved state of the inserted game
+    pub fn load_state(&mut self, data: &[u8]) -> serde_json::Result<()> {
+        let state: SaveState = serde_json::from_slice(data)?;
+        self.restore(state.console).map_err(serde_json::Error::custom)
+    }
+}
+
+// The thumbnail and timestamp of a saved state, skipping over the console
+pub fn state_preview(data: &[u8]) -> serde_json::Result<StatePreview> {
+    serde_json::from_slice(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{apu::Channel, cpu::breakpoint::Breakpoint, rom::builder::RomBuilder, test_support::console, watch::MemoryRegion, SystemVersion};
+    use super::*;
+
+    #[test]
+    fn states_carry_a_thumbnail_of_the_frame() {
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(RomBuilder::new(0).vectors(0x8000, 0x8000, 0x8000).build().unwrap());
+        for (i, byte) in nes.cpu.bus.ppu.frame_buffer.iter_mut().enumerate() {
+            // Alternate columns of 0 and 200 in red, so boxes average to 100
+            *byte = if i % 3 == 0 && (i / 3) & 1 == 1 { 200 } else { 0 };
+        }
+        nes.cpu.bus.ppu.frame_buffer[(17 * 256 + 9) * 3 + 1] = 160;
+
+        let state = nes.save_state().unwrap();
+        let preview = state_preview(&state).unwrap();
+        assert_eq!((preview.thumbnail.width, preview.thumbnail.height), (64, 60));
+        assert_eq!(preview.thumbnail.pixel(0, 0), [100, 0, 0]);
+        assert_eq!(preview.thumbnail.pixel(2, 4), [100, 10, 0]);
+        assert!(preview.saved_at > 0);
+
+        let cycles = nes.total_cycles();
+        nes.run_frame();
+        nes.load_state(&state).unwrap();
+        assert_eq!(nes.total_cycles(), cycles);
+        assert!(nes.load_state(b"{}").is_err());
+        assert_eq!(nes.total_cycles(), cycles);
+    }
+
+    #[test]
+    fn loading_keeps_the_debugging_attachments() {
+        let mut nes = console();
+        nes.run_frame();
+        let state = nes.save_state().unwrap();
+
+        nes.add_breakpoint(Breakpoint::Execute(0x1234));
+        nes.watch_memory(MemoryRegion::SystemRam, 0..16);
+        nes.record_history(16);
+        nes.set_layer_visibility(false, true);
+        nes.set_channel_enabled(Channel::Noise, false);
+        nes.run_frame();
+        nes.load_state(&state).unwrap();
+
+        assert_eq!(nes.breakpoints(), [Breakpoint::Execute(0x1234)]);
+        assert_eq!(nes.watches.len(), 1);
+        assert_eq!(nes.history().unwrap().len(), 16);
+        assert_eq!(nes.layer_visibility(), (false, true));
+        assert!(!nes.channel_enabled(Channel::Noise));
+
+        // Nor does a state saved muted mute anything
+        let muted = nes.save_state().unwrap();
+        nes.set_channel_enabled(Channel::Noise, true);
+        nes.load_state(&muted).unwrap();
+        assert!(nes.channel_enabled(Channel::Noise));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,144 @@
+const VISIBLE_LINES: usize = 240;
+
+// The PPU's scroll registers as a scanline started drawing: v holds the
+// coarse and fine scroll in use, t what $2005/$2006 last loaded, and x the
+// fine X scroll.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScrollLine {
+    pub v: u16,
+    pub t: u16,
+    pub x: u8,
+    // With rendering off v isn't stepped, so there's no scroll to speak of
+    pub rendering: bool,
+}
+
+impl ScrollLine {
+    // 0-511 across the two horizontally adjacent nametables
+    pub fn scroll_x(&self) -> u16 {
+        (self.v & 0x400) >> 2 | (self.v & 0x1F) << 3 | self.x as u16
+    }
+
+    // 0-479 down the two vertically adjacent nametables. Coarse Y 30 and
+    // 31 (the attribute rows) come out as 240-255 within a nametable.
+    pub fn scroll_y(&self) -> u16 {
+        (self.v & 0x800) / 0x800 * 240 + ((self.v & 0x3E0) >> 2 | (self.v >> 12) & 7)
+    }
+
+    pub fn nametable(&self) -> u16 {
+        (self.v >> 10) & 3
+    }
+}
+
+// Where the scroll moves by the Y increment alone, the same as the PPU
+// applies at dot 256
+pub(crate) fn next_line(v: u16) -> u16 {
+    if v & 0x7000 != 0x7000 {
+        return v + 0x1000;
+    }
+    let v = v & !0x7000;
+    match (v & 0x3E0) >> 5 {
+        29 => (v & !0x3E0) ^ 0x800,
+        31 => v & !0x3E0,
+        y => (v & !0x3E0) | (y + 1) << 5,
+    }
+}
+
+// Effective scroll for every visible scanline, for showing where a game
+// splits the screen (status bars, parallax). Line 0 is taken at dot 304 of
+// the pre-render line, after the vertical copy from t; every other line at
+// dot 257 of the one before, after the horizontal copy.
+pub struct ScrollCapture {
+    current: Vec<ScrollLine>,
+    lines: Vec<ScrollLine>,
+}
+
+impl Default for ScrollCapture {
+    fn default() -> Self {
+        ScrollCapture::new()
+    }
+}
+
+impl ScrollCapture {
+    pub fn new() -> Self {
+        ScrollCapture {
+            current: vec![ScrollLine::default(); VISIBLE_LINES],
+            lines: Vec::new(),
+        }
+    }
+
+    // The last completed frame, empty until one has finished
+    pub fn lines(&self) -> &[ScrollLine] {
+        &self.lines
+    }
+
+    // Scanlines whose scroll isn't just the previous line's moved down one,
+    // meaning the game rewrote the scroll or address registers before them
+    pub fn splits(&self) -> Vec<usize> {
+        (1..self.lines.len())
+            .filter(|&line| {
+                let (previous, current) = (self.lines[line - 1], self.lines[line]);
+                let horizontal = 0x041F;
+                previous.rendering && current.rendering
+                    && (current.v & horizontal != previous.v & horizontal || current.x != previous.x
+                        || current.v & !horizontal != next_line(previous.v) & !horizontal)
+            })
+            .collect()
+    }
+
+    pub(crate) fn record(&mut self, scanline: usize, line: ScrollLine) {
+        if let Some(entry) = self.current.get_mut(scanline) {
+            *entry = line;
+        }
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.lines.clone_from(&self.current);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::console, Nes};
+
+    fn set_scroll(nes: &mut Nes, x: u8, y: u8) {
+        nes.cpu.bus.read(0x2002);
+        nes.cpu.bus.write(0x2005, x);
+        nes.cpu.bus.write(0x2005, y);
+    }
+
+    #[test]
+    fn captures_scroll_and_mid_frame_splits() {
+        let mut nes = console();
+        nes.capture_scroll(true);
+        // Past the PPU's warm-up, when register writes are ignored. It
+        // finishes a frame, which run_frame() would otherwise stop at.
+        while nes.cpu.bus.cycles < 30_000 {
+            nes.step();
+        }
+        nes.poll_frame();
+        nes.cpu.bus.write(0x2001, 0x08);
+        set_scroll(&mut nes, 5, 16);
+        nes.run_frame();
+        nes.run_frame();
+
+        let capture = nes.scroll_capture().unwrap();
+        assert_eq!(capture.lines().len(), 240);
+        assert_eq!((capture.lines()[0].scroll_x(), capture.lines()[0].scroll_y()), (5, 16));
+        // Carries on into the nametable below
+        assert_eq!(capture.lines()[224].scroll_y(), 240);
+        assert_eq!(capture.lines()[224].nametable(), 2);
+        assert_eq!(capture.lines()[239].scroll_y(), 255);
+        assert!(capture.splits().is_empty());
+
+        // A status bar style split: new X scroll from line 101 on
+        while nes.cpu.bus.ppu.scanline != 100 {
+            nes.step();
+        }
+        set_scroll(&mut nes, 40, 0);
+        nes.run_frame();
+        let capture = nes.scroll_capture().unwrap();
+        assert_eq!(capture.lines()[100].scroll_x(), 5);
+        assert_eq!(capture.lines()[101].scroll_x(), 40);
+        assert_eq!(capture.splits(), [101]);
+    }
+}
\ No newline at end of file
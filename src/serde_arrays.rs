@@ -0,0 +1,29 @@
+// Serde only covers arrays up to 32 elements. Bigger ones (OAM, palettes,
+// the frame buffer) go through these as plain sequences, e.g.
+// #[serde(with = "crate::serde_arrays")].
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+pub fn serialize<S: Serializer, T: Serialize, const N: usize>(array: &[T; N], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(array)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>, const N: usize>(deserializer: D) -> Result<[T; N], D::Error> {
+    let items = Vec::<T>::deserialize(deserializer)?;
+    let len = items.len();
+    items.try_into().map_err(|_| D::Error::invalid_length(len, &format!("{} elements", N).as_str()))
+}
+
+// Same for arrays kept on the heap
+pub mod boxed {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: Serialize, const N: usize>(array: &[T; N], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(array)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>, const N: usize>(deserializer: D) -> Result<Box<[T; N]>, D::Error> {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        let len = items.len();
+        items.into_boxed_slice().try_into().map_err(|_| D::Error::invalid_length(len, &format!("{} elements", N).as_str()))
+    }
+}
\ No newline at end of file
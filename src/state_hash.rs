@@ -0,0 +1,460 @@
+use crate::{watch::MemoryRegion, Nes};
+
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+// 64-bit FNV-1a over explicitly ordered little-endian fields, so a hash
+// means the same thing on every build and platform (std's hashers promise
+// neither)
+pub(crate) struct StateHasher(u64);
+
+impl StateHasher {
+    pub fn new() -> Self {
+        StateHasher(FNV_OFFSET)
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+        self
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[value])
+    }
+
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// Checksums of each part of the console, cheap enough to take every frame.
+// Two consoles in the same state hash the same, and when they don't the
+// differing parts say where to look.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StateHashes {
+    // Registers, flags and cycle count
+    pub cpu: u64,
+    // The 2KB of system RAM
+    pub ram: u64,
+    // Registers, scroll, timing and frame count
+    pub ppu: u64,
+    // Nametable and palette RAM
+    pub vram: u64,
+    pub oam: u64,
+    // Banks, mirroring, registers and cartridge RAM, CHR-RAM included. With
+    // serde, everything else the board saves in a state too.
+    pub mapper: u64,
+}
+
+impl StateHashes {
+    const NAMES: [&'static str; 6] = ["cpu", "ram", "ppu", "vram", "oam", "mapper"];
+
+    fn values(&self) -> [u64; 6] {
+        [self.cpu, self.ram, self.ppu, self.vram, self.oam, self.mapper]
+    }
+
+    // All of them in one
+    pub fn combined(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        for value in self.values() {
+            hasher.u64(value);
+        }
+        hasher.finish()
+    }
+
+    // Names of the parts that differ from `other`
+    pub fn differences(&self, other: &StateHashes) -> Vec<&'static str> {
+        Self::NAMES.iter().zip(self.values().into_iter().zip(other.values()))
+            .filter(|(_, (a, b))| a != b)
+            .map(|(&name, _)| name)
+            .collect()
+    }
+}
+
+impl Nes {
+    // Compares consoles without serializing them. The APU, controllers and
+    // expansion devices aren't covered; audit::LockstepAudit checks all of it.
+    pub fn state_hash(&self) -> u64 {
+        self.state_hashes().combined()
+    }
+
+    pub fn state_hashes(&self) -> StateHashes {
+        let cpu = &self.cpu;
+        let hash = |fill: &dyn Fn(&mut StateHasher)| {
+            let mut hasher = StateHasher::new();
+            fill(&mut hasher);
+            hasher.finish()
+        };
+        StateHashes {
+            cpu: hash(&|h| {
+                h.u8(cpu.a).u8(cpu.x).u8(cpu.y).u8(cpu.sp).u8(cpu.p).u16(cpu.pc).u8(cpu.jammed as u8).u64(cpu.bus.cycles);
+            }),
+            ram: hash(&|h| {
+                h.bytes(self.memory(MemoryRegion::SystemRam));
+            }),
+            ppu: hash(&|h| cpu.bus.ppu.hash_registers(h)),
+            vram: hash(&|h| cpu.bus.ppu.hash_vram(h)),
+            oam: hash(&|h| {
+                h.bytes(&cpu.bus.ppu.oam_bytes());
+            }),
+            mapper: hash(&|h| {
+                let info = cpu.bus.cartridge.mapper_debug_info();
+                for window in info.prg_banks.iter().chain(&info.chr_banks) {
+                    h.u16(window.addr).u32(window.size).u32(window.bank);
+                }
+                h.bytes(format!("{:?}", info.mirroring).as_bytes());
+                for (name, value) in &info.registers {
+                    h.bytes(name.as_bytes()).u32(*value);
+                }
+                h.bytes(cpu.bus.cartridge.prg_ram());
+                h.bytes(cpu.bus.cartridge.chr_ram());
+                // IRQ counters, latches and the like that debug_state()
+                // doesn't show
+                #[cfg(feature = "serde")]
+                serde::Serialize::serialize(&cpu.bus.cartridge.mapper_state(), h).expect("mapper state hashes");
+            }),
+        }
+    }
+}
+
+// Hashes anything serializable by its values in order. Field names and
+// layout are the same for every value of a type, so they're left out.
+#[cfg(feature = "serde")]
+mod serialize {
+    use std::fmt;
+
+    use serde::ser::{self, Serialize};
+
+    use super::StateHasher;
+
+    #[derive(Debug)]
+    pub struct Unhashable;
+
+    impl fmt::Display for Unhashable {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("value can't be hashed")
+        }
+    }
+
+    impl std::error::Error for Unhashable {}
+
+    impl ser::Error for Unhashable {
+        fn custom<T: fmt::Display>(_msg: T) -> Self {
+            Unhashable
+        }
+    }
+
+    type Done = Result<(), Unhashable>;
+
+    impl ser::Serializer for &mut StateHasher {
+        type Ok = ();
+        type Error = Unhashable;
+        type SerializeSeq = Self;
+        type SerializeTuple = Self;
+        type SerializeTupleStruct = Self;
+        type SerializeTupleVariant = Self;
+        type SerializeMap = Self;
+        type SerializeStruct = Self;
+        type SerializeStructVariant = Self;
+
+        fn serialize_bool(self, v: bool) -> Done {
+            self.u8(v as u8);
+            Ok(())
+        }
+
+        fn serialize_i8(self, v: i8) -> Done {
+            self.u8(v as u8);
+            Ok(())
+        }
+
+        fn serialize_i16(self, v: i16) -> Done {
+            self.u16(v as u16);
+            Ok(())
+        }
+
+        fn serialize_i32(self, v: i32) -> Done {
+            self.u32(v as u32);
+            Ok(())
+        }
+
+        fn serialize_i64(self, v: i64) -> Done {
+            self.u64(v as u64);
+            Ok(())
+        }
+
+        fn serialize_u8(self, v: u8) -> Done {
+            self.u8(v);
+            Ok(())
+        }
+
+        fn serialize_u16(self, v: u16) -> Done {
+            self.u16(v);
+            Ok(())
+        }
+
+        fn serialize_u32(self, v: u32) -> Done {
+            self.u32(v);
+            Ok(())
+        }
+
+        fn serialize_u64(self, v: u64) -> Done {
+            self.u64(v);
+            Ok(())
+        }
+
+        fn serialize_f32(self, v: f32) -> Done {
+            self.u32(v.to_bits());
+            Ok(())
+        }
+
+        fn serialize_f64(self, v: f64) -> Done {
+            self.u64(v.to_bits());
+            Ok(())
+        }
+
+        fn serialize_char(self, v: char) -> Done {
+            self.u32(v as u32);
+            Ok(())
+        }
+
+        // Lengths first, so neighbouring strings can't run into each other
+        fn serialize_str(self, v: &str) -> Done {
+            self.serialize_bytes(v.as_bytes())
+        }
+
+        fn serialize_bytes(self, v: &[u8]) -> Done {
+            self.u64(v.len() as u64).bytes(v);
+            Ok(())
+        }
+
+        fn serialize_none(self) -> Done {
+            self.u8(0);
+            Ok(())
+        }
+
+        fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Done {
+            self.u8(1);
+            value.serialize(self)
+        }
+
+        fn serialize_unit(self) -> Done {
+            Ok(())
+        }
+
+        fn serialize_unit_struct(self, _name: &'static str) -> Done {
+            Ok(())
+        }
+
+        fn serialize_unit_variant(self, _name: &'static str, index: u32, _variant: &'static str) -> Done {
+            self.u32(index);
+            Ok(())
+        }
+
+        fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Done {
+            value.serialize(self)
+        }
+
+        fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _name: &'static str, index: u32, _variant: &'static str, value: &T) -> Done {
+            self.u32(index);
+            value.serialize(self)
+        }
+
+        fn serialize_seq(self, len: Option<usize>) -> Result<Self, Unhashable> {
+            self.u64(len.unwrap_or(0) as u64);
+            Ok(self)
+        }
+
+        fn serialize_tuple(self, _len: usize) -> Result<Self, Unhashable> {
+            Ok(self)
+        }
+
+        fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, Unhashable> {
+            Ok(self)
+        }
+
+        fn serialize_tuple_variant(self, _name: &'static str, index: u32, _variant: &'static str, _len: usize) -> Result<Self, Unhashable> {
+            self.u32(index);
+            Ok(self)
+        }
+
+        fn serialize_map(self, len: Option<usize>) -> Result<Self, Unhashable> {
+            self.serialize_seq(len)
+        }
+
+        fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, Unhashable> {
+            Ok(self)
+        }
+
+        fn serialize_struct_variant(self, _name: &'static str, index: u32, _variant: &'static str, _len: usize) -> Result<Self, Unhashable> {
+            self.u32(index);
+            Ok(self)
+        }
+    }
+
+    impl ser::SerializeSeq for &mut StateHasher {
+        type Ok = ();
+        type Error = Unhashable;
+
+        fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Done {
+            value.serialize(&mut **self)
+        }
+
+        fn end(self) -> Done {
+            Ok(())
+        }
+    }
+
+    impl ser::SerializeTuple for &mut StateHasher {
+        type Ok = ();
+        type Error = Unhashable;
+
+        fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Done {
+            value.serialize(&mut **self)
+        }
+
+        fn end(self) -> Done {
+            Ok(())
+        }
+    }
+
+    impl ser::SerializeTupleStruct for &mut StateHasher {
+        type Ok = ();
+        type Error = Unhashable;
+
+        fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Done {
+            value.serialize(&mut **self)
+        }
+
+        fn end(self) -> Done {
+            Ok(())
+        }
+    }
+
+    impl ser::SerializeTupleVariant for &mut StateHasher {
+        type Ok = ();
+        type Error = Unhashable;
+
+        fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Done {
+            value.serialize(&mut **self)
+        }
+
+        fn end(self) -> Done {
+            Ok(())
+        }
+    }
+
+    impl ser::SerializeMap for &mut StateHasher {
+        type Ok = ();
+        type Error = Unhashable;
+
+        fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Done {
+            key.serialize(&mut **self)
+        }
+
+        fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Done {
+            value.serialize(&mut **self)
+        }
+
+        fn end(self) -> Done {
+            Ok(())
+        }
+    }
+
+    impl ser::SerializeStruct for &mut StateHasher {
+        type Ok = ();
+        type Error = Unhashable;
+
+        fn serialize_field<T: ?Sized + Serialize>(&mut self, _key: &'static str, value: &T) -> Done {
+            value.serialize(&mut **self)
+        }
+
+        fn end(self) -> Done {
+            Ok(())
+        }
+    }
+
+    impl ser::SerializeStructVariant for &mut StateHasher {
+        type Ok = ();
+        type Error = Unhashable;
+
+        fn serialize_field<T: ?Sized + Serialize>(&mut self, _key: &'static str, value: &T) -> Done {
+            value.serialize(&mut **self)
+        }
+
+        fn end(self) -> Done {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{console_with, TestRom};
+    use super::*;
+
+    fn console() -> Nes {
+        let mut nes = console_with(&TestRom::new(1));
+        nes.run_frame();
+        nes
+    }
+
+    #[test]
+    fn fnv_matches_reference_values() {
+        assert_eq!(StateHasher::new().finish(), 0xCBF29CE484222325);
+        assert_eq!(StateHasher::new().bytes(b"a").finish(), 0xAF63DC4C8601EC8C);
+    }
+
+    #[test]
+    fn localizes_differences() {
+        let (mut a, b) = (console(), console());
+        assert_eq!(a.state_hashes(), b.state_hashes());
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        a.poke(0x0100, 0x42);
+        assert_eq!(a.state_hashes().differences(&b.state_hashes()), ["ram"]);
+        // MMC1's shift register
+        a.poke(0x8000, 0x01);
+        assert_eq!(a.state_hashes().differences(&b.state_hashes()), ["ram", "mapper"]);
+        // Also steps OAMADDR, a PPU register
+        a.cpu.bus.ppu.write_oamdata(0x10);
+        a.cpu.x ^= 1;
+        assert_eq!(a.state_hashes().differences(&b.state_hashes()), ["cpu", "ram", "ppu", "oam", "mapper"]);
+        assert_ne!(a.state_hash(), b.state_hash());
+    }
+
+    #[test]
+    fn mapper_covers_chr_ram() {
+        let new = || {
+            let mut nes = console_with(&TestRom::new(1).chr_8k(0));
+            nes.run_frame();
+            nes
+        };
+        let (mut a, b) = (new(), new());
+        a.cpu.bus.cartridge.chr_write(0x0010, 0x55);
+        assert_eq!(a.state_hashes().differences(&b.state_hashes()), ["mapper"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn mapper_covers_state_the_debugger_doesnt_show() {
+        let (mut a, b) = (console(), console());
+        // Resets the already reset MMC1 serial port, only noting the cycle
+        a.poke(0x8000, 0x80);
+        assert_eq!(a.cpu.bus.cartridge.mapper_debug_info(), b.cpu.bus.cartridge.mapper_debug_info());
+        assert_eq!(a.state_hashes().differences(&b.state_hashes()), ["mapper"]);
+    }
+}
\ No newline at end of file
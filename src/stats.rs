@@ -0,0 +1,144 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+// Frames the figures are averaged over, a second's worth at 60Hz
+const WINDOW: usize = 60;
+
+// Below this fraction of full speed the host isn't keeping up
+const BEHIND_SPEED: f64 = 0.95;
+
+// A longer gap between frames is the frontend pausing, not running slow
+const PAUSE: Duration = Duration::from_secs(1);
+
+// Rolling performance figures over the last WINDOW frames, for a
+// frontend's HUD. All zero until two frames have completed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SpeedStats {
+    // Emulated frames per second of wall clock time
+    pub fps: f64,
+    // CPU cycles emulated per second of wall clock time
+    pub cycles_per_second: f64,
+    // cycles_per_second over the region's CPU clock, 1.0 at full speed
+    pub speed: f64,
+    // Host time spent emulating a frame, from its first step() to the
+    // one that completes it, averaged and at its worst
+    pub frame_time: Duration,
+    pub worst_frame_time: Duration,
+    // How full the frontend's audio buffer is, 0.0 to 1.0, as last
+    // reported through Nes::report_audio_queue
+    pub audio_fill: Option<f32>,
+}
+
+impl SpeedStats {
+    pub fn falling_behind(&self) -> bool {
+        self.fps > 0.0 && self.speed < BEHIND_SPEED
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SpeedTracker {
+    // When each recent frame completed, with the cycle count then
+    frames: VecDeque<(Instant, u64)>,
+    // Host time spent emulating each of those frames
+    frame_times: VecDeque<Duration>,
+    // When the frame in progress got its first step
+    frame_begun: Option<Instant>,
+    audio_fill: Option<f32>,
+}
+
+impl SpeedTracker {
+    // Called before every step, so kept to a check until a frame starts
+    pub(crate) fn emulating(&mut self) {
+        if self.frame_begun.is_none() {
+            self.frame_begun = Some(Instant::now());
+        }
+    }
+
+    pub(crate) fn frame_done(&mut self, total_cycles: u64) {
+        let now = Instant::now();
+        if self.frames.back().is_some_and(|&(last, _)| now - last > PAUSE) {
+            self.frames.clear();
+        }
+        if let Some(begun) = self.frame_begun.take() {
+            self.frame_times.push_back(now - begun);
+            if self.frame_times.len() > WINDOW {
+                self.frame_times.pop_front();
+            }
+        }
+        // One more than the window, as it takes two ends to time a frame
+        self.frames.push_back((now, total_cycles));
+        if self.frames.len() > WINDOW + 1 {
+            self.frames.pop_front();
+        }
+    }
+
+    pub(crate) fn set_audio_fill(&mut self, fill: f32) {
+        self.audio_fill = Some(fill.clamp(0.0, 1.0));
+    }
+
+    // Forgets the frames so far, for a power cycle
+    pub(crate) fn restart(&mut self) {
+        self.frames.clear();
+        self.frame_times.clear();
+        self.frame_begun = None;
+    }
+
+    pub(crate) fn stats(&self, clock_hz: f64) -> SpeedStats {
+        let mut stats = SpeedStats { audio_fill: self.audio_fill, ..SpeedStats::default() };
+        if !self.frame_times.is_empty() {
+            stats.frame_time = self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32;
+            stats.worst_frame_time = self.frame_times.iter().copied().max().unwrap_or_default();
+        }
+        if let (Some(&(first, first_cycles)), Some(&(last, last_cycles))) = (self.frames.front(), self.frames.back()) {
+            let elapsed = (last - first).as_secs_f64();
+            if elapsed > 0.0 {
+                stats.fps = (self.frames.len() - 1) as f64 / elapsed;
+                stats.cycles_per_second = last_cycles.saturating_sub(first_cycles) as f64 / elapsed;
+                stats.speed = stats.cycles_per_second / clock_hz;
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::builder::RomBuilder, Nes, SystemVersion};
+    use super::*;
+
+    #[test]
+    fn averages_over_completed_frames() {
+        let mut tracker = SpeedTracker::default();
+        assert_eq!(tracker.stats(1.0), SpeedStats::default());
+
+        let start = Instant::now();
+        tracker.frames.push_back((start, 0));
+        tracker.frames.push_back((start + Duration::from_millis(500), 30_000));
+        tracker.frames.push_back((start + Duration::from_millis(1000), 60_000));
+        tracker.frame_times.extend([Duration::from_millis(2), Duration::from_millis(6)]);
+        tracker.set_audio_fill(1.5);
+
+        let stats = tracker.stats(120_000.0);
+        assert_eq!((stats.fps, stats.cycles_per_second, stats.speed), (2.0, 60_000.0, 0.5));
+        assert_eq!((stats.frame_time, stats.worst_frame_time), (Duration::from_millis(4), Duration::from_millis(6)));
+        assert_eq!(stats.audio_fill, Some(1.0));
+        assert!(stats.falling_behind());
+    }
+
+    #[test]
+    fn console_frames_are_counted() {
+        // JMP $8000
+        let rom = RomBuilder::new(0).prg(&[0x4C, 0x00, 0x80]).vectors(0x8000, 0x8000, 0x8000).build().unwrap();
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(rom);
+        nes.on();
+        for _ in 0..3 {
+            nes.run_frame();
+        }
+        let stats = nes.stats();
+        assert!(stats.fps > 0.0 && stats.cycles_per_second > 0.0);
+        assert!(stats.frame_time > Duration::ZERO);
+
+        nes.on();
+        assert_eq!(nes.stats().fps, 0.0);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,274 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::cpu::{history::TraceEntry, profiler::CodeAddress};
+
+// FCEUX writes one .nl file per 16KB PRG bank
+const NL_BANK_SIZE: u32 = 0x4000;
+// ca65 file offsets count the iNES header
+const INES_HEADER_SIZE: u32 = 16;
+
+// One line of a ca65 .dbg file: `sym id=3,name="reset",val=0x8000,seg=0`
+struct Record<'a> {
+    fields: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Record<'a> {
+    fn parse(text: &'a str) -> Self {
+        let mut fields = Vec::new();
+        let mut start = 0;
+        let mut quoted = false;
+        for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ','))) {
+            match c {
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    if let Some((key, value)) = text[start..i].split_once('=') {
+                        fields.push((key.trim(), value.trim().trim_matches('"')));
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        Record { fields }
+    }
+
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.fields.iter().find(|(k, _)| *k == key).map(|&(_, value)| value)
+    }
+
+    fn number(&self, key: &str) -> Option<u32> {
+        let value = self.get(key)?;
+        match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        }
+    }
+}
+
+// Labels for code and data, from an assembler's or another emulator's debug
+// output. Labels in cartridge ROM are keyed by PRG offset, so each bank
+// mapped at the same CPU address keeps its own; RAM, registers and anything
+// without a bank are keyed by CPU address.
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    prg: HashMap<u32, String>,
+    cpu: HashMap<u16, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    // Adds the labels in a ca65 .dbg or FCEUX .nl file, returning how many
+    // there were. FCEUX names its files after the bank, game.nes.0.nl for
+    // PRG bank 0 and game.nes.ram.nl for RAM.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let result = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("dbg") => self.add_ca65(&text),
+            Some("nl") => {
+                let bank = path.file_stem()
+                    .and_then(|stem| Path::new(stem).extension())
+                    .and_then(|bank| bank.to_str()?.parse().ok());
+                self.add_fceux(&text, bank)
+            }
+            _ => Err("Expected a .dbg or .nl file".to_string()),
+        };
+        result.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // The contents of a ca65/ld65 debug info file (ld65 --dbgfile). Labels
+    // in segments written to the ROM get their PRG offset from the segment's
+    // file offset.
+    pub fn add_ca65(&mut self, text: &str) -> Result<usize, String> {
+        // Segment id to its CPU address and offset in the .nes file
+        let mut segments = HashMap::new();
+        let mut symbols = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let Some((kind, fields)) = line.trim().split_once(char::is_whitespace) else {
+                continue;
+            };
+            let record = Record::parse(fields);
+            match kind {
+                "seg" => {
+                    let id = record.number("id").ok_or_else(|| format!("Line {}: segment without an id", number + 1))?;
+                    let start = record.number("start").unwrap_or(0);
+                    segments.insert(id, (start, record.number("ooffs")));
+                }
+                "sym" => symbols.push((number, record)),
+                _ => {}
+            }
+        }
+
+        let mut count = 0;
+        for (number, record) in symbols {
+            // Equates are only addresses when ca65 sized them as such; zero
+            // page sized ones are just as likely to be constants
+            let wanted = match record.get("type") {
+                Some("lab") => true,
+                Some("equ") => record.get("addrsize") == Some("absolute"),
+                _ => false,
+            };
+            let Some(name) = record.get("name").filter(|_| wanted) else {
+                continue;
+            };
+            let value = record.number("val").ok_or_else(|| format!("Line {}: symbol without a value", number + 1))?;
+            let Ok(addr) = u16::try_from(value) else {
+                continue;
+            };
+            let segment = record.number("seg").and_then(|id| segments.get(&id));
+            match segment {
+                Some(&(start, Some(file_offset))) if addr >= 0x8000 && file_offset >= INES_HEADER_SIZE => {
+                    self.add_prg(file_offset - INES_HEADER_SIZE + (value - start), name);
+                }
+                _ => self.add_cpu(addr, name),
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // The contents of an FCEUX .nl file: `$C000#Label#Comment` lines, with
+    // `$0300/10#Array#` for a range. `bank` is the 16KB PRG bank the file
+    // describes, None for the RAM file.
+    pub fn add_fceux(&mut self, text: &str, bank: Option<u32>) -> Result<usize, String> {
+        let mut count = 0;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split('#');
+            let addr = fields.next().unwrap_or("").split('/').next().unwrap_or("");
+            let addr = u16::from_str_radix(addr.trim().trim_start_matches('$'), 16)
+                .map_err(|_| format!("Line {}: bad address", number + 1))?;
+            // Comment-only entries have no name
+            let name = fields.next().unwrap_or("").trim();
+            if name.is_empty() {
+                continue;
+            }
+            match bank {
+                Some(bank) if addr >= 0x8000 => self.add_prg(bank * NL_BANK_SIZE + addr as u32 % NL_BANK_SIZE, name),
+                _ => self.add_cpu(addr, name),
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // The first label given to an address is the one kept
+    pub fn add_prg(&mut self, offset: u32, name: &str) {
+        self.prg.entry(offset).or_insert_with(|| name.to_string());
+    }
+
+    pub fn add_cpu(&mut self, addr: u16, name: &str) {
+        self.cpu.entry(addr).or_insert_with(|| name.to_string());
+    }
+
+    pub fn len(&self) -> usize {
+        self.prg.len() + self.cpu.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // A label for the bank mapped in wins over one for the bare address
+    pub fn label(&self, address: CodeAddress) -> Option<&str> {
+        address.prg_offset.and_then(|offset| self.prg.get(&offset))
+            .or_else(|| self.cpu.get(&address.pc))
+            .map(String::as_str)
+    }
+
+    // Names an instruction's address and the one its operand refers to:
+    // "reset", "-> PPUCTRL" or "reset -> PPUCTRL"
+    pub fn comment(&self, location: CodeAddress, target: Option<CodeAddress>) -> Option<String> {
+        match (self.label(location), target.and_then(|target| self.label(target))) {
+            (Some(at), Some(to)) => Some(format!("{} -> {}", at, to)),
+            (Some(at), None) => Some(at.to_string()),
+            (None, Some(to)) => Some(format!("-> {}", to)),
+            (None, None) => None,
+        }
+    }
+
+    // The entry's trace line, with its labels in a trailing comment
+    pub fn annotate(&self, entry: &TraceEntry) -> String {
+        match self.comment(entry.location, entry.target) {
+            Some(comment) => format!("{}  ; {}", entry, comment),
+            None => entry.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::console;
+    use super::*;
+
+    fn prg(pc: u16, offset: u32) -> CodeAddress {
+        CodeAddress { pc, prg_offset: Some(offset) }
+    }
+
+    fn ram(pc: u16) -> CodeAddress {
+        CodeAddress { pc, prg_offset: None }
+    }
+
+    #[test]
+    fn parses_ca65_debug_info() {
+        let dbg = "version\tmajor=2,minor=0\n\
+            seg\tid=0,name=\"ZEROPAGE\",start=0x000000,size=0x0010,addrsize=zeropage,type=rw\n\
+            seg\tid=1,name=\"CODE\",start=0x00C000,size=0x0100,addrsize=absolute,type=ro,oname=\"game.nes\",ooffs=16400\n\
+            sym\tid=0,name=\"reset\",addrsize=absolute,scope=0,def=1,val=0xC000,seg=1,type=lab\n\
+            sym\tid=1,name=\"@loop\",addrsize=absolute,scope=1,def=2,val=0xC012,seg=1,type=lab\n\
+            sym\tid=2,name=\"frame\",addrsize=zeropage,scope=0,def=3,val=0x3,seg=0,type=lab\n\
+            sym\tid=3,name=\"PPUCTRL\",addrsize=absolute,scope=0,def=4,val=0x2000,type=equ\n\
+            sym\tid=4,name=\"SPEED\",addrsize=zeropage,scope=0,def=5,val=0x3,type=equ\n\
+            sym\tid=5,name=\"nmi\",addrsize=absolute,scope=0,ref=6,type=imp\n";
+        let mut symbols = SymbolTable::new();
+        assert_eq!(symbols.add_ca65(dbg), Ok(4));
+
+        // CODE starts 16384 bytes into PRG, the second 16KB bank
+        assert_eq!(symbols.label(prg(0xC000, 0x4000)), Some("reset"));
+        assert_eq!(symbols.label(prg(0xC012, 0x4012)), Some("@loop"));
+        assert_eq!(symbols.label(prg(0xC000, 0x0000)), None);
+        assert_eq!(symbols.label(ram(0x0003)), Some("frame"));
+        assert_eq!(symbols.label(ram(0x2000)), Some("PPUCTRL"));
+    }
+
+    #[test]
+    fn parses_fceux_name_lists_per_bank() {
+        let mut symbols = SymbolTable::new();
+        assert_eq!(symbols.add_fceux("$8000#Bank2Start#\n$A010#Helper#Does things\n$9000##Just a comment\n", Some(2)), Ok(2));
+        assert_eq!(symbols.add_fceux("$0300/10#Buffer#\n$2002#PPUSTATUS#\n", None), Ok(2));
+        assert!(symbols.add_fceux("#Nope#\n", None).is_err());
+
+        assert_eq!(symbols.label(prg(0x8000, 0x8000)), Some("Bank2Start"));
+        assert_eq!(symbols.label(prg(0xA010, 0xA010)), Some("Helper"));
+        assert_eq!(symbols.label(ram(0x0300)), Some("Buffer"));
+        assert_eq!(symbols.comment(prg(0xA010, 0xA010), Some(ram(0x2002))), Some("Helper -> PPUSTATUS".to_string()));
+    }
+
+    #[test]
+    fn annotates_history_entries() {
+        let mut nes = console();
+        // $0200: LDA $2002; BPL $0200
+        for (i, byte) in [0xAD, 0x02, 0x20, 0x10, 0xFB].iter().enumerate() {
+            nes.cpu.bus.write(0x0200 + i as u16, *byte);
+        }
+        nes.set_start(0x0200);
+        let mut symbols = SymbolTable::new();
+        symbols.add_fceux("$0200#wait_vblank#\n$2002#PPUSTATUS#\n", None).unwrap();
+        nes.set_symbols(Some(symbols));
+        nes.record_history(2);
+        nes.step();
+        nes.step();
+
+        let symbols = nes.symbols().unwrap();
+        let lines: Vec<String> = nes.history().unwrap().entries().map(|entry| symbols.annotate(entry)).collect();
+        assert!(lines[0].starts_with("0200  AD 02 20"));
+        assert!(lines[0].ends_with("; wait_vblank -> PPUSTATUS"));
+        assert!(lines[1].ends_with("; -> wait_vblank"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,136 @@
+use crate::{rom::{header::{RomHeader, HEADER_SIZE}, Rom}, Nes, SystemVersion};
+
+// An NTSC console with an NROM test cartridge, switched on
+pub fn console() -> Nes {
+    console_with(&TestRom::new(0))
+}
+
+pub fn console_with(rom: &TestRom) -> Nes {
+    let mut nes = Nes::new(SystemVersion::NTSC);
+    nes.set_rom(Rom::new(rom.bytes()));
+    nes.on();
+    nes
+}
+
+// Builds minimal iNES images in memory so mapper banking can be tested
+// without real ROM dumps. Every PRG byte holds the number of the 8KB bank it
+// lives in and every CHR byte the number of its 1KB bank, so a single read
+// tells which bank got mapped.
+pub struct TestRom {
+    mapper: u16,
+    submapper: u8,
+    prg_16k: u8,
+    chr_8k: u8,
+    flags_6: u8,
+    prg_ram_shift: u8,
+    chr_ram_shift: u8,
+    pal: bool,
+    nes2: bool,
+}
+
+impl TestRom {
+    pub fn new(mapper: u16) -> Self {
+        TestRom {
+            mapper,
+            submapper: 0,
+            prg_16k: 2,
+            chr_8k: 1,
+            flags_6: 0,
+            prg_ram_shift: 0,
+            chr_ram_shift: 0,
+            pal: false,
+            nes2: false,
+        }
+    }
+
+    pub fn prg_16k(mut self, banks: u8) -> Self {
+        self.prg_16k = banks;
+        self
+    }
+
+    pub fn chr_8k(mut self, banks: u8) -> Self {
+        self.chr_8k = banks;
+        self
+    }
+
+    pub fn vertical(mut self) -> Self {
+        self.flags_6 |= 0x01;
+        self
+    }
+
+    pub fn battery(mut self) -> Self {
+        self.flags_6 |= 0x02;
+        self
+    }
+
+    pub fn four_screen(mut self) -> Self {
+        self.flags_6 |= 0x08;
+        self
+    }
+
+    pub fn pal(mut self) -> Self {
+        self.pal = true;
+        self
+    }
+
+    // The remaining options only exist in NES 2.0 headers.
+    pub fn submapper(mut self, submapper: u8) -> Self {
+        self.submapper = submapper;
+        self.nes2 = true;
+        self
+    }
+
+    pub fn prg_ram(mut self, size: u32) -> Self {
+        self.prg_ram_shift = Self::size_shift(size);
+        self.nes2 = true;
+        self
+    }
+
+    pub fn chr_ram(mut self, size: u32) -> Self {
+        self.chr_ram_shift = Self::size_shift(size);
+        self.nes2 = true;
+        self
+    }
+
+    fn size_shift(size: u32) -> u8 {
+        assert!(size >= 128 && size.is_power_of_two(), "RAM size must be a power of two >= 128");
+        (size.trailing_zeros() - 6) as u8
+    }
+
+    pub fn header_bytes(&self) -> Vec<u8> {
+        let mut flags_7 = (self.mapper & 0xF0) as u8;
+        let mut byte_8 = 0;
+        if self.nes2 {
+            flags_7 |= 0x08;
+            byte_8 = (self.submapper << 4) | ((self.mapper >> 8) & 0x0F) as u8;
+        }
+
+        vec![
+            0x4E, 0x45, 0x53, 0x1A,
+            self.prg_16k,
+            self.chr_8k,
+            self.flags_6 | ((self.mapper & 0x0F) << 4) as u8,
+            flags_7,
+            byte_8,
+            if self.nes2 { 0 } else { self.pal as u8 },
+            self.prg_ram_shift,
+            self.chr_ram_shift,
+            if self.nes2 { self.pal as u8 } else { 0 },
+            0, 0, 0,
+        ]
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut data = self.header_bytes();
+        let prg_size = self.prg_16k as usize * 0x4000;
+        data.extend((0..prg_size).map(|offset| (offset >> 13) as u8));
+        let chr_size = self.chr_8k as usize * 0x2000;
+        data.extend((0..chr_size).map(|offset| (offset >> 10) as u8));
+        data
+    }
+
+    pub fn build(&self) -> (RomHeader, Vec<u8>) {
+        let data = self.bytes();
+        (RomHeader::new(data[0..HEADER_SIZE].to_vec()), data)
+    }
+}
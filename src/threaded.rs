@@ -0,0 +1,134 @@
+use std::{sync::mpsc::{self, Receiver, Sender, SyncSender}, thread::{self, JoinHandle}};
+
+use crate::{controller::Button, Nes};
+
+// Frames of audio held for the frontend; more are dropped until it catches up
+const AUDIO_FRAMES: usize = 8;
+
+pub type Frame = Box<[u8; 256 * 240 * 3]>;
+
+// Sent from the frontend to the emulation thread
+#[derive(Debug, Clone, Copy)]
+pub enum Input {
+    Button { port: usize, button: Button, pressed: bool },
+    Reset,
+    Stop,
+}
+
+// Handles to a Nes running on its own thread. Frames go through a two slot
+// channel, so emulation runs at most two frames ahead of whoever is
+// receiving them; audio for each frame is sent right after it, and dropped
+// while AUDIO_FRAMES of it are waiting.
+pub struct ThreadedNes {
+    pub frames: Receiver<Frame>,
+    pub audio: Receiver<Vec<f32>>,
+    pub input: Sender<Input>,
+    handle: JoinHandle<Nes>,
+}
+
+impl ThreadedNes {
+    // Stops the thread and hands the Nes back
+    pub fn stop(self) -> Nes {
+        let _ = self.input.send(Input::Stop);
+        // Unblock a thread waiting on a full frame channel
+        drop(self.frames);
+        self.handle.join().expect("emulation thread panicked")
+    }
+}
+
+impl Nes {
+    // Moves the console onto a new thread that runs frame after frame until
+    // stopped or until the frame receiver is dropped. A console that isn't
+    // on is handed straight back, closing the frame channel.
+    pub fn run_threaded(self) -> ThreadedNes {
+        let (frame_tx, frames) = mpsc::sync_channel(2);
+        let (audio_tx, audio) = mpsc::sync_channel(AUDIO_FRAMES);
+        let (input, input_rx) = mpsc::channel();
+        let handle = thread::spawn(move || self.run_frames(input_rx, frame_tx, audio_tx));
+        ThreadedNes { frames, audio, input, handle }
+    }
+
+    fn run_frames(mut self, input: Receiver<Input>, frames: SyncSender<Frame>, audio: SyncSender<Vec<f32>>) -> Nes {
+        // It would never finish a frame
+        if !self.is_on() {
+            return self;
+        }
+        loop {
+            for event in input.try_iter() {
+                match event {
+                    Input::Button { port, button, pressed } => self.set_port_button(port, button, pressed),
+                    Input::Reset => self.reset(),
+                    Input::Stop => return self,
+                }
+            }
+
+            // A jammed CPU still ticks the PPU, so frames keep coming
+            self.run_frame();
+
+            if frames.send(Box::new(self.frame())).is_err() {
+                return self;
+            }
+            // The audio receiver is optional, and may not keep up
+            let _ = audio.try_send(self.take_audio_samples());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rom::builder::RomBuilder, test_support::console, SystemVersion};
+    use super::*;
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn nes_is_send() {
+        assert_send::<Nes>();
+    }
+
+    #[test]
+    fn runs_frames_on_another_thread() {
+        let nes = console();
+
+        let threaded = nes.run_threaded();
+        threaded.frames.recv().unwrap();
+        threaded.frames.recv().unwrap();
+        assert!(!threaded.audio.recv().unwrap().is_empty());
+
+        let nes = threaded.stop();
+        assert!(nes.cpu.bus.ppu.frame_count >= 2);
+    }
+
+    #[test]
+    fn undrained_audio_is_dropped() {
+        let nes = console();
+
+        let threaded = nes.run_threaded();
+        for _ in 0..AUDIO_FRAMES + 4 {
+            threaded.frames.recv().unwrap();
+        }
+        assert_eq!(threaded.audio.try_iter().count(), AUDIO_FRAMES);
+        threaded.stop();
+    }
+
+    #[test]
+    fn jammed_console_still_runs_whole_frames() {
+        let mut nes = Nes::new(SystemVersion::NTSC);
+        nes.set_rom(RomBuilder::new(0).prg(&[0x02]).vectors(0x8000, 0x8000, 0x8000).build().unwrap());
+        nes.on();
+
+        let threaded = nes.run_threaded();
+        threaded.frames.recv().unwrap();
+        threaded.frames.recv().unwrap();
+        let nes = threaded.stop();
+        assert!(nes.is_jammed());
+        assert!(nes.cpu.bus.ppu.frame_count >= 2);
+    }
+
+    #[test]
+    fn console_that_is_off_comes_straight_back() {
+        let threaded = Nes::new(SystemVersion::NTSC).run_threaded();
+        assert!(threaded.frames.recv().is_err());
+        assert!(!threaded.stop().is_on());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,137 @@
+use crate::{cartridge::Cartridge, mapper::NametableSource};
+
+const TILES: usize = 512;
+const NAMETABLE_ENTRIES: usize = 4 * 0x400;
+const CHR_PAGES: u16 = 8;
+
+// Which pattern table tiles and nametable bytes changed over a frame, so a
+// tile viewer or editor can redraw just those. Writes are tracked as they
+// happen; CHR bank switches and mirroring changes are caught by comparing
+// the mapping at the end of each frame, and mark everything they affect.
+pub struct TileChanges {
+    pending_tiles: Vec<bool>,
+    pending_entries: Vec<bool>,
+    chr_pages: Option<[u32; CHR_PAGES as usize]>,
+    sources: Option<[NametableSource; 4]>,
+
+    tiles: Vec<u16>,
+    entries: Vec<(usize, usize)>,
+}
+
+impl Default for TileChanges {
+    fn default() -> Self {
+        TileChanges::new()
+    }
+}
+
+impl TileChanges {
+    // Everything counts as changed in the first frame
+    pub fn new() -> Self {
+        TileChanges {
+            pending_tiles: vec![true; TILES],
+            pending_entries: vec![true; NAMETABLE_ENTRIES],
+            chr_pages: None,
+            sources: None,
+            tiles: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    // Tiles 0-255 are the pattern table at $0000, 256-511 the one at $1000
+    pub fn tiles(&self) -> &[u16] {
+        &self.tiles
+    }
+
+    // (nametable 0-3, byte 0-$3FF); bytes from $3C0 on are attributes
+    pub fn nametable_entries(&self) -> &[(usize, usize)] {
+        &self.entries
+    }
+
+    pub(crate) fn chr_written(&mut self, addr: u16) {
+        self.pending_tiles[(addr as usize >> 4) % TILES] = true;
+    }
+
+    // A write to nametable RAM shows up in every slot mirroring it
+    pub(crate) fn nametable_written(&mut self, cart: &Cartridge, addr: u16) {
+        let table = (addr >> 10) & 0x3;
+        let source = cart.nametable_source(table);
+        for slot in 0..4 {
+            if slot == table || (cart.nametable_source(slot) == source && source != NametableSource::Mapper) {
+                self.pending_entries[slot as usize * 0x400 + (addr & 0x3FF) as usize] = true;
+            }
+        }
+    }
+
+    pub(crate) fn end_frame(&mut self, cart: &Cartridge) {
+        let chr_pages: [u32; CHR_PAGES as usize] = std::array::from_fn(|page| cart.map(page as u16 * 0x400));
+        if let Some(previous) = self.chr_pages {
+            for page in 0..CHR_PAGES as usize {
+                if previous[page] != chr_pages[page] {
+                    self.pending_tiles[page * 64..(page + 1) * 64].fill(true);
+                }
+            }
+        }
+        self.chr_pages = Some(chr_pages);
+
+        let sources: [NametableSource; 4] = std::array::from_fn(|table| cart.nametable_source(table as u16));
+        if let Some(previous) = self.sources {
+            for table in 0..4 {
+                if previous[table] != sources[table] {
+                    self.pending_entries[table * 0x400..(table + 1) * 0x400].fill(true);
+                }
+            }
+        }
+        self.sources = Some(sources);
+
+        self.tiles = (0..TILES as u16).filter(|&tile| self.pending_tiles[tile as usize]).collect();
+        self.entries = (0..NAMETABLE_ENTRIES)
+            .filter(|&i| self.pending_entries[i])
+            .map(|i| (i / 0x400, i % 0x400))
+            .collect();
+        self.pending_tiles.fill(false);
+        self.pending_entries.fill(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::{console_with, TestRom}, Nes};
+
+    fn console(rom: TestRom) -> Nes {
+        let mut nes = console_with(&rom);
+        nes.track_tile_changes(true);
+        nes.run_frame();
+        assert_eq!(nes.tile_changes().unwrap().tiles().len(), 512);
+        nes.run_frame();
+        assert!(nes.tile_changes().unwrap().tiles().is_empty());
+        nes
+    }
+
+    fn write_vram(nes: &mut Nes, addr: u16, data: u8) {
+        nes.cpu.bus.write(0x2006, (addr >> 8) as u8);
+        nes.cpu.bus.write(0x2006, addr as u8);
+        nes.cpu.bus.write(0x2007, data);
+    }
+
+    #[test]
+    fn reports_written_tiles_and_mirrored_entries() {
+        let mut nes = console(TestRom::new(0).chr_8k(0));
+        write_vram(&mut nes, 0x1015, 0xFF);
+        write_vram(&mut nes, 0x27C1, 0x55);
+        nes.run_frame();
+
+        let changes = nes.tile_changes().unwrap();
+        assert_eq!(changes.tiles(), &[257]);
+        // Horizontal mirroring: $2400 shares its RAM with $2000
+        assert_eq!(changes.nametable_entries(), &[(0, 0x3C1), (1, 0x3C1)]);
+    }
+
+    #[test]
+    fn chr_bank_switch_marks_the_whole_page() {
+        let mut nes = console(TestRom::new(66).chr_8k(2));
+        nes.cpu.bus.write(0x8000, 0x01);
+        nes.run_frame();
+        assert_eq!(nes.tile_changes().unwrap().tiles().len(), 512);
+        assert!(nes.tile_changes().unwrap().nametable_entries().is_empty());
+    }
+}
\ No newline at end of file
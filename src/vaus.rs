@@ -0,0 +1,99 @@
+// Arkanoid "Vaus" paddle in the NES (second port) variant. A write to $4016
+// latches the potentiometer into a shift register that $4017 reads out MSB
+// first and inverted on D4, with the fire button on D3. On a Famicom's
+// expansion port the same two signals come in on D1, see Bus::read_4016.
+
+// Range the original potentiometer sweeps; Arkanoid clamps to roughly this.
+const PADDLE_MIN: u8 = 98;
+const PADDLE_MAX: u8 = 242;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vaus {
+    position: u8,
+    fire: bool,
+    strobe: bool,
+    shift: u8,
+}
+
+impl Default for Vaus {
+    fn default() -> Self {
+        Vaus::new()
+    }
+}
+
+impl Vaus {
+    pub fn new() -> Self {
+        Vaus {
+            position: (PADDLE_MIN / 2) + (PADDLE_MAX / 2),
+            fire: false,
+            strobe: false,
+            shift: 0,
+        }
+    }
+
+    pub fn set_position(&mut self, value: u8) {
+        self.position = value;
+    }
+
+    // 0.0 is the far left of the paddle's travel, 1.0 the far right.
+    pub fn set_position_fraction(&mut self, fraction: f32) {
+        let span = (PADDLE_MAX - PADDLE_MIN) as f32;
+        self.position = PADDLE_MIN + (fraction.clamp(0.0, 1.0) * span).round() as u8;
+    }
+
+    pub fn position(&self) -> u8 {
+        self.position
+    }
+
+    pub fn set_fire(&mut self, pressed: bool) {
+        self.fire = pressed;
+    }
+
+    pub fn fire(&self) -> bool {
+        self.fire
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift = !self.position;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        let data = (self.shift >> 7) & 1;
+        if !self.strobe {
+            self.shift <<= 1;
+        }
+        data << 4 | (self.fire as u8) << 3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts_out_inverted_position_msb_first() {
+        let mut vaus = Vaus::new();
+        vaus.set_position(0b1010_0000);
+        vaus.set_fire(true);
+        vaus.write(1);
+        vaus.write(0);
+
+        let bits: Vec<u8> = (0..8).map(|_| vaus.read()).collect();
+        assert_eq!(bits[0], 0x08);
+        assert_eq!(bits[1], 0x18);
+        assert_eq!(bits[2], 0x08);
+        assert_eq!(bits[3], 0x18);
+    }
+
+    #[test]
+    fn fraction_covers_paddle_range() {
+        let mut vaus = Vaus::new();
+        vaus.set_position_fraction(0.0);
+        assert_eq!(vaus.position(), PADDLE_MIN);
+        vaus.set_position_fraction(2.0);
+        assert_eq!(vaus.position(), PADDLE_MAX);
+    }
+}
\ No newline at end of file
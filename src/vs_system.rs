@@ -0,0 +1,46 @@
+// Cabinet inputs of a VS System board, read back through the upper bits of
+// $4016/$4017 alongside the regular controller data.
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VsSystem {
+    dip_switches: u8,
+    coins: [bool; 2],
+    service: bool,
+}
+
+impl VsSystem {
+    pub fn new() -> Self {
+        VsSystem::default()
+    }
+
+    // Bit 0 is DIP switch 1, bit 7 is DIP switch 8.
+    pub fn set_dip_switches(&mut self, value: u8) {
+        self.dip_switches = value;
+    }
+
+    pub fn dip_switches(&self) -> u8 {
+        self.dip_switches
+    }
+
+    // Games sample the coin line every frame, so hold it for a few frames.
+    pub fn set_coin(&mut self, slot: usize, inserted: bool) {
+        self.coins[slot & 1] = inserted;
+    }
+
+    pub fn set_service(&mut self, pressed: bool) {
+        self.service = pressed;
+    }
+
+    // $4016: bit 2 service, bits 3-4 DIP 1-2, bits 5-6 coin slots
+    pub fn read_4016(&self) -> u8 {
+        (self.service as u8) << 2
+            | (self.dip_switches & 0x03) << 3
+            | (self.coins[0] as u8) << 5
+            | (self.coins[1] as u8) << 6
+    }
+
+    // $4017: bits 2-7 DIP 3-8
+    pub fn read_4017(&self) -> u8 {
+        self.dip_switches & 0xFC
+    }
+}
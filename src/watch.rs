@@ -0,0 +1,146 @@
+use std::{collections::VecDeque, ops::Range};
+
+// Changes held for the frontend to take before the oldest are dropped, 8
+// frames of every byte of system RAM changing
+pub const MEMORY_CHANGE_LIMIT: usize = 16 * 1024;
+
+// Memory a frontend can watch, e.g. for achievements or auto-splitters.
+// Each region is exposed as one contiguous block with stable offsets,
+// independent of how it's mirrored or banked on the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemoryRegion {
+    // The 2KB at $0000-$07FF
+    SystemRam,
+    // Cartridge RAM at $6000-$7FFF, empty on boards without it
+    PrgRam,
+    // PPU nametable RAM, 4KB with the four-screen half after the console's 2KB
+    Nametables,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryChange {
+    pub region: MemoryRegion,
+    pub offset: usize,
+    pub old: u8,
+    pub new: u8,
+    // Frame the change was seen at the end of
+    pub frame: u64,
+}
+
+// Changes waiting to be taken. A frontend that stops taking them would
+// otherwise grow this for as long as the game runs.
+#[derive(Default)]
+pub(crate) struct MemoryChanges {
+    changes: VecDeque<MemoryChange>,
+    dropped: u64,
+}
+
+impl MemoryChanges {
+    pub fn push(&mut self, change: MemoryChange) {
+        if self.changes.len() == MEMORY_CHANGE_LIMIT {
+            self.changes.pop_front();
+            self.dropped += 1;
+        }
+        self.changes.push_back(change);
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+        self.dropped = 0;
+    }
+
+    // Oldest first
+    pub fn take(&mut self) -> Vec<MemoryChange> {
+        self.dropped = 0;
+        std::mem::take(&mut self.changes).into()
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+// A range compared against its contents at the end of the previous frame
+pub(crate) struct MemoryWatch {
+    region: MemoryRegion,
+    range: Range<usize>,
+    snapshot: Vec<u8>,
+}
+
+impl MemoryWatch {
+    pub fn new(region: MemoryRegion, range: Range<usize>, memory: &[u8]) -> Self {
+        let mut watch = MemoryWatch { region, range, snapshot: Vec::new() };
+        watch.snapshot = watch.slice(memory).to_vec();
+        watch
+    }
+
+    pub fn region(&self) -> MemoryRegion {
+        self.region
+    }
+
+    // The watched range, clipped to what the region actually has
+    fn slice<'a>(&self, memory: &'a [u8]) -> &'a [u8] {
+        let end = self.range.end.min(memory.len());
+        &memory[self.range.start.min(end)..end]
+    }
+
+    pub fn update(&mut self, memory: &[u8], frame: u64, changes: &mut MemoryChanges) {
+        let start = self.range.start;
+        let current = self.slice(memory);
+        for (i, (&old, &new)) in self.snapshot.iter().zip(current).enumerate() {
+            if old != new {
+                changes.push(MemoryChange { region: self.region, offset: start + i, old, new, frame });
+            }
+        }
+        self.snapshot.clear();
+        self.snapshot.extend_from_slice(current);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::console;
+    use super::*;
+
+    #[test]
+    fn reports_changes_once_per_frame() {
+        let mut nes = console();
+        nes.watch_memory(MemoryRegion::SystemRam, 0x300..0x310);
+        nes.watch_memory(MemoryRegion::PrgRam, 0..0x10);
+
+        nes.cpu.bus.write(0x0305, 0x42);
+        nes.cpu.bus.write(0x6001, 0x99);
+        nes.cpu.bus.write(0x0400, 0x01);
+        nes.run_frame();
+
+        let changes = nes.take_memory_changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[0].region, changes[0].offset, changes[0].new), (MemoryRegion::SystemRam, 0x305, 0x42));
+        assert_eq!((changes[1].region, changes[1].offset, changes[1].new), (MemoryRegion::PrgRam, 1, 0x99));
+        assert_eq!(nes.memory(MemoryRegion::PrgRam)[1], 0x99);
+
+        nes.run_frame();
+        assert!(nes.take_memory_changes().is_empty());
+    }
+
+    #[test]
+    fn ranges_are_clipped_to_the_region() {
+        let memory = [0u8; 4];
+        let mut watch = MemoryWatch::new(MemoryRegion::PrgRam, 2..100, &memory);
+        let mut changes = MemoryChanges::default();
+        watch.update(&[0, 0, 0, 5], 1, &mut changes);
+        assert_eq!(changes.take(), vec![MemoryChange { region: MemoryRegion::PrgRam, offset: 3, old: 0, new: 5, frame: 1 }]);
+    }
+
+    #[test]
+    fn untaken_changes_drop_the_oldest() {
+        let mut changes = MemoryChanges::default();
+        for offset in 0..MEMORY_CHANGE_LIMIT + 3 {
+            changes.push(MemoryChange { region: MemoryRegion::SystemRam, offset, old: 0, new: 1, frame: 1 });
+        }
+        assert_eq!(changes.dropped(), 3);
+        let taken = changes.take();
+        assert_eq!((taken.len(), taken[0].offset), (MEMORY_CHANGE_LIMIT, 3));
+        assert_eq!(changes.dropped(), 0);
+    }
+}
\ No newline at end of file